@@ -1,4 +1,4 @@
-use crate::Vec3;
+use crate::{Sphere, Vec3};
 
 #[derive(Clone)]
 pub struct AABB {
@@ -53,4 +53,37 @@ impl AABB {
             extent: extent,
         }
     }
+
+    pub fn min(&self) -> Vec3 {
+        self.center - self.extent
+    }
+
+    pub fn max(&self) -> Vec3 {
+        self.center + self.extent
+    }
+
+    //Smallest box enclosing both
+    pub fn merge(&self, other: &AABB) -> AABB {
+        let min = self.min().min(other.min());
+        let max = self.max().max(other.max());
+        let extent = (max - min).mul(0.5);
+        Self {
+            center: min + extent,
+            extent,
+        }
+    }
+
+    //Encloses the box, e.g. for Camera::frame_bounds
+    pub fn bounding_sphere(&self) -> Sphere {
+        Sphere::new(self.center, self.extent.distance())
+    }
+}
+
+impl From<&Sphere> for AABB {
+    fn from(sphere: &Sphere) -> Self {
+        Self {
+            center: sphere.center,
+            extent: Vec3::new(sphere.radius, sphere.radius, sphere.radius),
+        }
+    }
 }
@@ -0,0 +1,85 @@
+use crate::{Mat4, Sphere, Vec3, Vec4, AABB};
+
+/// A view frustum described by six planes pointing inwards.
+/// Each plane is stored as (a, b, c, d) with a normalized (a, b, c) so that
+/// `dot(normal, point) + d` is the signed distance to the plane.
+#[derive(Debug, Clone)]
+pub struct Frustum {
+    pub planes: [Vec4; 6],
+}
+
+impl Frustum {
+    pub const LEFT: usize = 0;
+    pub const RIGHT: usize = 1;
+    pub const BOTTOM: usize = 2;
+    pub const TOP: usize = 3;
+    pub const NEAR: usize = 4;
+    pub const FAR: usize = 5;
+
+    //Gribb/Hartmann plane extraction, expects the same clip conventions as Mat4::create_proj
+    pub fn from_view_proj(view_proj: &Mat4) -> Self {
+        let row0 = view_proj.extract_row(0);
+        let row1 = view_proj.extract_row(1);
+        let row2 = view_proj.extract_row(2);
+        let row3 = view_proj.extract_row(3);
+
+        let planes = [
+            Self::normalize_plane(Vec4::add(row3, row0)),
+            Self::normalize_plane(Self::sub(row3, row0)),
+            Self::normalize_plane(Vec4::add(row3, row1)),
+            Self::normalize_plane(Self::sub(row3, row1)),
+            Self::normalize_plane(Vec4::add(row3, row2)),
+            Self::normalize_plane(Self::sub(row3, row2)),
+        ];
+
+        Self { planes }
+    }
+
+    fn sub(lhs: Vec4, rhs: Vec4) -> Vec4 {
+        Vec4([
+            lhs[0] - rhs[0],
+            lhs[1] - rhs[1],
+            lhs[2] - rhs[2],
+            lhs[3] - rhs[3],
+        ])
+    }
+
+    fn normalize_plane(plane: Vec4) -> Vec4 {
+        let length = Vec3::new(plane[0], plane[1], plane[2]).distance();
+        if length == 0.0 {
+            return plane;
+        }
+        Vec4([
+            plane[0] / length,
+            plane[1] / length,
+            plane[2] / length,
+            plane[3] / length,
+        ])
+    }
+
+    fn signed_distance(plane: &Vec4, point: Vec3) -> f32 {
+        plane[0] * point[0] + plane[1] * point[1] + plane[2] * point[2] + plane[3]
+    }
+
+    pub fn contains_point(&self, point: Vec3) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| Self::signed_distance(plane, point) >= 0.0)
+    }
+
+    pub fn intersects_sphere(&self, sphere: &Sphere) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| Self::signed_distance(plane, sphere.center) >= -sphere.radius)
+    }
+
+    pub fn intersects_aabb(&self, aabb: &AABB) -> bool {
+        self.planes.iter().all(|plane| {
+            //Project the extent onto the plane normal to get the box "radius"
+            let radius = plane[0].abs() * aabb.extent[0]
+                + plane[1].abs() * aabb.extent[1]
+                + plane[2].abs() * aabb.extent[2];
+            Self::signed_distance(plane, aabb.center) >= -radius
+        })
+    }
+}
@@ -1,16 +1,20 @@
 #![allow(dead_code)]
 
 pub mod aabb;
+pub mod frustum;
 pub mod mat4;
 pub mod quat;
+pub mod rng;
 pub mod sphere;
 pub mod transform;
 pub mod vec3;
 pub mod vec4;
 
 pub use self::aabb::AABB;
+pub use self::frustum::Frustum;
 pub use self::mat4::Mat4;
 pub use self::quat::Quat;
+pub use self::rng::Rng;
 pub use self::sphere::Sphere;
 pub use self::transform::Transform;
 pub use self::vec3::Vec3;
@@ -41,3 +45,48 @@ pub fn mat4_mul_vec4(a: &Mat4, b: &Vec4) -> Vec4 {
         Vec4::dot(&row3, &b),
     ])
 }
+
+//Returns the (tangent, bitangent) of a triangle, given its positions and texture coordinates
+//Degenerate texture coordinates yield zero vectors
+pub fn triangle_tangents(positions: [Vec3; 3], tex_coords: [[f32; 2]; 3]) -> (Vec3, Vec3) {
+    let edge1 = positions[1] - positions[0];
+    let edge2 = positions[2] - positions[0];
+    let du1 = tex_coords[1][0] - tex_coords[0][0];
+    let dv1 = tex_coords[1][1] - tex_coords[0][1];
+    let du2 = tex_coords[2][0] - tex_coords[0][0];
+    let dv2 = tex_coords[2][1] - tex_coords[0][1];
+
+    let denominator = du1 * dv2 - du2 * dv1;
+    if denominator.abs() < f32::EPSILON {
+        return (Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.0));
+    }
+    let r = 1.0 / denominator;
+    let tangent = (edge1 * dv2 - edge2 * dv1) * r;
+    let bitangent = (edge2 * du1 - edge1 * du2) * r;
+    (tangent, bitangent)
+}
+
+//Coefficient for logarithmic depth into Vulkan's [0, 1] depth range, the vertex shader does
+//  gl_Position.z = log2(max(1e-6, 1.0 + gl_Position.w)) * coefficient * gl_Position.w;
+//which keeps precision evenly spread up to the far plane regardless of how close near is
+pub fn log_depth_coefficient(far: f32) -> f32 {
+    1.0 / (far + 1.0).log2()
+}
+
+//Shared by the Display impls, precision defaults to 4 decimals
+pub(crate) const DISPLAY_PRECISION: usize = 4;
+
+pub(crate) fn fmt_components(
+    f: &mut std::fmt::Formatter<'_>,
+    components: &[f32],
+) -> std::fmt::Result {
+    let precision = f.precision().unwrap_or(DISPLAY_PRECISION);
+    write!(f, "(")?;
+    for (index, component) in components.iter().enumerate() {
+        if index > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{:.*}", precision, component)?;
+    }
+    write!(f, ")")
+}
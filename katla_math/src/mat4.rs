@@ -1,6 +1,7 @@
 use crate::Vec3;
 use crate::Vec4;
 use core::ops::Index;
+use std::fmt;
 
 /// Mat4 is considered a column-major matrix, constructed using 4 Vec4s
 #[derive(Debug, Clone, PartialEq)]
@@ -85,6 +86,32 @@ impl Mat4 {
         ])
     }
 
+    pub fn from_columns(columns: [Vec4; 4]) -> Mat4 {
+        Mat4(columns)
+    }
+
+    pub fn column(&self, index: usize) -> &Vec4 {
+        &self[index]
+    }
+
+    pub fn set_column(&mut self, index: usize, column: Vec4) {
+        self.0[index] = column;
+    }
+
+    //Keeps the rotation and scale, the translation column becomes (0, 0, 0, 1)
+    pub fn without_translation(&self) -> Mat4 {
+        let mut mat = self.clone();
+        mat.set_column(3, Vec4([0.0, 0.0, 0.0, 1.0]));
+        mat
+    }
+
+    //Rows are scattered into the four columns
+    pub fn set_row(&mut self, index: usize, row: Vec4) {
+        for (column_index, column) in self.0.iter_mut().enumerate() {
+            column[index] = row[column_index];
+        }
+    }
+
     //Internal functions which makes less sense
     pub fn extract_row(&self, index: usize) -> Vec4 {
         Vec4([
@@ -388,3 +415,27 @@ impl Into<[[f32; 4]; 4]> for Mat4 {
         f_arr
     }
 }
+
+//Printed row by row, one line per row with the columns right-aligned
+impl fmt::Display for Mat4 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let precision = f.precision().unwrap_or(crate::DISPLAY_PRECISION);
+        //Room for a sign and three integer digits
+        let width = precision + 5;
+        for row_index in 0..4 {
+            if row_index > 0 {
+                writeln!(f)?;
+            }
+            let row = self.extract_row(row_index);
+            write!(f, "[")?;
+            for column in 0..4 {
+                if column > 0 {
+                    write!(f, " ")?;
+                }
+                write!(f, "{:>width$.precision$}", row[column])?;
+            }
+            write!(f, "]")?;
+        }
+        Ok(())
+    }
+}
@@ -1,6 +1,6 @@
 use crate::{vec3::Vec3, Mat4, Vec4};
 use core::ops::Index;
-use std::ops::Mul;
+use std::{fmt, ops::Mul};
 
 const QUAT_NORMALIZED_THRESHOLD: f32 = 0.001;
 
@@ -57,20 +57,28 @@ impl Quat {
         self.x * self.x + self.y * self.y + self.z * self.z + self.w * self.w
     }
 
-    pub fn is_normalized(&self) -> bool {
+    pub fn length(&self) -> f32 {
+        self.length_squared().sqrt()
+    }
+
+    pub fn is_normalized(&self, epsilon: f32) -> bool {
         let len_sq = self.length_squared();
-        f32::abs(1.0 - len_sq) < QUAT_NORMALIZED_THRESHOLD
+        f32::abs(1.0 - len_sq) < epsilon
     }
 
+    //Rotations composed over time drift off unit length, call this to correct it
     pub fn normalize(&mut self) {
-        let len_sq = self.length_squared();
-        self.x = self.x / len_sq;
-        self.y = self.y / len_sq;
-        self.z = self.z / len_sq;
-        self.w = self.w / len_sq;
+        let len = self.length();
+        if len == 0.0 {
+            return;
+        }
+        self.x /= len;
+        self.y /= len;
+        self.z /= len;
+        self.w /= len;
     }
 
-    pub fn inverse(&self) -> Self {
+    pub fn conjugate(&self) -> Self {
         Self {
             x: -self.x,
             y: -self.y,
@@ -79,6 +87,18 @@ impl Quat {
         }
     }
 
+    //For unit quaternions this is the same as the conjugate
+    pub fn inverse(&self) -> Self {
+        let len_sq = self.length_squared();
+        let conjugate = self.conjugate();
+        Self {
+            x: conjugate.x / len_sq,
+            y: conjugate.y / len_sq,
+            z: conjugate.z / len_sq,
+            w: conjugate.w / len_sq,
+        }
+    }
+
     pub fn dot(&self, rhs: Quat) -> f32 {
         let q1_v = Vec3::new(self.x, self.y, self.z);
         let q2_v = Vec3::new(rhs.x, rhs.y, rhs.z);
@@ -93,6 +113,13 @@ impl Quat {
         2.0 * u.dot(v) * u + (s * s - u.dot(u)) * v + 2.0 * s * u.cross(v)
     }
 
+    //Rotates v without building a matrix: v + 2w(u x v) + 2u x (u x v)
+    pub fn rotate(&self, v: Vec3) -> Vec3 {
+        let u = Vec3::new(self.x, self.y, self.z);
+        let t = 2.0 * u.cross(v);
+        v + (self.w * t) + u.cross(t)
+    }
+
     //Spherical interpolation, a slow version
     pub fn slerp(mut a: Quat, mut b: Quat, ratio: f32) -> Self {
         a.normalize();
@@ -122,6 +149,38 @@ impl Quat {
         out
     }
 
+    //Smallest rotation angle in radians between the two orientations, in [0, PI].
+    //q and -q are the same rotation, hence the abs
+    pub fn angle_between(&self, other: Quat) -> f32 {
+        let mut a = *self;
+        let mut b = other;
+        a.normalize();
+        b.normalize();
+        2.0 * a.dot(b).abs().min(1.0).acos()
+    }
+
+    //Steps at most max_radians towards target along the shortest arc, returns target
+    //once it is within reach
+    pub fn rotate_towards(&self, target: Quat, max_radians: f32) -> Quat {
+        let angle = self.angle_between(target);
+        if angle <= max_radians {
+            return target;
+        }
+        if max_radians <= 0.0 {
+            return *self;
+        }
+        let mut target = target;
+        if self.dot(target) < 0.0 {
+            target = Quat {
+                x: -target.x,
+                y: -target.y,
+                z: -target.z,
+                w: -target.w,
+            };
+        }
+        Self::slerp(*self, target, max_radians / angle)
+    }
+
     pub fn make_mat4(&self) -> Mat4 {
         let x2 = self.x + self.x;
         let y2 = self.y + self.y;
@@ -165,13 +224,61 @@ impl Quat {
             Vec4([m30, m31, m32, m33]),
         ])
     }
+
+    //Expects the upper 3x3 of the matrix to be a pure rotation, scale has to be removed first
+    pub fn from_rotation_mat4(mat: &Mat4) -> Quat {
+        //mat[column][row]
+        let (r00, r11, r22) = (mat[0][0], mat[1][1], mat[2][2]);
+        let trace = r00 + r11 + r22;
+        let mut quat = if trace > 0.0 {
+            let s = (trace + 1.0).sqrt() * 2.0;
+            Quat {
+                x: (mat[1][2] - mat[2][1]) / s,
+                y: (mat[2][0] - mat[0][2]) / s,
+                z: (mat[0][1] - mat[1][0]) / s,
+                w: 0.25 * s,
+            }
+        } else if r00 > r11 && r00 > r22 {
+            let s = (1.0 + r00 - r11 - r22).sqrt() * 2.0;
+            Quat {
+                x: 0.25 * s,
+                y: (mat[1][0] + mat[0][1]) / s,
+                z: (mat[2][0] + mat[0][2]) / s,
+                w: (mat[1][2] - mat[2][1]) / s,
+            }
+        } else if r11 > r22 {
+            let s = (1.0 + r11 - r00 - r22).sqrt() * 2.0;
+            Quat {
+                x: (mat[1][0] + mat[0][1]) / s,
+                y: 0.25 * s,
+                z: (mat[2][1] + mat[1][2]) / s,
+                w: (mat[2][0] - mat[0][2]) / s,
+            }
+        } else {
+            let s = (1.0 + r22 - r00 - r11).sqrt() * 2.0;
+            Quat {
+                x: (mat[2][0] + mat[0][2]) / s,
+                y: (mat[2][1] + mat[1][2]) / s,
+                z: 0.25 * s,
+                w: (mat[0][1] - mat[1][0]) / s,
+            }
+        };
+        quat.normalize();
+        quat
+    }
+}
+
+impl From<&Quat> for Mat4 {
+    fn from(quat: &Quat) -> Self {
+        quat.make_mat4()
+    }
 }
 impl Mul for Quat {
     type Output = Quat;
 
     fn mul(self, other: Quat) -> Self::Output {
-        assert!(self.is_normalized());
-        assert!(other.is_normalized());
+        assert!(self.is_normalized(QUAT_NORMALIZED_THRESHOLD));
+        assert!(other.is_normalized(QUAT_NORMALIZED_THRESHOLD));
 
         Self {
             x: self.w * other.x + self.x * other.w + self.y * other.z - self.z * other.y,
@@ -186,9 +293,13 @@ impl Mul<Vec3> for Quat {
     type Output = Vec3;
 
     fn mul(self, v: Vec3) -> Self::Output {
-        let q = Vec3::new(self.x, self.y, self.z);
-        let t = 2.0 * q.cross(v);
-        let result = v + (self.w * t) + q.cross(t);
-        result
+        self.rotate(v)
+    }
+}
+
+//Printed as (x, y, z, w)
+impl fmt::Display for Quat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        crate::fmt_components(f, &[self.x, self.y, self.z, self.w])
     }
 }
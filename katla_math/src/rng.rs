@@ -0,0 +1,55 @@
+use std::f32::consts::TAU;
+
+use crate::Vec3;
+
+const PCG_MULTIPLIER: u64 = 6364136223846793005;
+const PCG_DEFAULT_STREAM: u64 = 1442695040888963407;
+
+/// Seedable PCG32 generator for procedural content, the same seed always yields the same
+/// sequence. Not suitable for anything security related.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+    increment: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = Self {
+            state: 0,
+            increment: PCG_DEFAULT_STREAM | 1,
+        };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+        rng
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old_state = self.state;
+        self.state = old_state
+            .wrapping_mul(PCG_MULTIPLIER)
+            .wrapping_add(self.increment);
+        let xorshifted = (((old_state >> 18) ^ old_state) >> 27) as u32;
+        let rotation = (old_state >> 59) as u32;
+        xorshifted.rotate_right(rotation)
+    }
+
+    //Uniform in [0, 1), uses the top 24 bits so every value is exactly representable
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 * (1.0 / (1u32 << 24) as f32)
+    }
+
+    //Uniform in [min, max)
+    pub fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next_f32()
+    }
+
+    //Uniform point on the unit sphere, picks a height and an angle around the y axis
+    pub fn next_vec3_unit(&mut self) -> Vec3 {
+        let y = self.range(-1.0, 1.0);
+        let angle = self.range(0.0, TAU);
+        let radius = (1.0 - y * y).max(0.0).sqrt();
+        Vec3::new(radius * angle.cos(), y, radius * angle.sin())
+    }
+}
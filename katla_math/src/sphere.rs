@@ -32,6 +32,27 @@ impl Sphere {
         dist_sq <= radius_sum * radius_sum
     }
 
+    //Distance along dir to the nearest hit, in multiples of dir's length. A ray starting
+    //inside the sphere hits at 0.0
+    pub fn intersect_ray(&self, origin: Vec3, dir: Vec3) -> Option<f32> {
+        let to_origin = origin - self.center;
+        let c = to_origin.distance_squared() - self.radius * self.radius;
+        if c <= 0.0 {
+            return Some(0.0);
+        }
+        let a = dir.distance_squared();
+        let b = to_origin.dot(dir);
+        //Outside and pointing away
+        if a == 0.0 || b > 0.0 {
+            return None;
+        }
+        let discriminant = b * b - a * c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        Some((-b - discriminant.sqrt()) / a)
+    }
+
     //Create a bounding sphere from a slice that can be made into a vec3
     pub fn create_from_verts<'a, I, T: 'a>(verts: I) -> Self
     where
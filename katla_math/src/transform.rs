@@ -2,6 +2,7 @@ use std::ops::Mul;
 
 use crate::{Mat4, Quat, Vec3, Vec4};
 
+#[derive(Debug, Clone)]
 pub struct Transform {
     pub position: Vec3,
     pub scale: Vec3,
@@ -17,6 +18,10 @@ impl Transform {
         }
     }
 
+    pub fn identity() -> Self {
+        Self::new()
+    }
+
     pub fn new_from_rotation(rotation: Quat) -> Self {
         Self {
             position: Vec3::new(0.0, 0.0, 0.0),
@@ -41,6 +46,21 @@ impl Transform {
         }
     }
 
+    pub fn with_scale(mut self, scale: Vec3) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    //Largest absolute scale factor, a bounding radius has to grow by this much
+    pub fn max_scale(&self) -> f32 {
+        self.scale.abs().iter().copied().fold(0.0, f32::max)
+    }
+
+    //Local +z, the forward axis used by the camera, in world space
+    pub fn forward(&self) -> Vec3 {
+        self.rotation.rotate(Vec3::new(0.0, 0.0, 1.0))
+    }
+
     pub fn make_mat4(&self) -> Mat4 {
         let scale_mat = Mat4([
             Vec4([self.scale[0], 0.0, 0.0, 0.0]),
@@ -48,10 +68,45 @@ impl Transform {
             Vec4([0.0, 0.0, self.scale[2], 0.0]),
             Vec4([0.0, 0.0, 0.0, 1.0]),
         ]);
-        let rot_mat = self.rotation.make_mat4();
+        let rot_mat = Mat4::from(&self.rotation);
         let pos_mat = Mat4::from_translation(self.position.0);
         pos_mat.mul(&scale_mat.mul(&rot_mat))
     }
+
+    //Inverse of make_mat4, the matrix must not contain shear or projection.
+    //A mirrored basis is returned as a negative x scale
+    pub fn from_mat4(mat: &Mat4) -> Self {
+        let position = Vec3::new(mat[3][0], mat[3][1], mat[3][2]);
+        //make_mat4 scales the rows of the rotation, so each row length is one scale factor
+        let mut rows = [0, 1, 2].map(|row| Vec3::new(mat[0][row], mat[1][row], mat[2][row]));
+        let mut scale = Vec3(rows.map(|row| row.distance()));
+        if rows[0].dot(rows[1].cross(rows[2])) < 0.0 {
+            scale[0] = -scale[0];
+        }
+        for (row, scale) in rows.iter_mut().zip(scale.0) {
+            if scale != 0.0 {
+                *row = *row * (1.0 / scale);
+            }
+        }
+        let rot_mat = Mat4([
+            Vec4([rows[0][0], rows[1][0], rows[2][0], 0.0]),
+            Vec4([rows[0][1], rows[1][1], rows[2][1], 0.0]),
+            Vec4([rows[0][2], rows[1][2], rows[2][2], 0.0]),
+            Vec4([0.0, 0.0, 0.0, 1.0]),
+        ]);
+        Self {
+            position,
+            scale,
+            rotation: Quat::from_rotation_mat4(&rot_mat),
+        }
+    }
+}
+
+//Composed the same way as Transform::make_mat4
+impl From<&Transform> for Mat4 {
+    fn from(transform: &Transform) -> Self {
+        transform.make_mat4()
+    }
 }
 
 impl Mul for Transform {
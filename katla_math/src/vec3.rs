@@ -2,7 +2,7 @@ use core::{
     f32,
     ops::{Add, Index, IndexMut, Sub},
 };
-use std::ops::Mul;
+use std::{fmt, ops::Mul};
 
 #[derive(Debug, Copy, Clone)]
 pub struct Vec3(pub [f32; 3]);
@@ -103,6 +103,39 @@ impl Vec3 {
         ])
     }
 
+    pub fn iter(&self) -> std::slice::Iter<'_, f32> {
+        self.0.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, f32> {
+        self.0.iter_mut()
+    }
+
+    //Component-wise minimum
+    #[inline]
+    pub fn min(&self, other: Vec3) -> Vec3 {
+        Vec3([
+            self[0].min(other[0]),
+            self[1].min(other[1]),
+            self[2].min(other[2]),
+        ])
+    }
+
+    //Component-wise maximum
+    #[inline]
+    pub fn max(&self, other: Vec3) -> Vec3 {
+        Vec3([
+            self[0].max(other[0]),
+            self[1].max(other[1]),
+            self[2].max(other[2]),
+        ])
+    }
+
+    #[inline]
+    pub fn abs(&self) -> Vec3 {
+        Vec3([self[0].abs(), self[1].abs(), self[2].abs()])
+    }
+
     #[inline]
     //Linearly interpolate from a to b with a given ratio
     pub fn lerp(a: Vec3, b: Vec3, ratio: f32) -> Self {
@@ -133,3 +166,9 @@ impl Mul<f32> for Vec3 {
         Vec3([self[0] * rhs, self[1] * rhs, self[2] * rhs])
     }
 }
+
+impl fmt::Display for Vec3 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        crate::fmt_components(f, &self.0)
+    }
+}
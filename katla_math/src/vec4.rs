@@ -1,4 +1,5 @@
-use core::ops::Index;
+use core::ops::{Index, IndexMut};
+use std::fmt;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Vec4(pub [f32; 4]);
@@ -17,6 +18,18 @@ impl Index<usize> for Vec4 {
     }
 }
 
+impl IndexMut<usize> for Vec4 {
+    fn index_mut(&mut self, index: usize) -> &mut f32 {
+        match index {
+            0 => &mut self.0[0],
+            1 => &mut self.0[1],
+            2 => &mut self.0[2],
+            3 => &mut self.0[3],
+            _ => panic!("INDEXING OUT_OF_BOUNDS in Vec4"),
+        }
+    }
+}
+
 impl Into<[f32; 4]> for Vec4 {
     fn into(self) -> [f32; 4] {
         self.0
@@ -44,4 +57,45 @@ impl Vec4 {
     pub fn dot(a: &Vec4, b: &Vec4) -> f32 {
         a[0] * b[0] + a[1] * b[1] + a[2] * b[2] + a[3] * b[3]
     }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, f32> {
+        self.0.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, f32> {
+        self.0.iter_mut()
+    }
+
+    //Component-wise minimum
+    #[inline]
+    pub fn min(&self, other: Vec4) -> Vec4 {
+        Vec4([
+            self[0].min(other[0]),
+            self[1].min(other[1]),
+            self[2].min(other[2]),
+            self[3].min(other[3]),
+        ])
+    }
+
+    //Component-wise maximum
+    #[inline]
+    pub fn max(&self, other: Vec4) -> Vec4 {
+        Vec4([
+            self[0].max(other[0]),
+            self[1].max(other[1]),
+            self[2].max(other[2]),
+            self[3].max(other[3]),
+        ])
+    }
+
+    #[inline]
+    pub fn abs(&self) -> Vec4 {
+        Vec4([self[0].abs(), self[1].abs(), self[2].abs(), self[3].abs()])
+    }
+}
+
+impl fmt::Display for Vec4 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        crate::fmt_components(f, &self.0)
+    }
 }
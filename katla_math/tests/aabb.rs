@@ -1,5 +1,5 @@
-use katla_math::Vec3;
-use katla_math::AABB;
+use approx::assert_abs_diff_eq;
+use katla_math::{Sphere, Vec3, AABB};
 
 #[test]
 fn test_expand() {
@@ -18,3 +18,39 @@ fn test_expand() {
     assert_eq!(aabb.center[1], 0.0);
     assert_eq!(aabb.center[2], -5.0);
 }
+
+#[test]
+fn test_merge() {
+    let a = AABB::create_from_verts(&[Vec3::new(-1.0, 0.0, 0.0), Vec3::new(1.0, 2.0, 2.0)]);
+    let b = AABB::create_from_verts(&[Vec3::new(3.0, -4.0, 1.0), Vec3::new(5.0, -2.0, 1.5)]);
+    let merged = a.merge(&b);
+
+    assert_abs_diff_eq!(merged.min()[0], -1.0);
+    assert_abs_diff_eq!(merged.min()[1], -4.0);
+    assert_abs_diff_eq!(merged.min()[2], 0.0);
+    assert_abs_diff_eq!(merged.max()[0], 5.0);
+    assert_abs_diff_eq!(merged.max()[1], 2.0);
+    assert_abs_diff_eq!(merged.max()[2], 2.0);
+    for aabb in [&a, &b] {
+        for axis in 0..3 {
+            assert!(merged.min()[axis] <= aabb.min()[axis]);
+            assert!(merged.max()[axis] >= aabb.max()[axis]);
+        }
+    }
+}
+
+#[test]
+fn test_from_sphere() {
+    let first = AABB::from(&Sphere::new(Vec3::new(10.0, 0.0, 0.0), 1.0));
+    let second = AABB::from(&Sphere::new(Vec3::new(-10.0, 5.0, 0.0), 2.0));
+    let merged = first.merge(&second);
+
+    assert_abs_diff_eq!(merged.min()[0], -12.0);
+    assert_abs_diff_eq!(merged.max()[0], 11.0);
+    assert_abs_diff_eq!(merged.min()[1], -1.0);
+    assert_abs_diff_eq!(merged.max()[1], 7.0);
+
+    let sphere = merged.bounding_sphere();
+    assert!(sphere.point_inside(merged.min()));
+    assert!(sphere.point_inside(merged.max()));
+}
@@ -0,0 +1,40 @@
+use katla_math::{Frustum, Mat4, Sphere, Vec3, AABB};
+
+fn create_frustum() -> Frustum {
+    let proj = Mat4::create_proj(90.0, 1.0, 1.0, 100.0);
+    let view = Mat4::identity();
+    Frustum::from_view_proj(&proj.mul(&view))
+}
+
+#[test]
+fn test_contains_point() {
+    let frustum = create_frustum();
+    assert!(frustum.contains_point(Vec3::new(0.0, 0.0, -10.0)));
+    assert!(!frustum.contains_point(Vec3::new(0.0, 0.0, 10.0)));
+    assert!(!frustum.contains_point(Vec3::new(0.0, 0.0, -101.0)));
+    assert!(!frustum.contains_point(Vec3::new(20.0, 0.0, -10.0)));
+}
+
+#[test]
+fn test_sphere_straddling_near() {
+    let frustum = create_frustum();
+    let sphere = Sphere::new(Vec3::new(0.0, 0.0, -1.0), 0.5);
+    assert!(frustum.intersects_sphere(&sphere));
+    let sphere = Sphere::new(Vec3::new(0.0, 0.0, 5.0), 0.5);
+    assert!(!frustum.intersects_sphere(&sphere));
+}
+
+#[test]
+fn test_aabb_behind_camera() {
+    let frustum = create_frustum();
+    let aabb = AABB {
+        center: Vec3::new(0.0, 0.0, 10.0),
+        extent: Vec3::new(1.0, 1.0, 1.0),
+    };
+    assert!(!frustum.intersects_aabb(&aabb));
+    let aabb = AABB {
+        center: Vec3::new(0.0, 0.0, -50.0),
+        extent: Vec3::new(1.0, 1.0, 1.0),
+    };
+    assert!(frustum.intersects_aabb(&aabb));
+}
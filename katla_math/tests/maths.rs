@@ -1,7 +1,7 @@
 use std::time::{Duration, Instant};
 
 use approx::assert_abs_diff_eq;
-use katla_math::{Mat4, Vec3, Vec4};
+use katla_math::{log_depth_coefficient, triangle_tangents, Mat4, Vec3, Vec4};
 
 #[test]
 fn test_memcpy() {
@@ -46,6 +46,61 @@ fn test_lerp() {
     assert_abs_diff_eq!(c[2], b[2], epsilon = 0.0001);
 }
 
+#[test]
+fn test_min_max() {
+    let a = Vec3::new(1.0, -2.0, 3.0);
+    let b = Vec3::new(-1.0, 2.0, 4.0);
+    let min = a.min(b);
+    let max = a.max(b);
+    assert_eq!(min.0, [-1.0, -2.0, 3.0]);
+    assert_eq!(max.0, [1.0, 2.0, 4.0]);
+    assert_eq!(a.abs().0, [1.0, 2.0, 3.0]);
+
+    let a = Vec4([1.0, -2.0, 3.0, 0.0]);
+    let b = Vec4([-1.0, 2.0, 4.0, -5.0]);
+    assert_eq!(a.min(b), Vec4([-1.0, -2.0, 3.0, -5.0]));
+    assert_eq!(a.max(b), Vec4([1.0, 2.0, 4.0, 0.0]));
+}
+
+#[test]
+fn test_mut_indexing() {
+    let mut v = Vec3::new(0.0, 0.0, 0.0);
+    v[0] = 1.0;
+    v[2] = -1.0;
+    assert_eq!(v.0, [1.0, 0.0, -1.0]);
+    for component in v.iter_mut() {
+        *component *= 2.0;
+    }
+    assert_eq!(v.iter().sum::<f32>(), 0.0);
+    assert_eq!(v.0, [2.0, 0.0, -2.0]);
+
+    let mut v = Vec4::from_xyz(1.0, 2.0, 3.0);
+    v[3] = 0.0;
+    assert_eq!(v, Vec4([1.0, 2.0, 3.0, 0.0]));
+    assert_eq!(v.iter().count(), 4);
+}
+
+#[test]
+fn test_triangle_tangents() {
+    let positions = [
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(2.0, 0.0, 0.0),
+        Vec3::new(0.0, 2.0, 0.0),
+    ];
+    let tex_coords = [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]];
+    let (tangent, bitangent) = triangle_tangents(positions, tex_coords);
+    assert_abs_diff_eq!(tangent[0], 2.0, epsilon = 0.0001);
+    assert_abs_diff_eq!(tangent[1], 0.0, epsilon = 0.0001);
+    assert_abs_diff_eq!(tangent[2], 0.0, epsilon = 0.0001);
+    assert_abs_diff_eq!(bitangent[0], 0.0, epsilon = 0.0001);
+    assert_abs_diff_eq!(bitangent[1], 2.0, epsilon = 0.0001);
+    assert_abs_diff_eq!(bitangent[2], 0.0, epsilon = 0.0001);
+
+    let degenerate = [[0.0, 0.0], [0.0, 0.0], [0.0, 0.0]];
+    let (tangent, _) = triangle_tangents(positions, degenerate);
+    assert_eq!(tangent.distance_squared(), 0.0);
+}
+
 #[test]
 fn test_vec4_into() {
     let v = Vec4::from_xyz(1.0, 2.0, 3.0);
@@ -72,6 +127,39 @@ fn test_mat4_into() {
     }
 }
 
+#[test]
+fn test_mat4_rows_columns() {
+    let mut mat = Mat4::from_columns([
+        Vec4([1.0, 2.0, 3.0, 4.0]),
+        Vec4([5.0, 6.0, 7.0, 8.0]),
+        Vec4([9.0, 10.0, 11.0, 12.0]),
+        Vec4([13.0, 14.0, 15.0, 16.0]),
+    ]);
+    assert_eq!(*mat.column(1), Vec4([5.0, 6.0, 7.0, 8.0]));
+    assert_eq!(mat.extract_row(1), Vec4([2.0, 6.0, 10.0, 14.0]));
+
+    let row = Vec4([-1.0, -2.0, -3.0, -4.0]);
+    mat.set_row(2, row);
+    assert_eq!(mat.extract_row(2), row);
+    assert_eq!(*mat.column(0), Vec4([1.0, 2.0, -1.0, 4.0]));
+
+    let column = Vec4([0.0, 0.5, 1.0, 1.5]);
+    mat.set_column(3, column);
+    assert_eq!(*mat.column(3), column);
+    assert_eq!(mat.extract_row(2), Vec4([-1.0, -2.0, -3.0, 1.0]));
+}
+
+#[test]
+fn test_mat4_without_translation() {
+    let mat =
+        Mat4::from_rotaxis(&0.5, [0.0, 1.0, 0.0]).mul(&Mat4::from_translation([1.0, -2.5, 30.0]));
+    let stripped = mat.without_translation();
+    assert_eq!(*stripped.column(3), Vec4([0.0, 0.0, 0.0, 1.0]));
+    for column in 0..3 {
+        assert_eq!(stripped.column(column), mat.column(column));
+    }
+}
+
 // #[test]
 // fn test_inverse() {
 //     let orig_mat4 = Mat4([
@@ -87,3 +175,38 @@ fn test_mat4_into() {
 //     //These are not equal since of approximations and whatnot, whatever...
 //     assert_eq!(multiplied, ident);
 // }
+
+#[test]
+fn test_mat4_display() {
+    let expected = "[   1.0000    0.0000    0.0000    0.0000]\n\
+                    [   0.0000    1.0000    0.0000    0.0000]\n\
+                    [   0.0000    0.0000    1.0000    0.0000]\n\
+                    [   0.0000    0.0000    0.0000    1.0000]";
+    assert_eq!(format!("{}", Mat4::identity()), expected);
+    let translation = Mat4::from_translation([1.0, -2.5, 30.0]);
+    assert_eq!(
+        format!("{:.1}", translation).lines().next(),
+        Some("[   1.0    0.0    0.0    1.0]")
+    );
+}
+
+#[test]
+fn test_vec_display() {
+    assert_eq!(
+        format!("{}", Vec3::new(1.0, -2.0, 0.5)),
+        "(1.0000, -2.0000, 0.5000)"
+    );
+    assert_eq!(
+        format!("{:.2}", Vec4([1.0, 2.0, 3.0, 4.0])),
+        "(1.00, 2.00, 3.00, 4.00)"
+    );
+}
+
+#[test]
+fn test_log_depth_coefficient() {
+    assert_abs_diff_eq!(log_depth_coefficient(10000.0), 0.075257, epsilon = 1e-5);
+    //Depth written at the far plane ends up at exactly 1.0 after the perspective divide
+    let far: f32 = 250.0;
+    let depth = (1.0 + far).log2() * log_depth_coefficient(far);
+    assert_abs_diff_eq!(depth, 1.0, epsilon = 1e-6);
+}
@@ -1,7 +1,7 @@
 use approx::assert_abs_diff_eq;
 use std::f32::consts::FRAC_PI_2;
 
-use katla_math::{mat4_mul_vec3, Quat, Vec3};
+use katla_math::{mat4_mul_vec3, Mat4, Quat, Vec3};
 
 #[test]
 fn test_quat_identity() {
@@ -60,3 +60,115 @@ fn test_quat_mat() {
     assert_abs_diff_eq!(mat_rotated[1], quat_rotated[1], epsilon = 0.0001);
     assert_abs_diff_eq!(mat_rotated[2], quat_rotated[2], epsilon = 0.0001);
 }
+
+#[test]
+fn test_quat_normalize_drift() {
+    let step = Quat::new_from_axis_angle(Vec3::new(0.3, 1.0, 0.2), 0.001);
+    let mut quat = Quat::new();
+    for _ in 0..1000 {
+        quat = quat * step;
+        quat.normalize();
+    }
+    assert_abs_diff_eq!(quat.length(), 1.0, epsilon = 0.0001);
+    assert!(quat.is_normalized(0.0001));
+
+    let mut quat = Quat::new_from_axis_angle(Vec3::new(1.0, 0.0, 0.0), 0.5);
+    let conjugate = quat.conjugate();
+    let inverse = quat.inverse();
+    assert_abs_diff_eq!(conjugate[0], inverse[0], epsilon = 0.0001);
+    assert_abs_diff_eq!(conjugate[3], inverse[3], epsilon = 0.0001);
+    quat.normalize();
+    assert_abs_diff_eq!(quat.length(), 1.0, epsilon = 0.0001);
+}
+
+#[test]
+fn test_quat_rotate_matches_matrix() {
+    let yaw = Quat::new_from_axis_angle(Vec3::new(0.0, 1.0, 0.0), FRAC_PI_2);
+    let vec = Vec3::new(0.0, 0.0, -1.0);
+    let quat_rotated = yaw.rotate(vec);
+    let mat_rotated = mat4_mul_vec3(&Mat4::from_rotaxis(&FRAC_PI_2, [0.0, 1.0, 0.0]), &vec);
+    assert_abs_diff_eq!(quat_rotated[0], mat_rotated[0], epsilon = 0.0001);
+    assert_abs_diff_eq!(quat_rotated[1], mat_rotated[1], epsilon = 0.0001);
+    assert_abs_diff_eq!(quat_rotated[2], mat_rotated[2], epsilon = 0.0001);
+    assert_abs_diff_eq!(quat_rotated[0], -1.0, epsilon = 0.0001);
+}
+
+#[test]
+fn test_quat_display() {
+    assert_eq!(
+        format!("{}", Quat::new()),
+        "(0.0000, 0.0000, 0.0000, 1.0000)"
+    );
+    let quat = Quat::new_from_axis_angle(Vec3::new(0.0, 1.0, 0.0), FRAC_PI_2);
+    assert_eq!(format!("{}", quat), "(0.0000, 0.7071, 0.0000, 0.7071)");
+}
+
+#[test]
+fn test_quat_mat4_roundtrip() {
+    //Rotations past 180 degrees around each axis exercise every branch of the extraction
+    for axis in [
+        Vec3::new(1.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        Vec3::new(0.0, 0.0, 1.0),
+        Vec3::new(1.0, 2.0, 3.0),
+    ] {
+        for angle in [0.3, 1.5, 3.0] {
+            let quat = Quat::new_from_axis_angle(axis, angle);
+            let extracted = Quat::from_rotation_mat4(&Mat4::from(&quat));
+            assert_abs_diff_eq!(extracted.dot(quat).abs(), 1.0, epsilon = 0.0001);
+        }
+    }
+}
+
+#[test]
+fn test_quat_angle_between() {
+    let quarter_turn = Quat::new_from_axis_angle(Vec3::new(0.0, 1.0, 0.0), FRAC_PI_2);
+    assert_abs_diff_eq!(
+        Quat::new().angle_between(quarter_turn),
+        FRAC_PI_2,
+        epsilon = 0.0001
+    );
+    assert_abs_diff_eq!(
+        quarter_turn.angle_between(Quat::new()),
+        FRAC_PI_2,
+        epsilon = 0.0001
+    );
+    assert_abs_diff_eq!(
+        quarter_turn.angle_between(quarter_turn),
+        0.0,
+        epsilon = 0.001
+    );
+
+    //Past half a turn the shorter way around is measured
+    let three_quarters = Quat::new_from_axis_angle(Vec3::new(0.0, 1.0, 0.0), 3.0 * FRAC_PI_2);
+    assert_abs_diff_eq!(
+        Quat::new().angle_between(three_quarters),
+        FRAC_PI_2,
+        epsilon = 0.0001
+    );
+}
+
+#[test]
+fn test_quat_rotate_towards() {
+    let y_axis = Vec3::new(0.0, 1.0, 0.0);
+    let target = Quat::new_from_axis_angle(y_axis, FRAC_PI_2);
+
+    let stepped = Quat::new().rotate_towards(target, 0.1);
+    assert_abs_diff_eq!(Quat::new().angle_between(stepped), 0.1, epsilon = 0.0001);
+    assert_abs_diff_eq!(
+        stepped.angle_between(target),
+        FRAC_PI_2 - 0.1,
+        epsilon = 0.0001
+    );
+    let expected = Quat::new_from_axis_angle(y_axis, 0.1);
+    assert_abs_diff_eq!(stepped.dot(expected).abs(), 1.0, epsilon = 0.0001);
+
+    let reached = Quat::new().rotate_towards(target, 2.0);
+    assert_abs_diff_eq!(reached.dot(target), 1.0, epsilon = 0.0001);
+
+    //Steps the short way around even when the target is on the other hemisphere
+    let behind = Quat::new_from_axis_angle(y_axis, 3.0 * FRAC_PI_2);
+    let stepped = Quat::new().rotate_towards(behind, 0.1);
+    let expected = Quat::new_from_axis_angle(y_axis, -0.1);
+    assert_abs_diff_eq!(stepped.dot(expected).abs(), 1.0, epsilon = 0.0001);
+}
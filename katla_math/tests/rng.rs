@@ -0,0 +1,45 @@
+use approx::assert_abs_diff_eq;
+use katla_math::Rng;
+
+#[test]
+fn test_seed_is_reproducible() {
+    let mut a = Rng::new(42);
+    let mut b = Rng::new(42);
+    for _ in 0..100 {
+        assert_eq!(a.next_u32(), b.next_u32());
+    }
+
+    let mut a = Rng::new(42);
+    let mut c = Rng::new(43);
+    let same = (0..100).filter(|_| a.next_u32() == c.next_u32()).count();
+    assert!(same < 5);
+}
+
+#[test]
+fn test_range() {
+    let mut rng = Rng::new(7);
+    for _ in 0..1000 {
+        let value = rng.next_f32();
+        assert!((0.0..1.0).contains(&value));
+        let value = rng.range(-2.0, 3.0);
+        assert!((-2.0..3.0).contains(&value));
+    }
+}
+
+#[test]
+fn test_vec3_unit() {
+    let mut rng = Rng::new(1234);
+    let mut sum = [0.0f32; 3];
+    let count = 10000;
+    for _ in 0..count {
+        let point = rng.next_vec3_unit();
+        assert_abs_diff_eq!(point.distance(), 1.0, epsilon = 0.0001);
+        for i in 0..3 {
+            sum[i] += point[i];
+        }
+    }
+    //Uniform on the sphere, so the points should average out near the center
+    for component in sum {
+        assert_abs_diff_eq!(component / count as f32, 0.0, epsilon = 0.05);
+    }
+}
@@ -1,3 +1,4 @@
+use approx::assert_abs_diff_eq;
 use katla_math::Sphere;
 use katla_math::Vec3;
 
@@ -53,3 +54,30 @@ fn test_into() {
     let sphere = Sphere::create_from_verts(&list_of_verts);
     assert_eq!(sphere.radius, 0.5);
 }
+
+#[test]
+fn test_intersect_ray() {
+    let sphere = Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0);
+    let dir = Vec3::new(1.0, 0.0, 0.0);
+
+    let hit = sphere.intersect_ray(Vec3::new(-5.0, 0.0, 0.0), dir);
+    assert_abs_diff_eq!(hit.unwrap(), 4.0);
+    //Tangent to the top of the sphere
+    let tangent = sphere.intersect_ray(Vec3::new(-5.0, 1.0, 0.0), dir);
+    assert_abs_diff_eq!(tangent.unwrap(), 5.0);
+    assert!(sphere
+        .intersect_ray(Vec3::new(-5.0, 1.5, 0.0), dir)
+        .is_none());
+    //Behind the origin
+    assert!(sphere
+        .intersect_ray(Vec3::new(5.0, 0.0, 0.0), dir)
+        .is_none());
+    assert_eq!(
+        sphere.intersect_ray(Vec3::new(0.5, 0.0, 0.0), dir),
+        Some(0.0)
+    );
+
+    //Distances are in multiples of the direction's length
+    let hit = sphere.intersect_ray(Vec3::new(-5.0, 0.0, 0.0), dir.mul(2.0));
+    assert_abs_diff_eq!(hit.unwrap(), 2.0);
+}
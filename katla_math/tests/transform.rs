@@ -1,7 +1,7 @@
 use std::f32::consts::{FRAC_PI_2, PI};
 
 use approx::assert_abs_diff_eq;
-use katla_math::{mat4_mul_vec3, Quat, Transform, Vec3};
+use katla_math::{mat4_mul_vec3, Mat4, Quat, Transform, Vec3};
 
 #[test]
 fn test_scale_mat() {
@@ -82,3 +82,80 @@ fn test_transform() {
     assert_abs_diff_eq!(transformed_vertex[1], -2.0, epsilon = 0.0001);
     assert_abs_diff_eq!(transformed_vertex[2], 0.0, epsilon = 0.0001);
 }
+
+#[test]
+fn test_identity_into_mat4() {
+    let mat = Mat4::from(&Transform::identity());
+    assert_eq!(mat, Mat4::identity());
+}
+
+#[test]
+fn test_translation_into_mat4() {
+    let transform = Transform::new_from_position(Vec3::new(2.0, 1.0, -3.0));
+    let mat = Mat4::from(&transform);
+    assert_abs_diff_eq!(mat[3][0], 2.0, epsilon = 0.0001);
+    assert_abs_diff_eq!(mat[3][1], 1.0, epsilon = 0.0001);
+    assert_abs_diff_eq!(mat[3][2], -3.0, epsilon = 0.0001);
+    assert_abs_diff_eq!(mat[3][3], 1.0, epsilon = 0.0001);
+}
+
+#[test]
+fn test_from_mat4_roundtrip() {
+    let transform = Transform {
+        position: Vec3::new(1.0, -2.0, 3.0),
+        scale: Vec3::new(2.0, 0.5, 3.0),
+        rotation: Quat::new_from_axis_angle(Vec3::new(1.0, 1.0, 0.0), 2.5),
+    };
+    let mat = Mat4::from(&transform);
+    let decomposed = Transform::from_mat4(&mat);
+    for i in 0..3 {
+        assert_abs_diff_eq!(
+            decomposed.position[i],
+            transform.position[i],
+            epsilon = 0.0001
+        );
+        assert_abs_diff_eq!(decomposed.scale[i], transform.scale[i], epsilon = 0.0001);
+    }
+    //q and -q are the same rotation
+    assert_abs_diff_eq!(
+        decomposed.rotation.dot(transform.rotation).abs(),
+        1.0,
+        epsilon = 0.0001
+    );
+
+    let vertex = Vec3::new(1.0, 2.0, 3.0);
+    let expected = mat4_mul_vec3(&mat, &vertex);
+    let transformed_vertex = mat4_mul_vec3(&Mat4::from(&decomposed), &vertex);
+    for i in 0..3 {
+        assert_abs_diff_eq!(transformed_vertex[i], expected[i], epsilon = 0.0001);
+    }
+}
+
+#[test]
+fn test_with_scale_mat() {
+    let transform =
+        Transform::new_from_position(Vec3::new(1.0, 2.0, 3.0)).with_scale(Vec3::new(2.0, 3.0, 0.5));
+    let transformed_vertex = mat4_mul_vec3(&Mat4::from(&transform), &Vec3::new(1.0, 1.0, 1.0));
+    assert_abs_diff_eq!(transformed_vertex[0], 3.0, epsilon = 0.0001);
+    assert_abs_diff_eq!(transformed_vertex[1], 5.0, epsilon = 0.0001);
+    assert_abs_diff_eq!(transformed_vertex[2], 3.5, epsilon = 0.0001);
+}
+
+#[test]
+fn test_with_scale_decompose() {
+    let scale = Vec3::new(4.0, 0.25, 1.5);
+    let transform =
+        Transform::new_from_rotation(Quat::new_from_axis_angle(Vec3::new(0.0, 1.0, 0.0), 1.0))
+            .with_scale(scale);
+    let decomposed = Transform::from_mat4(&transform.make_mat4());
+    for i in 0..3 {
+        assert_abs_diff_eq!(decomposed.scale[i], scale[i], epsilon = 0.0001);
+    }
+}
+
+#[test]
+fn test_max_scale() {
+    assert_abs_diff_eq!(Transform::identity().max_scale(), 1.0);
+    let transform = Transform::new().with_scale(Vec3::new(-3.0, 2.0, 0.5));
+    assert_abs_diff_eq!(transform.max_scale(), 3.0);
+}
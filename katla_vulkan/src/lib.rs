@@ -4,26 +4,82 @@ pub use vulkan::*;
 
 use ash::vk;
 
-use std::{ffi::CString, sync::Arc};
-
-pub use ash::vk::{Format, IndexType, PipelineBindPoint};
-
+use std::{
+    ffi::CString,
+    ops::{Deref, DerefMut},
+    rc::Rc,
+    sync::Arc,
+    time::Instant,
+};
+
+pub use ash::vk::{
+    make_api_version, ColorSpaceKHR, Extent2D, Filter, Format, IndexType, PipelineBindPoint,
+    PipelineStageFlags, SamplerAddressMode, SamplerMipmapMode, Semaphore,
+};
+
+/// The shared context plus the renderer of the first window, derefs to that WindowRenderer.
+/// Additional windows get their own WindowRenderer from create_window_renderer.
 pub struct VulkanRenderer {
+    primary: WindowRenderer,
+}
+
+/// Per-window state: surface, swapchain, framebuffers and frame synchronization
+pub struct WindowRenderer {
     pub context: Arc<VulkanContext>,
     pub frame_context: VulkanFrameCtx,
     pub render_pass: RenderPass,
     pub swapchain_framebuffers: Vec<vk::Framebuffer>,
     swap_data: SwapData,
     current_framedata: Option<FrameData>,
+    frames_in_flight: usize,
+    global_uniform: GlobalUniform,
+    shading: Shading,
+    passes: PassList,
+    //Drawn between the scene's pass and the others, see VulkanRenderer::set_skybox
+    skybox: Option<Skybox>,
+    dynamic_rendering: bool,
+    latency: FrameLatency,
 }
 struct FrameData {
+    frame_index: usize,
     available_sem: vk::Semaphore,
     finished_sem: vk::Semaphore,
     in_flight_fence: vk::Fence,
     image_index: u32,
 }
 
-const FRAMES_IN_FLIGHT: usize = 2;
+impl FrameData {
+    fn frame_context(&self, extent: vk::Extent2D, shading: Shading) -> FrameContext {
+        FrameContext {
+            frame_index: self.frame_index,
+            image_index: self.image_index as usize,
+            extent,
+            shading,
+        }
+    }
+}
+
+pub const DEFAULT_FRAMES_IN_FLIGHT: usize = 2;
+
+/// Settings for VulkanRenderer::init, the swapchain ones apply to the first window
+#[derive(Debug, Clone)]
+pub struct RendererConfig {
+    //Lowered to what the installed runtime supports
+    pub api_version: u32,
+    //Clamped to the number of swapchain images
+    pub frames_in_flight: usize,
+    pub swapchain: SwapchainConfig,
+}
+
+impl Default for RendererConfig {
+    fn default() -> Self {
+        Self {
+            api_version: DEFAULT_API_VERSION,
+            frames_in_flight: DEFAULT_FRAMES_IN_FLIGHT,
+            swapchain: SwapchainConfig::default(),
+        }
+    }
+}
 
 impl VulkanRenderer {
     pub fn init(
@@ -32,6 +88,7 @@ impl VulkanRenderer {
         with_validation_layers: bool,
         app_name: CString,
         engine_name: CString,
+        config: &RendererConfig,
     ) -> Self {
         let context = Arc::new(VulkanContext::init(
             display,
@@ -39,16 +96,131 @@ impl VulkanRenderer {
             with_validation_layers,
             app_name,
             engine_name,
+            config.api_version,
         ));
 
-        let frame_context = VulkanFrameCtx::init(&context);
+        let frame_context = VulkanFrameCtx::init(&context, &config.swapchain);
+
+        Self {
+            primary: WindowRenderer::new(frame_context, config.frames_in_flight, false),
+        }
+    }
+
+    //The new window shares the device, its render pass is compatible with the first
+    //window's as long as the surface formats match, so pipelines can be reused
+    pub fn create_window_renderer(
+        &self,
+        display: &dyn HasDisplayHandle,
+        window: &dyn HasWindowHandle,
+        swapchain_config: &SwapchainConfig,
+    ) -> WindowRenderer {
+        let surface = self.context.create_surface(display, window);
+        let frame_context =
+            VulkanFrameCtx::init_for_surface(&self.context, surface, swapchain_config);
+        if frame_context.swapchain.format.format != self.frame_context.swapchain.format.format {
+            println!("Warning: the new window picked a different surface format, pipelines may be incompatible");
+        }
+        WindowRenderer::new(frame_context, self.frames_in_flight, self.dynamic_rendering)
+    }
+
+    //Draws the cubemap, created with Texture::create_cubemap, behind the scene of the first
+    //window. Replaces the previous skybox once the device is idle
+    pub fn set_skybox(&mut self, cubemap: Texture) -> Result<(), AllocationError> {
+        if self.primary.skybox.is_some() {
+            self.primary.wait_for_device();
+            self.primary.skybox = None;
+        }
+        let skybox = Skybox::new(self.context.clone(), &self.render_pass, cubemap)?;
+        self.primary.skybox = Some(skybox);
+        Ok(())
+    }
+
+    //Projection times the camera's rotation-only view, column-major. Does nothing without a
+    //skybox
+    pub fn update_skybox(&mut self, view_proj: [[f32; 4]; 4]) {
+        if let Some(skybox) = &mut self.primary.skybox {
+            skybox.set_view_proj(view_proj);
+        }
+    }
+
+    //Drawables, materials and additional WindowRenderers have to be destroyed before this,
+    //the context itself goes away when the last Arc to it is dropped
+    pub fn destroy(&mut self) {
+        self.primary.destroy();
+        println!("Clean shutdown!");
+    }
+}
+
+impl Deref for VulkanRenderer {
+    type Target = WindowRenderer;
+
+    fn deref(&self) -> &WindowRenderer {
+        &self.primary
+    }
+}
+
+impl DerefMut for VulkanRenderer {
+    fn deref_mut(&mut self) -> &mut WindowRenderer {
+        &mut self.primary
+    }
+}
+
+impl WindowRenderer {
+    fn new(
+        frame_context: VulkanFrameCtx,
+        frames_in_flight: usize,
+        dynamic_rendering: bool,
+    ) -> Self {
+        let context = frame_context.context.clone();
+        let render_pass = Self::create_render_pass(&frame_context, dynamic_rendering);
+        let swapchain_framebuffers = Self::create_framebuffers(&frame_context, &render_pass);
+
+        //More frames in flight than swapchain images would only end up waiting on image fences
+        let frames_in_flight = frames_in_flight.clamp(1, frame_context.swapchain_images.len());
+        let swap_data = SwapData::new(
+            &context.device,
+            &frame_context.swapchain_images,
+            frames_in_flight,
+        );
+        let global_uniform = GlobalUniform::new(context.clone(), frames_in_flight)
+            .expect("Failed to allocate the global uniform buffers");
+
+        Self {
+            context,
+            frame_context,
+            render_pass,
+            swapchain_framebuffers,
+            swap_data,
+            current_framedata: None,
+            frames_in_flight,
+            global_uniform,
+            shading: Shading::default(),
+            passes: PassList::default(),
+            skybox: None,
+            dynamic_rendering,
+            latency: FrameLatency::new(frames_in_flight, DEFAULT_LATENCY_SAMPLES),
+        }
+    }
 
+    fn create_render_pass(frame_context: &VulkanFrameCtx, dynamic_rendering: bool) -> RenderPass {
+        let device = frame_context.context.device.clone();
         let color_format = frame_context.swapchain.format.format;
         let depth_format = frame_context.depth_render_texture.format;
-        let render_pass =
-            RenderPass::create_opaque(context.device.clone(), color_format, depth_format);
+        if dynamic_rendering {
+            RenderPass::create_dynamic(device, color_format, depth_format)
+        } else {
+            RenderPass::create_opaque(device, color_format, depth_format)
+        }
+    }
 
-        let swapchain_framebuffers: Vec<_> = frame_context
+    fn create_framebuffers(
+        frame_context: &VulkanFrameCtx,
+        render_pass: &RenderPass,
+    ) -> Vec<vk::Framebuffer> {
+        if render_pass.is_dynamic() {
+            return vec![];
+        }
+        frame_context
             .swapchain_image_views
             .iter()
             .map(|image_view| {
@@ -60,25 +232,45 @@ impl VulkanRenderer {
                     .height(frame_context.swapchain.get_extent().height)
                     .layers(1);
 
-                unsafe { context.device.create_framebuffer(&create_info, None) }.unwrap()
+                unsafe {
+                    frame_context
+                        .context
+                        .device
+                        .create_framebuffer(&create_info, None)
+                }
+                .unwrap()
             })
-            .collect();
+            .collect()
+    }
 
-        let swap_data = SwapData::new(
-            &context.device,
-            &frame_context.swapchain_images,
-            FRAMES_IN_FLIGHT,
-        );
+    //Switches the opaque pass to dynamic rendering when the device supports it, returns
+    //whether it is in use. Has to be called before any pipelines are created.
+    pub fn enable_dynamic_rendering(&mut self) -> bool {
+        if !self.context.supports_dynamic_rendering() {
+            println!("Dynamic rendering is unavailable, keeping render pass objects");
+            return false;
+        }
+        if !self.dynamic_rendering {
+            self.wait_for_device();
+            self.destroy_render_pass_objects();
+            self.dynamic_rendering = true;
+            self.render_pass = Self::create_render_pass(&self.frame_context, true);
+            self.swapchain_framebuffers = vec![];
+        }
+        true
+    }
 
-        let renderer = Self {
-            context,
-            frame_context,
-            render_pass,
-            swapchain_framebuffers,
-            swap_data,
-            current_framedata: None,
-        };
-        renderer
+    pub fn uses_dynamic_rendering(&self) -> bool {
+        self.dynamic_rendering
+    }
+
+    fn destroy_render_pass_objects(&mut self) {
+        self.render_pass.destroy();
+        for &framebuffer in &self.swapchain_framebuffers {
+            unsafe {
+                self.context.device.destroy_framebuffer(framebuffer, None);
+            }
+        }
     }
 
     // fn create_framebuffers(context: &VulkanCtx) -> Vec<Framebuffer> {
@@ -99,18 +291,16 @@ impl VulkanRenderer {
     //         .collect()
     // }
 
+    //Waits for the whole device, so closing a window also stalls the others for a frame
     pub fn destroy(&mut self) {
-        unsafe {
-            self.context.pre_destroy();
-            self.swap_data.destroy(&self.context.device);
-            self.render_pass.destroy();
-            for &framebuffer in &self.swapchain_framebuffers {
-                self.context.device.destroy_framebuffer(framebuffer, None);
-            }
-
-            self.frame_context.destroy();
-        }
-        println!("Clean shutdown!");
+        self.context.pre_destroy();
+        self.passes.clear();
+        self.skybox = None;
+        self.swap_data.destroy(&self.context.device);
+        self.destroy_render_pass_objects();
+
+        self.frame_context.destroy();
+        self.frame_context.destroy_surface();
     }
 
     pub fn wait_for_device(&self) {
@@ -121,55 +311,55 @@ impl VulkanRenderer {
 
     pub fn recreate_swapchain(&mut self) {
         self.wait_for_device();
+        self.latency.discard_pending();
         self.frame_context.recreate_swapchain();
+        self.swap_data
+            .reset_images(self.frame_context.swapchain_images.len());
         //Destroy the previous state:
-        unsafe {
-            self.render_pass.destroy();
-            for &framebuffer in &self.swapchain_framebuffers {
-                self.context.device.destroy_framebuffer(framebuffer, None);
-            }
-        }
-
-        let color_format = self.frame_context.swapchain.format.format;
-        let depth_format = self.frame_context.depth_render_texture.format;
-        self.render_pass =
-            RenderPass::create_opaque(self.context.device.clone(), color_format, depth_format);
-
-        self.swapchain_framebuffers = self
-            .frame_context
-            .swapchain_image_views
-            .iter()
-            .map(|image_view| {
-                let attachments = vec![
-                    *image_view,
-                    self.frame_context.depth_render_texture.image_view,
-                ];
-                let create_info = vk::FramebufferCreateInfo::default()
-                    .render_pass(self.render_pass.get_vk_renderpass())
-                    .attachments(&attachments)
-                    .width(self.frame_context.swapchain.get_extent().width)
-                    .height(self.frame_context.swapchain.get_extent().height)
-                    .layers(1);
+        self.destroy_render_pass_objects();
 
-                unsafe { self.context.device.create_framebuffer(&create_info, None) }.unwrap()
-            })
-            .collect();
+        self.render_pass = Self::create_render_pass(&self.frame_context, self.dynamic_rendering);
+        self.swapchain_framebuffers =
+            Self::create_framebuffers(&self.frame_context, &self.render_pass);
     }
 
     pub fn num_images(&self) -> usize {
         self.frame_context.swapchain_image_views.len()
     }
 
+    pub fn frames_in_flight(&self) -> usize {
+        self.frames_in_flight
+    }
+
+    //Applies from the next current_frame on
+    pub fn set_shading(&mut self, shading: Shading) {
+        self.shading = shading;
+    }
+
+    pub fn shading(&self) -> Shading {
+        self.shading
+    }
+
+    pub fn latency(&self) -> &FrameLatency {
+        &self.latency
+    }
+
     pub fn swap_frames(&mut self) {
+        let frame_slot = self.swap_data.frame();
         self.swap_data.wait_for_fence(&self.context.device);
+        self.latency.frame_finished(frame_slot, Instant::now());
 
+        let acquire_started = Instant::now();
         let (available_sem, finished_sem, in_flight_fence, image_index) =
             self.swap_data.swap_images(
                 &self.context.device,
                 &self.context.swapchain_loader,
                 self.frame_context.swapchain.swapchain,
             );
+        self.latency
+            .image_acquired(frame_slot, acquire_started, Instant::now());
         self.current_framedata = Some(FrameData {
+            frame_index: frame_slot,
             available_sem,
             finished_sem,
             in_flight_fence,
@@ -177,17 +367,41 @@ impl VulkanRenderer {
         });
     }
 
-    pub fn get_commandbuffer_opaque_pass(&self) -> CommandBuffer {
-        let (framebuffer, command_buffer) = {
-            if let Some(frame_data) = &self.current_framedata {
-                (
-                    self.swapchain_framebuffers[frame_data.image_index as usize],
-                    self.frame_context.command_buffers[frame_data.image_index as usize].clone(),
-                )
-            } else {
-                panic!("No available frame index!");
+    //Only valid between swap_frames and submit_frame
+    pub fn current_frame(&self) -> FrameContext {
+        match &self.current_framedata {
+            Some(frame_data) => {
+                frame_data.frame_context(self.frame_context.swapchain.get_extent(), self.shading)
             }
-        };
+            None => panic!("No frame has been acquired, call swap_frames first"),
+        }
+    }
+
+    //Camera data for every material drawn this frame, see GLOBAL_UNIFORM_SIZE for the layout.
+    //Only valid between swap_frames and submit_frame
+    pub fn update_global_uniform(&mut self, data: &[u8]) {
+        let frame_index = self.current_frame().frame_index;
+        self.global_uniform.update(frame_index, data);
+    }
+
+    //The lights every material is shaded with this frame, see LIGHTS_UNIFORM_SIZE for the layout.
+    //Only valid between swap_frames and submit_frame
+    pub fn update_lights(&mut self, data: &[u8]) {
+        let frame_index = self.current_frame().frame_index;
+        self.global_uniform.update_lights(frame_index, data);
+    }
+
+    fn current_image_index(&self) -> usize {
+        match &self.current_framedata {
+            Some(frame_data) => frame_data.image_index as usize,
+            None => panic!("No available frame index!"),
+        }
+    }
+
+    //The returned buffer is the image's own, not a copy, so its recording state is kept
+    pub fn get_commandbuffer_opaque_pass(&self) -> Rc<CommandBuffer> {
+        let image_index = self.current_image_index();
+        let command_buffer = Rc::clone(&self.frame_context.command_buffers[image_index]);
         command_buffer.begin_command(vk::CommandBufferUsageFlags::default());
 
         let clear_values = vec![
@@ -208,21 +422,116 @@ impl VulkanRenderer {
             offset: vk::Offset2D { x: 0, y: 0 },
             extent: current_extent,
         };
-        command_buffer.begin_render_pass(
-            framebuffer,
-            self.render_pass.get_vk_renderpass(),
-            render_area,
-            &clear_values,
-        );
+        if self.dynamic_rendering {
+            self.begin_dynamic_rendering(&command_buffer, image_index, render_area, &clear_values);
+        } else {
+            command_buffer.begin_render_pass(
+                self.swapchain_framebuffers[image_index],
+                self.render_pass.get_vk_renderpass(),
+                render_area,
+                &clear_values,
+            );
+        }
+        self.global_uniform
+            .bind(&command_buffer, self.current_frame().frame_index);
         command_buffer
     }
 
+    //Does the layout transitions the opaque render pass would otherwise do for us
+    fn begin_dynamic_rendering(
+        &self,
+        command_buffer: &CommandBuffer,
+        image_index: usize,
+        render_area: vk::Rect2D,
+        clear_values: &[vk::ClearValue],
+    ) {
+        let depth_texture = &self.frame_context.depth_render_texture;
+        command_buffer.image_barrier(
+            self.frame_context.swapchain_images[image_index],
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            subresource_range(vk::ImageAspectFlags::COLOR),
+        );
+        //The depth texture is shared by every frame in flight, the previous frame's depth
+        //writes have to finish before this frame clears it
+        command_buffer.image_barrier_with_access(
+            depth_texture.image(),
+            (
+                vk::ImageLayout::UNDEFINED,
+                vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            ),
+            (
+                vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                layout_access_mask(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL),
+            ),
+            vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            subresource_range(vk::ImageAspectFlags::DEPTH),
+        );
+
+        let (color_attachment, depth_attachment) = opaque_rendering_attachments(
+            self.frame_context.swapchain_image_views[image_index],
+            depth_texture.image_view,
+            clear_values[0],
+            clear_values[1],
+        );
+        command_buffer.begin_rendering(&[color_attachment], &depth_attachment, render_area);
+    }
+
+    //Ends the pass begun by get_commandbuffer_opaque_pass and finishes recording
+    pub fn end_opaque_pass(&self, command_buffer: &CommandBuffer) {
+        if self.dynamic_rendering {
+            command_buffer.end_rendering();
+            command_buffer.image_barrier(
+                self.frame_context.swapchain_images[self.current_image_index()],
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                vk::ImageLayout::PRESENT_SRC_KHR,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                subresource_range(vk::ImageAspectFlags::COLOR),
+            );
+        } else {
+            command_buffer.end_render_pass();
+        }
+        command_buffer.end_command();
+    }
+
+    //Register the scene's pass first, the others draw on top of it
+    pub fn add_pass(&mut self, pass: Box<dyn Pass>) {
+        self.passes.add(pass);
+    }
+
+    //Records every added pass, in order, into the current frame's command buffer
+    pub fn record_passes(&self, command_buffer: &CommandBuffer) {
+        let skybox = self.skybox.as_ref().map(|skybox| skybox as &dyn Pass);
+        self.passes
+            .record_with_background(command_buffer, &self.current_frame(), skybox);
+    }
+
     pub fn submit_frame(&mut self, command_buffers: Vec<&CommandBuffer>) {
-        let frame_data = self.current_framedata.take().unwrap();
+        self.submit_frame_with(command_buffers, &[], &[]);
+    }
 
-        let wait_semaphores = vec![frame_data.available_sem];
+    //The extra semaphores are waited on and signaled alongside the swapchain ones,
+    //presenting still only waits for rendering to finish
+    pub fn submit_frame_with(
+        &mut self,
+        command_buffers: Vec<&CommandBuffer>,
+        extra_waits: &[(vk::Semaphore, vk::PipelineStageFlags)],
+        extra_signals: &[vk::Semaphore],
+    ) {
+        let frame_data = self.current_framedata.take().unwrap();
 
-        let signal_semaphores = vec![frame_data.finished_sem];
+        let (waits, signal_semaphores) = frame_submit_semaphores(
+            frame_data.available_sem,
+            frame_data.finished_sem,
+            extra_waits,
+            extra_signals,
+        );
         let in_flight_fence = frame_data.in_flight_fence;
         unsafe {
             self.context
@@ -230,27 +539,217 @@ impl VulkanRenderer {
                 .reset_fences(&[in_flight_fence])
                 .unwrap();
         }
-        self.context.gfx_queue.submit(
+        let gfx_queue = self.context.gfx_queue.lock().unwrap();
+        gfx_queue.submit_with_stages(
             &command_buffers,
-            &wait_semaphores,
+            &waits,
             &signal_semaphores,
             in_flight_fence,
         );
 
         let swapchains = vec![self.frame_context.swapchain.swapchain];
         let image_indices = vec![frame_data.image_index];
+        let present_wait_semaphores = [frame_data.finished_sem];
         let present_info = vk::PresentInfoKHR::default()
-            .wait_semaphores(&signal_semaphores)
+            .wait_semaphores(&present_wait_semaphores)
             .swapchains(&swapchains)
             .image_indices(&image_indices);
 
         unsafe {
             self.context
                 .swapchain_loader
-                .queue_present(self.context.graphics_queue, &present_info)
+                .queue_present(self.context.present_queue, &present_info)
         }
         .unwrap();
+        drop(gfx_queue);
 
         self.swap_data.step_frame();
     }
 }
+
+//The frame's own semaphores come first, the extra ones are appended in the given order
+fn frame_submit_semaphores(
+    available_sem: vk::Semaphore,
+    finished_sem: vk::Semaphore,
+    extra_waits: &[(vk::Semaphore, vk::PipelineStageFlags)],
+    extra_signals: &[vk::Semaphore],
+) -> (
+    Vec<(vk::Semaphore, vk::PipelineStageFlags)>,
+    Vec<vk::Semaphore>,
+) {
+    let mut waits = vec![(available_sem, vk::PipelineStageFlags::ALL_COMMANDS)];
+    waits.extend_from_slice(extra_waits);
+
+    let mut signal_semaphores = vec![finished_sem];
+    signal_semaphores.extend_from_slice(extra_signals);
+    (waits, signal_semaphores)
+}
+
+fn subresource_range(aspect_mask: vk::ImageAspectFlags) -> vk::ImageSubresourceRange {
+    vk::ImageSubresourceRange::default()
+        .aspect_mask(aspect_mask)
+        .base_mip_level(0)
+        .level_count(1)
+        .base_array_layer(0)
+        .layer_count(1)
+}
+
+//Color is cleared and stored for presenting, depth is only needed during the pass
+pub fn opaque_rendering_attachments(
+    color_view: vk::ImageView,
+    depth_view: vk::ImageView,
+    color_clear: vk::ClearValue,
+    depth_clear: vk::ClearValue,
+) -> (
+    vk::RenderingAttachmentInfo<'static>,
+    vk::RenderingAttachmentInfo<'static>,
+) {
+    let color_attachment = vk::RenderingAttachmentInfo::default()
+        .image_view(color_view)
+        .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+        .load_op(vk::AttachmentLoadOp::CLEAR)
+        .store_op(vk::AttachmentStoreOp::STORE)
+        .clear_value(color_clear);
+    let depth_attachment = vk::RenderingAttachmentInfo::default()
+        .image_view(depth_view)
+        .image_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+        .load_op(vk::AttachmentLoadOp::CLEAR)
+        .store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .clear_value(depth_clear);
+    (color_attachment, depth_attachment)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ash::vk::Handle;
+
+    #[test]
+    fn opaque_attachments_clear_color_and_discard_depth() {
+        let color_clear = vk::ClearValue {
+            color: vk::ClearColorValue {
+                float32: [0.3, 0.5, 0.3, 1.0],
+            },
+        };
+        let depth_clear = vk::ClearValue {
+            depth_stencil: vk::ClearDepthStencilValue {
+                depth: 1.0,
+                stencil: 0,
+            },
+        };
+        let (color, depth) = opaque_rendering_attachments(
+            vk::ImageView::from_raw(1),
+            vk::ImageView::from_raw(2),
+            color_clear,
+            depth_clear,
+        );
+
+        assert_eq!(color.image_view, vk::ImageView::from_raw(1));
+        assert_eq!(
+            color.image_layout,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+        );
+        assert_eq!(color.load_op, vk::AttachmentLoadOp::CLEAR);
+        assert_eq!(color.store_op, vk::AttachmentStoreOp::STORE);
+        assert_eq!(
+            unsafe { color.clear_value.color.float32 },
+            [0.3, 0.5, 0.3, 1.0]
+        );
+
+        assert_eq!(depth.image_view, vk::ImageView::from_raw(2));
+        assert_eq!(
+            depth.image_layout,
+            vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
+        );
+        assert_eq!(depth.load_op, vk::AttachmentLoadOp::CLEAR);
+        assert_eq!(depth.store_op, vk::AttachmentStoreOp::DONT_CARE);
+        let depth_stencil = unsafe { depth.clear_value.depth_stencil };
+        assert_eq!(depth_stencil.depth, 1.0);
+        assert_eq!(depth_stencil.stencil, 0);
+    }
+
+    #[test]
+    fn extra_semaphores_are_appended_after_the_frames_own() {
+        let sem = vk::Semaphore::from_raw;
+        let extra_waits = [
+            (sem(3), vk::PipelineStageFlags::TRANSFER),
+            (sem(4), vk::PipelineStageFlags::VERTEX_INPUT),
+        ];
+        let (waits, signals) =
+            frame_submit_semaphores(sem(1), sem(2), &extra_waits, &[sem(5), sem(6)]);
+
+        assert_eq!(
+            waits,
+            vec![
+                (sem(1), vk::PipelineStageFlags::ALL_COMMANDS),
+                (sem(3), vk::PipelineStageFlags::TRANSFER),
+                (sem(4), vk::PipelineStageFlags::VERTEX_INPUT),
+            ]
+        );
+        assert_eq!(signals, vec![sem(2), sem(5), sem(6)]);
+    }
+
+    #[test]
+    fn without_extras_only_the_frames_semaphores_are_used() {
+        let sem = vk::Semaphore::from_raw;
+        let (waits, signals) = frame_submit_semaphores(sem(1), sem(2), &[], &[]);
+
+        assert_eq!(waits, vec![(sem(1), vk::PipelineStageFlags::ALL_COMMANDS)]);
+        assert_eq!(signals, vec![sem(2)]);
+    }
+
+    //Keeps the frames it was recorded with
+    struct FrameRecordingPass {
+        frames: std::cell::RefCell<Vec<FrameContext>>,
+    }
+
+    impl Pass for FrameRecordingPass {
+        fn record(&self, _command_buffer: &CommandBuffer, frame: &FrameContext) {
+            self.frames.borrow_mut().push(*frame);
+        }
+    }
+
+    #[test]
+    fn frames_carry_the_slot_the_acquired_image_and_the_shading() {
+        let extent = vk::Extent2D {
+            width: 800,
+            height: 600,
+        };
+        //Images don't have to be acquired in order
+        let frames = [(0, 2), (1, 0)].map(|(frame_index, image_index)| FrameData {
+            frame_index,
+            available_sem: vk::Semaphore::null(),
+            finished_sem: vk::Semaphore::null(),
+            in_flight_fence: vk::Fence::null(),
+            image_index,
+        });
+        let pass = FrameRecordingPass {
+            frames: Default::default(),
+        };
+        let command_buffer = CommandBuffer::detached();
+        for frame_data in &frames {
+            pass.record(
+                &command_buffer,
+                &frame_data.frame_context(extent, Shading::Unlit),
+            );
+        }
+
+        assert_eq!(
+            *pass.frames.borrow(),
+            vec![
+                FrameContext {
+                    frame_index: 0,
+                    image_index: 2,
+                    extent,
+                    shading: Shading::Unlit,
+                },
+                FrameContext {
+                    frame_index: 1,
+                    image_index: 0,
+                    extent,
+                    shading: Shading::Unlit,
+                },
+            ]
+        );
+    }
+}
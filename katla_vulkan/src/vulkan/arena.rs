@@ -0,0 +1,132 @@
+use super::{
+    context::{AllocationError, VulkanContext},
+    Buffer,
+};
+use ash::vk;
+
+use std::sync::Arc;
+
+//Rounds value up to the closest multiple of align, align has to be a power of two
+pub fn align_up(value: vk::DeviceSize, align: vk::DeviceSize) -> vk::DeviceSize {
+    debug_assert!(align.is_power_of_two(), "Alignment must be a power of two");
+    (value + align - 1) & !(align - 1)
+}
+
+//Offset of a size byte allocation placed after head, None if it doesn't fit in capacity
+fn bump_offset(
+    head: vk::DeviceSize,
+    size: vk::DeviceSize,
+    align: vk::DeviceSize,
+    capacity: vk::DeviceSize,
+) -> Option<vk::DeviceSize> {
+    let offset = align_up(head, align);
+    match offset.checked_add(size) {
+        Some(end) if end <= capacity => Some(offset),
+        _ => None,
+    }
+}
+
+/// Linear allocator for transient per-frame uniform data.
+/// Holds one host-visible buffer per frame in flight, sub-ranges are handed out with
+/// `allocate` and the whole buffer is reclaimed when the frame slot comes around again.
+pub struct FrameArena {
+    buffers: Vec<Buffer>,
+    size: vk::DeviceSize,
+    frame_index: usize,
+    head: vk::DeviceSize,
+}
+
+impl FrameArena {
+    pub fn new(
+        context: Arc<VulkanContext>,
+        size: vk::DeviceSize,
+        frames_in_flight: usize,
+    ) -> Result<Self, AllocationError> {
+        let buffers = (0..frames_in_flight)
+            .map(|_| {
+                Buffer::new(
+                    context.clone(),
+                    size,
+                    vk::BufferUsageFlags::UNIFORM_BUFFER,
+                    gpu_allocator::MemoryLocation::CpuToGpu,
+                )
+            })
+            .collect::<Result<Vec<Buffer>, AllocationError>>()?;
+
+        Ok(Self {
+            buffers,
+            size,
+            frame_index: 0,
+            head: 0,
+        })
+    }
+
+    //Switches to the buffer of the given frame slot and reclaims all of it
+    pub fn begin_frame(&mut self, frame_index: usize) {
+        self.frame_index = frame_index % self.buffers.len();
+        self.reset();
+    }
+
+    pub fn reset(&mut self) {
+        self.head = 0;
+    }
+
+    ///Returns the offset into the current frame's buffer and a mapped pointer to it,
+    ///or None if the arena is exhausted for this frame
+    pub fn allocate(
+        &mut self,
+        size: vk::DeviceSize,
+        align: vk::DeviceSize,
+    ) -> Option<(vk::DeviceSize, *mut u8)> {
+        let offset = bump_offset(self.head, size, align, self.size)?;
+        self.head = offset + size;
+
+        let mapped_ptr = self.buffers[self.frame_index].map();
+        Some((offset, unsafe { mapped_ptr.add(offset as usize) }))
+    }
+
+    pub fn buffer(&self) -> vk::Buffer {
+        self.buffers[self.frame_index].vk_buffer()
+    }
+
+    pub fn used(&self) -> vk::DeviceSize {
+        self.head
+    }
+
+    pub fn capacity(&self) -> vk::DeviceSize {
+        self.size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn align_up_rounds_to_the_next_multiple() {
+        assert_eq!(align_up(0, 256), 0);
+        assert_eq!(align_up(1, 256), 256);
+        assert_eq!(align_up(256, 256), 256);
+        assert_eq!(align_up(257, 64), 320);
+        assert_eq!(align_up(13, 1), 13);
+    }
+
+    #[test]
+    fn allocations_are_aligned_after_the_head() {
+        assert_eq!(bump_offset(0, 100, 256, 1024), Some(0));
+        assert_eq!(bump_offset(100, 100, 256, 1024), Some(256));
+        assert_eq!(bump_offset(356, 4, 4, 1024), Some(356));
+    }
+
+    #[test]
+    fn allocations_fit_up_to_the_capacity() {
+        assert_eq!(bump_offset(768, 256, 256, 1024), Some(768));
+        assert_eq!(bump_offset(769, 1, 256, 1024), None);
+        assert_eq!(bump_offset(0, 1025, 1, 1024), None);
+    }
+
+    #[test]
+    fn huge_allocations_do_not_overflow() {
+        assert_eq!(bump_offset(256, vk::DeviceSize::MAX, 256, 1024), None);
+    }
+}
@@ -0,0 +1,112 @@
+use super::context::{AllocationError, VulkanContext};
+use ash::vk;
+use gpu_allocator::vulkan::Allocation;
+
+use std::sync::Arc;
+
+/// A GPU buffer and its allocation, both are freed when the Buffer is dropped.
+pub struct Buffer {
+    context: Arc<VulkanContext>,
+    buffer: vk::Buffer,
+    allocation: Option<Allocation>,
+    size: vk::DeviceSize,
+}
+
+impl Buffer {
+    pub fn new(
+        context: Arc<VulkanContext>,
+        size: vk::DeviceSize,
+        usage: vk::BufferUsageFlags,
+        location: gpu_allocator::MemoryLocation,
+    ) -> Result<Self, AllocationError> {
+        let create_info = vk::BufferCreateInfo::default()
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .usage(usage)
+            .size(size);
+        let (buffer, allocation) = context.allocate_buffer(&create_info, location)?;
+
+        Ok(Self {
+            context,
+            buffer,
+            allocation: Some(allocation),
+            size,
+        })
+    }
+
+    pub fn vk_buffer(&self) -> vk::Buffer {
+        self.buffer
+    }
+
+    pub fn size(&self) -> vk::DeviceSize {
+        self.size
+    }
+
+    //Only valid for host-visible memory locations
+    pub fn map(&self) -> *mut u8 {
+        self.context.map_buffer(self.allocation.as_ref().unwrap())
+    }
+
+    //Writes through the mapping, so the buffer must not be shared while it is written
+    pub fn write(&mut self, data: &[u8]) {
+        self.write_at(0, data);
+    }
+
+    pub fn write_at(&mut self, offset: vk::DeviceSize, data: &[u8]) {
+        let data_size = self.checked_size(offset, data);
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                data.as_ptr(),
+                self.map().add(offset as usize),
+                data_size as usize,
+            );
+        }
+    }
+
+    //For GpuOnly buffers created with TRANSFER_DST. The data goes through a staging buffer
+    //and a copy on the upload command pool, and has landed when this returns
+    pub fn upload_at(&self, offset: vk::DeviceSize, data: &[u8]) -> Result<(), AllocationError> {
+        let data_size = self.checked_size(offset, data);
+        if data.is_empty() {
+            return Ok(());
+        }
+        let mut staging = Buffer::new(
+            self.context.clone(),
+            data_size,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            gpu_allocator::MemoryLocation::CpuToGpu,
+        )?;
+        staging.write(data);
+
+        let commands = self.context.begin_single_time_commands();
+        commands.copy_buffer(
+            staging.buffer,
+            self.buffer,
+            &[vk::BufferCopy {
+                src_offset: 0,
+                dst_offset: offset,
+                size: data_size,
+            }],
+        );
+        self.context.end_single_time_commands(commands);
+        Ok(())
+    }
+
+    fn checked_size(&self, offset: vk::DeviceSize, data: &[u8]) -> vk::DeviceSize {
+        let data_size = std::mem::size_of_val(data) as vk::DeviceSize;
+        if self.size < offset + data_size {
+            panic!(
+                "Too little memory allocated for buffer of size {}",
+                offset + data_size
+            );
+        }
+        data_size
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        if let Some(allocation) = self.allocation.take() {
+            self.context.free_buffer(self.buffer, allocation);
+        }
+    }
+}
@@ -1,12 +1,112 @@
 use ash::{vk, Device};
 
-use super::CommandPool;
+use std::{cell::Cell, fmt};
 
-#[derive(Clone)]
+use super::{CommandPool, IndexBuffer, VertexBuffer};
+
+//Access masks guarding an image in the given layout, used when transitioning out of
+//(src) or into (dst) that layout. Unknown layouts fall back to the conservative masks.
+pub fn layout_access_mask(layout: vk::ImageLayout) -> vk::AccessFlags {
+    match layout {
+        vk::ImageLayout::UNDEFINED | vk::ImageLayout::PRESENT_SRC_KHR => vk::AccessFlags::empty(),
+        vk::ImageLayout::TRANSFER_DST_OPTIMAL => vk::AccessFlags::TRANSFER_WRITE,
+        vk::ImageLayout::TRANSFER_SRC_OPTIMAL => vk::AccessFlags::TRANSFER_READ,
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL => vk::AccessFlags::SHADER_READ,
+        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL => {
+            vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+        }
+        vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL => {
+            vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE
+        }
+        _ => vk::AccessFlags::MEMORY_READ | vk::AccessFlags::MEMORY_WRITE,
+    }
+}
+
+//Whether first..first + count fits in the bound buffer, unknown bounds always pass
+pub fn range_in_bounds(first: u32, count: u32, bound_count: Option<u32>) -> bool {
+    match bound_count {
+        Some(bound_count) => u64::from(first) + u64::from(count) <= u64::from(bound_count),
+        None => true,
+    }
+}
+
+/// Where a CommandBuffer is in its lifecycle, tracked so out of order calls fail in debug
+/// builds with a readable message instead of a validation error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordingState {
+    Initial,
+    Recording,
+    InRenderPass,
+    Executable,
+}
+
+/// Returned by a RecordingState transition that is illegal in the current state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidTransition {
+    pub transition: &'static str,
+    pub state: RecordingState,
+}
+
+impl fmt::Display for InvalidTransition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "can't {} in the {:?} state", self.transition, self.state)
+    }
+}
+
+impl std::error::Error for InvalidTransition {}
+
+type Transition = fn(RecordingState) -> Result<RecordingState, InvalidTransition>;
+
+//Each returns the state after the call
+impl RecordingState {
+    //Executable buffers are implicitly reset when recording begins again
+    pub fn begin_recording(self) -> Result<Self, InvalidTransition> {
+        match self {
+            RecordingState::Initial | RecordingState::Executable => Ok(RecordingState::Recording),
+            _ => Err(self.invalid("begin recording")),
+        }
+    }
+
+    pub fn end_recording(self) -> Result<Self, InvalidTransition> {
+        match self {
+            RecordingState::Recording => Ok(RecordingState::Executable),
+            _ => Err(self.invalid("end recording")),
+        }
+    }
+
+    pub fn begin_render_pass(self) -> Result<Self, InvalidTransition> {
+        match self {
+            RecordingState::Recording => Ok(RecordingState::InRenderPass),
+            _ => Err(self.invalid("begin a render pass")),
+        }
+    }
+
+    pub fn end_render_pass(self) -> Result<Self, InvalidTransition> {
+        match self {
+            RecordingState::InRenderPass => Ok(RecordingState::Recording),
+            _ => Err(self.invalid("end a render pass")),
+        }
+    }
+
+    fn invalid(self, transition: &'static str) -> InvalidTransition {
+        InvalidTransition {
+            transition,
+            state: self,
+        }
+    }
+}
+
+//Not Clone, the recording state has to stay with the one vk::CommandBuffer. Share it
+//through an Rc instead
 pub struct CommandBuffer {
     device: Device,
     command_pool: vk::CommandPool,
     command_buffer: vk::CommandBuffer,
+    //Element counts of the currently bound buffers, used to validate draws in debug builds
+    bound_index_count: Cell<Option<u32>>,
+    bound_vertex_count: Cell<Option<u32>>,
+    state: Cell<RecordingState>,
 }
 
 impl CommandBuffer {
@@ -22,6 +122,24 @@ impl CommandBuffer {
             device: device.clone(),
             command_pool: command_pool.vk_command_pool(),
             command_buffer,
+            bound_index_count: Cell::new(None),
+            bound_vertex_count: Cell::new(None),
+            state: Cell::new(RecordingState::Initial),
+        }
+    }
+
+    //Not backed by a device, recording into it panics. For tests of drawables and passes
+    //that only hand the command buffer on
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn detached() -> Self {
+        let device = unsafe { Device::load_with(|_| std::ptr::null(), vk::Device::null()) };
+        Self {
+            device,
+            command_pool: vk::CommandPool::null(),
+            command_buffer: vk::CommandBuffer::null(),
+            bound_index_count: Cell::new(None),
+            bound_vertex_count: Cell::new(None),
+            state: Cell::new(RecordingState::Initial),
         }
     }
 
@@ -29,7 +147,36 @@ impl CommandBuffer {
         self.command_buffer
     }
 
+    pub fn recording_state(&self) -> RecordingState {
+        self.state.get()
+    }
+
+    //Release builds skip the check and keep the current state on an illegal call
+    fn transition(&self, call: &str, next: Transition) {
+        match next(self.state.get()) {
+            Ok(next) => self.state.set(next),
+            Err(err) => {
+                if cfg!(debug_assertions) {
+                    panic!("{} called on a command buffer: {}", call, err);
+                }
+            }
+        }
+    }
+
+    fn assert_state(&self, call: &str, expected: RecordingState) {
+        debug_assert_eq!(
+            self.state.get(),
+            expected,
+            "{} needs a command buffer in the {:?} state",
+            call,
+            expected
+        );
+    }
+
     pub fn begin_single_time_command(&self) {
+        self.transition("begin_single_time_command", RecordingState::begin_recording);
+        self.bound_index_count.set(None);
+        self.bound_vertex_count.set(None);
         let begin_info = vk::CommandBufferBeginInfo::default()
             .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
         unsafe {
@@ -40,12 +187,16 @@ impl CommandBuffer {
     }
 
     pub fn end_single_time_command(&self) {
+        self.transition("end_single_time_command", RecordingState::end_recording);
         unsafe {
             self.device.end_command_buffer(self.command_buffer).unwrap();
         }
     }
 
     pub fn begin_command(&self, flags: vk::CommandBufferUsageFlags) {
+        self.transition("begin_command", RecordingState::begin_recording);
+        self.bound_index_count.set(None);
+        self.bound_vertex_count.set(None);
         let begin_info = vk::CommandBufferBeginInfo::default().flags(flags);
         unsafe {
             self.device
@@ -55,6 +206,7 @@ impl CommandBuffer {
     }
 
     pub fn end_command(&self) {
+        self.transition("end_command", RecordingState::end_recording);
         unsafe {
             self.device.end_command_buffer(self.command_buffer).unwrap();
         }
@@ -67,6 +219,7 @@ impl CommandBuffer {
         render_area: vk::Rect2D,
         clear_values: &[vk::ClearValue],
     ) {
+        self.transition("begin_render_pass", RecordingState::begin_render_pass);
         let begin_info = vk::RenderPassBeginInfo::default()
             .render_pass(render_pass)
             .framebuffer(framebuffer)
@@ -79,6 +232,41 @@ impl CommandBuffer {
                 &begin_info,
                 vk::SubpassContents::INLINE,
             );
+        }
+        self.set_viewport_and_scissor(render_area);
+    }
+
+    //Dynamic rendering counterpart of begin_render_pass, attachments have to be
+    //transitioned to their attachment layouts beforehand
+    pub fn begin_rendering(
+        &self,
+        color_attachments: &[vk::RenderingAttachmentInfo],
+        depth_attachment: &vk::RenderingAttachmentInfo,
+        render_area: vk::Rect2D,
+    ) {
+        self.transition("begin_rendering", RecordingState::begin_render_pass);
+        let rendering_info = vk::RenderingInfo::default()
+            .render_area(render_area)
+            .layer_count(1)
+            .color_attachments(color_attachments)
+            .depth_attachment(depth_attachment);
+
+        unsafe {
+            self.device
+                .cmd_begin_rendering(self.command_buffer, &rendering_info);
+        }
+        self.set_viewport_and_scissor(render_area);
+    }
+
+    pub fn end_rendering(&self) {
+        self.transition("end_rendering", RecordingState::end_render_pass);
+        unsafe {
+            self.device.cmd_end_rendering(self.command_buffer);
+        }
+    }
+
+    fn set_viewport_and_scissor(&self, render_area: vk::Rect2D) {
+        unsafe {
             self.device
                 .cmd_set_scissor(self.command_buffer, 0, &[render_area]);
 
@@ -96,7 +284,71 @@ impl CommandBuffer {
         }
     }
 
+    pub fn copy_buffer(&self, src: vk::Buffer, dst: vk::Buffer, regions: &[vk::BufferCopy]) {
+        self.assert_state("copy_buffer", RecordingState::Recording);
+        unsafe {
+            self.device
+                .cmd_copy_buffer(self.command_buffer, src, dst, regions)
+        }
+    }
+
+    //Layout transition with the access masks derived from the layout pair
+    pub fn image_barrier(
+        &self,
+        image: vk::Image,
+        old_layout: vk::ImageLayout,
+        new_layout: vk::ImageLayout,
+        src_stage_mask: vk::PipelineStageFlags,
+        dst_stage_mask: vk::PipelineStageFlags,
+        subresource_range: vk::ImageSubresourceRange,
+    ) {
+        self.image_barrier_with_access(
+            image,
+            (old_layout, layout_access_mask(old_layout)),
+            (new_layout, layout_access_mask(new_layout)),
+            src_stage_mask,
+            dst_stage_mask,
+            subresource_range,
+        );
+    }
+
+    //For transitions whose src access doesn't follow from the old layout, e.g. discarding
+    //an image from UNDEFINED that earlier work may still be writing to
+    pub fn image_barrier_with_access(
+        &self,
+        image: vk::Image,
+        (old_layout, src_access_mask): (vk::ImageLayout, vk::AccessFlags),
+        (new_layout, dst_access_mask): (vk::ImageLayout, vk::AccessFlags),
+        src_stage_mask: vk::PipelineStageFlags,
+        dst_stage_mask: vk::PipelineStageFlags,
+        subresource_range: vk::ImageSubresourceRange,
+    ) {
+        self.assert_state("image_barrier", RecordingState::Recording);
+        let barrier = vk::ImageMemoryBarrier::default()
+            .old_layout(old_layout)
+            .new_layout(new_layout)
+            .src_access_mask(src_access_mask)
+            .dst_access_mask(dst_access_mask)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(image)
+            .subresource_range(subresource_range);
+
+        unsafe {
+            self.device.cmd_pipeline_barrier(
+                self.command_buffer,
+                src_stage_mask,
+                dst_stage_mask,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[barrier],
+            );
+        }
+    }
+
     pub fn end_render_pass(&self) {
+        self.transition("end_render_pass", RecordingState::end_render_pass);
         unsafe {
             self.device.cmd_end_render_pass(self.command_buffer);
         }
@@ -117,6 +369,7 @@ impl CommandBuffer {
         &self,
         pipeline_bind_point: vk::PipelineBindPoint,
         pipeline_layout: vk::PipelineLayout,
+        first_set: u32,
         descriptor_sets: &[vk::DescriptorSet],
     ) {
         unsafe {
@@ -125,21 +378,52 @@ impl CommandBuffer {
                 self.command_buffer,
                 pipeline_bind_point,
                 pipeline_layout,
-                0,
+                first_set,
                 descriptor_sets,
                 &[],
             );
         }
     }
 
+    pub fn push_constants(
+        &self,
+        pipeline_layout: vk::PipelineLayout,
+        stage_flags: vk::ShaderStageFlags,
+        offset: u32,
+        constants: &[u8],
+    ) {
+        unsafe {
+            self.device.cmd_push_constants(
+                self.command_buffer,
+                pipeline_layout,
+                stage_flags,
+                offset,
+                constants,
+            );
+        }
+    }
+
+    //Raw buffers have unknown sizes, draws after this are not bounds checked
     pub fn bind_index_buffer(&self, buffer: vk::Buffer, offset: u64, index_type: vk::IndexType) {
+        self.bound_index_count.set(None);
         unsafe {
             self.device
                 .cmd_bind_index_buffer(self.command_buffer, buffer, offset, index_type)
         }
     }
 
+    pub fn bind_index_buffer_object(&self, index_buffer: &IndexBuffer) {
+        self.bind_index_buffer(index_buffer.object(), 0, index_buffer.index_type);
+        self.bound_index_count.set(Some(index_buffer.count()));
+    }
+
+    pub fn bind_vertex_buffer_object(&self, binding: u32, vertex_buffer: &VertexBuffer) {
+        self.bind_vertex_buffers(binding, &[vertex_buffer.object()], &[0]);
+        self.bound_vertex_count.set(Some(vertex_buffer.count()));
+    }
+
     pub fn bind_vertex_buffers(&self, first_binding: u32, buffers: &[vk::Buffer], offsets: &[u64]) {
+        self.bound_vertex_count.set(None);
         unsafe {
             self.device.cmd_bind_vertex_buffers(
                 self.command_buffer,
@@ -158,6 +442,14 @@ impl CommandBuffer {
         vertex_offset: i32,
         first_instance: u32,
     ) {
+        self.assert_state("draw_indexed", RecordingState::InRenderPass);
+        debug_assert!(
+            range_in_bounds(first_index, index_count, self.bound_index_count.get()),
+            "Drawing indices {}..{} but only {:?} are bound",
+            first_index,
+            u64::from(first_index) + u64::from(index_count),
+            self.bound_index_count.get()
+        );
         unsafe {
             self.device.cmd_draw_indexed(
                 self.command_buffer,
@@ -177,6 +469,14 @@ impl CommandBuffer {
         first_vertex: u32,
         first_instance: u32,
     ) {
+        self.assert_state("draw_array", RecordingState::InRenderPass);
+        debug_assert!(
+            range_in_bounds(first_vertex, vertex_count, self.bound_vertex_count.get()),
+            "Drawing vertices {}..{} but only {:?} are bound",
+            first_vertex,
+            u64::from(first_vertex) + u64::from(vertex_count),
+            self.bound_vertex_count.get()
+        );
         unsafe {
             self.device.cmd_draw(
                 self.command_buffer,
@@ -188,6 +488,26 @@ impl CommandBuffer {
         }
     }
 
+    //Reads draw_count commands of stride bytes each, starting at offset into buffer
+    pub fn draw_indexed_indirect(
+        &self,
+        buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        draw_count: u32,
+        stride: u32,
+    ) {
+        self.assert_state("draw_indexed_indirect", RecordingState::InRenderPass);
+        unsafe {
+            self.device.cmd_draw_indexed_indirect(
+                self.command_buffer,
+                buffer,
+                offset,
+                draw_count,
+                stride,
+            )
+        }
+    }
+
     pub fn return_to_pool(&self) {
         unsafe {
             self.device
@@ -195,3 +515,127 @@ impl CommandBuffer {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recording_lifecycle() {
+        let state = RecordingState::Initial.begin_recording().unwrap();
+        assert_eq!(state, RecordingState::Recording);
+        let state = state.begin_render_pass().unwrap();
+        assert_eq!(state, RecordingState::InRenderPass);
+        let state = state.end_render_pass().unwrap();
+        assert_eq!(state, RecordingState::Recording);
+        let state = state.end_recording().unwrap();
+        assert_eq!(state, RecordingState::Executable);
+        //Recording again resets the buffer
+        assert_eq!(state.begin_recording(), Ok(RecordingState::Recording));
+    }
+
+    #[test]
+    fn illegal_transitions_are_errors() {
+        use RecordingState::*;
+        let transitions: [(Transition, &[RecordingState]); 4] = [
+            (RecordingState::begin_recording, &[Recording, InRenderPass]),
+            (
+                RecordingState::end_recording,
+                &[Initial, InRenderPass, Executable],
+            ),
+            (
+                RecordingState::begin_render_pass,
+                &[Initial, InRenderPass, Executable],
+            ),
+            (
+                RecordingState::end_render_pass,
+                &[Initial, Recording, Executable],
+            ),
+        ];
+        for (transition, illegal_states) in transitions {
+            for &state in illegal_states {
+                let err = transition(state).unwrap_err();
+                assert_eq!(err.state, state);
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_transition_message() {
+        let err = RecordingState::Initial.end_render_pass().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "can't end a render pass in the Initial state"
+        );
+    }
+
+    #[test]
+    fn layout_access_masks() {
+        let expected = [
+            (vk::ImageLayout::UNDEFINED, vk::AccessFlags::empty()),
+            (vk::ImageLayout::PRESENT_SRC_KHR, vk::AccessFlags::empty()),
+            (
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::AccessFlags::TRANSFER_WRITE,
+            ),
+            (
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                vk::AccessFlags::TRANSFER_READ,
+            ),
+            (
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::AccessFlags::SHADER_READ,
+            ),
+            (
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            ),
+            (
+                vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            ),
+        ];
+        for (layout, access) in expected {
+            assert_eq!(layout_access_mask(layout), access, "{:?}", layout);
+        }
+    }
+
+    #[test]
+    fn ranges_inside_the_bound_buffer() {
+        assert!(range_in_bounds(0, 6, Some(6)));
+        assert!(range_in_bounds(3, 3, Some(6)));
+        assert!(range_in_bounds(6, 0, Some(6)));
+        assert!(range_in_bounds(0, 0, Some(0)));
+    }
+
+    #[test]
+    fn ranges_past_the_bound_buffer() {
+        assert!(!range_in_bounds(0, 7, Some(6)));
+        assert!(!range_in_bounds(4, 3, Some(6)));
+        assert!(!range_in_bounds(7, 0, Some(6)));
+    }
+
+    #[test]
+    fn ranges_do_not_overflow() {
+        //first + count wraps around in u32 and would pass a naive check
+        assert!(!range_in_bounds(u32::MAX, 2, Some(6)));
+        assert!(!range_in_bounds(u32::MAX, u32::MAX, Some(u32::MAX)));
+        assert!(range_in_bounds(u32::MAX - 1, 1, Some(u32::MAX)));
+    }
+
+    #[test]
+    fn unknown_bounds_always_pass() {
+        assert!(range_in_bounds(u32::MAX, u32::MAX, None));
+    }
+
+    #[test]
+    fn unknown_layouts_are_conservative() {
+        for layout in [vk::ImageLayout::GENERAL, vk::ImageLayout::PREINITIALIZED] {
+            assert_eq!(
+                layout_access_mask(layout),
+                vk::AccessFlags::MEMORY_READ | vk::AccessFlags::MEMORY_WRITE
+            );
+        }
+    }
+}
@@ -10,22 +10,108 @@ use gpu_allocator::{
 };
 use raw_window_handle::{HasDisplayHandle, HasWindowHandle};
 use std::{
-    cell::RefCell,
+    collections::HashMap,
     ffi::{c_void, CStr, CString},
+    fmt,
     mem::ManuallyDrop,
-    sync::Arc,
+    ops::Deref,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
 };
 // use winit::{
 //     raw_window_handle::{HasDisplayHandle, HasRawWindowHandle, HasWindowHandle},
 //     window::Window,
 // };
 
-use super::SwapchainInfo;
+use super::{
+    global::create_global_set_layout, DescriptorAllocator, DeviceFeatures, DeviceLimits,
+    SamplerConfig, SwapchainConfig, SwapchainInfo, DEFAULT_SETS_PER_POOL,
+};
+
+pub const DEFAULT_API_VERSION: u32 = vk::make_api_version(0, 1, 2, 0);
+//Dynamic rendering is only used through core 1.3
+pub const DYNAMIC_RENDERING_API_VERSION: u32 = vk::API_VERSION_1_3;
+
+//Clamps the requested Vulkan version to what the loader supports, ignoring patch versions
+pub fn select_api_version(requested: u32, available: u32) -> u32 {
+    let requested = vk::make_api_version(
+        0,
+        vk::api_version_major(requested),
+        vk::api_version_minor(requested),
+        0,
+    );
+    let available = vk::make_api_version(
+        0,
+        vk::api_version_major(available),
+        vk::api_version_minor(available),
+        0,
+    );
+    requested.min(available)
+}
+
+/// Returned when a buffer or image could not be given device memory.
+/// OutOfMemory covers both the allocator running dry and the driver refusing the request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AllocationError {
+    OutOfMemory,
+    Failed(String),
+}
+
+impl fmt::Display for AllocationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AllocationError::OutOfMemory => write!(f, "out of device memory"),
+            AllocationError::Failed(reason) => write!(f, "allocation failed: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for AllocationError {}
+
+impl From<gpu_allocator::AllocationError> for AllocationError {
+    fn from(err: gpu_allocator::AllocationError) -> Self {
+        match err {
+            gpu_allocator::AllocationError::OutOfMemory => AllocationError::OutOfMemory,
+            err => AllocationError::Failed(err.to_string()),
+        }
+    }
+}
+
+impl From<vk::Result> for AllocationError {
+    fn from(err: vk::Result) -> Self {
+        match err {
+            vk::Result::ERROR_OUT_OF_DEVICE_MEMORY | vk::Result::ERROR_OUT_OF_HOST_MEMORY => {
+                AllocationError::OutOfMemory
+            }
+            err => AllocationError::Failed(err.to_string()),
+        }
+    }
+}
+
+//Passes the error on as an AllocationError, calling release first so that whatever the
+//memory was meant for doesn't leak
+fn allocated_or_release<T, E>(
+    result: Result<T, E>,
+    release: impl FnOnce(),
+) -> Result<T, AllocationError>
+where
+    E: Into<AllocationError>,
+{
+    result.map_err(|err| {
+        release();
+        err.into()
+    })
+}
 
 const LAYER_KHRONOS_VALIDATION: &str = concat!("VK_LAYER_KHRONOS_validation", "\0");
 
-struct QueueFamilyIndices {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueFamilyIndices {
     pub graphics_idx: Option<u32>,
+    pub present_idx: Option<u32>,
     pub transfer_idx: Option<u32>,
 }
 
@@ -39,6 +125,47 @@ pub struct RenderTexture {
 }
 
 impl RenderTexture {
+    pub fn new(
+        context: Arc<VulkanContext>,
+        extent: vk::Extent2D,
+        format: vk::Format,
+        usage: vk::ImageUsageFlags,
+        aspect_mask: vk::ImageAspectFlags,
+    ) -> Result<Self, AllocationError> {
+        let extent_3d = vk::Extent3D {
+            width: extent.width,
+            height: extent.height,
+            depth: 1,
+        };
+        let create_info = vk::ImageCreateInfo::default()
+            .image_type(vk::ImageType::TYPE_2D)
+            .mip_levels(1)
+            .array_layers(1)
+            .format(format)
+            .extent(extent_3d)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .usage(usage);
+
+        let (image, image_memory) =
+            context.create_image(create_info, gpu_allocator::MemoryLocation::GpuOnly)?;
+
+        let image_view =
+            VulkanFrameCtx::create_image_view(&context.device, image, format, aspect_mask);
+        Ok(Self {
+            extent,
+            image_view,
+            format,
+            image,
+            image_memory: Some(image_memory),
+            context,
+        })
+    }
+
+    pub fn image(&self) -> vk::Image {
+        self.image
+    }
+
     fn destroy(&mut self) {
         unsafe {
             self.context
@@ -57,6 +184,10 @@ impl Drop for RenderTexture {
     }
 }
 
+/// Threading model: the context is Send + Sync so it can be shared as Arc<VulkanContext>.
+/// Allocation, freeing, sampler lookup, single-time commands and submitting to the graphics
+/// queue lock internally and are safe from any thread. Single-time commands record into their
+/// own pool, so texture uploads from a worker don't touch the frames' command buffers.
 pub struct VulkanContext {
     entry: Entry,
     pub instance: Instance,
@@ -64,23 +195,88 @@ pub struct VulkanContext {
     pub surface_loader: SurfaceInstance,
     pub swapchain_loader: Arc<SwapchainDevice>,
     pub physical_device: vk::PhysicalDevice,
-    pub allocator: ManuallyDrop<RefCell<Allocator>>,
+    pub allocator: ManuallyDrop<Mutex<Allocator>>,
     pub surface: vk::SurfaceKHR,
     pub graphics_queue: vk::Queue,
-    pub gfx_queue: super::Queue,
-    pub gfx_cmdpool: super::CommandPool,
+    //Same as graphics_queue unless the graphics family can't present to the surface
+    pub present_queue: vk::Queue,
+    pub queue_family_indices: QueueFamilyIndices,
+    //Also guards graphics_queue and present_queue, they may be the same VkQueue
+    pub gfx_queue: Mutex<super::Queue>,
+    pub gfx_cmdpool: Mutex<super::CommandPool>,
+    upload_cmdpool: Mutex<super::CommandPool>,
     pub transfer_command_pool: vk::CommandPool,
     pub transfer_queue: vk::Queue,
+    sampler_cache: SamplerCache,
+    descriptor_allocator: Mutex<DescriptorAllocator>,
+    //Set 0 of every material pipeline, see GlobalUniform
+    global_set_layout: vk::DescriptorSetLayout,
+    global_pipeline_layout: vk::PipelineLayout,
+    //RenderPipelines that have been created but not destroyed yet
+    live_pipelines: AtomicUsize,
+    device_limits: DeviceLimits,
+    device_features: DeviceFeatures,
+    dynamic_rendering: bool,
     debug_utils_loader: DebugInstance,
     debug_callback: Option<vk::DebugUtilsMessengerEXT>,
 }
+/// Command buffer from begin_single_time_commands. Holds the upload pool's lock until it is
+/// handed to end_single_time_commands, so only one can be recorded at a time per thread.
+pub struct SingleTimeCommands<'a> {
+    command_buffer: super::CommandBuffer,
+    _pool: MutexGuard<'a, super::CommandPool>,
+}
+
+impl Deref for SingleTimeCommands<'_> {
+    type Target = super::CommandBuffer;
+
+    fn deref(&self) -> &super::CommandBuffer {
+        &self.command_buffer
+    }
+}
+
+//Identical sampler configurations share one vk::Sampler, creation happens under the lock
+struct SamplerCache {
+    samplers: Mutex<HashMap<SamplerConfig, vk::Sampler>>,
+}
+
+impl SamplerCache {
+    fn new() -> Self {
+        Self {
+            samplers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn get_or_create(
+        &self,
+        config: &SamplerConfig,
+        create: impl FnOnce(&SamplerConfig) -> vk::Sampler,
+    ) -> vk::Sampler {
+        let mut samplers = self.samplers.lock().unwrap();
+        *samplers.entry(*config).or_insert_with(|| create(config))
+    }
+
+    fn drain(&mut self) -> Vec<vk::Sampler> {
+        self.samplers
+            .get_mut()
+            .unwrap()
+            .drain()
+            .map(|(_, sampler)| sampler)
+            .collect()
+    }
+}
+
 pub struct VulkanFrameCtx {
     pub context: Arc<VulkanContext>,
     pub swapchain_image_views: Vec<vk::ImageView>,
     pub swapchain: super::Swapchain,
     pub swapchain_images: Vec<vk::Image>,
     pub depth_render_texture: RenderTexture,
-    pub command_buffers: Vec<super::CommandBuffer>,
+    //One per swapchain image, shared with the caller while the frame is recorded
+    pub command_buffers: Vec<Rc<super::CommandBuffer>>,
+    swapchain_config: SwapchainConfig,
+    //Surface of an additional window, None uses the context's own surface
+    owned_surface: Option<vk::SurfaceKHR>,
 }
 
 impl QueueFamilyIndices {
@@ -90,50 +286,82 @@ impl QueueFamilyIndices {
         surface: vk::SurfaceKHR,
         physical_device: vk::PhysicalDevice,
     ) -> Self {
-        let mut queue_family_indices = Self {
-            graphics_idx: None,
-            transfer_idx: None,
-        };
-        unsafe {
+        let families: Vec<_> = unsafe {
             let family_props =
                 instance.get_physical_device_queue_family_properties(physical_device);
             println!("Num family indices: {}", family_props.len());
-            for (idx, properties) in family_props.iter().enumerate() {
-                if properties.queue_flags.contains(vk::QueueFlags::GRAPHICS)
-                    && surface_loader
+            family_props
+                .iter()
+                .enumerate()
+                .map(|(idx, properties)| {
+                    let supports_present = surface_loader
                         .get_physical_device_surface_support(physical_device, idx as u32, surface)
-                        .unwrap()
-                {
-                    if queue_family_indices.graphics_idx.is_none() {
-                        queue_family_indices.graphics_idx = Some(idx as u32);
-                        continue;
-                    }
-                }
-
-                if properties.queue_flags.contains(vk::QueueFlags::TRANSFER)
-                    && surface_loader
-                        .get_physical_device_surface_support(physical_device, idx as u32, surface)
-                        .unwrap()
-                {
-                    if queue_family_indices.transfer_idx.is_none() {
-                        queue_family_indices.transfer_idx = Some(idx as u32);
-                        continue;
-                    }
-                }
-            }
+                        .unwrap();
+                    (properties.queue_flags, supports_present)
+                })
+                .collect()
         };
 
-        queue_family_indices
+        Self::select(&families)
+    }
+
+    //Takes the queue flags and surface support of each family. A graphics family that can
+    //also present is preferred, otherwise presenting happens from a separate family.
+    pub fn select(families: &[(vk::QueueFlags, bool)]) -> Self {
+        let graphics_and_present = families
+            .iter()
+            .position(|(flags, present)| flags.contains(vk::QueueFlags::GRAPHICS) && *present);
+        let (graphics_idx, present_idx) = match graphics_and_present {
+            Some(idx) => (Some(idx as u32), Some(idx as u32)),
+            None => (
+                families
+                    .iter()
+                    .position(|(flags, _)| flags.contains(vk::QueueFlags::GRAPHICS))
+                    .map(|idx| idx as u32),
+                families
+                    .iter()
+                    .position(|(_, present)| *present)
+                    .map(|idx| idx as u32),
+            ),
+        };
+
+        let transfer_idx = families
+            .iter()
+            .enumerate()
+            .position(|(idx, (flags, present))| {
+                Some(idx as u32) != graphics_idx
+                    && flags.contains(vk::QueueFlags::TRANSFER)
+                    && *present
+            })
+            .map(|idx| idx as u32);
+
+        Self {
+            graphics_idx,
+            present_idx,
+            transfer_idx,
+        }
+    }
+
+    //The distinct families that access the swapchain images
+    pub fn swapchain_families(&self) -> Vec<u32> {
+        let mut families: Vec<u32> = self.graphics_idx.into_iter().collect();
+        if let Some(present_idx) = self.present_idx {
+            if !families.contains(&present_idx) {
+                families.push(present_idx);
+            }
+        }
+        families
     }
 }
 
 impl VulkanContext {
+    //The buffer is destroyed again if no memory can be bound to it
     pub fn allocate_buffer(
         &self,
         buffer_info: &vk::BufferCreateInfo,
         location: gpu_allocator::MemoryLocation,
-    ) -> (vk::Buffer, Allocation) {
-        let buffer = unsafe { self.device.create_buffer(&buffer_info, None) }.unwrap();
+    ) -> Result<(vk::Buffer, Allocation), AllocationError> {
+        let buffer = unsafe { self.device.create_buffer(buffer_info, None) }?;
         let requirements = unsafe { self.device.get_buffer_memory_requirements(buffer) };
         //TODO: Find better names...
         let allocation_info = gpu_allocator::vulkan::AllocationCreateDesc {
@@ -144,19 +372,25 @@ impl VulkanContext {
             allocation_scheme: AllocationScheme::GpuAllocatorManaged,
         };
 
-        let mut allocator = self.allocator.borrow_mut();
-        let allocation = allocator.allocate(&allocation_info).unwrap();
+        let mut allocator = self.allocator.lock().unwrap();
+        let allocation = allocated_or_release(allocator.allocate(&allocation_info), || unsafe {
+            self.device.destroy_buffer(buffer, None)
+        })?;
 
-        unsafe {
+        let bound = unsafe {
             self.device
                 .bind_buffer_memory(buffer, allocation.memory(), allocation.offset())
-                .unwrap()
         };
-        (buffer, allocation)
+        if let Err(err) = bound {
+            allocator.free(allocation).unwrap();
+            unsafe { self.device.destroy_buffer(buffer, None) };
+            return Err(err.into());
+        }
+        Ok((buffer, allocation))
     }
 
     pub fn free_buffer(&self, buffer: vk::Buffer, allocation: Allocation) {
-        let mut allocator = self.allocator.borrow_mut();
+        let mut allocator = self.allocator.lock().unwrap();
         allocator.free(allocation).unwrap();
         unsafe { self.device.destroy_buffer(buffer, None) };
     }
@@ -166,12 +400,13 @@ impl VulkanContext {
         allocation.mapped_ptr().unwrap().cast().as_ptr()
     }
 
+    //The image is destroyed again if no memory can be bound to it
     pub fn create_image(
         &self,
         image_create_info: vk::ImageCreateInfo,
         location: gpu_allocator::MemoryLocation,
-    ) -> (vk::Image, Allocation) {
-        let image = unsafe { self.device.create_image(&image_create_info, None) }.unwrap();
+    ) -> Result<(vk::Image, Allocation), AllocationError> {
+        let image = unsafe { self.device.create_image(&image_create_info, None) }?;
         let requirements = unsafe { self.device.get_image_memory_requirements(image) };
         let allocation_info = gpu_allocator::vulkan::AllocationCreateDesc {
             name: "Image Allocation",
@@ -181,32 +416,51 @@ impl VulkanContext {
             allocation_scheme: AllocationScheme::GpuAllocatorManaged,
         };
 
-        let mut allocator = self.allocator.borrow_mut();
-        let allocation = allocator.allocate(&allocation_info).unwrap();
+        let mut allocator = self.allocator.lock().unwrap();
+        let allocation = allocated_or_release(allocator.allocate(&allocation_info), || unsafe {
+            self.device.destroy_image(image, None)
+        })?;
 
-        unsafe {
+        let bound = unsafe {
             self.device
                 .bind_image_memory(image, allocation.memory(), allocation.offset())
-                .unwrap();
+        };
+        if let Err(err) = bound {
+            allocator.free(allocation).unwrap();
+            unsafe { self.device.destroy_image(image, None) };
+            return Err(err.into());
         }
-        (image, allocation)
+        Ok((image, allocation))
     }
 
     pub fn free_image(&self, image: vk::Image, allocation: Allocation) {
-        let mut allocator = self.allocator.borrow_mut();
+        let mut allocator = self.allocator.lock().unwrap();
         allocator.free(allocation).unwrap();
         unsafe {
             self.device.destroy_image(image, None);
         }
     }
 
+    //Identical sampler configurations share the same vk::Sampler
+    pub fn get_or_create_sampler(&self, config: &SamplerConfig) -> vk::Sampler {
+        self.sampler_cache.get_or_create(config, |config| unsafe {
+            let max_lod_bias = self.device_limits.max_sampler_lod_bias;
+            let create_info = config
+                .create_info()
+                .max_anisotropy(16.0f32.min(self.device_limits.max_sampler_anisotropy))
+                .mip_lod_bias(config.mip_lod_bias.clamp(-max_lod_bias, max_lod_bias));
+            self.device.create_sampler(&create_info, None).unwrap()
+        })
+    }
+
     fn create_instance(
         with_validation_layers: bool,
         app_name: &CStr,
         engine_name: &CStr,
+        api_version: u32,
         display: &dyn HasDisplayHandle,
         entry: &Entry,
-    ) -> Instance {
+    ) -> (Instance, u32) {
         if with_validation_layers && !check_validation_support(entry) {
             panic!("Validation layers requested, but unavailable!");
         }
@@ -222,12 +476,30 @@ impl VulkanContext {
             extension_names_raw.push(ash::ext::debug_utils::NAME.as_ptr());
             instance_layers.push(LAYER_KHRONOS_VALIDATION.as_ptr() as *const i8);
         }
+        let available_version = unsafe { entry.try_enumerate_instance_version() }
+            .unwrap()
+            .unwrap_or(vk::API_VERSION_1_0);
+        let selected_version = select_api_version(api_version, available_version);
+        let requested_version = (
+            vk::api_version_major(api_version),
+            vk::api_version_minor(api_version),
+        );
+        let selected = (
+            vk::api_version_major(selected_version),
+            vk::api_version_minor(selected_version),
+        );
+        if selected != requested_version {
+            println!(
+                "Warning: Vulkan {}.{} requested, but only {}.{} is available!",
+                requested_version.0, requested_version.1, selected.0, selected.1
+            );
+        }
         let app_info = vk::ApplicationInfo::default()
             .application_name(app_name)
             .application_version(0)
             .engine_name(engine_name)
             .engine_version(0)
-            .api_version(vk::make_api_version(0, 1, 2, 0));
+            .api_version(selected_version);
         let create_info = vk::InstanceCreateInfo::default()
             .application_info(&app_info)
             .enabled_extension_names(&extension_names_raw.as_slice())
@@ -239,7 +511,46 @@ impl VulkanContext {
                 .expect("Vk Instance creation error")
         };
 
-        instance
+        (instance, selected_version)
+    }
+
+    pub fn allocate_descriptor_set(
+        &self,
+        desc_layout: vk::DescriptorSetLayout,
+    ) -> (vk::DescriptorSet, vk::DescriptorPool) {
+        self.descriptor_allocator
+            .lock()
+            .unwrap()
+            .allocate(desc_layout)
+    }
+
+    pub fn free_descriptor_set(&self, desc_set: vk::DescriptorSet, desc_pool: vk::DescriptorPool) {
+        self.descriptor_allocator
+            .lock()
+            .unwrap()
+            .free(desc_set, desc_pool);
+    }
+
+    pub fn global_set_layout(&self) -> vk::DescriptorSetLayout {
+        self.global_set_layout
+    }
+
+    //Only holds the global set, used to bind it before any material pipeline is bound
+    pub fn global_pipeline_layout(&self) -> vk::PipelineLayout {
+        self.global_pipeline_layout
+    }
+
+    pub fn device_limits(&self) -> DeviceLimits {
+        self.device_limits
+    }
+
+    pub fn device_features(&self) -> DeviceFeatures {
+        self.device_features
+    }
+
+    //True when the device was created with the Vulkan 1.3 dynamic_rendering feature enabled
+    pub fn supports_dynamic_rendering(&self) -> bool {
+        self.dynamic_rendering
     }
 
     //https://vulkan-tutorial.com/Depth_buffering
@@ -272,40 +583,108 @@ impl VulkanContext {
         format.expect("No acceptable format found!")
     }
 
-    pub fn find_depth_format(&self) -> vk::Format {
-        let candidates = vec![
-            vk::Format::D32_SFLOAT_S8_UINT,
-            vk::Format::D32_SFLOAT,
-            vk::Format::D24_UNORM_S8_UINT,
-        ];
+    //Stencil-free formats are preferred when no stencil is used, the combined formats are fallbacks
+    pub fn depth_format_candidates(with_stencil: bool) -> Vec<vk::Format> {
+        if with_stencil {
+            vec![
+                vk::Format::D32_SFLOAT_S8_UINT,
+                vk::Format::D24_UNORM_S8_UINT,
+            ]
+        } else {
+            vec![
+                vk::Format::D32_SFLOAT,
+                vk::Format::D32_SFLOAT_S8_UINT,
+                vk::Format::D24_UNORM_S8_UINT,
+            ]
+        }
+    }
+
+    pub fn find_depth_format(&self, with_stencil: bool) -> vk::Format {
+        let candidates = Self::depth_format_candidates(with_stencil);
         let tiling = vk::ImageTiling::OPTIMAL;
         let features = vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT;
         self.find_supported_format(candidates, tiling, features)
     }
 
+    pub(crate) fn pipeline_created(&self) {
+        self.live_pipelines.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn pipeline_destroyed(&self) {
+        self.live_pipelines.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    //Surface for an additional window. The device was picked for the first window, so
+    //the present family has to be able to present to this one as well
+    pub fn create_surface(
+        &self,
+        display: &dyn HasDisplayHandle,
+        window: &dyn HasWindowHandle,
+    ) -> vk::SurfaceKHR {
+        let surface = unsafe {
+            ash_window::create_surface(
+                &self.entry,
+                &self.instance,
+                display.display_handle().unwrap().as_raw(),
+                window.window_handle().unwrap().as_raw(),
+                None,
+            )
+        }
+        .unwrap();
+        let present_idx = self.queue_family_indices.present_idx.unwrap();
+        let supported = unsafe {
+            self.surface_loader.get_physical_device_surface_support(
+                self.physical_device,
+                present_idx,
+                surface,
+            )
+        }
+        .unwrap_or(false);
+        if !supported {
+            unsafe {
+                self.surface_loader.destroy_surface(surface, None);
+            }
+            panic!("The present queue can't present to the new window's surface");
+        }
+        surface
+    }
+
     pub fn pre_destroy(&self) {
         unsafe {
             self.device.device_wait_idle().unwrap();
         }
     }
 
-    //TODO: Make a per-thread command pool and queue for upload purposes!
-    pub fn begin_single_time_commands(&self) -> super::CommandBuffer {
-        let command_buffer = super::CommandBuffer::new(&self.device, &self.gfx_cmdpool);
+    //Blocks while another thread is recording single-time commands
+    pub fn begin_single_time_commands(&self) -> SingleTimeCommands<'_> {
+        let pool = self.upload_cmdpool.lock().unwrap();
+        let command_buffer = super::CommandBuffer::new(&self.device, &pool);
         command_buffer.begin_single_time_command();
-        command_buffer
+        SingleTimeCommands {
+            command_buffer,
+            _pool: pool,
+        }
     }
 
-    pub fn end_single_time_commands(&self, command_buffer: super::CommandBuffer) {
-        command_buffer.end_single_time_command();
-        let command_buffers = vec![&command_buffer];
-        //TODO: This cannot be used by multiple frames at once,
-        //ensure that we're either using another queue/commandpool, or
-        //that we are doing this in a locked manner
+    //Waits on a fence rather than the queue, so the queue is only locked for the submit
+    pub fn end_single_time_commands(&self, commands: SingleTimeCommands<'_>) {
+        commands.end_single_time_command();
+        let fence = unsafe {
+            self.device
+                .create_fence(&vk::FenceCreateInfo::default(), None)
+                .unwrap()
+        };
         self.gfx_queue
-            .submit(&command_buffers, &[], &[], vk::Fence::null());
-        self.gfx_queue.wait_idle();
-        command_buffer.return_to_pool();
+            .lock()
+            .unwrap()
+            .submit(&[&*commands], &[], &[], fence);
+        unsafe {
+            self.device
+                .wait_for_fences(&[fence], true, u64::MAX)
+                .unwrap();
+            self.device.destroy_fence(fence, None);
+        }
+        commands.return_to_pool();
     }
 
     pub fn init(
@@ -314,12 +693,18 @@ impl VulkanContext {
         with_validation_layers: bool,
         app_name: CString,
         engine_name: CString,
+        api_version: u32,
     ) -> Self {
+        #[cfg(not(debug_assertions))]
+        if with_validation_layers {
+            println!("Warning: validation layers requested in a release build, expect a large performance cost!");
+        }
         let entry = unsafe { Entry::load() }.unwrap();
-        let instance = Self::create_instance(
+        let (instance, instance_version) = Self::create_instance(
             with_validation_layers,
             &app_name,
             &engine_name,
+            api_version,
             display,
             &entry,
         );
@@ -340,6 +725,17 @@ impl VulkanContext {
         let physical_device =
             unsafe { pick_physical_device(&instance, &surface_loader, surface) }.unwrap();
 
+        let dynamic_rendering =
+            unsafe { supports_dynamic_rendering(&instance, physical_device, instance_version) };
+        let (device_limits, device_features) = unsafe {
+            let properties = instance.get_physical_device_properties(physical_device);
+            let features = instance.get_physical_device_features(physical_device);
+            (
+                DeviceLimits::from(&properties.limits),
+                DeviceFeatures::from(&features),
+            )
+        };
+
         let queue_indices = QueueFamilyIndices::find_queue_families(
             &instance,
             &surface_loader,
@@ -347,16 +743,28 @@ impl VulkanContext {
             physical_device,
         );
 
-        let queue_create_infos = vec![
+        let graphics_queue_idx = queue_indices.graphics_idx.unwrap();
+        let present_queue_idx = queue_indices.present_idx.unwrap();
+        let mut queue_create_infos = vec![
             vk::DeviceQueueCreateInfo::default()
-                .queue_family_index(queue_indices.graphics_idx.unwrap())
+                .queue_family_index(graphics_queue_idx)
                 .queue_priorities(&[1.0]),
             // vk::DeviceQueueCreateInfo::builder()
             //     .queue_family_index(queue_indices.transfer_idx.unwrap())
             //     .queue_priorities(&[0.5])
             //     .build(),
         ];
-        let graphics_queue_idx = queue_indices.graphics_idx.unwrap();
+        if present_queue_idx != graphics_queue_idx {
+            println!(
+                "Presenting from a separate queue family: {}",
+                present_queue_idx
+            );
+            queue_create_infos.push(
+                vk::DeviceQueueCreateInfo::default()
+                    .queue_family_index(present_queue_idx)
+                    .queue_priorities(&[1.0]),
+            );
+        }
         let transfer_queue_idx = 0; //queue_indices.transfer_idx.unwrap();
 
         let device = create_device(
@@ -364,14 +772,19 @@ impl VulkanContext {
             physical_device,
             queue_create_infos,
             with_validation_layers,
+            dynamic_rendering,
+            device_features,
         );
 
         let swapchain_loader = Arc::new(SwapchainDevice::new(&instance, &device));
 
         let graphics_queue = unsafe { device.get_device_queue(graphics_queue_idx, 0) };
+        let present_queue = unsafe { device.get_device_queue(present_queue_idx, 0) };
 
-        let gfx_queue = super::Queue::new(device.clone(), graphics_queue_idx, 0);
-        let gfx_cmdpool = super::CommandPool::new(device.clone(), graphics_queue_idx);
+        let gfx_queue = Mutex::new(super::Queue::new(device.clone(), graphics_queue_idx, 0));
+        let gfx_cmdpool = Mutex::new(super::CommandPool::new(device.clone(), graphics_queue_idx));
+        let upload_cmdpool =
+            Mutex::new(super::CommandPool::new(device.clone(), graphics_queue_idx));
 
         let transfer_queue = unsafe { device.get_device_queue(transfer_queue_idx, 0) };
         let create_info = vk::CommandPoolCreateInfo::default()
@@ -393,7 +806,16 @@ impl VulkanContext {
             allocation_sizes: AllocationSizes::default(),
         };
 
-        let allocator = ManuallyDrop::new(RefCell::new(Allocator::new(&create_info).unwrap()));
+        let allocator = ManuallyDrop::new(Mutex::new(Allocator::new(&create_info).unwrap()));
+        let descriptor_allocator = Mutex::new(DescriptorAllocator::new(
+            device.clone(),
+            DEFAULT_SETS_PER_POOL,
+        ));
+        let global_set_layout = create_global_set_layout(&device);
+        let global_set_layouts = [global_set_layout];
+        let create_info = vk::PipelineLayoutCreateInfo::default().set_layouts(&global_set_layouts);
+        let global_pipeline_layout =
+            unsafe { device.create_pipeline_layout(&create_info, None) }.unwrap();
 
         Self {
             entry,
@@ -405,10 +827,21 @@ impl VulkanContext {
             allocator,
             surface,
             graphics_queue,
+            present_queue,
+            queue_family_indices: queue_indices,
             gfx_queue,
             gfx_cmdpool,
+            upload_cmdpool,
             transfer_command_pool,
             transfer_queue,
+            sampler_cache: SamplerCache::new(),
+            descriptor_allocator,
+            global_set_layout,
+            global_pipeline_layout,
+            live_pipelines: AtomicUsize::new(0),
+            device_limits,
+            device_features,
+            dynamic_rendering,
             debug_utils_loader,
             debug_callback,
         }
@@ -416,12 +849,29 @@ impl VulkanContext {
 }
 impl Drop for VulkanContext {
     fn drop(&mut self) {
+        //A pipeline that was dropped without destroy() leaks its Vulkan objects
+        if !std::thread::panicking() {
+            debug_assert_eq!(
+                *self.live_pipelines.get_mut(),
+                0,
+                "RenderPipelines have to be destroyed before the context"
+            );
+        }
         unsafe {
             self.device.device_wait_idle().unwrap();
 
+            self.descriptor_allocator.get_mut().unwrap().destroy();
+            self.device
+                .destroy_pipeline_layout(self.global_pipeline_layout, None);
+            self.device
+                .destroy_descriptor_set_layout(self.global_set_layout, None);
+            for sampler in self.sampler_cache.drain() {
+                self.device.destroy_sampler(sampler, None);
+            }
             self.device
                 .destroy_command_pool(self.transfer_command_pool, None);
-            self.gfx_cmdpool.destroy();
+            self.gfx_cmdpool.get_mut().unwrap().destroy();
+            self.upload_cmdpool.get_mut().unwrap().destroy();
             ManuallyDrop::drop(&mut self.allocator);
             self.device.destroy_device(None);
             self.surface_loader.destroy_surface(self.surface, None);
@@ -463,13 +913,32 @@ impl VulkanFrameCtx {
         unsafe { device.create_image_view(&create_info, None) }.unwrap()
     }
 
-    pub fn init(context: &Arc<VulkanContext>) -> Self {
+    pub fn init(context: &Arc<VulkanContext>, swapchain_config: &SwapchainConfig) -> Self {
+        Self::init_with_surface(context, None, swapchain_config)
+    }
+
+    //Takes ownership of the surface, see VulkanContext::create_surface
+    pub fn init_for_surface(
+        context: &Arc<VulkanContext>,
+        surface: vk::SurfaceKHR,
+        swapchain_config: &SwapchainConfig,
+    ) -> Self {
+        Self::init_with_surface(context, Some(surface), swapchain_config)
+    }
+
+    fn init_with_surface(
+        context: &Arc<VulkanContext>,
+        owned_surface: Option<vk::SurfaceKHR>,
+        swapchain_config: &SwapchainConfig,
+    ) -> Self {
         let swapchain = super::Swapchain::create_swapchain(
             context.swapchain_loader.clone(),
             &context.surface_loader,
             context.physical_device,
-            context.surface,
+            owned_surface.unwrap_or(context.surface),
             None,
+            swapchain_config,
+            &context.queue_family_indices.swapchain_families(),
         );
 
         let swapchain_images = swapchain.get_swapchain_images();
@@ -490,7 +959,12 @@ impl VulkanFrameCtx {
 
         let command_buffers = context
             .gfx_cmdpool
-            .create_command_buffers(swapchain_image_views.len() as _);
+            .lock()
+            .unwrap()
+            .create_command_buffers(swapchain_image_views.len() as _)
+            .into_iter()
+            .map(Rc::new)
+            .collect();
 
         let ctx = Self {
             context: context.clone(),
@@ -499,20 +973,31 @@ impl VulkanFrameCtx {
             swapchain_images,
             depth_render_texture,
             command_buffers,
+            swapchain_config: swapchain_config.clone(),
+            owned_surface,
         };
         ctx
     }
 
+    pub fn surface(&self) -> vk::SurfaceKHR {
+        self.owned_surface.unwrap_or(self.context.surface)
+    }
+
+    //The device has to be idle. The old swapchain stays alive until the new one has been
+    //created from it, then it and its image views are destroyed
     pub fn recreate_swapchain(&mut self) {
         let swapchain = super::Swapchain::create_swapchain(
             self.context.swapchain_loader.clone(),
             &self.context.surface_loader,
             self.context.physical_device,
-            self.context.surface,
+            self.surface(),
             Some(self.swapchain.swapchain),
+            &self.swapchain_config,
+            &self.context.queue_family_indices.swapchain_families(),
         );
-        self.destroy();
-        self.swapchain = swapchain;
+        let mut old_swapchain = std::mem::replace(&mut self.swapchain, swapchain);
+        self.destroy_image_views();
+        old_swapchain.destroy();
 
         self.swapchain_images = self.swapchain.get_swapchain_images();
 
@@ -530,15 +1015,42 @@ impl VulkanFrameCtx {
             .collect();
         self.depth_render_texture =
             create_depth_render_texture(self.context.clone(), self.swapchain.get_extent());
+        //The surface may hand out more images than before
+        let missing = self
+            .swapchain_images
+            .len()
+            .saturating_sub(self.command_buffers.len());
+        if missing > 0 {
+            let extra = self
+                .context
+                .gfx_cmdpool
+                .lock()
+                .unwrap()
+                .create_command_buffers(missing as _);
+            self.command_buffers.extend(extra.into_iter().map(Rc::new));
+        }
     }
 
-    pub fn destroy(&mut self) {
-        unsafe {
-            for &image_view in &self.swapchain_image_views {
+    fn destroy_image_views(&mut self) {
+        for image_view in self.swapchain_image_views.drain(..) {
+            unsafe {
                 self.context.device.destroy_image_view(image_view, None);
             }
-            self.swapchain.destroy();
-            // self.depth_render_texture.destroy();
+        }
+    }
+
+    pub fn destroy(&mut self) {
+        self.destroy_image_views();
+        self.swapchain.destroy();
+        // self.depth_render_texture.destroy();
+    }
+
+    //Only does anything for an additional window, the swapchain has to be destroyed first
+    pub fn destroy_surface(&mut self) {
+        if let Some(surface) = self.owned_surface.take() {
+            unsafe {
+                self.context.surface_loader.destroy_surface(surface, None);
+            }
         }
     }
 }
@@ -592,40 +1104,36 @@ unsafe fn is_physical_device_suitable(
 }
 
 fn create_depth_render_texture(context: Arc<VulkanContext>, extent: vk::Extent2D) -> RenderTexture {
-    let depth_format = context.find_depth_format();
-    let extent_3d = vk::Extent3D {
-        width: extent.width,
-        height: extent.height,
-        depth: 1,
-    };
-    let create_info = vk::ImageCreateInfo::default()
-        .image_type(vk::ImageType::TYPE_2D)
-        .mip_levels(1)
-        .array_layers(1)
-        .format(depth_format)
-        .extent(extent_3d)
-        .tiling(vk::ImageTiling::OPTIMAL)
-        .samples(vk::SampleCountFlags::TYPE_1)
-        .usage(vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT);
+    let depth_format = context.find_depth_format(false);
 
     //https://vulkan-tutorial.com/Depth_buffering
-    let (depth_image, image_memory) =
-        context.create_image(create_info, gpu_allocator::MemoryLocation::GpuOnly);
-
-    let image_view = VulkanFrameCtx::create_image_view(
-        &context.device,
-        depth_image,
+    //Without a depth buffer there is nothing to render into, so this stays fatal
+    RenderTexture::new(
+        context,
+        extent,
         depth_format,
+        vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
         vk::ImageAspectFlags::DEPTH,
-    );
-    RenderTexture {
-        extent,
-        image_view,
-        image: depth_image,
-        image_memory: Some(image_memory),
-        format: depth_format,
-        context,
+    )
+    .expect("Failed to allocate the depth buffer")
+}
+
+//Dynamic rendering is only used through core 1.3, both the instance and device need it
+unsafe fn supports_dynamic_rendering(
+    instance: &Instance,
+    physical_device: vk::PhysicalDevice,
+    instance_version: u32,
+) -> bool {
+    let device_version = instance
+        .get_physical_device_properties(physical_device)
+        .api_version;
+    if instance_version < vk::API_VERSION_1_3 || device_version < vk::API_VERSION_1_3 {
+        return false;
     }
+    let mut vulkan13_features = vk::PhysicalDeviceVulkan13Features::default();
+    let mut features2 = vk::PhysicalDeviceFeatures2::default().push_next(&mut vulkan13_features);
+    instance.get_physical_device_features2(physical_device, &mut features2);
+    vulkan13_features.dynamic_rendering == vk::TRUE
 }
 
 fn create_device(
@@ -633,6 +1141,8 @@ fn create_device(
     physical_device: vk::PhysicalDevice,
     queue_create_infos: Vec<vk::DeviceQueueCreateInfo>,
     with_validation_layers: bool,
+    dynamic_rendering: bool,
+    device_features: DeviceFeatures,
 ) -> Device {
     let device_extensions = [ash::khr::swapchain::NAME.as_ptr()];
     let mut device_layers = vec![];
@@ -641,16 +1151,24 @@ fn create_device(
     }
 
     // https://vulkan-tutorial.com/Drawing_a_triangle/Setup/Logical_device_and_queues
+    //Indirect draws of more than one command need multiDrawIndirect, enabled when available
     let features = vk::PhysicalDeviceFeatures {
         sampler_anisotropy: 1,
+        multi_draw_indirect: device_features.multi_draw_indirect.into(),
         ..Default::default()
     };
 
-    let create_info = vk::DeviceCreateInfo::default()
+    let mut vulkan13_features =
+        vk::PhysicalDeviceVulkan13Features::default().dynamic_rendering(true);
+
+    let mut create_info = vk::DeviceCreateInfo::default()
         .enabled_extension_names(&device_extensions)
         .enabled_layer_names(&device_layers)
         .queue_create_infos(&queue_create_infos)
         .enabled_features(&features);
+    if dynamic_rendering {
+        create_info = create_info.push_next(&mut vulkan13_features);
+    }
     let device = unsafe {
         instance
             .create_device(physical_device, &create_info, None)
@@ -714,3 +1232,193 @@ fn check_validation_support(entry: &Entry) -> bool {
 
     return false;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ash::vk::Handle;
+    use std::thread;
+
+    #[test]
+    fn api_version_is_kept_when_available() {
+        let requested = vk::make_api_version(0, 1, 2, 0);
+        assert_eq!(
+            select_api_version(requested, vk::make_api_version(0, 1, 3, 250)),
+            requested
+        );
+    }
+
+    #[test]
+    fn api_version_falls_back_to_loader() {
+        let requested = vk::make_api_version(0, 1, 3, 0);
+        assert_eq!(
+            select_api_version(requested, vk::API_VERSION_1_0),
+            vk::API_VERSION_1_0
+        );
+        assert_eq!(
+            select_api_version(requested, vk::make_api_version(0, 1, 2, 189)),
+            vk::API_VERSION_1_2
+        );
+    }
+
+    #[test]
+    fn api_version_ignores_patch() {
+        let requested = vk::make_api_version(0, 1, 2, 170);
+        assert_eq!(
+            select_api_version(requested, vk::make_api_version(0, 1, 2, 3)),
+            vk::API_VERSION_1_2
+        );
+    }
+
+    #[test]
+    fn depth_formats_without_stencil_prefer_depth_only() {
+        assert_eq!(
+            VulkanContext::depth_format_candidates(false),
+            vec![
+                vk::Format::D32_SFLOAT,
+                vk::Format::D32_SFLOAT_S8_UINT,
+                vk::Format::D24_UNORM_S8_UINT
+            ]
+        );
+    }
+
+    #[test]
+    fn depth_formats_with_stencil_all_have_stencil() {
+        assert_eq!(
+            VulkanContext::depth_format_candidates(true),
+            vec![
+                vk::Format::D32_SFLOAT_S8_UINT,
+                vk::Format::D24_UNORM_S8_UINT
+            ]
+        );
+    }
+
+    #[test]
+    fn context_is_send_sync() {
+        fn is_send_sync<T: Send + Sync>() {}
+        is_send_sync::<VulkanContext>();
+    }
+
+    const GRAPHICS: vk::QueueFlags = vk::QueueFlags::from_raw(
+        vk::QueueFlags::GRAPHICS.as_raw()
+            | vk::QueueFlags::COMPUTE.as_raw()
+            | vk::QueueFlags::TRANSFER.as_raw(),
+    );
+
+    #[test]
+    fn queue_families_prefer_graphics_with_present() {
+        let families = [
+            (GRAPHICS, false),
+            (vk::QueueFlags::TRANSFER, true),
+            (GRAPHICS, true),
+        ];
+        let indices = QueueFamilyIndices::select(&families);
+        assert_eq!(indices.graphics_idx, Some(2));
+        assert_eq!(indices.present_idx, Some(2));
+        assert_eq!(indices.transfer_idx, Some(1));
+        assert_eq!(indices.swapchain_families(), vec![2]);
+    }
+
+    #[test]
+    fn queue_families_separate_present() {
+        let families = [
+            (vk::QueueFlags::TRANSFER, false),
+            (GRAPHICS, false),
+            (vk::QueueFlags::COMPUTE, true),
+        ];
+        let indices = QueueFamilyIndices::select(&families);
+        assert_eq!(indices.graphics_idx, Some(1));
+        assert_eq!(indices.present_idx, Some(2));
+        assert_eq!(indices.swapchain_families(), vec![1, 2]);
+    }
+
+    #[test]
+    fn queue_families_without_present() {
+        let families = [(vk::QueueFlags::TRANSFER, false), (GRAPHICS, false)];
+        let indices = QueueFamilyIndices::select(&families);
+        assert_eq!(indices.graphics_idx, Some(1));
+        assert_eq!(indices.present_idx, None);
+        assert_eq!(indices.transfer_idx, None);
+        assert_eq!(indices.swapchain_families(), vec![1]);
+    }
+
+    #[test]
+    fn queue_families_without_graphics() {
+        let families = [(vk::QueueFlags::COMPUTE, true)];
+        let indices = QueueFamilyIndices::select(&families);
+        assert_eq!(indices.graphics_idx, None);
+        assert_eq!(indices.present_idx, Some(0));
+        assert_eq!(QueueFamilyIndices::select(&[]).swapchain_families(), vec![]);
+    }
+
+    #[test]
+    fn sampler_cache_creates_once_across_threads() {
+        let cache = SamplerCache::new();
+        let created = AtomicUsize::new(0);
+        let config = SamplerConfig::default();
+        let samplers: Vec<Vec<vk::Sampler>> = thread::scope(|scope| {
+            let workers: Vec<_> = (0..2)
+                .map(|_| {
+                    scope.spawn(|| {
+                        (0..100)
+                            .map(|_| {
+                                cache.get_or_create(&config, |_| {
+                                    let id = created.fetch_add(1, Ordering::SeqCst) as u64;
+                                    vk::Sampler::from_raw(id + 1)
+                                })
+                            })
+                            .collect()
+                    })
+                })
+                .collect();
+            workers.into_iter().map(|w| w.join().unwrap()).collect()
+        });
+        assert_eq!(created.load(Ordering::SeqCst), 1);
+        assert!(samplers
+            .iter()
+            .flatten()
+            .all(|sampler| *sampler == samplers[0][0]));
+    }
+
+    #[test]
+    fn sampler_cache_keeps_configs_apart() {
+        let mut cache = SamplerCache::new();
+        let nearest = SamplerConfig {
+            mag_filter: vk::Filter::NEAREST,
+            ..Default::default()
+        };
+        let first = cache.get_or_create(&SamplerConfig::default(), |_| vk::Sampler::from_raw(1));
+        let second = cache.get_or_create(&nearest, |_| vk::Sampler::from_raw(2));
+        assert_ne!(first, second);
+        assert_eq!(cache.drain().len(), 2);
+    }
+
+    #[test]
+    fn allocator_out_of_memory_reaches_the_caller() {
+        let mut released = false;
+        let result: Result<(), _> =
+            allocated_or_release(Err(gpu_allocator::AllocationError::OutOfMemory), || {
+                released = true
+            });
+        assert_eq!(result, Err(AllocationError::OutOfMemory));
+        assert!(released);
+    }
+
+    #[test]
+    fn driver_out_of_memory_reaches_the_caller() {
+        let result: Result<(), _> =
+            allocated_or_release(Err(vk::Result::ERROR_OUT_OF_DEVICE_MEMORY), || {});
+        assert_eq!(result, Err(AllocationError::OutOfMemory));
+        let result: Result<(), _> =
+            allocated_or_release(Err(vk::Result::ERROR_INITIALIZATION_FAILED), || {});
+        assert!(matches!(result, Err(AllocationError::Failed(_))));
+    }
+
+    #[test]
+    fn successful_allocations_are_not_released() {
+        let mut released = false;
+        let result = allocated_or_release(Ok::<_, vk::Result>(7), || released = true);
+        assert_eq!(result, Ok(7));
+        assert!(!released);
+    }
+}
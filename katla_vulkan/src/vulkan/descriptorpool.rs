@@ -0,0 +1,199 @@
+use ash::{vk, Device};
+
+pub const DEFAULT_SETS_PER_POOL: u32 = 64;
+
+/// Hands out descriptor sets from a list of larger pools, adding a pool when the
+/// current ones are exhausted. Pools are only destroyed together, in `destroy`.
+pub struct DescriptorAllocator {
+    device: Device,
+    pools: Vec<vk::DescriptorPool>,
+    usage: PoolUsage,
+}
+
+//Live set count of every pool, so full pools are skipped without asking Vulkan
+#[derive(Debug)]
+struct PoolUsage {
+    live_sets: Vec<u32>,
+    sets_per_pool: u32,
+}
+
+impl PoolUsage {
+    fn new(sets_per_pool: u32) -> Self {
+        Self {
+            live_sets: vec![],
+            sets_per_pool: sets_per_pool.max(1),
+        }
+    }
+
+    //Pools that may still have room, newest first. A pool can still turn out to be
+    //fragmented, so the caller has to try the next one then
+    fn candidates(&self) -> Vec<usize> {
+        (0..self.live_sets.len())
+            .rev()
+            .filter(|&pool| self.live_sets[pool] < self.sets_per_pool)
+            .collect()
+    }
+
+    fn add_pool(&mut self) -> usize {
+        self.live_sets.push(0);
+        self.live_sets.len() - 1
+    }
+
+    fn allocated(&mut self, pool: usize) {
+        self.live_sets[pool] += 1;
+    }
+
+    fn freed(&mut self, pool: usize) {
+        self.live_sets[pool] = self.live_sets[pool].saturating_sub(1);
+    }
+}
+
+//Sized for the largest layouts: the global sets take two uniform buffers, material sets
+//one uniform buffer and one image sampler
+fn pool_sizes(sets_per_pool: u32) -> [vk::DescriptorPoolSize; 2] {
+    [
+        vk::DescriptorPoolSize::default()
+            .descriptor_count(sets_per_pool * 2)
+            .ty(vk::DescriptorType::UNIFORM_BUFFER),
+        vk::DescriptorPoolSize::default()
+            .descriptor_count(sets_per_pool)
+            .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER),
+    ]
+}
+
+impl DescriptorAllocator {
+    pub fn new(device: Device, sets_per_pool: u32) -> Self {
+        Self {
+            device,
+            pools: vec![],
+            usage: PoolUsage::new(sets_per_pool),
+        }
+    }
+
+    fn create_pool(&self) -> vk::DescriptorPool {
+        let desc_pool_sizes = &pool_sizes(self.usage.sets_per_pool);
+        let desc_pool_info = vk::DescriptorPoolCreateInfo::default()
+            .flags(vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET)
+            .pool_sizes(desc_pool_sizes)
+            .max_sets(self.usage.sets_per_pool);
+        unsafe { self.device.create_descriptor_pool(&desc_pool_info, None) }.unwrap()
+    }
+
+    //Returns the set together with the pool it has to be freed to
+    pub fn allocate(
+        &mut self,
+        desc_layout: vk::DescriptorSetLayout,
+    ) -> (vk::DescriptorSet, vk::DescriptorPool) {
+        let desc_layouts = &[desc_layout];
+        for index in self.usage.candidates() {
+            let pool = self.pools[index];
+            let desc_info = vk::DescriptorSetAllocateInfo::default()
+                .descriptor_pool(pool)
+                .set_layouts(desc_layouts);
+            match unsafe { self.device.allocate_descriptor_sets(&desc_info) } {
+                Ok(sets) => {
+                    self.usage.allocated(index);
+                    return (sets[0], pool);
+                }
+                Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY)
+                | Err(vk::Result::ERROR_FRAGMENTED_POOL) => continue,
+                Err(err) => panic!("Failed to allocate descriptor set: {:?}", err),
+            }
+        }
+
+        let pool = self.create_pool();
+        self.pools.push(pool);
+        let index = self.usage.add_pool();
+        let desc_info = vk::DescriptorSetAllocateInfo::default()
+            .descriptor_pool(pool)
+            .set_layouts(desc_layouts);
+        let set = unsafe { self.device.allocate_descriptor_sets(&desc_info) }.unwrap()[0];
+        self.usage.allocated(index);
+        (set, pool)
+    }
+
+    pub fn free(&mut self, desc_set: vk::DescriptorSet, desc_pool: vk::DescriptorPool) {
+        unsafe {
+            self.device
+                .free_descriptor_sets(desc_pool, &[desc_set])
+                .unwrap();
+        }
+        if let Some(index) = self.pools.iter().position(|&pool| pool == desc_pool) {
+            self.usage.freed(index);
+        }
+    }
+
+    pub fn num_pools(&self) -> usize {
+        self.pools.len()
+    }
+
+    pub fn destroy(&mut self) {
+        for pool in self.pools.drain(..) {
+            unsafe {
+                self.device.destroy_descriptor_pool(pool, None);
+            }
+        }
+        self.usage.live_sets.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exhausted_pool_needs_a_new_one() {
+        let mut usage = PoolUsage::new(2);
+        assert!(usage.candidates().is_empty());
+
+        let first = usage.add_pool();
+        usage.allocated(first);
+        assert_eq!(usage.candidates(), vec![first]);
+        usage.allocated(first);
+        assert!(usage.candidates().is_empty());
+
+        let second = usage.add_pool();
+        assert_eq!(second, 1);
+        assert_eq!(usage.candidates(), vec![second]);
+    }
+
+    #[test]
+    fn freed_sets_make_room_again() {
+        let mut usage = PoolUsage::new(1);
+        let first = usage.add_pool();
+        usage.allocated(first);
+        let second = usage.add_pool();
+        usage.allocated(second);
+        assert!(usage.candidates().is_empty());
+
+        usage.freed(first);
+        assert_eq!(usage.candidates(), vec![first]);
+        usage.freed(second);
+        //Newest pools are tried first
+        assert_eq!(usage.candidates(), vec![second, first]);
+        usage.freed(second);
+        assert_eq!(usage.live_sets, vec![0, 0]);
+    }
+
+    #[test]
+    fn zero_sets_per_pool_still_allocates() {
+        let mut usage = PoolUsage::new(0);
+        let pool = usage.add_pool();
+        assert_eq!(usage.candidates(), vec![pool]);
+    }
+
+    #[test]
+    fn pool_sizes_cover_every_set() {
+        let sizes = pool_sizes(DEFAULT_SETS_PER_POOL);
+        let types: Vec<_> = sizes.iter().map(|size| size.ty).collect();
+        assert_eq!(
+            types,
+            vec![
+                vk::DescriptorType::UNIFORM_BUFFER,
+                vk::DescriptorType::COMBINED_IMAGE_SAMPLER
+            ]
+        );
+        assert_eq!(sizes[0].descriptor_count, DEFAULT_SETS_PER_POOL * 2);
+        assert_eq!(sizes[1].descriptor_count, DEFAULT_SETS_PER_POOL);
+    }
+}
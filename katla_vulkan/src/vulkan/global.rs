@@ -0,0 +1,179 @@
+use ash::{vk, Device};
+
+use crate::{Buffer, CommandBuffer};
+use std::sync::Arc;
+
+use super::context::{AllocationError, VulkanContext};
+
+//Descriptor set indices shared by every material pipeline
+pub const GLOBAL_SET: u32 = 0;
+pub const MATERIAL_SET: u32 = 1;
+
+//view and proj matrices, then the log depth coefficient and the elapsed time padded to a vec4.
+//Laid out like the Global uniform block of model_pbr.vert
+pub const GLOBAL_UNIFORM_SIZE: usize = 4 * 16 * 2 + 16;
+
+//Lights beyond this are not shaded, the application decides which ones to keep
+pub const MAX_LIGHTS: usize = 8;
+//The light count padded to a vec4, then a position and a color vec4 per light. Laid out
+//like the Lights uniform block of model.frag
+pub const LIGHTS_UNIFORM_SIZE: usize = 16 + 32 * MAX_LIGHTS;
+
+/// Camera matrices, elapsed time and lights, shared by every material. One descriptor set per
+/// frame in flight, bound once per frame at GLOBAL_SET so materials only bind their own set.
+pub struct GlobalUniform {
+    context: Arc<VulkanContext>,
+    descriptors: Vec<GlobalDescriptor>,
+}
+
+struct GlobalDescriptor {
+    desc_set: vk::DescriptorSet,
+    desc_pool: vk::DescriptorPool,
+    buffer: Buffer,
+    lights_buffer: Buffer,
+}
+
+//Every material pipeline layout starts with this set, so it stays bound across pipelines
+pub(crate) fn create_global_set_layout(device: &Device) -> vk::DescriptorSetLayout {
+    let bindings = [
+        vk::DescriptorSetLayoutBinding::default()
+            .binding(0)
+            .descriptor_count(1)
+            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+            .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT),
+        vk::DescriptorSetLayoutBinding::default()
+            .binding(1)
+            .descriptor_count(1)
+            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT),
+    ];
+    let create_info = vk::DescriptorSetLayoutCreateInfo::default().bindings(&bindings);
+    unsafe { device.create_descriptor_set_layout(&create_info, None) }.unwrap()
+}
+
+//Set layouts of a material pipeline, indexed by GLOBAL_SET and MATERIAL_SET
+pub fn material_set_layouts(
+    global_layout: vk::DescriptorSetLayout,
+    material_layout: vk::DescriptorSetLayout,
+) -> [vk::DescriptorSetLayout; 2] {
+    let mut layouts = [vk::DescriptorSetLayout::null(); 2];
+    layouts[GLOBAL_SET as usize] = global_layout;
+    layouts[MATERIAL_SET as usize] = material_layout;
+    layouts
+}
+
+impl GlobalUniform {
+    pub fn new(
+        context: Arc<VulkanContext>,
+        frames_in_flight: usize,
+    ) -> Result<Self, AllocationError> {
+        let mut uniform = Self {
+            context,
+            descriptors: vec![],
+        };
+        for _ in 0..frames_in_flight {
+            //Dropping the partial uniform frees the sets that were already allocated
+            let descriptor = uniform.create_descriptor()?;
+            uniform.descriptors.push(descriptor);
+        }
+        Ok(uniform)
+    }
+
+    fn create_descriptor(&self) -> Result<GlobalDescriptor, AllocationError> {
+        let buffer = self.create_buffer(GLOBAL_UNIFORM_SIZE)?;
+        //No lights until the first update_lights
+        let mut lights_buffer = self.create_buffer(LIGHTS_UNIFORM_SIZE)?;
+        lights_buffer.write(&[0u8; 16]);
+        let (desc_set, desc_pool) = self
+            .context
+            .allocate_descriptor_set(self.context.global_set_layout());
+        let buf_infos = [
+            [vk::DescriptorBufferInfo::default()
+                .buffer(buffer.vk_buffer())
+                .offset(0)
+                .range(GLOBAL_UNIFORM_SIZE as vk::DeviceSize)],
+            [vk::DescriptorBufferInfo::default()
+                .buffer(lights_buffer.vk_buffer())
+                .offset(0)
+                .range(LIGHTS_UNIFORM_SIZE as vk::DeviceSize)],
+        ];
+        let buffer_writes: Vec<_> = buf_infos
+            .iter()
+            .enumerate()
+            .map(|(binding, buf_info)| {
+                vk::WriteDescriptorSet::default()
+                    .dst_set(desc_set)
+                    .dst_binding(binding as u32)
+                    .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                    .buffer_info(buf_info)
+            })
+            .collect();
+        unsafe {
+            self.context
+                .device
+                .update_descriptor_sets(&buffer_writes, &[])
+        };
+        Ok(GlobalDescriptor {
+            desc_set,
+            desc_pool,
+            buffer,
+            lights_buffer,
+        })
+    }
+
+    fn create_buffer(&self, size: usize) -> Result<Buffer, AllocationError> {
+        Buffer::new(
+            self.context.clone(),
+            size as vk::DeviceSize,
+            vk::BufferUsageFlags::UNIFORM_BUFFER,
+            gpu_allocator::MemoryLocation::CpuToGpu,
+        )
+    }
+
+    //The slot's fence has been waited on before the frame is recorded, so the GPU is done with it
+    pub fn update(&mut self, frame_index: usize, data: &[u8]) {
+        debug_assert!(data.len() <= GLOBAL_UNIFORM_SIZE);
+        self.descriptors[frame_index].buffer.write(data);
+    }
+
+    pub fn update_lights(&mut self, frame_index: usize, data: &[u8]) {
+        debug_assert!(data.len() <= LIGHTS_UNIFORM_SIZE);
+        self.descriptors[frame_index].lights_buffer.write(data);
+    }
+
+    //Compatible with every material pipeline layout for set 0, binding a pipeline keeps it bound
+    pub fn bind(&self, command_buffer: &CommandBuffer, frame_index: usize) {
+        command_buffer.bind_descriptor_sets(
+            vk::PipelineBindPoint::GRAPHICS,
+            self.context.global_pipeline_layout(),
+            GLOBAL_SET,
+            &[self.descriptors[frame_index].desc_set],
+        );
+    }
+}
+
+impl Drop for GlobalUniform {
+    fn drop(&mut self) {
+        for descriptor in self.descriptors.drain(..) {
+            self.context
+                .free_descriptor_set(descriptor.desc_set, descriptor.desc_pool);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ash::vk::Handle;
+
+    #[test]
+    fn material_layouts_put_the_global_set_first() {
+        let global = vk::DescriptorSetLayout::from_raw(1);
+        let material = vk::DescriptorSetLayout::from_raw(2);
+        let layouts = material_set_layouts(global, material);
+        assert_eq!(layouts.len(), 2);
+        assert_eq!(GLOBAL_SET, 0);
+        assert_eq!(layouts[GLOBAL_SET as usize], global);
+        assert_eq!(layouts[MATERIAL_SET as usize], material);
+    }
+}
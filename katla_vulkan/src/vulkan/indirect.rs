@@ -0,0 +1,141 @@
+use super::{
+    buffer::Buffer,
+    context::{AllocationError, VulkanContext},
+    CommandBuffer,
+};
+use ash::vk;
+
+use std::sync::Arc;
+
+pub const DRAW_INDEXED_INDIRECT_STRIDE: u32 =
+    std::mem::size_of::<vk::DrawIndexedIndirectCommand>() as u32;
+
+/// Host-visible buffer of vk::DrawIndexedIndirectCommand entries, issued with a single
+/// draw_indexed_indirect call. Commands are collected on the CPU and written with upload,
+/// so the buffer must not be rewritten while a frame reading it is still in flight.
+pub struct DrawIndirectBuffer {
+    buffer: Buffer,
+    capacity: u32,
+    commands: Vec<vk::DrawIndexedIndirectCommand>,
+}
+
+impl DrawIndirectBuffer {
+    pub fn new(context: Arc<VulkanContext>, capacity: u32) -> Result<Self, AllocationError> {
+        let buffer = Buffer::new(
+            context,
+            u64::from(capacity.max(1)) * u64::from(DRAW_INDEXED_INDIRECT_STRIDE),
+            vk::BufferUsageFlags::INDIRECT_BUFFER,
+            gpu_allocator::MemoryLocation::CpuToGpu,
+        )?;
+        Ok(Self {
+            buffer,
+            capacity,
+            commands: vec![],
+        })
+    }
+
+    //Returns false if the buffer is full
+    pub fn push(&mut self, command: vk::DrawIndexedIndirectCommand) -> bool {
+        if self.commands.len() as u32 >= self.capacity {
+            return false;
+        }
+        self.commands.push(command);
+        true
+    }
+
+    pub fn clear(&mut self) {
+        self.commands.clear();
+    }
+
+    pub fn upload(&mut self) {
+        self.buffer.write(&indirect_command_bytes(&self.commands));
+    }
+
+    pub fn draw_count(&self) -> u32 {
+        self.commands.len() as u32
+    }
+
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    pub fn object(&self) -> vk::Buffer {
+        self.buffer.vk_buffer()
+    }
+
+    //Issues every pushed command, the index and vertex buffers have to be bound already.
+    //More than one draw needs the multiDrawIndirect device feature
+    pub fn draw(&self, command_buffer: &CommandBuffer) {
+        if self.commands.is_empty() {
+            return;
+        }
+        command_buffer.draw_indexed_indirect(
+            self.object(),
+            0,
+            self.draw_count(),
+            DRAW_INDEXED_INDIRECT_STRIDE,
+        );
+    }
+}
+
+//The layout the device reads, five tightly packed 32-bit fields per command
+pub fn indirect_command_bytes(commands: &[vk::DrawIndexedIndirectCommand]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(commands.len() * DRAW_INDEXED_INDIRECT_STRIDE as usize);
+    for command in commands {
+        bytes.extend_from_slice(&command.index_count.to_ne_bytes());
+        bytes.extend_from_slice(&command.instance_count.to_ne_bytes());
+        bytes.extend_from_slice(&command.first_index.to_ne_bytes());
+        bytes.extend_from_slice(&command.vertex_offset.to_ne_bytes());
+        bytes.extend_from_slice(&command.first_instance.to_ne_bytes());
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    fn read_u32(bytes: &[u8], field: usize) -> u32 {
+        u32::from_ne_bytes(bytes[field * 4..field * 4 + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn stride_is_five_packed_fields() {
+        assert_eq!(DRAW_INDEXED_INDIRECT_STRIDE, 20);
+        assert!(indirect_command_bytes(&[]).is_empty());
+    }
+
+    #[test]
+    fn commands_are_written_in_field_order() {
+        let commands = [
+            vk::DrawIndexedIndirectCommand {
+                index_count: 36,
+                instance_count: 1,
+                first_index: 0,
+                vertex_offset: 0,
+                first_instance: 0,
+            },
+            vk::DrawIndexedIndirectCommand {
+                index_count: 6,
+                instance_count: 4,
+                first_index: 36,
+                vertex_offset: -8,
+                first_instance: 2,
+            },
+        ];
+        let bytes = indirect_command_bytes(&commands);
+        assert_eq!(
+            bytes.len(),
+            commands.len() * DRAW_INDEXED_INDIRECT_STRIDE as usize
+        );
+
+        let second = &bytes[DRAW_INDEXED_INDIRECT_STRIDE as usize..];
+        assert_eq!(read_u32(&bytes, 0), 36);
+        assert_eq!(read_u32(second, 0), 6);
+        assert_eq!(read_u32(second, 1), 4);
+        assert_eq!(read_u32(second, 2), 36);
+        assert_eq!(read_u32(second, 3) as i32, -8);
+        assert_eq!(read_u32(second, 4), 2);
+    }
+}
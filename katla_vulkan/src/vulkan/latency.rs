@@ -0,0 +1,181 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+pub const DEFAULT_LATENCY_SAMPLES: usize = 100;
+
+/// Rolling statistics in milliseconds over the latest max_samples durations
+pub struct LatencyStats {
+    samples: VecDeque<f64>,
+    max_samples: usize,
+    sum: f64,
+}
+
+/// Per-window frame latency, measured on the CPU around the swapchain calls.
+/// gpu_latency runs from a successful acquire until the frame's fence is seen signaled,
+/// which happens when its frame slot is reused. It is an upper bound on acquire to
+/// rendering done, presentation itself can't be observed without VK_KHR_present_wait.
+/// acquire_wait is how long acquiring blocked, this is where FIFO and MAILBOX differ.
+pub struct FrameLatency {
+    acquire_times: Vec<Option<Instant>>,
+    gpu_latency: LatencyStats,
+    acquire_wait: LatencyStats,
+}
+
+impl LatencyStats {
+    pub fn new(max_samples: usize) -> Self {
+        Self {
+            samples: VecDeque::new(),
+            max_samples: max_samples.max(1),
+            sum: 0.0,
+        }
+    }
+
+    pub fn add_sample(&mut self, sample: Duration) {
+        let sample_ms = sample.as_secs_f64() * 1000.0;
+        self.samples.push_back(sample_ms);
+        self.sum += sample_ms;
+        if self.samples.len() > self.max_samples {
+            if let Some(oldest) = self.samples.pop_front() {
+                self.sum -= oldest;
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    //The following are 0.0 until the first sample
+    pub fn mean_ms(&self) -> f64 {
+        if self.samples.is_empty() {
+            return 0.0;
+        }
+        self.sum / self.samples.len() as f64
+    }
+
+    pub fn min_ms(&self) -> f64 {
+        self.samples.iter().copied().reduce(f64::min).unwrap_or(0.0)
+    }
+
+    pub fn max_ms(&self) -> f64 {
+        self.samples.iter().copied().reduce(f64::max).unwrap_or(0.0)
+    }
+
+    pub fn latest_ms(&self) -> f64 {
+        self.samples.back().copied().unwrap_or(0.0)
+    }
+}
+
+impl FrameLatency {
+    pub fn new(frames_in_flight: usize, max_samples: usize) -> Self {
+        Self {
+            acquire_times: vec![None; frames_in_flight],
+            gpu_latency: LatencyStats::new(max_samples),
+            acquire_wait: LatencyStats::new(max_samples),
+        }
+    }
+
+    //Call once the fence of frame_slot has been waited on
+    pub fn frame_finished(&mut self, frame_slot: usize, now: Instant) {
+        if let Some(acquired_at) = self.acquire_times[frame_slot].take() {
+            self.gpu_latency
+                .add_sample(now.saturating_duration_since(acquired_at));
+        }
+    }
+
+    pub fn image_acquired(&mut self, frame_slot: usize, acquire_started: Instant, now: Instant) {
+        self.acquire_wait
+            .add_sample(now.saturating_duration_since(acquire_started));
+        self.acquire_times[frame_slot] = Some(now);
+    }
+
+    //Frames in flight across a device wait would measure the wait, not the frame
+    pub fn discard_pending(&mut self) {
+        self.acquire_times.iter_mut().for_each(|time| *time = None);
+    }
+
+    pub fn gpu_latency(&self) -> &LatencyStats {
+        &self.gpu_latency
+    }
+
+    pub fn acquire_wait(&self) -> &LatencyStats {
+        &self.acquire_wait
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn assert_ms(value: f64, expected: f64) {
+        assert!((value - expected).abs() < 1e-9, "{} != {}", value, expected);
+    }
+
+    #[test]
+    fn empty_stats_are_zero() {
+        let stats = LatencyStats::new(4);
+        assert!(stats.is_empty());
+        assert_ms(stats.mean_ms(), 0.0);
+        assert_ms(stats.min_ms(), 0.0);
+        assert_ms(stats.max_ms(), 0.0);
+        assert_ms(stats.latest_ms(), 0.0);
+    }
+
+    #[test]
+    fn stats_cover_the_latest_samples() {
+        let mut stats = LatencyStats::new(3);
+        for sample in [40, 10, 20, 30] {
+            stats.add_sample(ms(sample));
+        }
+        assert_eq!(stats.len(), 3);
+        assert_ms(stats.mean_ms(), 20.0);
+        assert_ms(stats.min_ms(), 10.0);
+        assert_ms(stats.max_ms(), 30.0);
+        assert_ms(stats.latest_ms(), 30.0);
+    }
+
+    #[test]
+    fn at_least_one_sample_is_kept() {
+        let mut stats = LatencyStats::new(0);
+        stats.add_sample(ms(5));
+        stats.add_sample(ms(7));
+        assert_eq!(stats.len(), 1);
+        assert_ms(stats.mean_ms(), 7.0);
+    }
+
+    #[test]
+    fn frame_latency_runs_from_acquire_to_finish() {
+        let start = Instant::now();
+        let mut latency = FrameLatency::new(2, 10);
+        latency.image_acquired(0, start, start + ms(2));
+        latency.image_acquired(1, start + ms(5), start + ms(5));
+        latency.frame_finished(0, start + ms(18));
+        latency.frame_finished(1, start + ms(25));
+
+        assert_ms(latency.acquire_wait().mean_ms(), 1.0);
+        assert_ms(latency.gpu_latency().min_ms(), 16.0);
+        assert_ms(latency.gpu_latency().max_ms(), 20.0);
+    }
+
+    #[test]
+    fn discarded_frames_are_not_measured() {
+        let start = Instant::now();
+        let mut latency = FrameLatency::new(1, 10);
+        latency.image_acquired(0, start, start);
+        latency.discard_pending();
+        latency.frame_finished(0, start + ms(500));
+        assert!(latency.gpu_latency().is_empty());
+        latency.frame_finished(0, start + ms(600));
+        assert!(latency.gpu_latency().is_empty());
+    }
+}
@@ -0,0 +1,145 @@
+use ash::vk;
+
+/// The subset of the physical device limits that applications care about.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DeviceLimits {
+    pub max_image_dimension_2d: u32,
+    pub max_sampler_anisotropy: f32,
+    pub max_sampler_lod_bias: f32,
+    pub max_uniform_buffer_range: u32,
+    pub min_uniform_buffer_offset_alignment: vk::DeviceSize,
+    pub max_push_constants_size: u32,
+    pub max_bound_descriptor_sets: u32,
+    pub framebuffer_color_sample_counts: vk::SampleCountFlags,
+    pub framebuffer_depth_sample_counts: vk::SampleCountFlags,
+    pub timestamp_period: f32,
+}
+
+impl DeviceLimits {
+    //Highest sample count usable for both color and depth attachments
+    pub fn max_sample_count(&self) -> vk::SampleCountFlags {
+        let supported = self.framebuffer_color_sample_counts & self.framebuffer_depth_sample_counts;
+        [
+            vk::SampleCountFlags::TYPE_64,
+            vk::SampleCountFlags::TYPE_32,
+            vk::SampleCountFlags::TYPE_16,
+            vk::SampleCountFlags::TYPE_8,
+            vk::SampleCountFlags::TYPE_4,
+            vk::SampleCountFlags::TYPE_2,
+        ]
+        .iter()
+        .copied()
+        .find(|count| supported.contains(*count))
+        .unwrap_or(vk::SampleCountFlags::TYPE_1)
+    }
+}
+
+impl From<&vk::PhysicalDeviceLimits> for DeviceLimits {
+    fn from(limits: &vk::PhysicalDeviceLimits) -> Self {
+        Self {
+            max_image_dimension_2d: limits.max_image_dimension2_d,
+            max_sampler_anisotropy: limits.max_sampler_anisotropy,
+            max_sampler_lod_bias: limits.max_sampler_lod_bias,
+            max_uniform_buffer_range: limits.max_uniform_buffer_range,
+            min_uniform_buffer_offset_alignment: limits.min_uniform_buffer_offset_alignment,
+            max_push_constants_size: limits.max_push_constants_size,
+            max_bound_descriptor_sets: limits.max_bound_descriptor_sets,
+            framebuffer_color_sample_counts: limits.framebuffer_color_sample_counts,
+            framebuffer_depth_sample_counts: limits.framebuffer_depth_sample_counts,
+            timestamp_period: limits.timestamp_period,
+        }
+    }
+}
+
+/// The subset of the physical device features that applications care about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceFeatures {
+    pub sampler_anisotropy: bool,
+    pub fill_mode_non_solid: bool,
+    pub wide_lines: bool,
+    pub multi_draw_indirect: bool,
+    pub texture_compression_bc: bool,
+}
+
+impl From<&vk::PhysicalDeviceFeatures> for DeviceFeatures {
+    fn from(features: &vk::PhysicalDeviceFeatures) -> Self {
+        Self {
+            sampler_anisotropy: features.sampler_anisotropy == vk::TRUE,
+            fill_mode_non_solid: features.fill_mode_non_solid == vk::TRUE,
+            wide_lines: features.wide_lines == vk::TRUE,
+            multi_draw_indirect: features.multi_draw_indirect == vk::TRUE,
+            texture_compression_bc: features.texture_compression_bc == vk::TRUE,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_copy_every_field() {
+        let physical_limits = vk::PhysicalDeviceLimits {
+            max_image_dimension2_d: 16384,
+            max_sampler_anisotropy: 8.0,
+            max_sampler_lod_bias: 15.5,
+            max_uniform_buffer_range: 65536,
+            min_uniform_buffer_offset_alignment: 256,
+            max_push_constants_size: 128,
+            max_bound_descriptor_sets: 32,
+            framebuffer_color_sample_counts: vk::SampleCountFlags::TYPE_1
+                | vk::SampleCountFlags::TYPE_8,
+            framebuffer_depth_sample_counts: vk::SampleCountFlags::TYPE_4,
+            timestamp_period: 52.08,
+            ..Default::default()
+        };
+        assert_eq!(
+            DeviceLimits::from(&physical_limits),
+            DeviceLimits {
+                max_image_dimension_2d: 16384,
+                max_sampler_anisotropy: 8.0,
+                max_sampler_lod_bias: 15.5,
+                max_uniform_buffer_range: 65536,
+                min_uniform_buffer_offset_alignment: 256,
+                max_push_constants_size: 128,
+                max_bound_descriptor_sets: 32,
+                framebuffer_color_sample_counts: vk::SampleCountFlags::TYPE_1
+                    | vk::SampleCountFlags::TYPE_8,
+                framebuffer_depth_sample_counts: vk::SampleCountFlags::TYPE_4,
+                timestamp_period: 52.08,
+            }
+        );
+    }
+
+    #[test]
+    fn max_sample_count_needs_color_and_depth_support() {
+        let mut limits = DeviceLimits::from(&vk::PhysicalDeviceLimits::default());
+        assert_eq!(limits.max_sample_count(), vk::SampleCountFlags::TYPE_1);
+        limits.framebuffer_color_sample_counts = vk::SampleCountFlags::TYPE_1
+            | vk::SampleCountFlags::TYPE_4
+            | vk::SampleCountFlags::TYPE_8;
+        limits.framebuffer_depth_sample_counts =
+            vk::SampleCountFlags::TYPE_1 | vk::SampleCountFlags::TYPE_4;
+        assert_eq!(limits.max_sample_count(), vk::SampleCountFlags::TYPE_4);
+    }
+
+    #[test]
+    fn features_are_read_from_vk_bools() {
+        let physical_features = vk::PhysicalDeviceFeatures {
+            sampler_anisotropy: vk::TRUE,
+            wide_lines: vk::TRUE,
+            texture_compression_bc: vk::TRUE,
+            ..Default::default()
+        };
+        assert_eq!(
+            DeviceFeatures::from(&physical_features),
+            DeviceFeatures {
+                sampler_anisotropy: true,
+                fill_mode_non_solid: false,
+                wide_lines: true,
+                multi_draw_indirect: false,
+                texture_compression_bc: true,
+            }
+        );
+    }
+}
@@ -1,23 +1,49 @@
+pub mod arena;
+pub mod buffer;
 pub mod commandbuffer;
 pub mod commandpool;
 pub mod context;
+pub mod descriptorpool;
+pub mod global;
+pub mod indirect;
+pub mod latency;
+pub mod limits;
+pub mod pass;
 pub mod pipeline;
 pub mod queue;
 pub mod renderpass;
+pub mod rendertarget;
+pub mod skybox;
 pub mod swapchain;
 pub mod swapdata;
+pub mod text;
 pub mod texture;
+pub mod unlit;
+pub mod upload;
 pub mod vertexbinding;
 pub mod vertexbuffer;
 
+pub use arena::*;
+pub use buffer::*;
 pub use commandbuffer::*;
 pub use commandpool::*;
 pub use context::*;
+pub use descriptorpool::*;
+pub use global::*;
+pub use indirect::*;
+pub use latency::*;
+pub use limits::*;
+pub use pass::*;
 pub use pipeline::*;
 pub use queue::*;
 pub use renderpass::*;
+pub use rendertarget::*;
+pub use skybox::*;
 pub use swapchain::*;
 pub use swapdata::*;
+pub use text::*;
 pub use texture::*;
+pub use unlit::*;
+pub use upload::*;
 pub use vertexbinding::*;
 pub use vertexbuffer::*;
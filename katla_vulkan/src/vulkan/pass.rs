@@ -0,0 +1,171 @@
+use super::CommandBuffer;
+use ash::vk;
+
+/// How materials draw this frame, see WindowRenderer::set_shading
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Shading {
+    #[default]
+    Lit,
+    //Base color only, through each material's UnlitPipeline
+    Unlit,
+}
+
+/// The frame being recorded, handed to drawables and passes so they can pick their per-frame
+/// resources.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameContext {
+    //Frame-in-flight slot, below the renderer's frames_in_flight
+    pub frame_index: usize,
+    //Swapchain image being rendered to, below the renderer's num_images
+    pub image_index: usize,
+    pub extent: vk::Extent2D,
+    pub shading: Shading,
+}
+
+/// A unit of recording inside the opaque render pass, run in the order passes were added.
+/// The scene itself is drawn by the first registered pass, debug or UI passes come after it.
+pub trait Pass {
+    fn record(&self, command_buffer: &CommandBuffer, frame: &FrameContext);
+}
+
+/// The passes of one window, recorded every frame in registration order.
+#[derive(Default)]
+pub struct PassList {
+    passes: Vec<Box<dyn Pass>>,
+}
+
+impl PassList {
+    pub fn add(&mut self, pass: Box<dyn Pass>) {
+        self.passes.push(pass);
+    }
+
+    pub fn record(&self, command_buffer: &CommandBuffer, frame: &FrameContext) {
+        self.record_with_background(command_buffer, frame, None);
+    }
+
+    //The background, like the skybox, is recorded right after the scene's pass so it only
+    //covers what the scene left empty, and before anything drawn on top of the scene
+    pub fn record_with_background(
+        &self,
+        command_buffer: &CommandBuffer,
+        frame: &FrameContext,
+        background: Option<&dyn Pass>,
+    ) {
+        let mut passes = self.passes.iter();
+        if let Some(scene) = passes.next() {
+            scene.record(command_buffer, frame);
+        }
+        if let Some(background) = background {
+            background.record(command_buffer, frame);
+        }
+        for pass in passes {
+            pass.record(command_buffer, frame);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.passes.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.passes.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::RefCell, rc::Rc};
+
+    struct NamedPass {
+        name: &'static str,
+        recorded: Rc<RefCell<Vec<(&'static str, usize)>>>,
+    }
+
+    impl Pass for NamedPass {
+        fn record(&self, _command_buffer: &CommandBuffer, frame: &FrameContext) {
+            self.recorded
+                .borrow_mut()
+                .push((self.name, frame.frame_index));
+        }
+    }
+
+    #[test]
+    fn passes_record_in_registration_order() {
+        let recorded = Rc::new(RefCell::new(vec![]));
+        let mut passes = PassList::default();
+        for name in ["opaque", "ui"] {
+            passes.add(Box::new(NamedPass {
+                name,
+                recorded: recorded.clone(),
+            }));
+        }
+        let command_buffer = CommandBuffer::detached();
+        for frame_index in 0..2 {
+            let frame = FrameContext {
+                frame_index,
+                image_index: frame_index,
+                extent: vk::Extent2D {
+                    width: 800,
+                    height: 600,
+                },
+                shading: Shading::Lit,
+            };
+            passes.record(&command_buffer, &frame);
+        }
+
+        assert_eq!(
+            *recorded.borrow(),
+            vec![("opaque", 0), ("ui", 0), ("opaque", 1), ("ui", 1)]
+        );
+    }
+
+    #[test]
+    fn background_records_between_the_scene_and_the_overlays() {
+        let recorded = Rc::new(RefCell::new(vec![]));
+        let mut passes = PassList::default();
+        for name in ["opaque", "ui"] {
+            passes.add(Box::new(NamedPass {
+                name,
+                recorded: recorded.clone(),
+            }));
+        }
+        let background = NamedPass {
+            name: "skybox",
+            recorded: recorded.clone(),
+        };
+        let frame = FrameContext {
+            frame_index: 1,
+            image_index: 0,
+            extent: vk::Extent2D::default(),
+            shading: Shading::Lit,
+        };
+        passes.record_with_background(&CommandBuffer::detached(), &frame, Some(&background));
+
+        assert_eq!(
+            *recorded.borrow(),
+            vec![("opaque", 1), ("skybox", 1), ("ui", 1)]
+        );
+    }
+
+    #[test]
+    fn cleared_lists_record_nothing() {
+        let recorded = Rc::new(RefCell::new(vec![]));
+        let mut passes = PassList::default();
+        passes.add(Box::new(NamedPass {
+            name: "opaque",
+            recorded: recorded.clone(),
+        }));
+        passes.clear();
+        assert!(passes.is_empty());
+
+        let frame = FrameContext {
+            frame_index: 0,
+            image_index: 0,
+            extent: vk::Extent2D::default(),
+            shading: Shading::Lit,
+        };
+        passes.record(&CommandBuffer::detached(), &frame);
+        assert!(recorded.borrow().is_empty());
+    }
+}
@@ -1,10 +1,13 @@
 use ash::{util::read_spv, vk};
-use gpu_allocator::vulkan::Allocation;
 
-use crate::vertexbinding::VertexBinding;
-use std::{ffi::CString, io::Cursor, sync::Arc};
+use crate::{material_set_layouts, vertexbinding::VertexBinding, Buffer, RenderPass};
+use std::{
+    ffi::{CString, NulError},
+    io::Cursor,
+    sync::Arc,
+};
 
-use super::context::VulkanContext;
+use super::context::{AllocationError, VulkanContext};
 //TODO: A more flexible shader system
 const SHADER_VERT: &[u8] = include_bytes!("../../../resources/shaders/model_pbr.vert.spv");
 const SHADER_FRAG: &[u8] = include_bytes!("../../../resources/shaders/model.frag.spv");
@@ -25,12 +28,17 @@ pub struct RenderPipeline {
     pub desc_layout: vk::DescriptorSetLayout,
     vert_module: vk::ShaderModule,
     frag_module: vk::ShaderModule,
+    destroyed: bool,
 }
 
 pub struct UniformBuffer {
-    allocation: Allocation,
-    buffer: vk::Buffer,
-    buf_size: vk::DeviceSize,
+    buffer: Buffer,
+}
+
+/// SPIR-V code for one shader stage and the name of the entry point to run in it
+pub struct ShaderStageDesc<'a> {
+    pub code: &'a [u8],
+    pub entry: CString,
 }
 
 #[derive(Clone)]
@@ -41,9 +49,8 @@ pub struct ImageInfo {
     image_info: Vec<vk::DescriptorImageInfo>,
 }
 
+/// One material descriptor set per frame in flight, bound at MATERIAL_SET
 pub struct UniformHandle {
-    next_bind_index: usize,
-    next_update_index: usize,
     descriptors: Vec<UniformDescriptor>,
 }
 
@@ -99,20 +106,25 @@ impl ImageInfo {
 impl UniformHandle {
     pub fn new(
         num_buffered_frames: usize,
-        context: &VulkanContext,
+        context: &Arc<VulkanContext>,
         desc_layout: &vk::DescriptorSetLayout,
-    ) -> Self {
-        let mut uniform_descs = vec![];
+    ) -> Result<Self, AllocationError> {
+        let mut uniform_descs: Vec<UniformDescriptor> = vec![];
         for _ in 0..num_buffered_frames {
-            let uniform_desc = Self::create_descriptor_sets(context, &desc_layout);
-            uniform_descs.push(uniform_desc);
+            match Self::create_descriptor_sets(context, desc_layout) {
+                Ok(uniform_desc) => uniform_descs.push(uniform_desc),
+                Err(err) => {
+                    for desc in &mut uniform_descs {
+                        desc.destroy(context);
+                    }
+                    return Err(err);
+                }
+            }
         }
 
-        Self {
-            next_bind_index: 0,
-            next_update_index: 0,
+        Ok(Self {
             descriptors: uniform_descs,
-        }
+        })
     }
 
     pub fn add_image_info(&mut self, image_info: ImageInfo) {
@@ -123,16 +135,14 @@ impl UniformHandle {
         }
     }
 
-    pub fn update_buffer(&mut self, context: &VulkanContext, data: &[u8]) {
-        self.descriptors[self.next_update_index].update_buffer(context, data);
-
-        self.next_bind_index = self.next_update_index;
-        self.next_update_index = (self.next_update_index + 1) % self.descriptors.len();
+    //One descriptor per frame in flight. The slot's fence has been waited on before the frame
+    //is recorded, so its descriptor is no longer in use by the GPU
+    pub fn update_buffer(&mut self, context: &VulkanContext, frame_index: usize, data: &[u8]) {
+        self.descriptors[frame_index].update_buffer(context, data);
     }
 
-    pub fn next_descriptor(&self) -> &UniformDescriptor {
-        let out_descr = &self.descriptors[self.next_bind_index];
-        out_descr
+    pub fn descriptor(&self, frame_index: usize) -> &UniformDescriptor {
+        &self.descriptors[frame_index]
     }
 
     pub fn destroy(&mut self, context: &VulkanContext) {
@@ -142,153 +152,135 @@ impl UniformHandle {
     }
 
     fn create_descriptor_sets(
-        context: &VulkanContext,
+        context: &Arc<VulkanContext>,
         desc_layout: &vk::DescriptorSetLayout,
-    ) -> UniformDescriptor {
-        let data_size = 4 * 16 * 3 as vk::DeviceSize;
-
-        let create_info = vk::BufferCreateInfo::default()
-            .sharing_mode(vk::SharingMode::EXCLUSIVE)
-            .usage(vk::BufferUsageFlags::UNIFORM_BUFFER)
-            .size(data_size);
-
-        let (buffer, allocation) =
-            context.allocate_buffer(&create_info, gpu_allocator::MemoryLocation::CpuToGpu);
-        let uniform_buffer = Some(UniformBuffer {
-            allocation,
-            buffer,
-            buf_size: data_size,
-        });
-
-        let desc_pool_sizes = &[
-            vk::DescriptorPoolSize::default()
-                .descriptor_count(1)
-                .ty(vk::DescriptorType::UNIFORM_BUFFER),
-            vk::DescriptorPoolSize::default()
-                .descriptor_count(1)
-                .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER),
-        ];
-        let desc_pool_info = vk::DescriptorPoolCreateInfo::default()
-            .pool_sizes(desc_pool_sizes)
-            .max_sets(1);
-        let desc_pool =
-            unsafe { context.device.create_descriptor_pool(&desc_pool_info, None) }.unwrap();
-
-        let desc_layouts = &[desc_layout.clone()];
-        let desc_info = vk::DescriptorSetAllocateInfo::default()
-            .descriptor_pool(desc_pool)
-            .set_layouts(desc_layouts);
-        let desc_set = unsafe { context.device.allocate_descriptor_sets(&desc_info) }.unwrap()[0];
+    ) -> Result<UniformDescriptor, AllocationError> {
+        //The world matrix, camera data lives in the GlobalUniform
+        let data_size = (4 * 16) as vk::DeviceSize;
+
+        let buffer = Buffer::new(
+            context.clone(),
+            data_size,
+            vk::BufferUsageFlags::UNIFORM_BUFFER,
+            gpu_allocator::MemoryLocation::CpuToGpu,
+        )?;
+        let (desc_set, desc_pool) = context.allocate_descriptor_set(*desc_layout);
+        //The binding never changes, frames only rewrite the buffer's contents
+        let buf_info = [vk::DescriptorBufferInfo::default()
+            .buffer(buffer.vk_buffer())
+            .offset(0)
+            .range(data_size)];
+        let buffer_write = vk::WriteDescriptorSet::default()
+            .dst_set(desc_set)
+            .dst_binding(0)
+            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+            .buffer_info(&buf_info);
+        unsafe { context.device.update_descriptor_sets(&[buffer_write], &[]) };
+        let uniform_buffer = Some(UniformBuffer { buffer });
 
         let image_info = None;
 
-        UniformDescriptor {
+        Ok(UniformDescriptor {
             desc_set,
             desc_pool,
             uniform_buffer,
             image_info,
             static_descriptors: vec![],
-        }
+        })
     }
 }
 
 impl UniformDescriptor {
-    //TODO: Uniform buffer updates:
+    //Only copies into the mapped buffer, the descriptor set is written the first time an
+    //image is available and never again
     pub fn update_buffer(&mut self, context: &VulkanContext, data: &[u8]) {
-        if let Some(uniform_buffer) = &self.uniform_buffer {
-            let data_size = std::mem::size_of_val(data) as vk::DeviceSize;
-            if uniform_buffer.buf_size < data_size {
-                panic!(
-                    "Too little memory allocated for buffer of size {}",
-                    data_size
-                );
-            }
-
-            let mapped_data = context.map_buffer(&uniform_buffer.allocation);
-            unsafe {
-                std::ptr::copy_nonoverlapping(data.as_ptr(), mapped_data, data_size as usize);
-            }
+        if let Some(uniform_buffer) = &mut self.uniform_buffer {
+            uniform_buffer.buffer.write(data);
 
-            let buf_info = [vk::DescriptorBufferInfo::default()
-                .buffer(uniform_buffer.buffer)
-                .offset(0)
-                .range(data_size)];
-            let mut desc_writes = vec![];
-            desc_writes.push(
-                vk::WriteDescriptorSet::default()
-                    .dst_set(self.desc_set)
-                    .dst_binding(0)
-                    .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
-                    .buffer_info(&buf_info),
-            );
             if let Some(image_info) = &mut self.image_info {
                 if !image_info.is_updated {
                     image_info.is_updated = true;
                     let write_set = image_info.update_once(self.desc_set, 1);
-                    desc_writes.push(write_set);
+                    unsafe { context.device.update_descriptor_sets(&[write_set], &[]) };
                 }
             } else {
                 println!("No descriptor image to update!!!");
             }
-
-            unsafe {
-                context
-                    .device
-                    .update_descriptor_sets(desc_writes.as_slice(), &[])
-            };
         } else {
             println!("No descriptor buffer to update!!!");
         }
     }
 
     pub fn destroy(&mut self, context: &VulkanContext) {
-        if self.uniform_buffer.is_some() {
-            let buffer = self.uniform_buffer.take().unwrap();
-            context.free_buffer(buffer.buffer, buffer.allocation);
-        }
-        unsafe {
-            context.device.destroy_descriptor_pool(self.desc_pool, None);
-        }
+        self.uniform_buffer.take();
+        context.free_descriptor_set(self.desc_set, self.desc_pool);
+    }
+}
+
+impl<'a> ShaderStageDesc<'a> {
+    //Fails if the entry point name contains a NUL
+    pub fn new(code: &'a [u8], entry: &str) -> Result<Self, NulError> {
+        Ok(Self {
+            code,
+            entry: CString::new(entry)?,
+        })
+    }
+
+    pub fn default_vertex() -> ShaderStageDesc<'static> {
+        ShaderStageDesc::new(SHADER_VERT, "main").unwrap()
+    }
+
+    pub fn default_fragment() -> ShaderStageDesc<'static> {
+        ShaderStageDesc::new(SHADER_FRAG, "main").unwrap()
+    }
+
+    pub(crate) fn create_module(&self, context: &VulkanContext) -> vk::ShaderModule {
+        let decoded = read_spv(&mut Cursor::new(self.code)).expect("Invalid SPIR-V");
+        let create_info = vk::ShaderModuleCreateInfo::default().code(&decoded);
+        unsafe { context.device.create_shader_module(&create_info, None) }.unwrap()
     }
 }
 
 impl RenderPipeline {
+    //Uses the built-in PBR shaders
     pub fn new(
         context: Arc<VulkanContext>,
-        render_pass: vk::RenderPass,
+        render_pass: &RenderPass,
         num_buffered_frames: usize,
         vertex_binding: VertexBinding,
-    ) -> Self {
-        let entry_point = CString::new("main").unwrap();
-        let mut vertex_spv_file = Cursor::new(SHADER_VERT);
-        let vert_decoded = read_spv(&mut vertex_spv_file).unwrap();
-        let create_info = vk::ShaderModuleCreateInfo::default().code(&vert_decoded);
-        let shader_vert =
-            unsafe { context.device.create_shader_module(&create_info, None) }.unwrap();
-
-        let mut frag_spv_file = Cursor::new(SHADER_FRAG);
-        let frag_decoded = read_spv(&mut frag_spv_file).unwrap();
-        let create_info = vk::ShaderModuleCreateInfo::default().code(&frag_decoded);
-        let shader_frag =
-            unsafe { context.device.create_shader_module(&create_info, None) }.unwrap();
-
-        let shader_stages = vec![
-            vk::PipelineShaderStageCreateInfo::default()
-                .stage(vk::ShaderStageFlags::VERTEX)
-                .module(shader_vert)
-                .name(&entry_point),
-            vk::PipelineShaderStageCreateInfo::default()
-                .stage(vk::ShaderStageFlags::FRAGMENT)
-                .module(shader_frag)
-                .name(&entry_point),
-        ];
+    ) -> Result<Self, AllocationError> {
+        Self::new_with_shaders(
+            context,
+            render_pass,
+            num_buffered_frames,
+            vertex_binding,
+            &ShaderStageDesc::default_vertex(),
+            &ShaderStageDesc::default_fragment(),
+        )
+    }
+
+    //The shaders have to use the same descriptor sets and vertex inputs as the built-in
+    //ones: the global data in set 0 and the material's uniform and sampler in set 1
+    pub fn new_with_shaders(
+        context: Arc<VulkanContext>,
+        render_pass: &RenderPass,
+        num_buffered_frames: usize,
+        vertex_binding: VertexBinding,
+        vertex_shader: &ShaderStageDesc,
+        fragment_shader: &ShaderStageDesc,
+    ) -> Result<Self, AllocationError> {
+        let shader_vert = vertex_shader.create_module(&context);
+        let shader_frag = fragment_shader.create_module(&context);
+
+        let shader_stages =
+            shader_stage_infos(shader_vert, vertex_shader, shader_frag, fragment_shader);
         //TODO: Descripitor sets
         let desc_layout_bindings = &[
             vk::DescriptorSetLayoutBinding::default()
                 .binding(0)
                 .descriptor_count(1)
                 .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
-                .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT),
+                .stage_flags(vk::ShaderStageFlags::VERTEX),
             vk::DescriptorSetLayoutBinding::default()
                 .binding(1)
                 .descriptor_count(1)
@@ -304,90 +296,39 @@ impl RenderPipeline {
         }
         .unwrap();
 
-        let uniform = UniformHandle::new(num_buffered_frames, &context, &desc_layout);
+        let uniform = match UniformHandle::new(num_buffered_frames, &context, &desc_layout) {
+            Ok(uniform) => uniform,
+            Err(err) => {
+                unsafe {
+                    context.device.destroy_shader_module(shader_vert, None);
+                    context.device.destroy_shader_module(shader_frag, None);
+                    context
+                        .device
+                        .destroy_descriptor_set_layout(desc_layout, None);
+                }
+                return Err(err);
+            }
+        };
 
-        let pipeline_layout_desc_layouts = &[desc_layout];
+        let pipeline_layout_desc_layouts =
+            material_set_layouts(context.global_set_layout(), desc_layout);
 
         let create_info =
-            vk::PipelineLayoutCreateInfo::default().set_layouts(pipeline_layout_desc_layouts);
+            vk::PipelineLayoutCreateInfo::default().set_layouts(&pipeline_layout_desc_layouts);
         let pipeline_layout =
             unsafe { context.device.create_pipeline_layout(&create_info, None) }.unwrap();
 
-        let vertex_binding_desc = [vertex_binding.get_binding_desc(0)];
-        let vertex_attrib_descs = vertex_binding.get_attribute_desc(0);
-        let vertex_input = vk::PipelineVertexInputStateCreateInfo::default()
-            .vertex_binding_descriptions(&vertex_binding_desc)
-            .vertex_attribute_descriptions(vertex_attrib_descs.as_slice());
-
-        // https://vulkan-tutorial.com/Drawing_a_triangle/Graphics_pipeline_basics/Fixed_functions
-        let input_assembly = vk::PipelineInputAssemblyStateCreateInfo::default()
-            .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
-            .primitive_restart_enable(false);
-
-        let viewport_state = vk::PipelineViewportStateCreateInfo::default()
-            .viewport_count(1)
-            .scissor_count(1);
-
-        let rasterizer = vk::PipelineRasterizationStateCreateInfo::default()
-            .depth_clamp_enable(false)
-            .rasterizer_discard_enable(false)
-            .polygon_mode(vk::PolygonMode::FILL)
-            .line_width(1.0)
-            .cull_mode(vk::CullModeFlags::BACK)
-            .front_face(vk::FrontFace::CLOCKWISE);
-
-        let multisampling = vk::PipelineMultisampleStateCreateInfo::default()
-            .sample_shading_enable(false)
-            .rasterization_samples(vk::SampleCountFlags::TYPE_1);
-
-        let color_blend_attachments = vec![vk::PipelineColorBlendAttachmentState::default()
-            .color_write_mask(
-                vk::ColorComponentFlags::R
-                    | vk::ColorComponentFlags::G
-                    | vk::ColorComponentFlags::B
-                    | vk::ColorComponentFlags::A,
-            )
-            .blend_enable(false)];
-
-        let color_blending = vk::PipelineColorBlendStateCreateInfo::default()
-            .logic_op_enable(false)
-            .attachments(&color_blend_attachments);
-
-        let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::default()
-            .depth_test_enable(true)
-            .depth_write_enable(true)
-            .depth_compare_op(vk::CompareOp::LESS)
-            .depth_bounds_test_enable(false)
-            .min_depth_bounds(0.0)
-            .max_depth_bounds(1.0)
-            .stencil_test_enable(false);
-        let dynamic_state = vk::PipelineDynamicStateCreateInfo::default()
-            .dynamic_states(&[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR]);
-
-        let create_info = vk::GraphicsPipelineCreateInfo::default()
-            .stages(&shader_stages)
-            .vertex_input_state(&vertex_input)
-            .input_assembly_state(&input_assembly)
-            .viewport_state(&viewport_state)
-            .depth_stencil_state(&depth_stencil_state)
-            .rasterization_state(&rasterizer)
-            .multisample_state(&multisampling)
-            .color_blend_state(&color_blending)
-            .dynamic_state(&dynamic_state)
-            .layout(pipeline_layout)
-            .render_pass(render_pass)
-            .subpass(0);
-
-        let pipeline = unsafe {
-            context.device.create_graphics_pipelines(
-                vk::PipelineCache::null(),
-                &[create_info],
-                None,
-            )
-        }
-        .unwrap()[0];
+        let pipeline = create_graphics_pipeline(
+            &context,
+            render_pass,
+            pipeline_layout,
+            &vertex_binding,
+            &shader_stages,
+            &FixedFunctionState::opaque(render_pass),
+        );
 
-        RenderPipeline {
+        context.pipeline_created();
+        Ok(RenderPipeline {
             context,
             pipeline,
             pipeline_layout,
@@ -395,14 +336,21 @@ impl RenderPipeline {
             uniform,
             vert_module: shader_vert,
             frag_module: shader_frag,
-        }
+            destroyed: false,
+        })
     }
 
-    pub fn update_buffer(&mut self, data: &[u8]) {
-        self.uniform.update_buffer(&self.context, data);
+    pub fn update_buffer(&mut self, frame_index: usize, data: &[u8]) {
+        self.uniform.update_buffer(&self.context, frame_index, data);
     }
 
+    //Must be called before the context is dropped, calling it again does nothing
     pub fn destroy(&mut self) {
+        if self.destroyed {
+            return;
+        }
+        self.destroyed = true;
+        self.context.pipeline_destroyed();
         unsafe {
             self.context.device.destroy_pipeline(self.pipeline, None);
             self.context
@@ -421,3 +369,231 @@ impl RenderPipeline {
         }
     }
 }
+
+/// The fixed function state that differs between the built-in pipelines
+pub(crate) struct FixedFunctionState {
+    pub cull_mode: vk::CullModeFlags,
+    //Blends the output over the target by its alpha
+    pub alpha_blend: bool,
+    pub depth_stencil: vk::PipelineDepthStencilStateCreateInfo<'static>,
+}
+
+impl FixedFunctionState {
+    //Material pipelines: back faces culled, no blending and depth tested if the pass has it
+    pub fn opaque(render_pass: &RenderPass) -> Self {
+        Self {
+            cull_mode: vk::CullModeFlags::BACK,
+            alpha_blend: false,
+            depth_stencil: depth_stencil_state(render_pass.has_depth()),
+        }
+    }
+}
+
+pub(crate) fn create_graphics_pipeline(
+    context: &VulkanContext,
+    render_pass: &RenderPass,
+    pipeline_layout: vk::PipelineLayout,
+    vertex_binding: &VertexBinding,
+    shader_stages: &[vk::PipelineShaderStageCreateInfo],
+    state: &FixedFunctionState,
+) -> vk::Pipeline {
+    let vertex_binding_desc = [vertex_binding.get_binding_desc(0)];
+    let vertex_attrib_descs = vertex_binding.get_attribute_desc(0);
+    let vertex_input = vk::PipelineVertexInputStateCreateInfo::default()
+        .vertex_binding_descriptions(&vertex_binding_desc)
+        .vertex_attribute_descriptions(vertex_attrib_descs.as_slice());
+
+    // https://vulkan-tutorial.com/Drawing_a_triangle/Graphics_pipeline_basics/Fixed_functions
+    let input_assembly = vk::PipelineInputAssemblyStateCreateInfo::default()
+        .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+        .primitive_restart_enable(false);
+
+    let viewport_state = vk::PipelineViewportStateCreateInfo::default()
+        .viewport_count(1)
+        .scissor_count(1);
+
+    let rasterizer = vk::PipelineRasterizationStateCreateInfo::default()
+        .depth_clamp_enable(false)
+        .rasterizer_discard_enable(false)
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0)
+        .cull_mode(state.cull_mode)
+        .front_face(vk::FrontFace::CLOCKWISE);
+
+    let multisampling = vk::PipelineMultisampleStateCreateInfo::default()
+        .sample_shading_enable(false)
+        .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+
+    let color_blend_attachments = vec![vk::PipelineColorBlendAttachmentState::default()
+        .color_write_mask(
+            vk::ColorComponentFlags::R
+                | vk::ColorComponentFlags::G
+                | vk::ColorComponentFlags::B
+                | vk::ColorComponentFlags::A,
+        )
+        .blend_enable(state.alpha_blend)
+        .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+        .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+        .color_blend_op(vk::BlendOp::ADD)
+        .src_alpha_blend_factor(vk::BlendFactor::ONE)
+        .dst_alpha_blend_factor(vk::BlendFactor::ZERO)
+        .alpha_blend_op(vk::BlendOp::ADD)];
+
+    let color_blending = vk::PipelineColorBlendStateCreateInfo::default()
+        .logic_op_enable(false)
+        .attachments(&color_blend_attachments);
+
+    let dynamic_state = vk::PipelineDynamicStateCreateInfo::default()
+        .dynamic_states(&[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR]);
+
+    let color_formats = [render_pass.color_format()];
+    let mut rendering_info = vk::PipelineRenderingCreateInfo::default()
+        .color_attachment_formats(&color_formats)
+        .depth_attachment_format(render_pass.depth_format());
+
+    let mut create_info = vk::GraphicsPipelineCreateInfo::default()
+        .stages(&shader_stages)
+        .vertex_input_state(&vertex_input)
+        .input_assembly_state(&input_assembly)
+        .viewport_state(&viewport_state)
+        .depth_stencil_state(&state.depth_stencil)
+        .rasterization_state(&rasterizer)
+        .multisample_state(&multisampling)
+        .color_blend_state(&color_blending)
+        .dynamic_state(&dynamic_state)
+        .layout(pipeline_layout)
+        .render_pass(render_pass.get_vk_renderpass())
+        .subpass(0);
+    if render_pass.is_dynamic() {
+        create_info = create_info.push_next(&mut rendering_info);
+    }
+
+    unsafe {
+        context
+            .device
+            .create_graphics_pipelines(vk::PipelineCache::null(), &[create_info], None)
+    }
+    .unwrap()[0]
+}
+
+pub(crate) fn shader_stage_infos<'a>(
+    vert_module: vk::ShaderModule,
+    vertex_shader: &'a ShaderStageDesc,
+    frag_module: vk::ShaderModule,
+    fragment_shader: &'a ShaderStageDesc,
+) -> [vk::PipelineShaderStageCreateInfo<'a>; 2] {
+    [
+        vk::PipelineShaderStageCreateInfo::default()
+            .stage(vk::ShaderStageFlags::VERTEX)
+            .module(vert_module)
+            .name(&vertex_shader.entry),
+        vk::PipelineShaderStageCreateInfo::default()
+            .stage(vk::ShaderStageFlags::FRAGMENT)
+            .module(frag_module)
+            .name(&fragment_shader.entry),
+    ]
+}
+
+//Passes without a depth attachment, like RenderPass::create_color_only, must not test or write depth
+pub fn depth_stencil_state(
+    depth_enabled: bool,
+) -> vk::PipelineDepthStencilStateCreateInfo<'static> {
+    vk::PipelineDepthStencilStateCreateInfo::default()
+        .depth_test_enable(depth_enabled)
+        .depth_write_enable(depth_enabled)
+        .depth_compare_op(vk::CompareOp::LESS)
+        .depth_bounds_test_enable(false)
+        .min_depth_bounds(0.0)
+        .max_depth_bounds(1.0)
+        .stencil_test_enable(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GLOBAL_SET, MATERIAL_SET};
+
+    #[test]
+    fn entry_point_names_are_kept() {
+        let stage = ShaderStageDesc::new(SHADER_VERT, "vs_main").unwrap();
+        assert_eq!(stage.entry.to_str(), Ok("vs_main"));
+        assert_eq!(stage.code.len(), SHADER_VERT.len());
+    }
+
+    #[test]
+    fn entry_point_names_with_nul_are_rejected() {
+        assert!(ShaderStageDesc::new(SHADER_FRAG, "ma\0in").is_err());
+    }
+
+    #[test]
+    fn default_stages_are_valid_spirv() {
+        for stage in [
+            ShaderStageDesc::default_vertex(),
+            ShaderStageDesc::default_fragment(),
+        ] {
+            assert_eq!(stage.entry.to_str(), Ok("main"));
+            let words = read_spv(&mut Cursor::new(stage.code)).unwrap();
+            assert_eq!(words[0], 0x0723_0203);
+        }
+    }
+
+    //The global data has the coefficient right after the view and proj matrices
+    #[test]
+    fn default_vertex_shader_reads_the_log_depth_coefficient() {
+        const OP_MEMBER_DECORATE: u32 = 72;
+        const DECORATION_OFFSET: u32 = 35;
+        let words = read_spv(&mut Cursor::new(SHADER_VERT)).unwrap();
+        let has_offset = words.windows(5).any(|inst| {
+            inst[0] == (5 << 16 | OP_MEMBER_DECORATE)
+                && inst[2] == 2
+                && inst[3] == DECORATION_OFFSET
+                && inst[4] == 4 * 16 * 2
+        });
+        assert!(has_offset);
+    }
+
+    //Every set number the shader's resources are declared in
+    fn descriptor_sets(code: &[u8]) -> Vec<u32> {
+        const OP_DECORATE: u32 = 71;
+        const DECORATION_DESCRIPTOR_SET: u32 = 34;
+        let words = read_spv(&mut Cursor::new(code)).unwrap();
+        let mut sets = vec![];
+        let mut index = 5;
+        while index < words.len() {
+            let count = (words[index] >> 16) as usize;
+            let inst = &words[index..index + count];
+            if inst[0] & 0xffff == OP_DECORATE && inst[2] == DECORATION_DESCRIPTOR_SET {
+                sets.push(inst[3]);
+            }
+            index += count;
+        }
+        sets.sort_unstable();
+        sets.dedup();
+        sets
+    }
+
+    #[test]
+    fn default_shaders_split_global_and_material_sets() {
+        assert_eq!(descriptor_sets(SHADER_VERT), vec![GLOBAL_SET, MATERIAL_SET]);
+        //The lights are in the global set
+        assert_eq!(descriptor_sets(SHADER_FRAG), vec![GLOBAL_SET, MATERIAL_SET]);
+    }
+
+    #[test]
+    fn depth_stencil_state_tests_and_writes_depth_when_enabled() {
+        let state = depth_stencil_state(true);
+        assert_eq!(state.depth_test_enable, vk::TRUE);
+        assert_eq!(state.depth_write_enable, vk::TRUE);
+        assert_eq!(state.depth_compare_op, vk::CompareOp::LESS);
+        assert_eq!(state.stencil_test_enable, vk::FALSE);
+    }
+
+    #[test]
+    fn depth_stencil_state_leaves_depth_alone_when_disabled() {
+        let state = depth_stencil_state(false);
+        assert_eq!(state.depth_test_enable, vk::FALSE);
+        assert_eq!(state.depth_write_enable, vk::FALSE);
+        assert_eq!(state.depth_bounds_test_enable, vk::FALSE);
+        assert_eq!(state.stencil_test_enable, vk::FALSE);
+    }
+}
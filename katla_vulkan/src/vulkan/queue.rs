@@ -28,22 +28,35 @@ impl Queue {
         }
     }
 
+    //Waits on every semaphore at ALL_COMMANDS, see submit_with_stages for finer control
     pub fn submit(
         &self,
         command_buffers: &[&CommandBuffer],
         wait_semaphores: &[Semaphore],
         signal_semaphores: &[Semaphore],
         signal_fence: Fence,
+    ) {
+        let waits = wait_semaphores
+            .iter()
+            .map(|semaphore| (*semaphore, vk::PipelineStageFlags::ALL_COMMANDS))
+            .collect::<Vec<_>>();
+        self.submit_with_stages(command_buffers, &waits, signal_semaphores, signal_fence);
+    }
+
+    pub fn submit_with_stages(
+        &self,
+        command_buffers: &[&CommandBuffer],
+        waits: &[(Semaphore, vk::PipelineStageFlags)],
+        signal_semaphores: &[Semaphore],
+        signal_fence: Fence,
     ) {
         let mut vk_cmd_buffers = Vec::with_capacity(command_buffers.len());
         for command_buffer in command_buffers {
             vk_cmd_buffers.push(command_buffer.vk_command_buffer());
         }
 
-        let mut wait_dst_stage_mask = Vec::with_capacity(wait_semaphores.len());
-        for _ in wait_semaphores {
-            wait_dst_stage_mask.push(vk::PipelineStageFlags::ALL_COMMANDS);
-        }
+        let (wait_semaphores, wait_dst_stage_mask): (Vec<_>, Vec<_>) =
+            waits.iter().copied().unzip();
 
         let submit_info = vk::SubmitInfo::default()
             .wait_dst_stage_mask(&wait_dst_stage_mask)
@@ -3,6 +3,8 @@ use ash::{vk, Device};
 pub struct RenderPass {
     vk_renderpass: vk::RenderPass,
     device: Device,
+    color_format: vk::Format,
+    depth_format: vk::Format,
 }
 
 impl RenderPass {
@@ -61,16 +63,191 @@ impl RenderPass {
         Self {
             vk_renderpass,
             device,
+            color_format,
+            depth_format,
         }
     }
 
+    //Like the opaque pass, but the color attachment ends up readable from fragment shaders
+    pub fn create_offscreen(
+        device: Device,
+        color_format: vk::Format,
+        depth_format: vk::Format,
+    ) -> Self {
+        let color_attachment = vk::AttachmentDescription::default()
+            .format(color_format)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .load_op(vk::AttachmentLoadOp::CLEAR)
+            .store_op(vk::AttachmentStoreOp::STORE)
+            .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .initial_layout(vk::ImageLayout::UNDEFINED)
+            .final_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+
+        let depth_attachment = vk::AttachmentDescription::default()
+            .format(depth_format)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .load_op(vk::AttachmentLoadOp::CLEAR)
+            .store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .initial_layout(vk::ImageLayout::UNDEFINED)
+            .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+
+        let attachments = [color_attachment, depth_attachment];
+
+        let color_attachment_refs = [vk::AttachmentReference::default()
+            .attachment(0)
+            .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)];
+        let depth_attachment_ref = vk::AttachmentReference::default()
+            .attachment(1)
+            .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+        let subpasses = [vk::SubpassDescription::default()
+            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+            .color_attachments(&color_attachment_refs)
+            .depth_stencil_attachment(&depth_attachment_ref)];
+        //Previous reads of the texture have to finish before we write to it again,
+        //and our writes have to be visible before anyone samples the result.
+        let dependencies = [
+            vk::SubpassDependency::default()
+                .src_subpass(vk::SUBPASS_EXTERNAL)
+                .dst_subpass(0)
+                .src_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+                .src_access_mask(vk::AccessFlags::SHADER_READ)
+                .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+                .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE),
+            vk::SubpassDependency::default()
+                .src_subpass(0)
+                .dst_subpass(vk::SUBPASS_EXTERNAL)
+                .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+                .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+                .dst_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+                .dst_access_mask(vk::AccessFlags::SHADER_READ),
+        ];
+
+        let create_info = vk::RenderPassCreateInfo::default()
+            .attachments(&attachments)
+            .subpasses(&subpasses)
+            .dependencies(&dependencies);
+
+        let vk_renderpass = unsafe { device.create_render_pass(&create_info, None) }.unwrap();
+
+        Self {
+            vk_renderpass,
+            device,
+            color_format,
+            depth_format,
+        }
+    }
+
+    //A single color attachment drawn on top of what the opaque pass presented, for 2D overlays.
+    //Pipelines created against it get depth testing disabled
+    pub fn create_color_only(device: Device, color_format: vk::Format) -> Self {
+        let attachments = [color_only_attachment(color_format)];
+
+        let color_attachment_refs = [vk::AttachmentReference::default()
+            .attachment(0)
+            .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)];
+        let subpasses = [vk::SubpassDescription::default()
+            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+            .color_attachments(&color_attachment_refs)];
+        //The previous pass' writes have to land before we load and blend on top of them
+        let dependencies = [vk::SubpassDependency::default()
+            .src_subpass(vk::SUBPASS_EXTERNAL)
+            .dst_subpass(0)
+            .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+            .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+            .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+            .dst_access_mask(
+                vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            )];
+
+        let create_info = vk::RenderPassCreateInfo::default()
+            .attachments(&attachments)
+            .subpasses(&subpasses)
+            .dependencies(&dependencies);
+
+        let vk_renderpass = unsafe { device.create_render_pass(&create_info, None) }.unwrap();
+
+        Self {
+            vk_renderpass,
+            device,
+            color_format,
+            depth_format: vk::Format::UNDEFINED,
+        }
+    }
+
+    //No vk::RenderPass at all, rendering is begun with cmd_begin_rendering and pipelines
+    //are created against the attachment formats instead
+    pub fn create_dynamic(
+        device: Device,
+        color_format: vk::Format,
+        depth_format: vk::Format,
+    ) -> Self {
+        Self {
+            vk_renderpass: vk::RenderPass::null(),
+            device,
+            color_format,
+            depth_format,
+        }
+    }
+
+    pub fn is_dynamic(&self) -> bool {
+        self.vk_renderpass == vk::RenderPass::null()
+    }
+
     pub fn get_vk_renderpass(&self) -> vk::RenderPass {
         self.vk_renderpass
     }
 
+    pub fn color_format(&self) -> vk::Format {
+        self.color_format
+    }
+
+    pub fn depth_format(&self) -> vk::Format {
+        self.depth_format
+    }
+
+    pub fn has_depth(&self) -> bool {
+        self.depth_format != vk::Format::UNDEFINED
+    }
+
     pub fn destroy(&self) {
+        if self.is_dynamic() {
+            return;
+        }
         unsafe {
             self.device.destroy_render_pass(self.vk_renderpass, None);
         }
     }
 }
+
+//Keeps what the previous pass presented and hands the image back ready to present
+fn color_only_attachment(color_format: vk::Format) -> vk::AttachmentDescription {
+    vk::AttachmentDescription::default()
+        .format(color_format)
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .load_op(vk::AttachmentLoadOp::LOAD)
+        .store_op(vk::AttachmentStoreOp::STORE)
+        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .initial_layout(vk::ImageLayout::PRESENT_SRC_KHR)
+        .final_layout(vk::ImageLayout::PRESENT_SRC_KHR)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn color_only_attachment_loads_and_stays_presentable() {
+        let attachment = color_only_attachment(vk::Format::B8G8R8A8_SRGB);
+
+        assert_eq!(attachment.format, vk::Format::B8G8R8A8_SRGB);
+        assert_eq!(attachment.samples, vk::SampleCountFlags::TYPE_1);
+        assert_eq!(attachment.load_op, vk::AttachmentLoadOp::LOAD);
+        assert_eq!(attachment.store_op, vk::AttachmentStoreOp::STORE);
+        assert_eq!(attachment.initial_layout, vk::ImageLayout::PRESENT_SRC_KHR);
+        assert_eq!(attachment.final_layout, vk::ImageLayout::PRESENT_SRC_KHR);
+    }
+}
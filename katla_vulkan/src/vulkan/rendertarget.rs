@@ -0,0 +1,200 @@
+use super::{
+    AllocationError, CommandBuffer, ImageInfo, RenderPass, RenderTexture, SamplerConfig,
+    VulkanContext,
+};
+use ash::vk;
+
+use std::sync::Arc;
+
+/// Whether a color attachment stores values as sRGB (encoded on write, decoded on sample)
+/// or as plain linear values.
+/// The swapchain is sRGB so the final image is gamma encoded once. Intermediate targets that
+/// are sampled again later (render-to-texture, post processing inputs) should be Linear,
+/// otherwise the result gets gamma corrected twice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorSpaceKind {
+    Srgb,
+    Linear,
+}
+
+impl ColorSpaceKind {
+    //Picks the UNORM or SRGB variant of the format, formats without a variant are kept as-is
+    pub fn select_format(self, format: vk::Format) -> vk::Format {
+        const PAIRS: [(vk::Format, vk::Format); 6] = [
+            (vk::Format::R8_UNORM, vk::Format::R8_SRGB),
+            (vk::Format::R8G8_UNORM, vk::Format::R8G8_SRGB),
+            (vk::Format::R8G8B8_UNORM, vk::Format::R8G8B8_SRGB),
+            (vk::Format::B8G8R8_UNORM, vk::Format::B8G8R8_SRGB),
+            (vk::Format::R8G8B8A8_UNORM, vk::Format::R8G8B8A8_SRGB),
+            (vk::Format::B8G8R8A8_UNORM, vk::Format::B8G8R8A8_SRGB),
+        ];
+        for (unorm, srgb) in PAIRS {
+            if format == unorm || format == srgb {
+                return match self {
+                    ColorSpaceKind::Srgb => srgb,
+                    ColorSpaceKind::Linear => unorm,
+                };
+            }
+        }
+        format
+    }
+}
+
+/// An offscreen color + depth target that can be sampled after it has been rendered to.
+/// The render pass transitions the color texture to SHADER_READ_ONLY_OPTIMAL when it ends.
+pub struct RenderTarget {
+    context: Arc<VulkanContext>,
+    color_texture: RenderTexture,
+    depth_texture: RenderTexture,
+    render_pass: RenderPass,
+    framebuffer: vk::Framebuffer,
+    sampler: vk::Sampler,
+}
+
+impl RenderTarget {
+    pub fn new(
+        context: Arc<VulkanContext>,
+        extent: vk::Extent2D,
+        color_format: vk::Format,
+        color_space: ColorSpaceKind,
+        sampler_config: &SamplerConfig,
+    ) -> Result<Self, AllocationError> {
+        let color_format = color_space.select_format(color_format);
+        let color_texture = RenderTexture::new(
+            context.clone(),
+            extent,
+            color_format,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+            vk::ImageAspectFlags::COLOR,
+        )?;
+        let depth_texture = RenderTexture::new(
+            context.clone(),
+            extent,
+            context.find_depth_format(false),
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            vk::ImageAspectFlags::DEPTH,
+        )?;
+        let render_pass = RenderPass::create_offscreen(
+            context.device.clone(),
+            color_format,
+            depth_texture.format,
+        );
+
+        let attachments = [color_texture.image_view, depth_texture.image_view];
+        let create_info = vk::FramebufferCreateInfo::default()
+            .render_pass(render_pass.get_vk_renderpass())
+            .attachments(&attachments)
+            .width(extent.width)
+            .height(extent.height)
+            .layers(1);
+        let framebuffer = unsafe { context.device.create_framebuffer(&create_info, None) }.unwrap();
+        let sampler = context.get_or_create_sampler(sampler_config);
+
+        Ok(Self {
+            context,
+            color_texture,
+            depth_texture,
+            render_pass,
+            framebuffer,
+            sampler,
+        })
+    }
+
+    pub fn extent(&self) -> vk::Extent2D {
+        self.color_texture.extent
+    }
+
+    pub fn depth_format(&self) -> vk::Format {
+        self.depth_texture.format
+    }
+
+    //Pipelines drawing into this target have to be created against this render pass
+    pub fn render_pass(&self) -> &RenderPass {
+        &self.render_pass
+    }
+
+    pub fn begin(&self, command_buffer: &CommandBuffer, clear_color: [f32; 4]) {
+        let clear_values = [
+            vk::ClearValue {
+                color: vk::ClearColorValue {
+                    float32: clear_color,
+                },
+            },
+            vk::ClearValue {
+                depth_stencil: vk::ClearDepthStencilValue {
+                    depth: 1.0,
+                    stencil: 0,
+                },
+            },
+        ];
+        let render_area = vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent: self.extent(),
+        };
+        command_buffer.begin_render_pass(
+            self.framebuffer,
+            self.render_pass.get_vk_renderpass(),
+            render_area,
+            &clear_values,
+        );
+    }
+
+    pub fn end(&self, command_buffer: &CommandBuffer) {
+        command_buffer.end_render_pass();
+    }
+
+    //Only valid to sample once the commands recorded between begin/end have executed
+    pub fn image_info(&self) -> ImageInfo {
+        ImageInfo::new(self.color_texture.image_view, self.sampler)
+    }
+}
+
+impl Drop for RenderTarget {
+    fn drop(&mut self) {
+        unsafe {
+            self.context
+                .device
+                .destroy_framebuffer(self.framebuffer, None);
+        }
+        self.render_pass.destroy();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn srgb_and_unorm_variants_are_swapped() {
+        assert_eq!(
+            ColorSpaceKind::Srgb.select_format(vk::Format::R8G8B8A8_UNORM),
+            vk::Format::R8G8B8A8_SRGB
+        );
+        assert_eq!(
+            ColorSpaceKind::Linear.select_format(vk::Format::B8G8R8A8_SRGB),
+            vk::Format::B8G8R8A8_UNORM
+        );
+    }
+
+    #[test]
+    fn matching_variants_are_kept() {
+        assert_eq!(
+            ColorSpaceKind::Srgb.select_format(vk::Format::R8_SRGB),
+            vk::Format::R8_SRGB
+        );
+        assert_eq!(
+            ColorSpaceKind::Linear.select_format(vk::Format::R8G8_UNORM),
+            vk::Format::R8G8_UNORM
+        );
+    }
+
+    #[test]
+    fn formats_without_variant_are_kept() {
+        for kind in [ColorSpaceKind::Srgb, ColorSpaceKind::Linear] {
+            assert_eq!(
+                kind.select_format(vk::Format::R16G16B16A16_SFLOAT),
+                vk::Format::R16G16B16A16_SFLOAT
+            );
+        }
+    }
+}
@@ -0,0 +1,302 @@
+use ash::vk;
+
+use crate::{
+    create_graphics_pipeline, material_set_layouts, shader_stage_infos, CommandBuffer,
+    FixedFunctionState, FrameContext, Pass, RenderPass, ShaderStageDesc, Texture, VertexBinding,
+    VertexBuffer, VertexFormat, MATERIAL_SET,
+};
+use std::sync::Arc;
+
+use super::context::{AllocationError, VulkanContext};
+
+const SKYBOX_VERT: &[u8] = include_bytes!("../../../resources/shaders/skybox.vert.spv");
+const SKYBOX_FRAG: &[u8] = include_bytes!("../../../resources/shaders/skybox.frag.spv");
+
+//Bytes of the view_proj push constant of skybox.vert
+const VIEW_PROJ_SIZE: usize = 4 * 16;
+
+//Corners of the cube, x in the lowest bit, then y and z. A set bit is +1.0
+fn cube_corner(index: usize) -> [f32; 3] {
+    let coord = |bit: usize| if index & (1 << bit) != 0 { 1.0 } else { -1.0 };
+    [coord(0), coord(1), coord(2)]
+}
+
+//Two triangles per face, culling is off since the camera is inside the cube
+fn cube_vertices() -> Vec<[f32; 3]> {
+    const FACES: [[usize; 4]; 6] = [
+        [1, 3, 7, 5],
+        [0, 4, 6, 2],
+        [2, 6, 7, 3],
+        [0, 1, 5, 4],
+        [4, 5, 7, 6],
+        [0, 2, 3, 1],
+    ];
+    FACES
+        .iter()
+        .flat_map(|[a, b, c, d]| [*a, *b, *c, *a, *c, *d])
+        .map(cube_corner)
+        .collect()
+}
+
+//Only the texels the scene left at the cleared depth of 1.0 get the sky, which is pushed to
+//exactly that depth
+pub fn skybox_depth_state(depth_enabled: bool) -> vk::PipelineDepthStencilStateCreateInfo<'static> {
+    vk::PipelineDepthStencilStateCreateInfo::default()
+        .depth_test_enable(depth_enabled)
+        .depth_write_enable(false)
+        .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL)
+        .depth_bounds_test_enable(false)
+        .min_depth_bounds(0.0)
+        .max_depth_bounds(1.0)
+        .stencil_test_enable(false)
+}
+
+/// A cubemap drawn behind the scene, see VulkanRenderer::set_skybox. The global set stays
+/// bound at set 0, the cubemap is bound at MATERIAL_SET.
+pub struct Skybox {
+    context: Arc<VulkanContext>,
+    pipeline: vk::Pipeline,
+    pipeline_layout: vk::PipelineLayout,
+    desc_layout: vk::DescriptorSetLayout,
+    desc_set: vk::DescriptorSet,
+    desc_pool: vk::DescriptorPool,
+    vert_module: vk::ShaderModule,
+    frag_module: vk::ShaderModule,
+    vertex_buffer: VertexBuffer,
+    cubemap: Option<Texture>,
+    //Projection times the rotation-only view, column-major
+    view_proj: [[f32; 4]; 4],
+}
+
+impl Skybox {
+    //Takes ownership of the cubemap, it is destroyed with the skybox or if this fails
+    pub fn new(
+        context: Arc<VulkanContext>,
+        render_pass: &RenderPass,
+        cubemap: Texture,
+    ) -> Result<Self, AllocationError> {
+        let vertices = cube_vertices();
+        let vertex_bytes = unsafe {
+            std::slice::from_raw_parts(
+                vertices.as_ptr() as *const u8,
+                std::mem::size_of_val(vertices.as_slice()),
+            )
+        };
+        let vertex_buffer = VertexBuffer::new(
+            context.clone(),
+            vertex_bytes.len() as vk::DeviceSize,
+            vertices.len() as u32,
+        )
+        .and_then(|mut vertex_buffer| {
+            vertex_buffer.upload_data(vertex_bytes)?;
+            Ok(vertex_buffer)
+        });
+        let vertex_buffer = match vertex_buffer {
+            Ok(vertex_buffer) => vertex_buffer,
+            Err(err) => {
+                cubemap.destroy(&context);
+                return Err(err);
+            }
+        };
+
+        let desc_layout_bindings = [vk::DescriptorSetLayoutBinding::default()
+            .binding(0)
+            .descriptor_count(1)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)];
+        let desc_layout_info =
+            vk::DescriptorSetLayoutCreateInfo::default().bindings(&desc_layout_bindings);
+        let desc_layout = unsafe {
+            context
+                .device
+                .create_descriptor_set_layout(&desc_layout_info, None)
+        }
+        .unwrap();
+        let (desc_set, desc_pool) = context.allocate_descriptor_set(desc_layout);
+        let image_info = [vk::DescriptorImageInfo::default()
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .image_view(cubemap.image_view)
+            .sampler(cubemap.image_sampler)];
+        let image_write = vk::WriteDescriptorSet::default()
+            .dst_set(desc_set)
+            .dst_binding(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(&image_info);
+        unsafe { context.device.update_descriptor_sets(&[image_write], &[]) };
+
+        let set_layouts = material_set_layouts(context.global_set_layout(), desc_layout);
+        let push_constant_ranges = [vk::PushConstantRange::default()
+            .stage_flags(vk::ShaderStageFlags::VERTEX)
+            .offset(0)
+            .size(VIEW_PROJ_SIZE as u32)];
+        let create_info = vk::PipelineLayoutCreateInfo::default()
+            .set_layouts(&set_layouts)
+            .push_constant_ranges(&push_constant_ranges);
+        let pipeline_layout =
+            unsafe { context.device.create_pipeline_layout(&create_info, None) }.unwrap();
+
+        let vertex_shader = Self::vertex_stage();
+        let fragment_shader = Self::fragment_stage();
+        let vert_module = vertex_shader.create_module(&context);
+        let frag_module = fragment_shader.create_module(&context);
+        let shader_stages =
+            shader_stage_infos(vert_module, &vertex_shader, frag_module, &fragment_shader);
+        let vertex_binding = VertexBinding {
+            formats: vec![VertexFormat::RGB32f],
+        };
+        let state = FixedFunctionState {
+            cull_mode: vk::CullModeFlags::NONE,
+            alpha_blend: false,
+            depth_stencil: skybox_depth_state(render_pass.has_depth()),
+        };
+        let pipeline = create_graphics_pipeline(
+            &context,
+            render_pass,
+            pipeline_layout,
+            &vertex_binding,
+            &shader_stages,
+            &state,
+        );
+
+        context.pipeline_created();
+        Ok(Self {
+            context,
+            pipeline,
+            pipeline_layout,
+            desc_layout,
+            desc_set,
+            desc_pool,
+            vert_module,
+            frag_module,
+            vertex_buffer,
+            cubemap: Some(cubemap),
+            view_proj: [
+                [1.0, 0.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0, 0.0],
+                [0.0, 0.0, 1.0, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        })
+    }
+
+    pub fn vertex_stage() -> ShaderStageDesc<'static> {
+        ShaderStageDesc::new(SKYBOX_VERT, "main").unwrap()
+    }
+
+    pub fn fragment_stage() -> ShaderStageDesc<'static> {
+        ShaderStageDesc::new(SKYBOX_FRAG, "main").unwrap()
+    }
+
+    //The camera's projection times its view with the translation stripped, so the sky never
+    //gets closer. Pushed with every recorded frame
+    pub fn set_view_proj(&mut self, view_proj: [[f32; 4]; 4]) {
+        self.view_proj = view_proj;
+    }
+}
+
+impl Pass for Skybox {
+    fn record(&self, command_buffer: &CommandBuffer, _frame: &FrameContext) {
+        command_buffer.bind_pipeline(self.pipeline, vk::PipelineBindPoint::GRAPHICS);
+        command_buffer.bind_descriptor_sets(
+            vk::PipelineBindPoint::GRAPHICS,
+            self.pipeline_layout,
+            MATERIAL_SET,
+            &[self.desc_set],
+        );
+        let view_proj = unsafe {
+            std::slice::from_raw_parts(self.view_proj.as_ptr() as *const u8, VIEW_PROJ_SIZE)
+        };
+        command_buffer.push_constants(
+            self.pipeline_layout,
+            vk::ShaderStageFlags::VERTEX,
+            0,
+            view_proj,
+        );
+        command_buffer.bind_vertex_buffer_object(0, &self.vertex_buffer);
+        command_buffer.draw_array(self.vertex_buffer.count(), 1, 0, 0);
+    }
+}
+
+impl Drop for Skybox {
+    fn drop(&mut self) {
+        self.context.pipeline_destroyed();
+        self.context
+            .free_descriptor_set(self.desc_set, self.desc_pool);
+        if let Some(cubemap) = self.cubemap.take() {
+            cubemap.destroy(&self.context);
+        }
+        unsafe {
+            self.context.device.destroy_pipeline(self.pipeline, None);
+            self.context
+                .device
+                .destroy_shader_module(self.vert_module, None);
+            self.context
+                .device
+                .destroy_shader_module(self.frag_module, None);
+            self.context
+                .device
+                .destroy_descriptor_set_layout(self.desc_layout, None);
+            self.context
+                .device
+                .destroy_pipeline_layout(self.pipeline_layout, None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ash::util::read_spv;
+    use std::io::Cursor;
+
+    #[test]
+    fn cube_has_two_triangles_per_face() {
+        let vertices = cube_vertices();
+        assert_eq!(vertices.len(), 36);
+        //Every triangle lies in one face, so one coordinate is the same for all three corners
+        for triangle in vertices.chunks(3) {
+            let flat_axes = (0..3)
+                .filter(|&axis| triangle.iter().all(|v| v[axis] == triangle[0][axis]))
+                .count();
+            assert_eq!(flat_axes, 1);
+        }
+        for axis in 0..3 {
+            for side in [-1.0, 1.0] {
+                let on_face = vertices.chunks(3).filter(|t| t[0][axis] == side);
+                assert_eq!(
+                    on_face
+                        .filter(|t| t.iter().all(|v| v[axis] == side))
+                        .count(),
+                    2
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn depth_is_tested_but_never_written() {
+        let state = skybox_depth_state(true);
+        assert_eq!(state.depth_test_enable, vk::TRUE);
+        assert_eq!(state.depth_write_enable, vk::FALSE);
+        assert_eq!(state.depth_compare_op, vk::CompareOp::LESS_OR_EQUAL);
+        assert_eq!(skybox_depth_state(false).depth_test_enable, vk::FALSE);
+    }
+
+    //The push constant is the only vertex input besides the position
+    #[test]
+    fn vertex_stage_reads_the_view_proj_push_constant() {
+        const OP_VARIABLE: u32 = 59;
+        const STORAGE_CLASS_PUSH_CONSTANT: u32 = 9;
+        let stage = Skybox::vertex_stage();
+        assert_eq!(stage.entry.to_str(), Ok("main"));
+        let words = read_spv(&mut Cursor::new(stage.code)).unwrap();
+        assert_eq!(words[0], 0x0723_0203);
+        let push_constants = words
+            .windows(4)
+            .filter(|inst| {
+                inst[0] == (4 << 16 | OP_VARIABLE) && inst[3] == STORAGE_CLASS_PUSH_CONSTANT
+            })
+            .count();
+        assert_eq!(push_constants, 1);
+    }
+}
@@ -6,6 +6,28 @@ use ash::{
     vk::{self, PhysicalDevice},
 };
 
+//Used when no preference is given, an sRGB swapchain so that shaders can output linear colors
+pub const DEFAULT_SURFACE_FORMATS: &[(vk::Format, vk::ColorSpaceKHR)] =
+    &[(vk::Format::B8G8R8A8_SRGB, vk::ColorSpaceKHR::SRGB_NONLINEAR)];
+
+/// How a window's swapchain is created, kept by the frame context for recreation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SwapchainConfig {
+    //Tried in order, the surface's first format is used if none of them are supported
+    pub preferred_formats: Vec<(vk::Format, vk::ColorSpaceKHR)>,
+    //Clamped to what the surface supports, None asks for one more than the minimum
+    pub image_count: Option<u32>,
+}
+
+impl Default for SwapchainConfig {
+    fn default() -> Self {
+        Self {
+            preferred_formats: DEFAULT_SURFACE_FORMATS.to_vec(),
+            image_count: None,
+        }
+    }
+}
+
 pub struct SwapchainInfo {
     pub surface_caps: vk::SurfaceCapabilitiesKHR,
     pub surface_formats: Vec<vk::SurfaceFormatKHR>,
@@ -29,23 +51,29 @@ impl Swapchain {
         physical_device: PhysicalDevice,
         surface: vk::SurfaceKHR,
         old_swapchain: Option<vk::SwapchainKHR>,
+        config: &SwapchainConfig,
+        queue_family_indices: &[u32],
     ) -> Self {
         let swapchain_info =
             SwapchainInfo::query_swapchain_support(surface_loader, physical_device, surface);
 
         let surface_caps = &swapchain_info.surface_caps;
-        let format = swapchain_info.choose_surface_format().unwrap();
+        let format = swapchain_info
+            .choose_surface_format(&config.preferred_formats)
+            .unwrap();
 
         let present_mode = swapchain_info.choose_present_mode();
 
         let current_extent = surface_caps.current_extent;
 
-        let mut image_count = surface_caps.min_image_count + 1;
-
-        if surface_caps.max_image_count > 0 && image_count > surface_caps.max_image_count {
-            image_count = surface_caps.max_image_count;
-        }
+        let image_count = choose_image_count(surface_caps, config.image_count);
         let old_swapchain = old_swapchain.unwrap_or(vk::SwapchainKHR::null());
+        //Rendering and presenting from different families shares the images between them
+        let sharing_mode = if queue_family_indices.len() > 1 {
+            vk::SharingMode::CONCURRENT
+        } else {
+            vk::SharingMode::EXCLUSIVE
+        };
         let create_info = vk::SwapchainCreateInfoKHR::default()
             .surface(surface)
             .min_image_count(image_count)
@@ -54,7 +82,8 @@ impl Swapchain {
             .image_extent(current_extent)
             .image_array_layers(1)
             .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
-            .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .image_sharing_mode(sharing_mode)
+            .queue_family_indices(queue_family_indices)
             .pre_transform(surface_caps.current_transform)
             .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
             .present_mode(present_mode)
@@ -80,11 +109,16 @@ impl Swapchain {
         self.swapchain_info.surface_caps.current_extent
     }
 
+    //Calling it again does nothing
     pub fn destroy(&mut self) {
+        if self.swapchain == vk::SwapchainKHR::null() {
+            return;
+        }
         unsafe {
             self.swapchain_loader
                 .destroy_swapchain(self.swapchain, None);
         }
+        self.swapchain = vk::SwapchainKHR::null();
     }
 }
 
@@ -100,20 +134,12 @@ impl SwapchainInfo {
             .unwrap_or(vk::PresentModeKHR::FIFO)
     }
 
-    pub fn choose_surface_format(&self) -> Option<vk::SurfaceFormatKHR> {
-        if self.surface_formats.is_empty() {
-            None
-        } else {
-            for surface_format in &self.surface_formats {
-                if surface_format.format == vk::Format::B8G8R8A8_SRGB
-                    && surface_format.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR
-                {
-                    return Some(*surface_format);
-                }
-            }
-
-            Some(self.surface_formats[0])
-        }
+    //Picks the first preference the surface supports, or the first available format
+    pub fn choose_surface_format(
+        &self,
+        preferred_formats: &[(vk::Format, vk::ColorSpaceKHR)],
+    ) -> Option<vk::SurfaceFormatKHR> {
+        choose_surface_format(&self.surface_formats, preferred_formats)
     }
 
     pub fn query_swapchain_support(
@@ -140,3 +166,144 @@ impl SwapchainInfo {
         }
     }
 }
+
+//A single UNDEFINED entry means the surface takes any format, the first preference then
+pub fn choose_surface_format(
+    surface_formats: &[vk::SurfaceFormatKHR],
+    preferred_formats: &[(vk::Format, vk::ColorSpaceKHR)],
+) -> Option<vk::SurfaceFormatKHR> {
+    if let [only] = surface_formats {
+        if only.format == vk::Format::UNDEFINED {
+            let &(format, color_space) = preferred_formats
+                .first()
+                .unwrap_or(&DEFAULT_SURFACE_FORMATS[0]);
+            return Some(vk::SurfaceFormatKHR {
+                format,
+                color_space,
+            });
+        }
+    }
+    preferred_formats
+        .iter()
+        .find_map(|&(format, color_space)| {
+            surface_formats
+                .iter()
+                .find(|surface_format| {
+                    surface_format.format == format && surface_format.color_space == color_space
+                })
+                .cloned()
+        })
+        .or_else(|| surface_formats.first().cloned())
+}
+
+//Defaults to one more than the minimum, a max_image_count of 0 means there is no upper limit
+pub fn choose_image_count(
+    surface_caps: &vk::SurfaceCapabilitiesKHR,
+    requested: Option<u32>,
+) -> u32 {
+    let image_count = requested.unwrap_or(surface_caps.min_image_count + 1);
+    let image_count = image_count.max(surface_caps.min_image_count);
+    if surface_caps.max_image_count > 0 {
+        image_count.min(surface_caps.max_image_count)
+    } else {
+        image_count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn surface_format(format: vk::Format) -> vk::SurfaceFormatKHR {
+        vk::SurfaceFormatKHR {
+            format,
+            color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+        }
+    }
+
+    fn surface_caps(min_image_count: u32, max_image_count: u32) -> vk::SurfaceCapabilitiesKHR {
+        vk::SurfaceCapabilitiesKHR {
+            min_image_count,
+            max_image_count,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn image_count_defaults_to_one_above_minimum() {
+        assert_eq!(choose_image_count(&surface_caps(2, 8), None), 3);
+        //Even when that is past the maximum
+        assert_eq!(choose_image_count(&surface_caps(2, 2), None), 2);
+    }
+
+    #[test]
+    fn image_count_is_clamped() {
+        let caps = surface_caps(2, 4);
+        assert_eq!(choose_image_count(&caps, Some(1)), 2);
+        assert_eq!(choose_image_count(&caps, Some(3)), 3);
+        assert_eq!(choose_image_count(&caps, Some(16)), 4);
+    }
+
+    #[test]
+    fn zero_max_image_count_is_unbounded() {
+        let caps = surface_caps(1, 0);
+        assert_eq!(choose_image_count(&caps, Some(16)), 16);
+        assert_eq!(choose_image_count(&caps, Some(0)), 1);
+        assert_eq!(choose_image_count(&caps, None), 2);
+    }
+
+    #[test]
+    fn surface_format_follows_preference_order() {
+        let available = [
+            surface_format(vk::Format::B8G8R8A8_UNORM),
+            surface_format(vk::Format::R8G8B8A8_SRGB),
+            surface_format(vk::Format::B8G8R8A8_SRGB),
+        ];
+        let preferred = [
+            (
+                vk::Format::R16G16B16A16_SFLOAT,
+                vk::ColorSpaceKHR::SRGB_NONLINEAR,
+            ),
+            (vk::Format::B8G8R8A8_SRGB, vk::ColorSpaceKHR::SRGB_NONLINEAR),
+            (vk::Format::R8G8B8A8_SRGB, vk::ColorSpaceKHR::SRGB_NONLINEAR),
+        ];
+        let chosen = choose_surface_format(&available, &preferred).unwrap();
+        assert_eq!(chosen, surface_format(vk::Format::B8G8R8A8_SRGB));
+    }
+
+    #[test]
+    fn surface_format_needs_matching_color_space() {
+        let available = [
+            surface_format(vk::Format::B8G8R8A8_UNORM),
+            surface_format(vk::Format::B8G8R8A8_SRGB),
+        ];
+        let preferred = [(
+            vk::Format::B8G8R8A8_SRGB,
+            vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT,
+        )];
+        let chosen = choose_surface_format(&available, &preferred).unwrap();
+        assert_eq!(chosen, surface_format(vk::Format::B8G8R8A8_UNORM));
+    }
+
+    #[test]
+    fn surface_format_falls_back_to_first() {
+        let available = [
+            surface_format(vk::Format::R8G8B8A8_UNORM),
+            surface_format(vk::Format::B8G8R8A8_UNORM),
+        ];
+        let chosen = choose_surface_format(&available, DEFAULT_SURFACE_FORMATS).unwrap();
+        assert_eq!(chosen, available[0]);
+        assert_eq!(choose_surface_format(&[], DEFAULT_SURFACE_FORMATS), None);
+    }
+
+    #[test]
+    fn surface_format_undefined_takes_any() {
+        let available = [surface_format(vk::Format::UNDEFINED)];
+        let preferred = [(vk::Format::R8G8B8A8_SRGB, vk::ColorSpaceKHR::SRGB_NONLINEAR)];
+        let chosen = choose_surface_format(&available, &preferred).unwrap();
+        assert_eq!(chosen, surface_format(vk::Format::R8G8B8A8_SRGB));
+
+        let chosen = choose_surface_format(&available, &[]).unwrap();
+        assert_eq!(chosen.format, DEFAULT_SURFACE_FORMATS[0].0);
+    }
+}
@@ -83,6 +83,15 @@ impl SwapData {
         )
     }
 
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    //After swapchain recreation, the old image fences refer to images that are gone
+    pub fn reset_images(&mut self, image_count: usize) {
+        self.images_in_flight = vec![vk::Fence::null(); image_count];
+    }
+
     pub fn step_frame(&mut self) {
         self.frame = (self.frame + 1) % self.frames_in_flight;
     }
@@ -103,3 +112,46 @@ impl SwapData {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //Only the bookkeeping is exercised, so the sync objects can stay null
+    fn swap_data(frames_in_flight: usize, image_count: usize) -> SwapData {
+        SwapData {
+            frames_in_flight,
+            frame: 0,
+            images_in_flight: vec![vk::Fence::null(); image_count],
+            in_flight_fences: vec![vk::Fence::null(); frames_in_flight],
+            image_available_semaphores: vec![vk::Semaphore::null(); frames_in_flight],
+            render_finished_semaphores: vec![vk::Semaphore::null(); frames_in_flight],
+        }
+    }
+
+    #[test]
+    fn frame_index_wraps_at_frames_in_flight() {
+        let mut swap_data = swap_data(3, 4);
+        let mut frames = vec![];
+        for _ in 0..7 {
+            frames.push(swap_data.frame());
+            swap_data.step_frame();
+        }
+        assert_eq!(frames, vec![0, 1, 2, 0, 1, 2, 0]);
+    }
+
+    #[test]
+    fn reset_images_sizes_to_the_image_count() {
+        use ash::vk::Handle;
+
+        let mut swap_data = swap_data(3, 2);
+        swap_data.images_in_flight[1] = vk::Fence::from_raw(1);
+
+        swap_data.reset_images(5);
+        assert_eq!(swap_data.images_in_flight, vec![vk::Fence::null(); 5]);
+
+        swap_data.reset_images(2);
+        assert_eq!(swap_data.images_in_flight, vec![vk::Fence::null(); 2]);
+        assert_eq!(swap_data.in_flight_fences.len(), 3);
+    }
+}
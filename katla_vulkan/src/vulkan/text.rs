@@ -0,0 +1,446 @@
+use ash::vk;
+
+use crate::{
+    create_graphics_pipeline, depth_stencil_state, shader_stage_infos, Buffer, CommandBuffer,
+    FixedFunctionState, FrameContext, RenderPass, SamplerConfig, ShaderStageDesc, Texture,
+    VertexBinding, VertexFormat,
+};
+use std::sync::Arc;
+
+use super::context::{AllocationError, VulkanContext};
+
+const TEXT_VERT: &[u8] = include_bytes!("../../../resources/shaders/text.vert.spv");
+const TEXT_FRAG: &[u8] = include_bytes!("../../../resources/shaders/text.frag.spv");
+
+//Printable ASCII, ' ' to '~'. Anything else is drawn as '?'
+const FIRST_GLYPH: u8 = b' ';
+const LAST_GLYPH: u8 = b'~';
+pub const GLYPH_WIDTH: u32 = 5;
+pub const GLYPH_HEIGHT: u32 = 7;
+//A glyph and a column and row of spacing, so neighbouring glyphs never bleed into each other
+const CELL_WIDTH: u32 = GLYPH_WIDTH + 1;
+const CELL_HEIGHT: u32 = GLYPH_HEIGHT + 1;
+const ATLAS_COLUMNS: u32 = 16;
+pub const ATLAS_WIDTH: u32 = ATLAS_COLUMNS * CELL_WIDTH;
+pub const ATLAS_HEIGHT: u32 = 6 * CELL_HEIGHT;
+
+//Quads that fit in a frame's vertex buffer, text past this is cut off
+pub const MAX_TEXT_QUADS: usize = 1024;
+const VERTICES_PER_QUAD: usize = 6;
+
+//5x7 glyphs from ' ' to '~', one byte per column from left to right with the top row in the
+//lowest bit
+#[rustfmt::skip]
+const FONT: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], [0x00, 0x00, 0x5f, 0x00, 0x00], [0x00, 0x07, 0x00, 0x07, 0x00],
+    [0x14, 0x7f, 0x14, 0x7f, 0x14], [0x24, 0x2a, 0x7f, 0x2a, 0x12], [0x23, 0x13, 0x08, 0x64, 0x62],
+    [0x36, 0x49, 0x55, 0x22, 0x50], [0x00, 0x05, 0x03, 0x00, 0x00], [0x00, 0x1c, 0x22, 0x41, 0x00],
+    [0x00, 0x41, 0x22, 0x1c, 0x00], [0x08, 0x2a, 0x1c, 0x2a, 0x08], [0x08, 0x08, 0x3e, 0x08, 0x08],
+    [0x00, 0x50, 0x30, 0x00, 0x00], [0x08, 0x08, 0x08, 0x08, 0x08], [0x00, 0x60, 0x60, 0x00, 0x00],
+    [0x20, 0x10, 0x08, 0x04, 0x02], [0x3e, 0x51, 0x49, 0x45, 0x3e], [0x00, 0x42, 0x7f, 0x40, 0x00],
+    [0x42, 0x61, 0x51, 0x49, 0x46], [0x21, 0x41, 0x45, 0x4b, 0x31], [0x18, 0x14, 0x12, 0x7f, 0x10],
+    [0x27, 0x45, 0x45, 0x45, 0x39], [0x3c, 0x4a, 0x49, 0x49, 0x30], [0x01, 0x71, 0x09, 0x05, 0x03],
+    [0x36, 0x49, 0x49, 0x49, 0x36], [0x06, 0x49, 0x49, 0x29, 0x1e], [0x00, 0x36, 0x36, 0x00, 0x00],
+    [0x00, 0x56, 0x36, 0x00, 0x00], [0x08, 0x14, 0x22, 0x41, 0x00], [0x14, 0x14, 0x14, 0x14, 0x14],
+    [0x00, 0x41, 0x22, 0x14, 0x08], [0x02, 0x01, 0x51, 0x09, 0x06], [0x32, 0x49, 0x79, 0x41, 0x3e],
+    [0x7e, 0x11, 0x11, 0x11, 0x7e], [0x7f, 0x49, 0x49, 0x49, 0x36], [0x3e, 0x41, 0x41, 0x41, 0x22],
+    [0x7f, 0x41, 0x41, 0x22, 0x1c], [0x7f, 0x49, 0x49, 0x49, 0x41], [0x7f, 0x09, 0x09, 0x09, 0x01],
+    [0x3e, 0x41, 0x49, 0x49, 0x7a], [0x7f, 0x08, 0x08, 0x08, 0x7f], [0x00, 0x41, 0x7f, 0x41, 0x00],
+    [0x20, 0x40, 0x41, 0x3f, 0x01], [0x7f, 0x08, 0x14, 0x22, 0x41], [0x7f, 0x40, 0x40, 0x40, 0x40],
+    [0x7f, 0x02, 0x0c, 0x02, 0x7f], [0x7f, 0x04, 0x08, 0x10, 0x7f], [0x3e, 0x41, 0x41, 0x41, 0x3e],
+    [0x7f, 0x09, 0x09, 0x09, 0x06], [0x3e, 0x41, 0x51, 0x21, 0x5e], [0x7f, 0x09, 0x19, 0x29, 0x46],
+    [0x46, 0x49, 0x49, 0x49, 0x31], [0x01, 0x01, 0x7f, 0x01, 0x01], [0x3f, 0x40, 0x40, 0x40, 0x3f],
+    [0x1f, 0x20, 0x40, 0x20, 0x1f], [0x3f, 0x40, 0x38, 0x40, 0x3f], [0x63, 0x14, 0x08, 0x14, 0x63],
+    [0x07, 0x08, 0x70, 0x08, 0x07], [0x61, 0x51, 0x49, 0x45, 0x43], [0x00, 0x7f, 0x41, 0x41, 0x00],
+    [0x02, 0x04, 0x08, 0x10, 0x20], [0x00, 0x41, 0x41, 0x7f, 0x00], [0x04, 0x02, 0x01, 0x02, 0x04],
+    [0x40, 0x40, 0x40, 0x40, 0x40], [0x00, 0x01, 0x02, 0x04, 0x00], [0x20, 0x54, 0x54, 0x54, 0x78],
+    [0x7f, 0x48, 0x44, 0x44, 0x38], [0x38, 0x44, 0x44, 0x44, 0x20], [0x38, 0x44, 0x44, 0x48, 0x7f],
+    [0x38, 0x54, 0x54, 0x54, 0x18], [0x08, 0x7e, 0x09, 0x01, 0x02], [0x0c, 0x52, 0x52, 0x52, 0x3e],
+    [0x7f, 0x08, 0x04, 0x04, 0x78], [0x00, 0x44, 0x7d, 0x40, 0x00], [0x20, 0x40, 0x44, 0x3d, 0x00],
+    [0x7f, 0x10, 0x28, 0x44, 0x00], [0x00, 0x41, 0x7f, 0x40, 0x00], [0x7c, 0x04, 0x18, 0x04, 0x78],
+    [0x7c, 0x08, 0x04, 0x04, 0x78], [0x38, 0x44, 0x44, 0x44, 0x38], [0x7c, 0x14, 0x14, 0x14, 0x08],
+    [0x08, 0x14, 0x14, 0x18, 0x7c], [0x7c, 0x08, 0x04, 0x04, 0x08], [0x48, 0x54, 0x54, 0x54, 0x20],
+    [0x04, 0x3f, 0x44, 0x40, 0x20], [0x3c, 0x40, 0x40, 0x20, 0x7c], [0x1c, 0x20, 0x40, 0x20, 0x1c],
+    [0x3c, 0x40, 0x30, 0x40, 0x3c], [0x44, 0x28, 0x10, 0x28, 0x44], [0x0c, 0x50, 0x50, 0x50, 0x3c],
+    [0x44, 0x64, 0x54, 0x4c, 0x44], [0x00, 0x08, 0x36, 0x41, 0x00], [0x00, 0x00, 0x7f, 0x00, 0x00],
+    [0x00, 0x41, 0x36, 0x08, 0x00], [0x08, 0x04, 0x08, 0x10, 0x08],
+];
+
+/// A corner of a glyph quad, laid out like the inputs of text.vert
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextVertex {
+    //Normalized device coordinates
+    pub pos: [f32; 2],
+    pub tex_coords: [f32; 2],
+}
+
+//One byte of coverage per texel, ATLAS_COLUMNS glyphs per row with ' ' in the top left
+pub fn bake_font_atlas() -> Vec<u8> {
+    let mut pixels = vec![0; (ATLAS_WIDTH * ATLAS_HEIGHT) as usize];
+    for (index, columns) in FONT.iter().enumerate() {
+        let (cell_x, cell_y) = atlas_cell(index as u32);
+        for (x, column) in columns.iter().enumerate() {
+            for y in 0..GLYPH_HEIGHT {
+                if column & (1 << y) != 0 {
+                    let texel = (cell_y + y) * ATLAS_WIDTH + cell_x + x as u32;
+                    pixels[texel as usize] = 0xff;
+                }
+            }
+        }
+    }
+    pixels
+}
+
+//Top left texel of a glyph's cell
+fn atlas_cell(glyph_index: u32) -> (u32, u32) {
+    (
+        glyph_index % ATLAS_COLUMNS * CELL_WIDTH,
+        glyph_index / ATLAS_COLUMNS * CELL_HEIGHT,
+    )
+}
+
+fn glyph_index(c: char) -> u32 {
+    let c = match c {
+        ' '..='~' => c as u8,
+        _ => b'?',
+    };
+    (c - FIRST_GLYPH) as u32
+}
+
+//Two triangles per visible character, spaces and line breaks only move the pen. (x, y) is the
+//top left of the first character in pixels from the top left of the target, each font texel
+//covers scale pixels
+pub fn layout_text(
+    x: f32,
+    y: f32,
+    text: &str,
+    scale: f32,
+    extent: vk::Extent2D,
+) -> Vec<TextVertex> {
+    debug_assert!(LAST_GLYPH - FIRST_GLYPH + 1 == FONT.len() as u8);
+    let to_ndc = |px: f32, py: f32| {
+        [
+            px / extent.width as f32 * 2.0 - 1.0,
+            py / extent.height as f32 * 2.0 - 1.0,
+        ]
+    };
+    let glyph_size = (GLYPH_WIDTH as f32 * scale, GLYPH_HEIGHT as f32 * scale);
+    let mut vertices = vec![];
+    let (mut pen_x, mut pen_y) = (x, y);
+    for c in text.chars() {
+        match c {
+            '\n' => {
+                pen_x = x;
+                pen_y += CELL_HEIGHT as f32 * scale;
+                continue;
+            }
+            ' ' => {}
+            _ => {
+                let (cell_x, cell_y) = atlas_cell(glyph_index(c));
+                let u0 = cell_x as f32 / ATLAS_WIDTH as f32;
+                let v0 = cell_y as f32 / ATLAS_HEIGHT as f32;
+                let u1 = (cell_x + GLYPH_WIDTH) as f32 / ATLAS_WIDTH as f32;
+                let v1 = (cell_y + GLYPH_HEIGHT) as f32 / ATLAS_HEIGHT as f32;
+                let top_left = TextVertex {
+                    pos: to_ndc(pen_x, pen_y),
+                    tex_coords: [u0, v0],
+                };
+                let top_right = TextVertex {
+                    pos: to_ndc(pen_x + glyph_size.0, pen_y),
+                    tex_coords: [u1, v0],
+                };
+                let bottom_left = TextVertex {
+                    pos: to_ndc(pen_x, pen_y + glyph_size.1),
+                    tex_coords: [u0, v1],
+                };
+                let bottom_right = TextVertex {
+                    pos: to_ndc(pen_x + glyph_size.0, pen_y + glyph_size.1),
+                    tex_coords: [u1, v1],
+                };
+                vertices.extend_from_slice(&[
+                    top_left,
+                    top_right,
+                    bottom_left,
+                    bottom_left,
+                    top_right,
+                    bottom_right,
+                ]);
+            }
+        }
+        pen_x += CELL_WIDTH as f32 * scale;
+    }
+    vertices
+}
+
+/// Draws screen-space text with the embedded bitmap font, on top of whatever the pass has
+/// drawn so far. Call begin once per frame before any draw_text.
+pub struct TextRenderer {
+    context: Arc<VulkanContext>,
+    pipeline: vk::Pipeline,
+    pipeline_layout: vk::PipelineLayout,
+    desc_layout: vk::DescriptorSetLayout,
+    desc_set: vk::DescriptorSet,
+    desc_pool: vk::DescriptorPool,
+    vert_module: vk::ShaderModule,
+    frag_module: vk::ShaderModule,
+    atlas: Option<Texture>,
+    //One per frame in flight, rewritten by the frame that owns it
+    vertex_buffers: Vec<Buffer>,
+    frame: Option<FrameContext>,
+    used_vertices: usize,
+    //Pixels per font texel
+    pub scale: f32,
+}
+
+impl TextRenderer {
+    pub fn new(
+        context: Arc<VulkanContext>,
+        render_pass: &RenderPass,
+        frames_in_flight: usize,
+    ) -> Result<Self, AllocationError> {
+        let mut vertex_buffers = vec![];
+        for _ in 0..frames_in_flight {
+            vertex_buffers.push(Buffer::new(
+                context.clone(),
+                (MAX_TEXT_QUADS * VERTICES_PER_QUAD * std::mem::size_of::<TextVertex>())
+                    as vk::DeviceSize,
+                vk::BufferUsageFlags::VERTEX_BUFFER,
+                gpu_allocator::MemoryLocation::CpuToGpu,
+            )?);
+        }
+        let sampler_config = SamplerConfig {
+            mag_filter: vk::Filter::NEAREST,
+            min_filter: vk::Filter::NEAREST,
+            mipmap_mode: vk::SamplerMipmapMode::NEAREST,
+            address_mode_u: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            address_mode_v: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            anisotropy_enable: false,
+            ..Default::default()
+        };
+        let atlas = Texture::create_image(
+            &context,
+            ATLAS_WIDTH,
+            ATLAS_HEIGHT,
+            vk::Format::R8_UNORM,
+            &bake_font_atlas(),
+            &sampler_config,
+        )?;
+
+        let desc_layout_bindings = [vk::DescriptorSetLayoutBinding::default()
+            .binding(0)
+            .descriptor_count(1)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)];
+        let desc_layout_info =
+            vk::DescriptorSetLayoutCreateInfo::default().bindings(&desc_layout_bindings);
+        let desc_layout = unsafe {
+            context
+                .device
+                .create_descriptor_set_layout(&desc_layout_info, None)
+        }
+        .unwrap();
+        //The atlas never changes, so a single set serves every frame
+        let (desc_set, desc_pool) = context.allocate_descriptor_set(desc_layout);
+        let image_info = [vk::DescriptorImageInfo::default()
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .image_view(atlas.image_view)
+            .sampler(atlas.image_sampler)];
+        let image_write = vk::WriteDescriptorSet::default()
+            .dst_set(desc_set)
+            .dst_binding(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(&image_info);
+        unsafe { context.device.update_descriptor_sets(&[image_write], &[]) };
+
+        let set_layouts = [desc_layout];
+        let create_info = vk::PipelineLayoutCreateInfo::default().set_layouts(&set_layouts);
+        let pipeline_layout =
+            unsafe { context.device.create_pipeline_layout(&create_info, None) }.unwrap();
+
+        let vertex_shader = Self::vertex_stage();
+        let fragment_shader = Self::fragment_stage();
+        let vert_module = vertex_shader.create_module(&context);
+        let frag_module = fragment_shader.create_module(&context);
+        let shader_stages =
+            shader_stage_infos(vert_module, &vertex_shader, frag_module, &fragment_shader);
+        let vertex_binding = VertexBinding {
+            formats: vec![VertexFormat::RG32f, VertexFormat::RG32f],
+        };
+        //Drawn over the scene, so the depth it left behind is ignored
+        let state = FixedFunctionState {
+            cull_mode: vk::CullModeFlags::NONE,
+            alpha_blend: true,
+            depth_stencil: depth_stencil_state(false),
+        };
+        let pipeline = create_graphics_pipeline(
+            &context,
+            render_pass,
+            pipeline_layout,
+            &vertex_binding,
+            &shader_stages,
+            &state,
+        );
+
+        context.pipeline_created();
+        Ok(Self {
+            context,
+            pipeline,
+            pipeline_layout,
+            desc_layout,
+            desc_set,
+            desc_pool,
+            vert_module,
+            frag_module,
+            atlas: Some(atlas),
+            vertex_buffers,
+            frame: None,
+            used_vertices: 0,
+            scale: 2.0,
+        })
+    }
+
+    pub fn vertex_stage() -> ShaderStageDesc<'static> {
+        ShaderStageDesc::new(TEXT_VERT, "main").unwrap()
+    }
+
+    pub fn fragment_stage() -> ShaderStageDesc<'static> {
+        ShaderStageDesc::new(TEXT_FRAG, "main").unwrap()
+    }
+
+    //Binds the text pipeline and starts filling the frame's vertex buffer from the beginning
+    pub fn begin(&mut self, command_buffer: &CommandBuffer, frame: &FrameContext) {
+        self.frame = Some(*frame);
+        self.used_vertices = 0;
+        command_buffer.bind_pipeline(self.pipeline, vk::PipelineBindPoint::GRAPHICS);
+        command_buffer.bind_descriptor_sets(
+            vk::PipelineBindPoint::GRAPHICS,
+            self.pipeline_layout,
+            0,
+            &[self.desc_set],
+        );
+        command_buffer.bind_vertex_buffers(
+            0,
+            &[self.vertex_buffers[frame.frame_index].vk_buffer()],
+            &[0],
+        );
+    }
+
+    //(x, y) is the top left of the text in pixels. Returns the number of quads drawn, which is
+    //less than the visible characters once MAX_TEXT_QUADS is reached for the frame
+    pub fn draw_text(&mut self, command_buffer: &CommandBuffer, x: f32, y: f32, text: &str) -> u32 {
+        let frame = match self.frame {
+            Some(frame) => frame,
+            None => panic!("TextRenderer::begin has to be called before draw_text"),
+        };
+        let mut vertices = layout_text(x, y, text, self.scale, frame.extent);
+        let capacity = MAX_TEXT_QUADS * VERTICES_PER_QUAD - self.used_vertices;
+        vertices.truncate(capacity);
+        if vertices.is_empty() {
+            return 0;
+        }
+        let bytes = unsafe {
+            std::slice::from_raw_parts(
+                vertices.as_ptr() as *const u8,
+                std::mem::size_of_val(vertices.as_slice()),
+            )
+        };
+        let offset = self.used_vertices * std::mem::size_of::<TextVertex>();
+        self.vertex_buffers[frame.frame_index].write_at(offset as vk::DeviceSize, bytes);
+        command_buffer.draw_array(vertices.len() as u32, 1, self.used_vertices as u32, 0);
+        self.used_vertices += vertices.len();
+        (vertices.len() / VERTICES_PER_QUAD) as u32
+    }
+}
+
+impl Drop for TextRenderer {
+    fn drop(&mut self) {
+        self.context.pipeline_destroyed();
+        self.context
+            .free_descriptor_set(self.desc_set, self.desc_pool);
+        if let Some(atlas) = self.atlas.take() {
+            atlas.destroy(&self.context);
+        }
+        unsafe {
+            self.context.device.destroy_pipeline(self.pipeline, None);
+            self.context
+                .device
+                .destroy_shader_module(self.vert_module, None);
+            self.context
+                .device
+                .destroy_shader_module(self.frag_module, None);
+            self.context
+                .device
+                .destroy_descriptor_set_layout(self.desc_layout, None);
+            self.context
+                .device
+                .destroy_pipeline_layout(self.pipeline_layout, None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXTENT: vk::Extent2D = vk::Extent2D {
+        width: 800,
+        height: 600,
+    };
+
+    fn quads(text: &str) -> usize {
+        let vertices = layout_text(10.0, 10.0, text, 2.0, EXTENT);
+        assert_eq!(vertices.len() % VERTICES_PER_QUAD, 0);
+        vertices.len() / VERTICES_PER_QUAD
+    }
+
+    #[test]
+    fn one_quad_per_character_except_spaces() {
+        assert_eq!(quads(""), 0);
+        assert_eq!(quads("FPS"), 3);
+        assert_eq!(quads("60 FPS 16.67 ms"), 12);
+        assert_eq!(quads("   "), 0);
+        assert_eq!(quads("a\nb"), 2);
+        //Characters outside the font still take a quad, they are drawn as '?'
+        assert_eq!(quads("é"), 1);
+    }
+
+    #[test]
+    fn spaces_advance_the_pen() {
+        let vertices = layout_text(0.0, 0.0, "a b", 1.0, EXTENT);
+        let first_x = vertices[0].pos[0];
+        let second_x = vertices[VERTICES_PER_QUAD].pos[0];
+        let cell = 2.0 * CELL_WIDTH as f32 / EXTENT.width as f32;
+        assert!((second_x - first_x - 2.0 * cell).abs() < 1e-6);
+    }
+
+    #[test]
+    fn text_starts_at_the_top_left_in_device_coordinates() {
+        let vertices = layout_text(0.0, 0.0, "A", 1.0, EXTENT);
+        assert_eq!(vertices[0].pos, [-1.0, -1.0]);
+        let (cell_x, cell_y) = atlas_cell(glyph_index('A'));
+        assert_eq!(
+            vertices[0].tex_coords,
+            [
+                cell_x as f32 / ATLAS_WIDTH as f32,
+                cell_y as f32 / ATLAS_HEIGHT as f32
+            ]
+        );
+    }
+
+    #[test]
+    fn atlas_holds_every_glyph_in_its_own_cell() {
+        let atlas = bake_font_atlas();
+        assert_eq!(atlas.len(), (ATLAS_WIDTH * ATLAS_HEIGHT) as usize);
+        assert!(FONT.len() as u32 <= ATLAS_COLUMNS * (ATLAS_HEIGHT / CELL_HEIGHT));
+        //'|' is a single full column in the middle of its glyph
+        let (cell_x, cell_y) = atlas_cell(glyph_index('|'));
+        for y in 0..CELL_HEIGHT {
+            for x in 0..CELL_WIDTH {
+                let texel = atlas[((cell_y + y) * ATLAS_WIDTH + cell_x + x) as usize];
+                let expected = if x == 2 && y < GLYPH_HEIGHT { 0xff } else { 0 };
+                assert_eq!(texel, expected, "texel ({}, {})", x, y);
+            }
+        }
+    }
+}
@@ -1,11 +1,106 @@
-use super::VulkanContext;
+use super::{AllocationError, CommandBuffer, VulkanContext};
 use crate::VulkanFrameCtx;
 
-use std::time::Instant;
+use std::{
+    hash::{Hash, Hasher},
+    time::Instant,
+};
 
 use ash::vk;
 use gpu_allocator::vulkan::Allocation;
 
+/// Used as the key of the context's sampler cache, the float fields are compared bitwise.
+#[derive(Debug, Clone, Copy)]
+pub struct SamplerConfig {
+    pub mag_filter: vk::Filter,
+    pub min_filter: vk::Filter,
+    pub mipmap_mode: vk::SamplerMipmapMode,
+    pub address_mode_u: vk::SamplerAddressMode,
+    pub address_mode_v: vk::SamplerAddressMode,
+    pub anisotropy_enable: bool,
+    //Added to the computed mip level, negative is sharper. Clamped to the device limit
+    pub mip_lod_bias: f32,
+    //Range of mip levels that can be sampled, max_lod is raised to min_lod if it is lower
+    pub min_lod: f32,
+    pub max_lod: f32,
+}
+
+impl SamplerConfig {
+    fn float_bits(&self) -> [u32; 3] {
+        [
+            self.mip_lod_bias.to_bits(),
+            self.min_lod.to_bits(),
+            self.max_lod.to_bits(),
+        ]
+    }
+}
+
+impl PartialEq for SamplerConfig {
+    fn eq(&self, other: &Self) -> bool {
+        self.mag_filter == other.mag_filter
+            && self.min_filter == other.min_filter
+            && self.mipmap_mode == other.mipmap_mode
+            && self.address_mode_u == other.address_mode_u
+            && self.address_mode_v == other.address_mode_v
+            && self.anisotropy_enable == other.anisotropy_enable
+            && self.float_bits() == other.float_bits()
+    }
+}
+
+impl Eq for SamplerConfig {}
+
+impl Hash for SamplerConfig {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.mag_filter.hash(state);
+        self.min_filter.hash(state);
+        self.mipmap_mode.hash(state);
+        self.address_mode_u.hash(state);
+        self.address_mode_v.hash(state);
+        self.anisotropy_enable.hash(state);
+        self.float_bits().hash(state);
+    }
+}
+
+impl Default for SamplerConfig {
+    fn default() -> Self {
+        Self {
+            mag_filter: vk::Filter::LINEAR,
+            min_filter: vk::Filter::LINEAR,
+            mipmap_mode: vk::SamplerMipmapMode::LINEAR,
+            address_mode_u: vk::SamplerAddressMode::REPEAT,
+            address_mode_v: vk::SamplerAddressMode::REPEAT,
+            anisotropy_enable: true,
+            mip_lod_bias: 0.0,
+            min_lod: 0.0,
+            max_lod: 0.0,
+        }
+    }
+}
+
+impl SamplerConfig {
+    pub fn create_info(&self) -> vk::SamplerCreateInfo<'static> {
+        vk::SamplerCreateInfo::default()
+            .anisotropy_enable(self.anisotropy_enable)
+            .max_anisotropy(if self.anisotropy_enable { 16.0 } else { 1.0 })
+            .border_color(vk::BorderColor::INT_OPAQUE_BLACK)
+            .unnormalized_coordinates(false)
+            .min_filter(self.min_filter)
+            .mag_filter(self.mag_filter)
+            .address_mode_u(self.address_mode_u)
+            .address_mode_v(self.address_mode_v)
+            .address_mode_w(vk::SamplerAddressMode::REPEAT)
+            .compare_enable(false)
+            .compare_op(vk::CompareOp::ALWAYS)
+            .mipmap_mode(self.mipmap_mode)
+            .mip_lod_bias(self.mip_lod_bias)
+            .min_lod(self.min_lod)
+            .max_lod(self.max_lod.max(self.min_lod))
+    }
+}
+
+//Layers of a cubemap image, one per face
+pub const CUBEMAP_FACES: usize = 6;
+
 pub struct Texture {
     pub width: u32,
     pub height: u32,
@@ -20,7 +115,7 @@ impl Texture {
     fn create_staging_buffer(
         context: &VulkanContext,
         size: vk::DeviceSize,
-    ) -> (vk::Buffer, Allocation) {
+    ) -> Result<(vk::Buffer, Allocation), AllocationError> {
         let create_info = vk::BufferCreateInfo::default()
             .sharing_mode(vk::SharingMode::EXCLUSIVE)
             .usage(vk::BufferUsageFlags::TRANSFER_SRC)
@@ -30,9 +125,9 @@ impl Texture {
     }
 
     fn transition_image_layout(
-        context: &VulkanContext,
-        command_buffer: vk::CommandBuffer,
+        command_buffer: &CommandBuffer,
         image: vk::Image,
+        layer_count: u32,
         old_layout: vk::ImageLayout,
         new_layout: vk::ImageLayout,
     ) {
@@ -41,51 +136,51 @@ impl Texture {
             .base_mip_level(0)
             .level_count(1)
             .base_array_layer(0)
-            .layer_count(1);
+            .layer_count(layer_count);
 
-        unsafe {
-            let src_stage_mask;
-            let dst_stage_mask;
-            let mut barrier_default = vk::ImageMemoryBarrier::default()
-                .old_layout(old_layout)
-                .new_layout(new_layout)
-                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
-                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
-                .image(image)
-                .subresource_range(subresource_range);
-
-            if old_layout == vk::ImageLayout::UNDEFINED
-                && new_layout == vk::ImageLayout::TRANSFER_DST_OPTIMAL
-            {
-                barrier_default = barrier_default
-                    .src_access_mask(vk::AccessFlags::empty())
-                    .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE);
-
-                src_stage_mask = vk::PipelineStageFlags::TOP_OF_PIPE;
-                dst_stage_mask = vk::PipelineStageFlags::TRANSFER;
-            } else if old_layout == vk::ImageLayout::TRANSFER_DST_OPTIMAL
-                && new_layout == vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
-            {
-                barrier_default = barrier_default
-                    .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
-                    .dst_access_mask(vk::AccessFlags::SHADER_READ);
-
-                src_stage_mask = vk::PipelineStageFlags::TRANSFER;
-                dst_stage_mask = vk::PipelineStageFlags::FRAGMENT_SHADER;
-            } else {
-                panic!("unsupported layout transition!");
-            }
+        let (src_stage_mask, dst_stage_mask) = if old_layout == vk::ImageLayout::UNDEFINED
+            && new_layout == vk::ImageLayout::TRANSFER_DST_OPTIMAL
+        {
+            (
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::PipelineStageFlags::TRANSFER,
+            )
+        } else if old_layout == vk::ImageLayout::TRANSFER_DST_OPTIMAL
+            && new_layout == vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+        {
+            (
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::FRAGMENT_SHADER,
+            )
+        } else {
+            panic!("unsupported layout transition!");
+        };
 
-            context.device.cmd_pipeline_barrier(
-                command_buffer,
-                src_stage_mask,
-                dst_stage_mask,
-                vk::DependencyFlags::empty(),
-                &[],
-                &[],
-                &[barrier_default],
-            );
-        }
+        command_buffer.image_barrier(
+            image,
+            old_layout,
+            new_layout,
+            src_stage_mask,
+            dst_stage_mask,
+            subresource_range,
+        );
+    }
+
+    //The layers are read one after the other from the start of the buffer
+    fn copy_region(
+        offset: vk::Offset3D,
+        extent: vk::Extent3D,
+        layer_count: u32,
+    ) -> vk::BufferImageCopy {
+        let subresources = vk::ImageSubresourceLayers::default()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .mip_level(0)
+            .base_array_layer(0)
+            .layer_count(layer_count);
+        vk::BufferImageCopy::default()
+            .image_offset(offset)
+            .image_extent(extent)
+            .image_subresource(subresources)
     }
 
     fn copy_buffer_to_image(
@@ -94,18 +189,10 @@ impl Texture {
         src_buffer: vk::Buffer,
         dst_image: vk::Image,
         dst_image_layout: vk::ImageLayout,
-        extent: vk::Extent3D,
+        regions: vk::BufferImageCopy,
     ) {
         //TODO: expose a transfer command buffer?
-        let subresources = vk::ImageSubresourceLayers::default()
-            .aspect_mask(vk::ImageAspectFlags::COLOR)
-            .mip_level(0)
-            .base_array_layer(0)
-            .layer_count(1);
         unsafe {
-            let regions = vk::BufferImageCopy::default()
-                .image_extent(extent)
-                .image_subresource(subresources);
             context.device.cmd_copy_buffer_to_image(
                 command_buffer,
                 src_buffer,
@@ -116,30 +203,14 @@ impl Texture {
         }
     }
 
-    fn create_texture_sampler(context: &VulkanContext) -> vk::Sampler {
-        let create_info = vk::SamplerCreateInfo::default()
-            .anisotropy_enable(true)
-            .max_anisotropy(16.0)
-            .border_color(vk::BorderColor::INT_OPAQUE_BLACK)
-            .unnormalized_coordinates(false)
-            .min_filter(vk::Filter::LINEAR)
-            .mag_filter(vk::Filter::LINEAR)
-            .compare_enable(false)
-            .compare_op(vk::CompareOp::ALWAYS)
-            .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
-            .mip_lod_bias(0.0)
-            .min_lod(0.0)
-            .max_lod(0.0);
-        unsafe { context.device.create_sampler(&create_info, None).unwrap() }
-    }
-
     pub fn create_image(
         context: &VulkanContext,
         width: u32,
         height: u32,
         format: vk::Format,
         pixel_data: &[u8],
-    ) -> Self {
+        sampler_config: &SamplerConfig,
+    ) -> Result<Self, AllocationError> {
         let total_start = Instant::now();
         let extent = vk::Extent3D {
             width,
@@ -160,12 +231,37 @@ impl Texture {
             .sharing_mode(vk::SharingMode::EXCLUSIVE);
 
         let (image_object, image_memory) =
-            context.create_image(create_info, gpu_allocator::MemoryLocation::GpuOnly);
+            context.create_image(create_info, gpu_allocator::MemoryLocation::GpuOnly)?;
         let ms_image = total_start.elapsed().as_micros() as f64 / 1000.0;
 
         let total_size = pixel_data.len() as u64;
 
-        let (staging_buffer, staging_allocation) = Self::create_staging_buffer(context, total_size);
+        let (staging_buffer, staging_allocation) =
+            match Self::create_staging_buffer(context, total_size) {
+                Ok(staging) => staging,
+                Err(AllocationError::OutOfMemory) if height > 1 => {
+                    //Not enough room to stage the whole image, copy it over a few rows at a time
+                    return match Self::upload_in_bands(context, image_object, extent, pixel_data) {
+                        Ok(()) => Ok(Self::from_uploaded_image(
+                            context,
+                            width,
+                            height,
+                            format,
+                            image_object,
+                            image_memory,
+                            sampler_config,
+                        )),
+                        Err(err) => {
+                            context.free_image(image_object, image_memory);
+                            Err(err)
+                        }
+                    };
+                }
+                Err(err) => {
+                    context.free_image(image_object, image_memory);
+                    return Err(err);
+                }
+            };
         let ms_staging = total_start.elapsed().as_micros() as f64 / 1000.0;
 
         let map = context.map_buffer(&staging_allocation);
@@ -180,9 +276,9 @@ impl Texture {
 
             let command_buffer = context.begin_single_time_commands();
             Self::transition_image_layout(
-                context,
-                command_buffer.vk_command_buffer(),
+                &command_buffer,
                 image_object,
+                1,
                 vk::ImageLayout::UNDEFINED,
                 vk::ImageLayout::TRANSFER_DST_OPTIMAL,
             );
@@ -194,13 +290,13 @@ impl Texture {
                 staging_buffer,
                 image_object,
                 vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-                extent,
+                Self::copy_region(vk::Offset3D::default(), extent, 1),
             );
             let ms_copy_im = total_start.elapsed().as_micros() as f64 / 1000.0;
             Self::transition_image_layout(
-                context,
-                command_buffer.vk_command_buffer(),
+                &command_buffer,
                 image_object,
+                1,
                 vk::ImageLayout::TRANSFER_DST_OPTIMAL,
                 vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
             );
@@ -213,13 +309,15 @@ impl Texture {
             context.free_buffer(staging_buffer, staging_allocation);
             let ms_free = total_start.elapsed().as_micros() as f64 / 1000.0;
 
-            let image_view = VulkanFrameCtx::create_image_view(
-                &context.device,
-                image_object,
+            let texture = Self::from_uploaded_image(
+                context,
+                width,
+                height,
                 format,
-                vk::ImageAspectFlags::COLOR,
+                image_object,
+                image_memory,
+                sampler_config,
             );
-            let image_sampler = Self::create_texture_sampler(context);
             let ms_total = total_start.elapsed().as_micros() as f64 / 1000.0;
             println!(
                 "[Create Image] Image size: {:.2}MiB",
@@ -275,23 +373,330 @@ impl Texture {
                 (ms_free - ms_trans_2) / ms_total * 100.0
             );
 
-            Self {
-                width,
-                height,
-                channels: 4,
-                image_memory,
-                image: image_object,
-                image_view,
-                image_sampler,
+            Ok(texture)
+        }
+    }
+
+    //Faces in Vulkan layer order: +X, -X, +Y, -Y, +Z, -Z. Each face is size*size texels of
+    //format and all faces have the same length. Sampled through a CUBE image view
+    pub fn create_cubemap(
+        context: &VulkanContext,
+        size: u32,
+        format: vk::Format,
+        faces: [&[u8]; CUBEMAP_FACES],
+        sampler_config: &SamplerConfig,
+    ) -> Result<Self, AllocationError> {
+        let face_size = faces[0].len();
+        assert!(
+            faces.iter().all(|face| face.len() == face_size),
+            "Cubemap faces differ in size"
+        );
+        let extent = vk::Extent3D {
+            width: size,
+            height: size,
+            depth: 1,
+        };
+        let create_info = vk::ImageCreateInfo::default()
+            .flags(vk::ImageCreateFlags::CUBE_COMPATIBLE)
+            .extent(extent)
+            .image_type(vk::ImageType::TYPE_2D)
+            .mip_levels(1)
+            .array_layers(CUBEMAP_FACES as u32)
+            .format(format)
+            .usage(vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED)
+            .initial_layout(vk::ImageLayout::UNDEFINED)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let (image, image_memory) =
+            context.create_image(create_info, gpu_allocator::MemoryLocation::GpuOnly)?;
+
+        let total_size = (face_size * CUBEMAP_FACES) as vk::DeviceSize;
+        let (staging_buffer, staging_allocation) =
+            match Self::create_staging_buffer(context, total_size) {
+                Ok(staging) => staging,
+                Err(err) => {
+                    context.free_image(image, image_memory);
+                    return Err(err);
+                }
+            };
+        let map = context.map_buffer(&staging_allocation);
+        for (index, face) in faces.iter().enumerate() {
+            unsafe {
+                std::ptr::copy_nonoverlapping(face.as_ptr(), map.add(index * face_size), face_size);
             }
         }
+
+        let command_buffer = context.begin_single_time_commands();
+        Self::transition_image_layout(
+            &command_buffer,
+            image,
+            CUBEMAP_FACES as u32,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        );
+        Self::copy_buffer_to_image(
+            context,
+            command_buffer.vk_command_buffer(),
+            staging_buffer,
+            image,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            Self::copy_region(vk::Offset3D::default(), extent, CUBEMAP_FACES as u32),
+        );
+        Self::transition_image_layout(
+            &command_buffer,
+            image,
+            CUBEMAP_FACES as u32,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        );
+        context.end_single_time_commands(command_buffer);
+        context.free_buffer(staging_buffer, staging_allocation);
+
+        let view_info = cubemap_view_info(image, format);
+        let image_view = unsafe { context.device.create_image_view(&view_info, None) }.unwrap();
+        Ok(Self {
+            width: size,
+            height: size,
+            channels: 4,
+            image_memory,
+            image,
+            image_view,
+            image_sampler: context.get_or_create_sampler(sampler_config),
+        })
     }
 
+    //Fallback for when the whole image does not fit in a staging buffer. The staging buffer
+    //is halved until it can be allocated and reused for every band, each band waits for
+    //its copy to finish before the next one is written
+    fn upload_in_bands(
+        context: &VulkanContext,
+        image: vk::Image,
+        extent: vk::Extent3D,
+        pixel_data: &[u8],
+    ) -> Result<(), AllocationError> {
+        let row_size = pixel_data.len() / extent.height as usize;
+        let mut band_rows = extent.height;
+        let (staging_buffer, staging_allocation) = loop {
+            band_rows = band_rows.div_ceil(2);
+            let band_size = (row_size * band_rows as usize) as vk::DeviceSize;
+            match Self::create_staging_buffer(context, band_size) {
+                Ok(staging) => break staging,
+                Err(AllocationError::OutOfMemory) if band_rows > 1 => continue,
+                Err(err) => return Err(err),
+            }
+        };
+        println!(
+            "[Create Image] Out of staging memory, uploading {} rows at a time",
+            band_rows
+        );
+
+        let map = context.map_buffer(&staging_allocation);
+        let mut first_row = 0;
+        while first_row < extent.height {
+            let rows = band_rows.min(extent.height - first_row);
+            let band =
+                &pixel_data[first_row as usize * row_size..(first_row + rows) as usize * row_size];
+            unsafe {
+                std::ptr::copy_nonoverlapping(band.as_ptr(), map, band.len());
+            }
+
+            let command_buffer = context.begin_single_time_commands();
+            if first_row == 0 {
+                Self::transition_image_layout(
+                    &command_buffer,
+                    image,
+                    1,
+                    vk::ImageLayout::UNDEFINED,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                );
+            }
+            Self::copy_buffer_to_image(
+                context,
+                command_buffer.vk_command_buffer(),
+                staging_buffer,
+                image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                Self::copy_region(
+                    vk::Offset3D {
+                        x: 0,
+                        y: first_row as i32,
+                        z: 0,
+                    },
+                    vk::Extent3D {
+                        height: rows,
+                        ..extent
+                    },
+                    1,
+                ),
+            );
+            first_row += rows;
+            if first_row == extent.height {
+                Self::transition_image_layout(
+                    &command_buffer,
+                    image,
+                    1,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                );
+            }
+            context.end_single_time_commands(command_buffer);
+        }
+
+        context.free_buffer(staging_buffer, staging_allocation);
+        Ok(())
+    }
+
+    fn from_uploaded_image(
+        context: &VulkanContext,
+        width: u32,
+        height: u32,
+        format: vk::Format,
+        image: vk::Image,
+        image_memory: Allocation,
+        sampler_config: &SamplerConfig,
+    ) -> Self {
+        let image_view = VulkanFrameCtx::create_image_view(
+            &context.device,
+            image,
+            format,
+            vk::ImageAspectFlags::COLOR,
+        );
+        let image_sampler = context.get_or_create_sampler(sampler_config);
+        Self {
+            width,
+            height,
+            channels: 4,
+            image_memory,
+            image,
+            image_view,
+            image_sampler,
+        }
+    }
+
+    //The sampler is shared through the context's sampler cache and is destroyed with it
     pub fn destroy(self, context: &VulkanContext) {
         unsafe {
-            context.device.destroy_sampler(self.image_sampler, None);
             context.device.destroy_image_view(self.image_view, None);
         }
         context.free_image(self.image, self.image_memory);
     }
 }
+
+//All six layers of image as one cube
+fn cubemap_view_info(image: vk::Image, format: vk::Format) -> vk::ImageViewCreateInfo<'static> {
+    let subresource_range = vk::ImageSubresourceRange::default()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .base_mip_level(0)
+        .level_count(1)
+        .base_array_layer(0)
+        .layer_count(CUBEMAP_FACES as u32);
+    vk::ImageViewCreateInfo::default()
+        .image(image)
+        .view_type(vk::ImageViewType::CUBE)
+        .format(format)
+        .subresource_range(subresource_range)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ash::vk::Handle;
+
+    #[test]
+    fn cubemap_views_cover_every_face() {
+        let view_info = cubemap_view_info(vk::Image::from_raw(1), vk::Format::R8G8B8A8_SRGB);
+        assert_eq!(view_info.view_type, vk::ImageViewType::CUBE);
+        assert_eq!(view_info.subresource_range.base_array_layer, 0);
+        assert_eq!(view_info.subresource_range.layer_count, 6);
+    }
+
+    #[test]
+    fn copy_regions_read_the_layers_from_the_start_of_the_buffer() {
+        let extent = vk::Extent3D {
+            width: 4,
+            height: 4,
+            depth: 1,
+        };
+        let region = Texture::copy_region(vk::Offset3D::default(), extent, CUBEMAP_FACES as u32);
+        assert_eq!(region.buffer_offset, 0);
+        //Zero means tightly packed, so each face follows the previous one
+        assert_eq!(region.buffer_row_length, 0);
+        assert_eq!(region.buffer_image_height, 0);
+        assert_eq!(region.image_subresource.layer_count, 6);
+        assert_eq!(region.image_extent, extent);
+    }
+
+    #[test]
+    fn create_info_copies_filters_and_addressing() {
+        let config = SamplerConfig {
+            mag_filter: vk::Filter::NEAREST,
+            min_filter: vk::Filter::LINEAR,
+            mipmap_mode: vk::SamplerMipmapMode::NEAREST,
+            address_mode_u: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            address_mode_v: vk::SamplerAddressMode::MIRRORED_REPEAT,
+            ..Default::default()
+        };
+        let create_info = config.create_info();
+        assert_eq!(create_info.mag_filter, vk::Filter::NEAREST);
+        assert_eq!(create_info.min_filter, vk::Filter::LINEAR);
+        assert_eq!(create_info.mipmap_mode, vk::SamplerMipmapMode::NEAREST);
+        assert_eq!(
+            create_info.address_mode_u,
+            vk::SamplerAddressMode::CLAMP_TO_EDGE
+        );
+        assert_eq!(
+            create_info.address_mode_v,
+            vk::SamplerAddressMode::MIRRORED_REPEAT
+        );
+        assert_eq!(create_info.address_mode_w, vk::SamplerAddressMode::REPEAT);
+    }
+
+    #[test]
+    fn create_info_anisotropy() {
+        let enabled = SamplerConfig::default().create_info();
+        assert_eq!(enabled.anisotropy_enable, vk::TRUE);
+        assert_eq!(enabled.max_anisotropy, 16.0);
+
+        let disabled = SamplerConfig {
+            anisotropy_enable: false,
+            ..Default::default()
+        }
+        .create_info();
+        assert_eq!(disabled.anisotropy_enable, vk::FALSE);
+        assert_eq!(disabled.max_anisotropy, 1.0);
+    }
+
+    #[test]
+    fn create_info_lod_range() {
+        let config = SamplerConfig {
+            mip_lod_bias: -0.5,
+            min_lod: 2.0,
+            max_lod: 6.0,
+            ..Default::default()
+        };
+        let create_info = config.create_info();
+        assert_eq!(create_info.mip_lod_bias, -0.5);
+        assert_eq!(create_info.min_lod, 2.0);
+        assert_eq!(create_info.max_lod, 6.0);
+
+        //max_lod below min_lod is raised to it
+        let inverted = SamplerConfig {
+            min_lod: 4.0,
+            max_lod: 1.0,
+            ..Default::default()
+        }
+        .create_info();
+        assert_eq!(inverted.max_lod, 4.0);
+    }
+
+    #[test]
+    fn equal_configs_compare_by_float_bits() {
+        let config = SamplerConfig {
+            mip_lod_bias: 0.25,
+            ..Default::default()
+        };
+        assert_eq!(config, config);
+        assert_ne!(config, SamplerConfig::default());
+    }
+}
@@ -0,0 +1,145 @@
+use ash::vk;
+
+use crate::{
+    create_graphics_pipeline, shader_stage_infos, vertexbinding::VertexBinding, FixedFunctionState,
+    RenderPass, RenderPipeline, ShaderStageDesc,
+};
+use std::sync::Arc;
+
+use super::context::VulkanContext;
+
+const UNLIT_VERT: &[u8] = include_bytes!("../../../resources/shaders/unlit.vert.spv");
+const UNLIT_FRAG: &[u8] = include_bytes!("../../../resources/shaders/unlit.frag.spv");
+
+/// Draws with the base color only, for telling lighting bugs apart from everything else.
+/// Built on a material's RenderPipeline layout, so the material's descriptor sets bind as is.
+pub struct UnlitPipeline {
+    context: Arc<VulkanContext>,
+    pub pipeline: vk::Pipeline,
+    vert_module: vk::ShaderModule,
+    frag_module: vk::ShaderModule,
+    destroyed: bool,
+}
+
+impl UnlitPipeline {
+    //The vertex binding has to be the one the material's pipeline was created with
+    pub fn new(
+        context: Arc<VulkanContext>,
+        render_pass: &RenderPass,
+        material_pipeline: &RenderPipeline,
+        vertex_binding: VertexBinding,
+    ) -> Self {
+        let vertex_shader = Self::vertex_stage();
+        let fragment_shader = Self::fragment_stage();
+        let vert_module = vertex_shader.create_module(&context);
+        let frag_module = fragment_shader.create_module(&context);
+        let shader_stages =
+            shader_stage_infos(vert_module, &vertex_shader, frag_module, &fragment_shader);
+        let pipeline = create_graphics_pipeline(
+            &context,
+            render_pass,
+            material_pipeline.pipeline_layout,
+            &vertex_binding,
+            &shader_stages,
+            &FixedFunctionState::opaque(render_pass),
+        );
+
+        context.pipeline_created();
+        Self {
+            context,
+            pipeline,
+            vert_module,
+            frag_module,
+            destroyed: false,
+        }
+    }
+
+    pub fn vertex_stage() -> ShaderStageDesc<'static> {
+        ShaderStageDesc::new(UNLIT_VERT, "main").unwrap()
+    }
+
+    pub fn fragment_stage() -> ShaderStageDesc<'static> {
+        ShaderStageDesc::new(UNLIT_FRAG, "main").unwrap()
+    }
+
+    //Must be called before the context is dropped, calling it again does nothing
+    pub fn destroy(&mut self) {
+        if self.destroyed {
+            return;
+        }
+        self.destroyed = true;
+        self.context.pipeline_destroyed();
+        unsafe {
+            self.context.device.destroy_pipeline(self.pipeline, None);
+            self.context
+                .device
+                .destroy_shader_module(self.vert_module, None);
+            self.context
+                .device
+                .destroy_shader_module(self.frag_module, None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ash::util::read_spv;
+    use std::io::Cursor;
+
+    const OP_ENTRY_POINT: u32 = 15;
+    const OP_DECORATE: u32 = 71;
+    const DECORATION_BINDING: u32 = 33;
+    const DECORATION_DESCRIPTOR_SET: u32 = 34;
+
+    fn instructions(words: &[u32]) -> Vec<&[u32]> {
+        let mut instructions = vec![];
+        let mut index = 5;
+        while index < words.len() {
+            let count = (words[index] >> 16) as usize;
+            instructions.push(&words[index..index + count]);
+            index += count;
+        }
+        instructions
+    }
+
+    #[test]
+    fn embedded_stages_are_valid_spirv_with_a_main_entry_point() {
+        for (stage, execution_model) in [
+            (UnlitPipeline::vertex_stage(), 0),
+            (UnlitPipeline::fragment_stage(), 4),
+        ] {
+            assert_eq!(stage.entry.to_str(), Ok("main"));
+            let words = read_spv(&mut Cursor::new(stage.code)).unwrap();
+            assert_eq!(words[0], 0x0723_0203);
+            //The name follows the execution model and the function id, NUL terminated
+            let has_entry_point = instructions(&words).iter().any(|inst| {
+                inst[0] & 0xffff == OP_ENTRY_POINT
+                    && inst[1] == execution_model
+                    && inst[3] == u32::from_le_bytes(*b"main")
+                    && inst[4] == 0
+            });
+            assert!(has_entry_point);
+        }
+    }
+
+    //The material's descriptor sets are bound to it unchanged
+    #[test]
+    fn fragment_stage_samples_the_material_albedo_binding() {
+        let stage = UnlitPipeline::fragment_stage();
+        let words = read_spv(&mut Cursor::new(stage.code)).unwrap();
+        let decorations: Vec<(u32, u32)> = instructions(&words)
+            .iter()
+            .filter(|inst| inst[0] & 0xffff == OP_DECORATE)
+            .filter(|inst| [DECORATION_BINDING, DECORATION_DESCRIPTOR_SET].contains(&inst[2]))
+            .map(|inst| (inst[2], inst[3]))
+            .collect();
+        assert_eq!(
+            decorations,
+            vec![
+                (DECORATION_DESCRIPTOR_SET, crate::MATERIAL_SET),
+                (DECORATION_BINDING, 1)
+            ]
+        );
+    }
+}
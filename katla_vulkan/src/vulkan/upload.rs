@@ -0,0 +1,295 @@
+use super::{buffer::Buffer, context::AllocationError};
+
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+pub const DEFAULT_UPLOAD_BUDGET: usize = 4 * 1024 * 1024;
+//Chunks are halved down to this size when the staging buffer can't be allocated
+const MIN_UPLOAD_CHUNK: usize = 64 * 1024;
+
+/// Tells the owner of a queued upload when all of its bytes have been written.
+#[derive(Clone, Default)]
+pub struct UploadStatus(Arc<AtomicBool>);
+
+impl UploadStatus {
+    pub fn is_ready(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+}
+
+//Where queued bytes end up, only buffers outside of the tests
+trait UploadTarget {
+    fn upload_at(&self, offset: u64, data: &[u8]) -> Result<(), AllocationError>;
+}
+
+impl UploadTarget for Buffer {
+    fn upload_at(&self, offset: u64, data: &[u8]) -> Result<(), AllocationError> {
+        Buffer::upload_at(self, offset, data)
+    }
+}
+
+struct PendingUpload {
+    target: Arc<dyn UploadTarget>,
+    data: Vec<u8>,
+    written: usize,
+    status: UploadStatus,
+}
+
+/// Spreads uploads into device-local buffers over several frames.
+/// Queued data is staged and copied in order, at most budget_bytes per tick, so a large model
+/// does not stall a single frame. Targets must not be read by the GPU until their
+/// UploadStatus is ready.
+#[derive(Default)]
+pub struct UploadManager {
+    pending: VecDeque<PendingUpload>,
+}
+
+impl UploadManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn queue(&mut self, target: Arc<Buffer>, data: Vec<u8>) -> UploadStatus {
+        self.queue_to(target, data)
+    }
+
+    fn queue_to(&mut self, target: Arc<dyn UploadTarget>, data: Vec<u8>) -> UploadStatus {
+        let status = UploadStatus::default();
+        if data.is_empty() {
+            status.0.store(true, Ordering::Release);
+        } else {
+            self.pending.push_back(PendingUpload {
+                target,
+                data,
+                written: 0,
+                status: status.clone(),
+            });
+        }
+        status
+    }
+
+    //Returns the number of bytes written this tick
+    pub fn tick(&mut self, budget_bytes: usize) -> usize {
+        let mut remaining = budget_bytes;
+        let mut max_chunk = budget_bytes;
+        while remaining > 0 {
+            let Some(upload) = self.pending.front_mut() else {
+                break;
+            };
+            let chunk = remaining
+                .min(max_chunk)
+                .min(upload.data.len() - upload.written);
+            let end = upload.written + chunk;
+            match upload
+                .target
+                .upload_at(upload.written as u64, &upload.data[upload.written..end])
+            {
+                Ok(()) => {}
+                //Less room for staging than the budget allows, try a smaller chunk
+                Err(AllocationError::OutOfMemory) if chunk > MIN_UPLOAD_CHUNK => {
+                    max_chunk = chunk / 2;
+                    continue;
+                }
+                //The rest waits for memory to be freed, it is retried next tick
+                Err(AllocationError::OutOfMemory) => break,
+                Err(err) => {
+                    println!("Dropping upload: {}", err);
+                    self.pending.pop_front();
+                    continue;
+                }
+            }
+            upload.written = end;
+            remaining -= chunk;
+
+            if upload.written == upload.data.len() {
+                upload.status.0.store(true, Ordering::Release);
+                self.pending.pop_front();
+            }
+        }
+        budget_bytes - remaining
+    }
+
+    //Drops unfinished uploads along with their references to the target buffers
+    pub fn clear(&mut self) {
+        self.pending.clear();
+    }
+
+    pub fn pending_bytes(&self) -> usize {
+        self.pending
+            .iter()
+            .map(|upload| upload.data.len() - upload.written)
+            .sum()
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    //Staging larger chunks than max_staging fails like an exhausted allocator would
+    struct MemoryTarget {
+        contents: Mutex<Vec<u8>>,
+        max_staging: usize,
+        chunks: Mutex<Vec<usize>>,
+    }
+
+    impl MemoryTarget {
+        fn new(size: usize) -> Arc<Self> {
+            Self::with_max_staging(size, usize::MAX)
+        }
+
+        fn with_max_staging(size: usize, max_staging: usize) -> Arc<Self> {
+            Arc::new(Self {
+                contents: Mutex::new(vec![0; size]),
+                max_staging,
+                chunks: Mutex::new(vec![]),
+            })
+        }
+
+        fn contents(&self) -> Vec<u8> {
+            self.contents.lock().unwrap().clone()
+        }
+
+        fn chunks(&self) -> Vec<usize> {
+            self.chunks.lock().unwrap().clone()
+        }
+    }
+
+    impl UploadTarget for MemoryTarget {
+        fn upload_at(&self, offset: u64, data: &[u8]) -> Result<(), AllocationError> {
+            if data.len() > self.max_staging {
+                return Err(AllocationError::OutOfMemory);
+            }
+            let offset = offset as usize;
+            self.contents.lock().unwrap()[offset..offset + data.len()].copy_from_slice(data);
+            self.chunks.lock().unwrap().push(data.len());
+            Ok(())
+        }
+    }
+
+    struct BrokenTarget;
+
+    impl UploadTarget for BrokenTarget {
+        fn upload_at(&self, _offset: u64, _data: &[u8]) -> Result<(), AllocationError> {
+            Err(AllocationError::Failed("device lost".to_owned()))
+        }
+    }
+
+    #[test]
+    fn uploads_are_split_over_ticks() {
+        let mut uploads = UploadManager::new();
+        let target = MemoryTarget::new(10);
+        let status = uploads.queue_to(target.clone(), (1..=10).collect());
+
+        assert_eq!(uploads.tick(4), 4);
+        assert!(!status.is_ready());
+        assert_eq!(uploads.pending_bytes(), 6);
+        assert_eq!(target.contents(), vec![1, 2, 3, 4, 0, 0, 0, 0, 0, 0]);
+
+        assert_eq!(uploads.tick(4), 4);
+        assert_eq!(uploads.tick(4), 2);
+        assert!(status.is_ready());
+        assert!(uploads.is_idle());
+        assert_eq!(target.contents(), (1..=10).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn one_tick_can_finish_several_uploads_in_order() {
+        let mut uploads = UploadManager::new();
+        let first = MemoryTarget::new(3);
+        let second = MemoryTarget::new(3);
+        let first_status = uploads.queue_to(first.clone(), vec![1; 3]);
+        let second_status = uploads.queue_to(second.clone(), vec![2; 3]);
+
+        assert_eq!(uploads.tick(5), 5);
+        assert!(first_status.is_ready());
+        assert!(!second_status.is_ready());
+        assert_eq!(second.contents(), vec![2, 2, 0]);
+
+        assert_eq!(uploads.tick(100), 1);
+        assert!(second_status.is_ready());
+        assert_eq!(uploads.tick(100), 0);
+    }
+
+    #[test]
+    fn empty_uploads_are_ready_at_once() {
+        let mut uploads = UploadManager::new();
+        let status = uploads.queue_to(MemoryTarget::new(0), vec![]);
+        assert!(status.is_ready());
+        assert!(uploads.is_idle());
+    }
+
+    #[test]
+    fn cleared_uploads_never_become_ready() {
+        let mut uploads = UploadManager::new();
+        let status = uploads.queue_to(MemoryTarget::new(8), vec![1; 8]);
+        uploads.tick(2);
+        uploads.clear();
+        assert_eq!(uploads.pending_bytes(), 0);
+        assert_eq!(uploads.tick(8), 0);
+        assert!(!status.is_ready());
+    }
+
+    #[test]
+    fn large_uploads_finish_after_enough_ticks() {
+        const MB: usize = 1024 * 1024;
+        let mut uploads = UploadManager::new();
+        let target = MemoryTarget::new(10 * MB);
+        let status = uploads.queue_to(target.clone(), vec![7; 10 * MB]);
+
+        for _ in 0..9 {
+            assert_eq!(uploads.tick(MB), MB);
+            assert!(!status.is_ready());
+        }
+        assert_eq!(uploads.tick(MB), MB);
+        assert!(status.is_ready());
+        assert!(uploads.is_idle());
+    }
+
+    #[test]
+    fn out_of_staging_memory_halves_the_chunk() {
+        let chunk = MIN_UPLOAD_CHUNK;
+        let mut uploads = UploadManager::new();
+        let target = MemoryTarget::with_max_staging(4 * chunk, chunk);
+        let status = uploads.queue_to(target.clone(), vec![3; 4 * chunk]);
+
+        assert_eq!(uploads.tick(4 * chunk), 4 * chunk);
+        assert_eq!(target.chunks(), vec![chunk; 4]);
+        assert!(status.is_ready());
+        assert_eq!(target.contents(), vec![3; 4 * chunk]);
+    }
+
+    #[test]
+    fn uploads_wait_while_even_small_chunks_run_out_of_memory() {
+        let mut uploads = UploadManager::new();
+        let target = MemoryTarget::with_max_staging(MIN_UPLOAD_CHUNK, 0);
+        let status = uploads.queue_to(target.clone(), vec![1; MIN_UPLOAD_CHUNK]);
+
+        assert_eq!(uploads.tick(4 * MIN_UPLOAD_CHUNK), 0);
+        assert_eq!(uploads.pending_bytes(), MIN_UPLOAD_CHUNK);
+        assert!(!status.is_ready());
+    }
+
+    #[test]
+    fn failed_uploads_are_dropped_and_the_next_one_continues() {
+        let mut uploads = UploadManager::new();
+        let broken_status = uploads.queue_to(Arc::new(BrokenTarget), vec![1; 4]);
+        let target = MemoryTarget::new(4);
+        let status = uploads.queue_to(target.clone(), vec![2; 4]);
+
+        assert_eq!(uploads.tick(8), 4);
+        assert!(!broken_status.is_ready());
+        assert!(status.is_ready());
+        assert!(uploads.is_idle());
+    }
+}
@@ -1,5 +1,6 @@
 use ash::vk::{self};
 
+#[derive(Debug, Clone, Copy)]
 pub enum VertexFormat {
     R32u,
     R32i,
@@ -43,6 +44,7 @@ impl VertexFormat {
         }
     }
 }
+#[derive(Debug, Clone)]
 pub struct VertexBinding {
     pub formats: Vec<VertexFormat>,
 }
@@ -1,26 +1,16 @@
-use super::context::VulkanContext;
+use super::{
+    buffer::Buffer,
+    context::{AllocationError, VulkanContext},
+    upload::UploadManager,
+    UploadStatus,
+};
 use ash::vk;
-use gpu_allocator::vulkan::Allocation;
 
 use std::sync::Arc;
 
 struct BufferObject {
-    allocation: Option<Allocation>,
-    buffer: vk::Buffer,
-    buf_size: vk::DeviceSize,
+    buffer: Arc<Buffer>,
     count: u32,
-    context: Arc<VulkanContext>,
-}
-
-//TODO: Holding an RC for every buffer is... meh.
-// figure out a better way of pooling this, also for safer dropping of buffers
-// that are in-flight
-impl Drop for BufferObject {
-    fn drop(&mut self) {
-        if let Some(allocation) = self.allocation.take() {
-            self.context.free_buffer(self.buffer, allocation);
-        }
-    }
 }
 
 pub struct VertexBuffer {
@@ -33,23 +23,31 @@ pub struct IndexBuffer {
 }
 
 impl BufferObject {
-    fn upload_data(&mut self, data: &[u8]) {
-        let data_size = std::mem::size_of_val(data) as vk::DeviceSize;
-        if self.buf_size < data_size {
-            panic!(
-                "Too little memory allocated for buffer of size {}",
-                data_size
-            );
-        }
-        match &self.allocation {
-            Some(allocation) => {
-                let mapped_ptr = self.context.map_buffer(allocation);
-                unsafe {
-                    std::ptr::copy_nonoverlapping(data.as_ptr(), mapped_ptr, data_size as usize);
-                }
-            }
-            _ => {}
-        }
+    fn new(
+        context: Arc<VulkanContext>,
+        buf_size: vk::DeviceSize,
+        usage: vk::BufferUsageFlags,
+        count: u32,
+    ) -> Result<Self, AllocationError> {
+        //Filled through staging copies, see Buffer::upload_at
+        let buffer = Buffer::new(
+            context,
+            buf_size,
+            usage | vk::BufferUsageFlags::TRANSFER_DST,
+            gpu_allocator::MemoryLocation::GpuOnly,
+        )?;
+        Ok(Self {
+            buffer: Arc::new(buffer),
+            count,
+        })
+    }
+
+    fn upload_data(&mut self, data: &[u8]) -> Result<(), AllocationError> {
+        self.buffer.upload_at(0, data)
+    }
+
+    fn queue_upload(&self, uploads: &mut UploadManager, data: Vec<u8>) -> UploadStatus {
+        uploads.queue(self.buffer.clone(), data)
     }
 }
 
@@ -59,32 +57,24 @@ impl IndexBuffer {
         buf_size: vk::DeviceSize,
         index_type: vk::IndexType,
         count: u32,
-    ) -> Self {
-        let buffer = {
-            let create_info = vk::BufferCreateInfo::default()
-                .sharing_mode(vk::SharingMode::EXCLUSIVE)
-                .usage(vk::BufferUsageFlags::INDEX_BUFFER)
-                .size(buf_size);
-            let (buffer, allocation) =
-                context.allocate_buffer(&create_info, gpu_allocator::MemoryLocation::CpuToGpu);
-
-            BufferObject {
-                allocation: Some(allocation),
-                buffer,
-                buf_size,
-                count,
-                context,
-            }
-        };
-        Self { buffer, index_type }
+    ) -> Result<Self, AllocationError> {
+        let buffer =
+            BufferObject::new(context, buf_size, vk::BufferUsageFlags::INDEX_BUFFER, count)?;
+        Ok(Self { buffer, index_type })
+    }
+
+    //Blocks until the data is on the GPU, queue_upload spreads it over frames instead
+    pub fn upload_data(&mut self, data: &[u8]) -> Result<(), AllocationError> {
+        self.buffer.upload_data(data)
     }
 
-    pub fn upload_data(&mut self, data: &[u8]) {
-        self.buffer.upload_data(data);
+    //The buffer must not be drawn from until the returned status is ready
+    pub fn queue_upload(&self, uploads: &mut UploadManager, data: Vec<u8>) -> UploadStatus {
+        self.buffer.queue_upload(uploads, data)
     }
 
     pub fn object(&self) -> vk::Buffer {
-        self.buffer.buffer
+        self.buffer.buffer.vk_buffer()
     }
 
     pub fn count(&self) -> u32 {
@@ -93,35 +83,35 @@ impl IndexBuffer {
 }
 
 impl VertexBuffer {
-    pub fn new(context: Arc<VulkanContext>, buf_size: vk::DeviceSize, count: u32) -> Self {
-        let buffer = {
-            let create_info = vk::BufferCreateInfo::default()
-                .sharing_mode(vk::SharingMode::EXCLUSIVE)
-                .usage(vk::BufferUsageFlags::VERTEX_BUFFER)
-                .size(buf_size);
-            let (buffer, allocation) =
-                context.allocate_buffer(&create_info, gpu_allocator::MemoryLocation::CpuToGpu);
-
-            BufferObject {
-                allocation: Some(allocation),
-                buffer,
-                buf_size,
-                count,
-                context,
-            }
-        };
-        Self { buffer }
+    pub fn new(
+        context: Arc<VulkanContext>,
+        buf_size: vk::DeviceSize,
+        count: u32,
+    ) -> Result<Self, AllocationError> {
+        let buffer = BufferObject::new(
+            context,
+            buf_size,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            count,
+        )?;
+        Ok(Self { buffer })
     }
 
     pub fn object(&self) -> vk::Buffer {
-        self.buffer.buffer
+        self.buffer.buffer.vk_buffer()
     }
 
     pub fn count(&self) -> u32 {
         self.buffer.count
     }
 
-    pub fn upload_data(&mut self, data: &[u8]) {
-        self.buffer.upload_data(data);
+    //Blocks until the data is on the GPU, queue_upload spreads it over frames instead
+    pub fn upload_data(&mut self, data: &[u8]) -> Result<(), AllocationError> {
+        self.buffer.upload_data(data)
+    }
+
+    //The buffer must not be drawn from until the returned status is ready
+    pub fn queue_upload(&self, uploads: &mut UploadManager, data: Vec<u8>) -> UploadStatus {
+        self.buffer.queue_upload(uploads, data)
     }
 }
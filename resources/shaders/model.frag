@@ -1,6 +1,22 @@
 #version 450
 
-layout(binding=1) uniform sampler2D albedo_sampler;
+// Same as katla_vulkan::MAX_LIGHTS
+const uint MAX_LIGHTS = 8;
+const vec3 ambient_color = vec3(0.1, 0.1, 0.1);
+
+struct Light {
+    // w is 0.0 for directional lights, xyz is then the direction towards the light
+    vec4 position;
+    // rgb color, intensity in a
+    vec4 color;
+};
+
+layout(set = 0, binding = 1) uniform Lights {
+    uint count;
+    Light lights[MAX_LIGHTS];
+} lights;
+
+layout(set = 1, binding = 1) uniform sampler2D albedo_sampler;
 // layout(binding=1) uniform sampler2D normal_sampler;
 // layout(binding=2) uniform sampler2D roughness_sampler;
 // layout(binding=3) uniform sampler2D emissive_sampler;
@@ -24,7 +40,22 @@ layout(location=0) out vec4 out_col;
 void main()
 {
     vec4 color = texture(albedo_sampler, tex_coords);
-    out_col = vec4(color.rgb, 1.0);
+    vec3 lit = color.rgb;
+    // Without any lights the albedo is shown as is
+    if (lights.count > 0) {
+        vec3 normal = normalize(vs_norm);
+        vec3 light_sum = ambient_color;
+        for (uint i = 0; i < lights.count; i++) {
+            vec4 position = lights.lights[i].position;
+            vec3 to_light = position.xyz - vs_pos * position.w;
+            // Point lights fall off with the squared distance
+            float attenuation = 1.0 / mix(1.0, max(dot(to_light, to_light), 1e-4), position.w);
+            float diffuse = max(dot(normal, normalize(to_light)), 0.0);
+            light_sum += lights.lights[i].color.rgb * lights.lights[i].color.a * diffuse * attenuation;
+        }
+        lit = color.rgb * light_sum;
+    }
+    out_col = vec4(lit, 1.0);
 
     // vec3 ambient_color = 0.1 * light_color;
 
@@ -4,10 +4,17 @@ layout(location=1) in vec3 normal;
 layout(location=2) in vec4 vert_tangent;
 layout(location=3) in vec2 vert_texcoord0;
 
-layout(set = 0, binding = 0) uniform Data {
-    mat4 world;
+// Shared by every material, bound once per frame
+layout(set = 0, binding = 0) uniform Global {
     mat4 view;
     mat4 proj;
+    // 0.0 keeps the regular depth, see katla_math::log_depth_coefficient
+    float log_depth_coefficient;
+    float time;
+} global;
+
+layout(set = 1, binding = 0) uniform Data {
+    mat4 world;
 } uniforms;
 
 layout(location=0) out vec3 vs_pos;
@@ -25,8 +32,12 @@ void main()
     // vs_TBN = mat3(1.0);
     // vs_pos = (uniforms.world * vec4(position, 1.0)).xyz;
 
-    vs_pos = vs_pos;
-    vs_norm = normal * 0.5 + 0.5;
+    // World space, for the lighting in model.frag
+    vs_pos = (uniforms.world * vec4(position, 1.0)).xyz;
+    vs_norm = (uniforms.world * vec4(normal, 0.0)).xyz;
     tex_coords = vert_texcoord0;
-    gl_Position = uniforms.proj * uniforms.view * uniforms.world * vec4(position, 1.0);
+    gl_Position = global.proj * global.view * uniforms.world * vec4(position, 1.0);
+    if (global.log_depth_coefficient > 0.0) {
+        gl_Position.z = log2(max(1e-6, 1.0 + gl_Position.w)) * global.log_depth_coefficient * gl_Position.w;
+    }
 }
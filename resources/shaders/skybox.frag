@@ -0,0 +1,12 @@
+#version 450
+// katla_vulkan::Skybox, set 0 is left to the global uniform
+layout(set = 1, binding = 0) uniform samplerCube skybox;
+
+layout(location=0) in vec3 direction;
+
+layout(location=0) out vec4 out_col;
+
+void main()
+{
+    out_col = vec4(texture(skybox, direction).rgb, 1.0);
+}
@@ -0,0 +1,18 @@
+#version 450
+// katla_vulkan::Skybox, a cube around the camera pushed to the far plane
+layout(location=0) in vec3 position;
+
+// The projection times the camera's rotation-only view matrix
+layout(push_constant) uniform Sky {
+    mat4 view_proj;
+} sky;
+
+layout(location=0) out vec3 direction;
+
+void main()
+{
+    direction = position;
+    vec4 clip = sky.view_proj * vec4(position, 1.0);
+    // Depth 1.0, so only what the scene left at the cleared depth is covered
+    gl_Position = clip.xyww;
+}
@@ -0,0 +1,12 @@
+#version 450
+// katla_vulkan::TextRenderer, white text covering what the font atlas marks
+layout(set = 0, binding = 0) uniform sampler2D font_atlas;
+
+layout(location=0) in vec2 vs_tex_coords;
+
+layout(location=0) out vec4 out_col;
+
+void main()
+{
+    out_col = vec4(1.0, 1.0, 1.0, texture(font_atlas, vs_tex_coords).r);
+}
@@ -0,0 +1,12 @@
+#version 450
+// katla_vulkan::TextRenderer, positions are laid out in normalized device coordinates
+layout(location=0) in vec2 in_pos;
+layout(location=1) in vec2 in_tex_coords;
+
+layout(location=0) out vec2 vs_tex_coords;
+
+void main()
+{
+    vs_tex_coords = in_tex_coords;
+    gl_Position = vec4(in_pos, 0.0, 1.0);
+}
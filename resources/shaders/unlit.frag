@@ -0,0 +1,12 @@
+#version 450
+// Debug shader of katla_vulkan::UnlitPipeline, the base color as is
+layout(set = 1, binding = 1) uniform sampler2D albedo_sampler;
+
+layout(location=1) in vec2 tex_coords;
+
+layout(location=0) out vec4 out_col;
+
+void main()
+{
+    out_col = vec4(texture(albedo_sampler, tex_coords).rgb, 1.0);
+}
@@ -0,0 +1,23 @@
+#version 450
+// Debug shader of katla_vulkan::UnlitPipeline. Same descriptor sets as model_pbr.vert,
+// but without logarithmic depth or anything the lighting needs
+layout(location=0) in vec3 position;
+layout(location=3) in vec2 vert_texcoord0;
+
+layout(set = 0, binding = 0) uniform Global {
+    mat4 view;
+    mat4 proj;
+    float log_depth_coefficient;
+    float time;
+} global;
+
+layout(set = 1, binding = 0) uniform Data {
+    mat4 world;
+} uniforms;
+
+layout(location=1) out vec2 tex_coords;
+void main()
+{
+    tex_coords = vert_texcoord0;
+    gl_Position = global.proj * global.view * uniforms.world * vec4(position, 1.0);
+}
@@ -1,84 +1,224 @@
 pub mod model;
+pub mod overlay;
 pub mod scene;
 
-use std::{cell::RefCell, ffi::CString, path::PathBuf, rc::Rc, time::Instant};
+use std::{cell::RefCell, ffi::CString, path::PathBuf, rc::Rc, sync::Arc, time::Instant};
 
 use env_logger::Env;
 use katla_math::Vec3;
-use katla_vulkan::VulkanRenderer;
+use katla_vulkan::{
+    ColorSpaceKHR, Format, RendererConfig, Shading, SwapchainConfig, TextRenderer, UploadManager,
+    VulkanRenderer, WindowRenderer, CUBEMAP_FACES, DEFAULT_API_VERSION, DEFAULT_FRAMES_IN_FLIGHT,
+    DEFAULT_UPLOAD_BUDGET, DYNAMIC_RENDERING_API_VERSION,
+};
 pub use model::*;
+pub use overlay::*;
 pub use scene::*;
 use winit::{
     application::ApplicationHandler,
     dpi::LogicalSize,
-    event::{DeviceEvent, DeviceId, ElementState, WindowEvent},
+    event::{DeviceEvent, DeviceId, ElementState, StartCause, WindowEvent},
     event_loop::{ActiveEventLoop, ControlFlow, EventLoop},
     keyboard::{KeyCode, PhysicalKey},
-    window::{Window, WindowId},
+    window::{Fullscreen, Window, WindowAttributes, WindowId},
 };
 
 use crate::{
-    cameracontroller, cameracontroller::Camera, input::InputController, util::FileCache,
-    util::GLTFModel, util::Timer,
+    cameracontroller,
+    cameracontroller::{Camera, ScrollMode},
+    input::InputController,
+    rendering::{load_cubemap, GlobalData, GridDrawable, LightData},
+    util::FileCache,
+    util::GLTFModel,
+    util::Time,
+    util::{target_frame_interval, TaskPool, Timer},
 };
 
+//Main window options collected by the builder
+#[derive(Debug, Clone)]
+struct WindowSettings {
+    size: Option<(u32, u32)>,
+    fullscreen: bool,
+    decorations: bool,
+    maximized: bool,
+}
+
+impl Default for WindowSettings {
+    fn default() -> Self {
+        Self {
+            size: None,
+            fullscreen: false,
+            decorations: true,
+            maximized: false,
+        }
+    }
+}
+
+impl WindowSettings {
+    fn attributes(&self, title: &str) -> WindowAttributes {
+        let mut attributes = Window::default_attributes()
+            .with_title(title)
+            .with_resizable(true)
+            .with_min_inner_size(LogicalSize {
+                width: 1.0,
+                height: 1.0,
+            })
+            .with_decorations(self.decorations)
+            .with_maximized(self.maximized);
+        if let Some((width, height)) = self.size {
+            attributes = attributes.with_inner_size(LogicalSize { width, height });
+        }
+        if self.fullscreen {
+            attributes = attributes.with_fullscreen(Some(Fullscreen::Borderless(None)));
+        }
+        attributes
+    }
+}
+
 struct ApplicationInfo {
     name: String,
     validation_layer_enabled: bool,
+    frames_in_flight: usize,
+    api_version: u32,
+    dynamic_rendering: bool,
+    window: WindowSettings,
+    upload_budget: usize,
+    swapchain: SwapchainConfig,
+    target_fps: Option<u32>,
+    viewport_titles: Vec<String>,
+    init_logger: bool,
+    default_scene: bool,
+    fps_overlay: bool,
+    skybox: Option<[PathBuf; CUBEMAP_FACES]>,
+}
+
+//Runs once per frame before the scene updates, see ApplicationBuilder::with_update
+type UpdateFn = Box<dyn FnMut(&mut Scene, &Time)>;
+
+/// An additional window showing the scene through the main camera
+struct Viewport {
+    window: Window,
+    renderer: WindowRenderer,
 }
 
 pub struct Application {
     window: Option<Window>,
     renderer: Option<VulkanRenderer>,
+    viewports: Vec<Viewport>,
+    update_callbacks: Vec<UpdateFn>,
     camera: Rc<RefCell<Camera>>,
     input_controller: InputController,
-    scene: Scene,
+    scene: Rc<RefCell<Scene>>,
     gltf_cache: FileCache<GLTFModel>,
-    stage_upload: bool,
+    uploads: UploadManager,
+    task_pool: Arc<TaskPool>,
     timer: Timer,
+    time: Time,
+    //Shown by the OverlayPass, rewritten every frame
+    overlay_text: Rc<RefCell<String>>,
     info: ApplicationInfo,
 }
 
 impl ApplicationHandler for Application {
+    //Wakes up from the WaitUntil set by a capped frame
+    fn new_events(&mut self, event_loop: &ActiveEventLoop, cause: StartCause) {
+        if let StartCause::ResumeTimeReached { .. } = cause {
+            event_loop.set_control_flow(ControlFlow::Poll);
+            if let Some(window) = &self.window {
+                window.request_redraw();
+            }
+        }
+    }
+
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
         if self.window.is_none() {
-            let window = event_loop
-                .create_window(
-                    Window::default_attributes()
-                        .with_title(&self.info.name)
-                        .with_resizable(true)
-                        .with_min_inner_size(LogicalSize {
-                            width: 1.0,
-                            height: 1.0,
-                        })
-                        .with_maximized(false),
-                )
-                .unwrap();
+            let attributes = self.info.window.attributes(&self.info.name);
+            let window = event_loop.create_window(attributes).unwrap();
 
             let engine_name = CString::new("Katla Engine").unwrap();
-            let renderer = VulkanRenderer::init(
+            let mut renderer = VulkanRenderer::init(
                 &event_loop,
                 &window,
                 self.info.validation_layer_enabled,
-                CString::new(self.info.name.as_str()).unwrap(),
+                vulkan_app_name(&self.info.name),
                 engine_name,
+                &RendererConfig {
+                    api_version: self.info.api_version,
+                    frames_in_flight: self.info.frames_in_flight,
+                    swapchain: self.info.swapchain.clone(),
+                },
             );
+            if self.info.dynamic_rendering {
+                renderer.enable_dynamic_rendering();
+            }
+            renderer.add_pass(Box::new(ScenePass::new(self.scene.clone())));
+            if let Some(face_paths) = &self.info.skybox {
+                let skybox = load_cubemap(&renderer.context, face_paths)
+                    .and_then(|cubemap| Ok(renderer.set_skybox(cubemap)?));
+                if let Err(err) = skybox {
+                    println!("Skipping the skybox: {}", err);
+                }
+            }
+            if self.info.fps_overlay {
+                let text_renderer = TextRenderer::new(
+                    renderer.context.clone(),
+                    &renderer.render_pass,
+                    renderer.frames_in_flight(),
+                );
+                match text_renderer {
+                    Ok(text_renderer) => renderer.add_pass(Box::new(OverlayPass::new(
+                        text_renderer,
+                        self.overlay_text.clone(),
+                    ))),
+                    Err(err) => println!("Skipping the FPS overlay: {}", err),
+                }
+            }
             let window_size = window.inner_size();
-            let win_x = window_size.width as f32;
-            let win_y = window_size.height as f32;
-            self.camera.borrow_mut().aspect_ratio_changed(win_x / win_y);
-            let mesh = Model::new_from_gltf(
-                self.gltf_cache
-                    .read(PathBuf::from("resources/models/Fox.glb")),
-                renderer.context.clone(),
-                //TODO: (mikpe) - should not have to send these when creating a mesh... The scene should be enough and "Mesh" should be a higher level abstraction
-                &renderer.render_pass,
-                renderer.num_images(),
-                Vec3::new(0.0, 0.0, 0.0),
+            self.camera
+                .borrow_mut()
+                .set_viewport(window_size.width, window_size.height);
+            if self.info.default_scene {
+                self.scene
+                    .borrow_mut()
+                    .load_model("resources/models/Fox.glb", Vec3::new(0.0, 0.0, 0.0));
+                let grid = GridDrawable::new(
+                    renderer.context.clone(),
+                    &renderer.render_pass,
+                    renderer.frames_in_flight(),
+                    500.0,
+                    50.0,
+                );
+                match grid {
+                    Ok(grid) => self
+                        .scene
+                        .borrow_mut()
+                        .add_object(SceneObject::new(Box::new(grid))),
+                    Err(err) => println!("Skipping grid: {}", err),
+                }
+            }
+            load_pending_models(
+                &renderer,
+                &mut self.scene.borrow_mut(),
+                &mut self.gltf_cache,
+                &mut self.uploads,
             );
-            let bounds = mesh.bounds.clone();
-            self.scene
-                .add_object(SceneObject::new(Box::new(mesh), bounds));
+
+            for title in &self.info.viewport_titles {
+                let attributes = Window::default_attributes()
+                    .with_title(title)
+                    .with_resizable(true)
+                    .with_min_inner_size(LogicalSize {
+                        width: 1.0,
+                        height: 1.0,
+                    });
+                let window = event_loop.create_window(attributes).unwrap();
+                let window_renderer =
+                    renderer.create_window_renderer(&event_loop, &window, &self.info.swapchain);
+                self.viewports.push(Viewport {
+                    window,
+                    renderer: window_renderer,
+                });
+            }
 
             self.window = Some(window);
             self.renderer = Some(renderer);
@@ -97,21 +237,28 @@ impl ApplicationHandler for Application {
     fn window_event(
         &mut self,
         event_loop: &ActiveEventLoop,
-        _window_id: WindowId,
+        window_id: WindowId,
         event: WindowEvent,
     ) {
+        if let Some(index) = self
+            .viewports
+            .iter()
+            .position(|viewport| viewport.window.id() == window_id)
+        {
+            self.viewport_event(index, event);
+            return;
+        }
         self.camera.borrow_mut().handle_window_event(&event);
         if let Some(renderer) = &mut self.renderer {
             self.input_controller.handle_event(&event);
             match event {
-                WindowEvent::Resized(logical_size) => {
-                    let win_x = logical_size.width as f32;
-                    let win_y = logical_size.height as f32;
-                    if win_x > 0.0 && win_y > 0.0 {
-                        self.camera.borrow_mut().aspect_ratio_changed(win_x / win_y);
-
-                        renderer.recreate_swapchain();
-                    }
+                WindowEvent::Resized(physical_size)
+                    if self
+                        .camera
+                        .borrow_mut()
+                        .set_viewport(physical_size.width, physical_size.height) =>
+                {
+                    renderer.recreate_swapchain();
                 }
                 WindowEvent::CloseRequested => {
                     event_loop.exit();
@@ -124,7 +271,20 @@ impl ApplicationHandler for Application {
                                     event_loop.exit();
                                 }
                                 KeyCode::KeyT => {
-                                    self.stage_upload = true;
+                                    self.scene.borrow_mut().load_model(
+                                        "resources/models/Tiger.glb",
+                                        Vec3::new(100.0, 0.0, 0.0),
+                                    );
+                                }
+                                KeyCode::KeyU => {
+                                    let shading = match renderer.shading() {
+                                        Shading::Lit => Shading::Unlit,
+                                        Shading::Unlit => Shading::Lit,
+                                    };
+                                    renderer.set_shading(shading);
+                                    for viewport in &mut self.viewports {
+                                        viewport.renderer.set_shading(shading);
+                                    }
                                 }
                                 _ => {}
                             }
@@ -134,41 +294,70 @@ impl ApplicationHandler for Application {
                 },
                 WindowEvent::RedrawRequested => {
                     renderer.swap_frames();
+                    let frame = renderer.current_frame();
                     self.timer.add_timestamp();
+                    *self.overlay_text.borrow_mut() = frame_stats_text(&self.timer.stats());
+                    self.uploads.tick(self.info.upload_budget);
+
+                    let dt = update_step(
+                        &mut self.time,
+                        self.timer.get_delta() as f32,
+                        &mut self.input_controller,
+                        &self.camera,
+                        &mut self.update_callbacks,
+                        &mut self.scene.borrow_mut(),
+                    );
 
-                    let dt = self.timer.get_delta() as f32;
-                    self.camera.borrow_mut().update(dt);
-
-                    self.scene.update(
-                        &self.camera.borrow().get_proj_mat(),
-                        &self.camera.borrow().get_view_mat().inverse(),
-                        dt,
+                    let global_data = global_data(&self.camera.borrow(), &self.time);
+                    renderer.update_global_uniform(global_data.as_bytes());
+                    let light_data = LightData::gather(
+                        &self.scene.borrow().lights,
+                        self.camera.borrow().position(),
                     );
+                    renderer.update_lights(light_data.as_bytes());
+                    renderer.update_skybox(skybox_view_proj(&self.camera.borrow()));
+                    self.scene.borrow_mut().update(&frame, dt);
 
                     let command_buffer = renderer.get_commandbuffer_opaque_pass();
-                    self.scene.render(&command_buffer);
+                    renderer.record_passes(&command_buffer);
+                    renderer.end_opaque_pass(&command_buffer);
                     renderer.submit_frame(vec![&command_buffer]);
-                    if self.stage_upload {
-                        let start = Instant::now();
-                        let mesh = Model::new_from_gltf(
-                            self.gltf_cache
-                                .read(PathBuf::from("resources/models/Tiger.glb")),
-                            renderer.context.clone(),
-                            &renderer.render_pass,
-                            renderer.num_images(),
-                            Vec3::new(100.0, 0.0, 0.0),
-                        );
-                        let millisecs = start.elapsed().as_micros() as f64 / 1000.0;
-
-                        println!("Mesh new took {} ms", millisecs);
-                        // offset -= 100.0;
-                        let bounds = mesh.bounds.clone();
-                        self.scene
-                            .add_object(SceneObject::new(Box::new(mesh), bounds));
-                        self.stage_upload = false;
+                    //The material uniforms were written for the main window's frame, so
+                    //viewports bind those as well and draw the scene directly instead of
+                    //through a ScenePass, which would be handed their own frame. Their global
+                    //uniform is their own and gets the same camera and lights
+                    for viewport in &mut self.viewports {
+                        let size = viewport.window.inner_size();
+                        if size.width == 0 || size.height == 0 {
+                            continue;
+                        }
+                        let window_renderer = &mut viewport.renderer;
+                        window_renderer.swap_frames();
+                        window_renderer.update_global_uniform(global_data.as_bytes());
+                        window_renderer.update_lights(light_data.as_bytes());
+                        let command_buffer = window_renderer.get_commandbuffer_opaque_pass();
+                        self.scene.borrow().render(&command_buffer, &frame);
+                        window_renderer.end_opaque_pass(&command_buffer);
+                        window_renderer.submit_frame(vec![&command_buffer]);
                     }
-                    if let Some(window) = &self.window {
-                        window.request_redraw();
+                    load_pending_models(
+                        renderer,
+                        &mut self.scene.borrow_mut(),
+                        &mut self.gltf_cache,
+                        &mut self.uploads,
+                    );
+                    match self.info.target_fps {
+                        Some(target_fps) => {
+                            let deadline = self
+                                .timer
+                                .next_frame_deadline(target_frame_interval(target_fps));
+                            event_loop.set_control_flow(ControlFlow::WaitUntil(deadline));
+                        }
+                        None => {
+                            if let Some(window) = &self.window {
+                                window.request_redraw();
+                            }
+                        }
                     }
                 }
                 _ => {}
@@ -176,10 +365,16 @@ impl ApplicationHandler for Application {
         }
     }
 
+    //Teardown order: wait for the GPU, drop everything using renderer resources,
+    //then the renderer. The context is freed once its last Arc is gone
     fn exiting(&mut self, _event_loop: &ActiveEventLoop) {
         if let Some(mut renderer) = self.renderer.take() {
             renderer.wait_for_device();
-            self.scene.teardown();
+            self.uploads.clear();
+            self.scene.borrow_mut().teardown();
+            for mut viewport in self.viewports.drain(..) {
+                viewport.renderer.destroy();
+            }
             renderer.destroy();
         }
     }
@@ -187,7 +382,44 @@ impl ApplicationHandler for Application {
 
 impl Application {
     pub fn init(&mut self) {
-        env_logger::Builder::from_env(Env::default().default_filter_or("debug")).init();
+        if self.info.init_logger {
+            init_logger();
+        }
+    }
+
+    pub fn time(&self) -> &Time {
+        &self.time
+    }
+
+    //Frame time statistics over the last 100 frames
+    pub fn timer(&self) -> &Timer {
+        &self.timer
+    }
+
+    //For runtime changes such as InputController::rebind
+    pub fn input_controller_mut(&mut self) -> &mut InputController {
+        &mut self.input_controller
+    }
+
+    //Clone the Arc to hand the workers to anything doing background work
+    pub fn task_pool(&self) -> &Arc<TaskPool> {
+        &self.task_pool
+    }
+
+    //Viewports only resize their own swapchain, the camera keeps the main window's aspect
+    fn viewport_event(&mut self, index: usize, event: WindowEvent) {
+        match event {
+            WindowEvent::Resized(physical_size)
+                if physical_size.width > 0 && physical_size.height > 0 =>
+            {
+                self.viewports[index].renderer.recreate_swapchain();
+            }
+            WindowEvent::CloseRequested => {
+                let mut viewport = self.viewports.remove(index);
+                viewport.renderer.destroy();
+            }
+            _ => {}
+        }
     }
 
     // fn swap_frames(&mut self) {
@@ -208,14 +440,140 @@ impl Application {
     // }
 }
 
-#[derive(Default)]
+//A host application may already have set up a logger, in which case that one is kept
+fn init_logger() {
+    let _ = env_logger::Builder::from_env(Env::default().default_filter_or("debug")).try_init();
+}
+
+//The window title keeps the name as given. Vulkan takes a null terminated UTF-8 string,
+//so only interior NULs have to go
+fn vulkan_app_name(name: &str) -> CString {
+    CString::new(name.replace('\0', "")).unwrap()
+}
+
+//Advances the frame time and runs input, camera and the update callbacks in that order.
+//Returns the frame's delta time
+fn update_step(
+    time: &mut Time,
+    delta_seconds: f32,
+    input_controller: &mut InputController,
+    camera: &RefCell<Camera>,
+    update_callbacks: &mut [UpdateFn],
+    scene: &mut Scene,
+) -> f32 {
+    time.advance(delta_seconds);
+    let dt = time.delta();
+    input_controller.update(dt);
+    camera.borrow_mut().update(dt);
+    for update in update_callbacks {
+        update(scene, time);
+    }
+    dt
+}
+
+//The skybox follows the camera's rotation but never its position
+fn skybox_view_proj(camera: &Camera) -> [[f32; 4]; 4] {
+    camera.get_proj_mat().mul(&camera.skybox_view_mat()).into()
+}
+
+//The camera as the shaders see it, written once per frame to the renderer's global uniform
+fn global_data(camera: &Camera, time: &Time) -> GlobalData {
+    GlobalData::new(
+        camera.get_view_mat().inverse(),
+        camera.get_proj_mat().clone(),
+        camera.log_depth_coefficient(),
+        time.elapsed() as f32,
+    )
+}
+
+const VALIDATION_LAYER_ENV: &str = "KATLA_VALIDATION_LAYER";
+
+//Unrecognized override values fall back to the build profile
+fn validation_layer_auto_enabled(debug_build: bool, env_override: Option<&str>) -> bool {
+    match env_override.map(str::trim) {
+        Some("1") | Some("true") => true,
+        Some("0") | Some("false") => false,
+        _ => debug_build,
+    }
+}
+
+//Loads the models queued with Scene::load_model. Takes the fields separately since the
+//renderer is borrowed from the application while handling window events
+fn load_pending_models(
+    renderer: &VulkanRenderer,
+    scene: &mut Scene,
+    gltf_cache: &mut FileCache<GLTFModel>,
+    uploads: &mut UploadManager,
+) {
+    for (model_path, position) in scene.take_pending_models() {
+        let start = Instant::now();
+        let model = gltf_cache.read(model_path).and_then(|gltf_model| {
+            Model::new_from_gltf(
+                gltf_model,
+                renderer.context.clone(),
+                //TODO: (mikpe) - should not have to send these when creating a mesh... The scene should be enough and "Mesh" should be a higher level abstraction
+                &renderer.render_pass,
+                renderer.frames_in_flight(),
+                uploads,
+                position,
+            )
+        });
+        let millisecs = start.elapsed().as_micros() as f64 / 1000.0;
+
+        println!("Mesh new took {} ms", millisecs);
+        match model {
+            Ok(model) => scene.add_object(SceneObject::new(Box::new(model))),
+            Err(err) => println!("Skipping model: {}", err),
+        }
+    }
+}
+
 pub struct ApplicationBuilder {
     app_name: String,
     validation_layer_enabled: bool,
+    frames_in_flight: usize,
+    api_version: u32,
+    dynamic_rendering: bool,
+    window_settings: WindowSettings,
+    upload_budget: usize,
+    swapchain: SwapchainConfig,
+    target_fps: Option<u32>,
+    viewport_titles: Vec<String>,
+    update_callbacks: Vec<UpdateFn>,
+    init_logger: bool,
+    default_scene: bool,
+    fps_overlay: bool,
+    skybox: Option<[PathBuf; CUBEMAP_FACES]>,
+    worker_threads: usize,
     camera: Rc<RefCell<Camera>>,
     input_controller: InputController,
 }
 
+impl Default for ApplicationBuilder {
+    fn default() -> Self {
+        Self {
+            app_name: String::default(),
+            validation_layer_enabled: false,
+            frames_in_flight: DEFAULT_FRAMES_IN_FLIGHT,
+            api_version: DEFAULT_API_VERSION,
+            dynamic_rendering: false,
+            window_settings: WindowSettings::default(),
+            upload_budget: DEFAULT_UPLOAD_BUDGET,
+            swapchain: SwapchainConfig::default(),
+            target_fps: None,
+            viewport_titles: vec![],
+            update_callbacks: vec![],
+            init_logger: true,
+            default_scene: true,
+            fps_overlay: true,
+            skybox: None,
+            worker_threads: TaskPool::default_num_threads(),
+            camera: Rc::default(),
+            input_controller: InputController::default(),
+        }
+    }
+}
+
 impl ApplicationBuilder {
     pub fn new() -> Self {
         Self::default()
@@ -231,6 +589,144 @@ impl ApplicationBuilder {
         self
     }
 
+    //Only enables validation layers in debug builds, unless KATLA_VALIDATION_LAYER is set
+    //to 1 or 0
+    pub fn validation_layer_auto(mut self) -> Self {
+        let env_override = std::env::var(VALIDATION_LAYER_ENV).ok();
+        self.validation_layer_enabled =
+            validation_layer_auto_enabled(cfg!(debug_assertions), env_override.as_deref());
+        self
+    }
+
+    //E.g. 3 for triple buffering, clamped to what the surface supports
+    pub fn with_swapchain_image_count(mut self, image_count: u32) -> Self {
+        self.swapchain.image_count = Some(image_count);
+        self
+    }
+
+    //Format and color space pairs in order of preference, for every window. The surface's
+    //first format is used when none of them are supported
+    pub fn with_surface_formats(mut self, formats: &[(Format, ColorSpaceKHR)]) -> Self {
+        self.swapchain.preferred_formats = formats.to_vec();
+        self
+    }
+
+    //Clamped by the renderer to the number of swapchain images
+    pub fn with_frames_in_flight(mut self, frames_in_flight: usize) -> Self {
+        self.frames_in_flight = frames_in_flight;
+        self
+    }
+
+    //Requested Vulkan version, lowered to what the installed runtime supports
+    pub fn with_api_version(mut self, major: u32, minor: u32) -> Self {
+        self.api_version = katla_vulkan::make_api_version(0, major, minor, 0);
+        self
+    }
+
+    //Raises the requested Vulkan version to 1.3 when it is available. Falls back to render
+    //pass objects otherwise
+    pub fn with_dynamic_rendering(mut self, enabled: bool) -> Self {
+        self.dynamic_rendering = enabled;
+        self
+    }
+
+    pub fn with_scroll_mode(self, scroll_mode: ScrollMode) -> Self {
+        self.camera.borrow_mut().set_scroll_mode(scroll_mode);
+        self
+    }
+
+    pub fn with_clip_planes(self, near_plane: f32, far_plane: f32) -> Self {
+        self.camera
+            .borrow_mut()
+            .set_clip_planes(near_plane, far_plane);
+        self
+    }
+
+    //Spreads depth precision logarithmically up to the far plane, for scenes where a small
+    //near plane and a large far plane z-fight. Only the built-in shaders support it
+    pub fn with_logarithmic_depth(self, enabled: bool) -> Self {
+        self.camera.borrow_mut().set_logarithmic_depth(enabled);
+        self
+    }
+
+    //Logical size of the window, the platform default is used otherwise
+    pub fn with_window_size(mut self, width: u32, height: u32) -> Self {
+        self.window_settings.size = Some((width, height));
+        self
+    }
+
+    //Borderless fullscreen on the current monitor
+    pub fn with_fullscreen(mut self, fullscreen: bool) -> Self {
+        self.window_settings.fullscreen = fullscreen;
+        self
+    }
+
+    pub fn with_decorations(mut self, decorations: bool) -> Self {
+        self.window_settings.decorations = decorations;
+        self
+    }
+
+    pub fn with_initial_maximized(mut self, maximized: bool) -> Self {
+        self.window_settings.maximized = maximized;
+        self
+    }
+
+    //Bytes of mesh data written to the GPU per frame, larger models are spread over several frames
+    pub fn with_upload_budget(mut self, bytes: usize) -> Self {
+        self.upload_budget = bytes;
+        self
+    }
+
+    //Caps the redraw rate by waiting out the rest of each frame, None redraws as fast as possible
+    pub fn with_target_fps(mut self, target_fps: Option<u32>) -> Self {
+        self.target_fps = target_fps;
+        self
+    }
+
+    //Opens an additional window with its own swapchain, drawn after the main window each frame
+    pub fn with_viewport_window(mut self, title: impl Into<String>) -> Self {
+        self.viewport_titles.push(title.into());
+        self
+    }
+
+    //Size of the shared background TaskPool, defaults to one less than the number of cores
+    pub fn with_worker_threads(mut self, num_threads: usize) -> Self {
+        self.worker_threads = num_threads;
+        self
+    }
+
+    //Pass false when embedding Katla in a program that sets up logging itself
+    pub fn with_logger(mut self, init_logger: bool) -> Self {
+        self.init_logger = init_logger;
+        self
+    }
+
+    //Pass false to start with an empty scene, models can be added later with
+    //Scene::load_model from an update callback
+    pub fn with_default_scene(mut self, default_scene: bool) -> Self {
+        self.default_scene = default_scene;
+        self
+    }
+
+    //Frame rate and frame times drawn in the top left corner of the main window, on by default
+    pub fn with_fps_overlay(mut self, fps_overlay: bool) -> Self {
+        self.fps_overlay = fps_overlay;
+        self
+    }
+
+    //Images of the six cubemap faces drawn behind the scene, ordered +X, -X, +Y, -Y, +Z, -Z
+    pub fn with_skybox(mut self, face_paths: [PathBuf; CUBEMAP_FACES]) -> Self {
+        self.skybox = Some(face_paths);
+        self
+    }
+
+    //Called every frame after input has been handled and before the scene updates,
+    //callbacks run in the order they were added
+    pub fn with_update(mut self, update: impl FnMut(&mut Scene, &Time) + 'static) -> Self {
+        self.update_callbacks.push(Box::new(update));
+        self
+    }
+
     pub fn with_axis_input<S>(mut self, key_event: KeyCode, input: S, value: f32) -> Self
     where
         S: Into<u32>,
@@ -240,6 +736,25 @@ impl ApplicationBuilder {
         self
     }
 
+    //Has to be called after the axis has been bound with with_axis_input
+    pub fn with_axis_smoothing<S>(mut self, input: S, rise: f32, fall: f32) -> Self
+    where
+        S: Into<u32>,
+    {
+        self.input_controller
+            .assign_axis_smoothing(input.into(), rise, fall);
+        self
+    }
+
+    pub fn with_action_input<S>(mut self, key_event: KeyCode, input: S, value: f32) -> Self
+    where
+        S: Into<u32>,
+    {
+        self.input_controller
+            .assign_action_input(key_event, input.into(), value);
+        self
+    }
+
     fn build_event_loop() -> EventLoop<()> {
         let event_loop = EventLoop::new().unwrap();
         event_loop.set_control_flow(ControlFlow::Poll);
@@ -255,20 +770,171 @@ impl ApplicationBuilder {
         let info = ApplicationInfo {
             name: self.app_name,
             validation_layer_enabled: self.validation_layer_enabled,
+            frames_in_flight: self.frames_in_flight,
+            api_version: if self.dynamic_rendering {
+                self.api_version.max(DYNAMIC_RENDERING_API_VERSION)
+            } else {
+                self.api_version
+            },
+            dynamic_rendering: self.dynamic_rendering,
+            window: self.window_settings,
+            upload_budget: self.upload_budget,
+            swapchain: self.swapchain,
+            target_fps: self.target_fps,
+            viewport_titles: self.viewport_titles,
+            init_logger: self.init_logger,
+            default_scene: self.default_scene,
+            fps_overlay: self.fps_overlay,
+            skybox: self.skybox,
         };
 
         let app = Application {
             window: None,
             renderer: None,
+            viewports: vec![],
+            update_callbacks: self.update_callbacks,
             camera: self.camera,
             input_controller: input_controller,
-            scene: Scene::new(),
+            scene: Rc::new(RefCell::new(Scene::new())),
             gltf_cache: FileCache::new(),
-            stage_upload: false,
+            uploads: UploadManager::new(),
+            task_pool: Arc::new(TaskPool::new(self.worker_threads)),
             timer: Timer::new(100),
+            time: Time::new(),
+            overlay_text: Rc::default(),
             info,
         };
 
         (app, event_loop)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn app_name_keeps_text() {
+        assert_eq!(vulkan_app_name("Katla"), CString::new("Katla").unwrap());
+        assert_eq!(vulkan_app_name("Kätla ⛰"), CString::new("Kätla ⛰").unwrap());
+    }
+
+    #[test]
+    fn app_name_drops_interior_nuls() {
+        assert_eq!(vulkan_app_name("Kat\0la\0"), CString::new("Katla").unwrap());
+        assert_eq!(vulkan_app_name("\0"), CString::new("").unwrap());
+    }
+
+    #[test]
+    fn builder_window_settings_end_up_in_the_window_attributes() {
+        let builder = ApplicationBuilder::default()
+            .with_window_size(640, 480)
+            .with_window_size(1280, 720)
+            .with_fullscreen(true)
+            .with_decorations(false)
+            .with_initial_maximized(true);
+        let attributes = builder.window_settings.attributes("Katla");
+
+        assert_eq!(attributes.title, "Katla");
+        assert_eq!(
+            attributes.inner_size,
+            Some(LogicalSize::new(1280, 720).into())
+        );
+        assert_eq!(attributes.fullscreen, Some(Fullscreen::Borderless(None)));
+        assert!(!attributes.decorations);
+        assert!(attributes.maximized);
+        assert!(attributes.resizable);
+    }
+
+    #[test]
+    fn default_window_settings_leave_the_platform_defaults() {
+        let attributes = ApplicationBuilder::default()
+            .window_settings
+            .attributes("Katla");
+
+        assert_eq!(attributes.inner_size, None);
+        assert_eq!(attributes.fullscreen, None);
+        assert!(attributes.decorations);
+        assert!(!attributes.maximized);
+    }
+
+    #[test]
+    fn later_builder_calls_override_earlier_ones() {
+        let attributes = ApplicationBuilder::default()
+            .with_fullscreen(true)
+            .with_fullscreen(false)
+            .with_decorations(false)
+            .with_decorations(true)
+            .window_settings
+            .attributes("Katla");
+
+        assert_eq!(attributes.fullscreen, None);
+        assert!(attributes.decorations);
+    }
+
+    #[test]
+    fn initializing_the_logger_twice_keeps_the_first() {
+        init_logger();
+        init_logger();
+    }
+
+    #[test]
+    fn update_callbacks_see_the_frames_delta_time() {
+        let seen = Rc::new(RefCell::new(vec![]));
+        let mut update_callbacks: Vec<UpdateFn> = vec![];
+        for callback in 0..2 {
+            let seen = seen.clone();
+            update_callbacks.push(Box::new(move |_: &mut Scene, time: &Time| {
+                seen.borrow_mut()
+                    .push((callback, time.delta(), time.frame_count()));
+            }));
+        }
+        let mut time = Time::new();
+        let mut input_controller = InputController::default();
+        let camera = RefCell::new(Camera::default());
+        let mut scene = Scene::new();
+
+        for delta in [0.016, 0.033] {
+            let dt = update_step(
+                &mut time,
+                delta,
+                &mut input_controller,
+                &camera,
+                &mut update_callbacks,
+                &mut scene,
+            );
+            assert_eq!(dt, delta);
+        }
+
+        assert_eq!(
+            *seen.borrow(),
+            vec![(0, 0.016, 1), (1, 0.016, 1), (0, 0.033, 2), (1, 0.033, 2)]
+        );
+    }
+
+    #[test]
+    fn validation_layer_auto_follows_the_build_profile() {
+        assert!(validation_layer_auto_enabled(true, None));
+        assert!(!validation_layer_auto_enabled(false, None));
+    }
+
+    #[test]
+    fn validation_layer_env_override_wins_over_the_profile() {
+        for value in ["1", "true", " 1\n"] {
+            assert!(
+                validation_layer_auto_enabled(false, Some(value)),
+                "{:?}",
+                value
+            );
+        }
+        for value in ["0", "false"] {
+            assert!(
+                !validation_layer_auto_enabled(true, Some(value)),
+                "{:?}",
+                value
+            );
+        }
+        assert!(validation_layer_auto_enabled(true, Some("yes")));
+        assert!(!validation_layer_auto_enabled(false, Some("")));
+    }
+}
@@ -1,11 +1,11 @@
 use std::{f32::consts::FRAC_PI_2, rc::Rc, sync::Arc};
 
 use katla_math::{Mat4, Quat, Sphere, Transform, Vec3};
-use katla_vulkan::{CommandBuffer, RenderPass, VulkanContext};
+use katla_vulkan::{CommandBuffer, FrameContext, RenderPass, UploadManager, VulkanContext};
 
 use crate::{
     rendering::{Drawable, Material, Mesh},
-    util::GLTFModel,
+    util::{GLTFModel, ModelError, ObjModel},
 };
 
 pub struct Model {
@@ -13,6 +13,8 @@ pub struct Model {
     pub material: Material,
     pub transform: Transform,
     pub bounds: Sphere,
+    //Bounding radius at scale 1, set_transform scales it
+    radius: f32,
 }
 
 impl Model {
@@ -20,38 +22,85 @@ impl Model {
         model: Rc<GLTFModel>,
         context: Arc<VulkanContext>,
         render_pass: &RenderPass,
-        num_images: usize,
+        frames_in_flight: usize,
+        uploads: &mut UploadManager,
         position: Vec3,
-    ) -> Self {
-        let material = Material::new(model.clone(), context.clone(), render_pass, num_images);
+    ) -> Result<Self, ModelError> {
+        if model.vertex_data.is_empty() {
+            return Err(ModelError::Unsupported(
+                "no triangle mesh primitives found".to_owned(),
+            ));
+        }
+        let material = Material::new(
+            model.clone(),
+            context.clone(),
+            render_pass,
+            frames_in_flight,
+        )?;
         let mut bounds = model.bounds.clone();
         bounds.center = position;
         let transform = Transform::new_from_position(position);
 
-        let mesh = Mesh::new_from_model(model, context.clone());
-        Self {
+        let mesh = Mesh::new_from_model(model, context.clone(), uploads)?;
+        Ok(Self {
             meshes: vec![mesh],
             material,
             transform,
+            radius: bounds.radius,
             bounds,
-        }
+        })
+    }
+
+    //OBJ files carry no usable material, they are drawn with a plain white one
+    pub fn new_from_obj(
+        model: Rc<ObjModel>,
+        context: Arc<VulkanContext>,
+        render_pass: &RenderPass,
+        frames_in_flight: usize,
+        position: Vec3,
+    ) -> Result<Self, ModelError> {
+        let material =
+            Material::new_solid_color(context.clone(), render_pass, frames_in_flight, [255; 4])?;
+        let mut bounds = model.bounds.clone();
+        bounds.center = position;
+        let transform = Transform::new_from_position(position);
+
+        let mesh =
+            Mesh::new_from_data(context, model.vertex_data.clone(), model.index_data.clone())?;
+        Ok(Self {
+            meshes: vec![mesh],
+            material,
+            transform,
+            radius: bounds.radius,
+            bounds,
+        })
     }
 }
 
 impl Drawable for Model {
-    fn update(&mut self, view: &Mat4, proj: &Mat4, dt: f32) {
+    fn update(&mut self, frame: &FrameContext, dt: f32) {
         let quat = Quat::new_from_axis_angle(Vec3::new(0.0, 1.0, 0.0), FRAC_PI_2 * dt);
         self.transform.rotation = self.transform.rotation * quat;
-        let model = self.transform.make_mat4();
-        self.material
-            .upload_pipeline_data(view.clone(), proj.clone(), model);
+        self.transform.rotation.normalize();
+        let model = Mat4::from(&self.transform);
+        self.material.upload_pipeline_data(frame, model);
     }
 
-    fn draw(&self, command_buffer: &CommandBuffer) {
-        self.material.bind(command_buffer);
+    fn draw(&self, command_buffer: &CommandBuffer, frame: &FrameContext) {
+        self.material.bind(command_buffer, frame);
 
         for mesh in &self.meshes {
             mesh.draw(command_buffer);
         }
     }
+
+    fn bounds(&self) -> Sphere {
+        self.bounds.clone()
+    }
+
+    fn set_transform(&mut self, transform: Transform) {
+        self.bounds.center = transform.position;
+        self.bounds.radius = self.radius * transform.max_scale();
+        self.transform = transform;
+    }
 }
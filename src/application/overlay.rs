@@ -0,0 +1,60 @@
+use std::{cell::RefCell, rc::Rc};
+
+use katla_vulkan::{CommandBuffer, FrameContext, Pass, TextRenderer};
+
+use crate::util::FrameStats;
+
+//Pixels between the top left corner of the window and the text
+const MARGIN: f32 = 8.0;
+
+/// Frame rate and frame times in the top left corner, drawn over the scene. Registered after
+/// the ScenePass, the application rewrites the text every frame.
+pub struct OverlayPass {
+    text_renderer: RefCell<TextRenderer>,
+    text: Rc<RefCell<String>>,
+}
+
+impl OverlayPass {
+    pub fn new(text_renderer: TextRenderer, text: Rc<RefCell<String>>) -> Self {
+        Self {
+            text_renderer: RefCell::new(text_renderer),
+            text,
+        }
+    }
+}
+
+impl Pass for OverlayPass {
+    fn record(&self, command_buffer: &CommandBuffer, frame: &FrameContext) {
+        let mut text_renderer = self.text_renderer.borrow_mut();
+        text_renderer.begin(command_buffer, frame);
+        text_renderer.draw_text(command_buffer, MARGIN, MARGIN, &self.text.borrow());
+    }
+}
+
+pub fn frame_stats_text(stats: &FrameStats) -> String {
+    format!(
+        "{:.0} FPS\n{:.2} ms (min {:.2}, max {:.2})",
+        stats.fps(),
+        stats.mean_ms,
+        stats.min_ms,
+        stats.max_ms
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats_text_shows_the_rate_and_frame_times() {
+        let stats = FrameStats {
+            mean_ms: 20.0,
+            min_ms: 15.5,
+            max_ms: 25.25,
+        };
+        assert_eq!(
+            frame_stats_text(&stats),
+            "50 FPS\n20.00 ms (min 15.50, max 25.25)"
+        );
+    }
+}
@@ -1,7 +1,7 @@
-use crate::rendering::Drawable;
-use katla_math::{Mat4, Sphere, Vec3};
-use katla_vulkan::CommandBuffer;
-use std::rc::Rc;
+use crate::rendering::{Drawable, Light};
+use katla_math::{Sphere, Transform, Vec3, AABB};
+use katla_vulkan::{CommandBuffer, FrameContext, Pass};
+use std::{cell::RefCell, path::PathBuf, rc::Rc};
 
 pub struct Player {
     pub position: Vec3,
@@ -16,11 +16,14 @@ pub struct SceneObject {
 pub struct Scene {
     pub player: Player,
     pub scene_objects: Vec<SceneObject>,
+    pub lights: Vec<Light>,
+    pending_models: Vec<(PathBuf, Vec3)>,
 }
 
 impl SceneObject {
-    pub fn new(drawable: Box<dyn Drawable>, bounds: Sphere) -> Self {
+    pub fn new(drawable: Box<dyn Drawable>) -> Self {
         let position = Vec3::new(0.0, 0.0, 0.0);
+        let bounds = drawable.bounds();
         Self {
             position,
             drawable,
@@ -28,6 +31,15 @@ impl SceneObject {
             bounds,
         }
     }
+
+    //Moves the object without touching the camera, the drawable uploads the new
+    //model matrix on the next Scene::update. The bounds follow the drawable's, which
+    //also take the scale into account
+    pub fn set_transform(&mut self, transform: Transform) {
+        self.position = transform.position;
+        self.drawable.set_transform(transform);
+        self.bounds = self.drawable.bounds();
+    }
 }
 
 impl Scene {
@@ -39,32 +51,273 @@ impl Scene {
         Self {
             player,
             scene_objects,
+            lights: vec![],
+            pending_models: vec![],
         }
     }
 
-    // pub fn add_model(&mut self, _model_path: PathBuf) {
-    //     todo!("Add a model to the scene, talk with the renderer?");
-    // }
+    //Queues a glTF model, the application loads it with the renderer after the current
+    //frame has been submitted. Failing loads are skipped, the scene stays as it was
+    pub fn load_model(&mut self, model_path: impl Into<PathBuf>, position: Vec3) {
+        self.pending_models.push((model_path.into(), position));
+    }
+
+    pub(crate) fn take_pending_models(&mut self) -> Vec<(PathBuf, Vec3)> {
+        std::mem::take(&mut self.pending_models)
+    }
 
     pub fn teardown(&mut self) {
         self.scene_objects.clear();
+        self.lights.clear();
+        self.pending_models.clear();
     }
 
-    pub fn update(&mut self, proj: &Mat4, view: &Mat4, dt: f32) {
+    pub fn update(&mut self, frame: &FrameContext, dt: f32) {
         for object in &mut self.scene_objects {
-            object.drawable.update(&view, &proj, dt);
+            object.drawable.update(frame, dt);
         }
     }
 
+    //World space box around every object's bounds, None for an empty scene. Use its
+    //bounding_sphere with Camera::frame_bounds to fit the whole scene in view
+    pub fn compute_bounds(&self) -> Option<AABB> {
+        self.scene_objects
+            .iter()
+            .map(|object| AABB::from(&object.bounds))
+            .reduce(|bounds, object_bounds| bounds.merge(&object_bounds))
+    }
+
     pub fn add_object(&mut self, scene_object: SceneObject) {
         self.scene_objects.push(scene_object);
     }
 
-    pub fn render(&self, command_buffer: &CommandBuffer) {
+    //Only the nearest MAX_LIGHTS are shaded each frame, see LightData::gather
+    pub fn add_light(&mut self, light: Light) {
+        self.lights.push(light);
+    }
+
+    pub fn render(&self, command_buffer: &CommandBuffer, frame: &FrameContext) {
         for object in &self.scene_objects {
-            object.drawable.draw(command_buffer);
+            object.drawable.draw(command_buffer, frame);
+        }
+    }
+}
+
+/// The opaque pass, draws every scene object. Registered as the renderer's first pass
+pub struct ScenePass {
+    scene: Rc<RefCell<Scene>>,
+}
+
+impl ScenePass {
+    pub fn new(scene: Rc<RefCell<Scene>>) -> Self {
+        Self { scene }
+    }
+}
+
+impl Pass for ScenePass {
+    fn record(&self, command_buffer: &CommandBuffer, frame: &FrameContext) {
+        self.scene.borrow().render(command_buffer, frame);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+    use katla_math::Mat4;
+    use katla_vulkan::{Extent2D, Shading};
+
+    //Bounds follow the transform like a mesh's do, nothing gets drawn
+    struct MockDrawable {
+        local_bounds: Sphere,
+        transform: Transform,
+    }
+
+    impl MockDrawable {
+        fn boxed(center: Vec3, radius: f32) -> Box<dyn Drawable> {
+            Box::new(Self {
+                local_bounds: Sphere::new(center, radius),
+                transform: Transform::new(),
+            })
         }
-        command_buffer.end_render_pass();
-        command_buffer.end_command();
+    }
+
+    impl Drawable for MockDrawable {
+        fn update(&mut self, _frame: &FrameContext, _dt: f32) {}
+        fn draw(&self, _command_buffer: &CommandBuffer, _frame: &FrameContext) {}
+        fn bounds(&self) -> Sphere {
+            let scale = self.transform.max_scale();
+            Sphere::new(
+                self.transform.position + self.local_bounds.center.mul(scale),
+                self.local_bounds.radius * scale,
+            )
+        }
+        fn set_transform(&mut self, transform: Transform) {
+            self.transform = transform;
+        }
+    }
+
+    fn assert_vec3_eq(a: Vec3, b: Vec3) {
+        for i in 0..3 {
+            assert_abs_diff_eq!(a[i], b[i], epsilon = 1e-5);
+        }
+    }
+
+    #[test]
+    fn empty_scene_has_no_bounds() {
+        assert!(Scene::new().compute_bounds().is_none());
+    }
+
+    #[test]
+    fn single_object_bounds_box_its_sphere() {
+        let mut scene = Scene::new();
+        scene.add_object(SceneObject::new(MockDrawable::boxed(
+            Vec3::new(1.0, 2.0, 3.0),
+            2.0,
+        )));
+        let bounds = scene.compute_bounds().unwrap();
+        assert_vec3_eq(bounds.min(), Vec3::new(-1.0, 0.0, 1.0));
+        assert_vec3_eq(bounds.max(), Vec3::new(3.0, 4.0, 5.0));
+    }
+
+    #[test]
+    fn bounds_cover_every_transformed_object() {
+        let mut scene = Scene::new();
+        let mut moved = SceneObject::new(MockDrawable::boxed(Vec3::new(0.0, 0.0, 0.0), 1.0));
+        moved.set_transform(Transform::new_from_position(Vec3::new(10.0, 0.0, 0.0)));
+        let mut scaled = SceneObject::new(MockDrawable::boxed(Vec3::new(0.0, 1.0, 0.0), 1.0));
+        scaled.set_transform(Transform::new_from_scale(Vec3::new(2.0, 2.0, 2.0)));
+        scene.add_object(moved);
+        scene.add_object(scaled);
+
+        let bounds = scene.compute_bounds().unwrap();
+        assert_vec3_eq(bounds.min(), Vec3::new(-2.0, -1.0, -2.0));
+        assert_vec3_eq(bounds.max(), Vec3::new(11.0, 4.0, 2.0));
+        let sphere = bounds.bounding_sphere();
+        assert_vec3_eq(sphere.center, Vec3::new(4.5, 1.5, 0.0));
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum Call {
+        Update { frame: FrameContext, dt: f32 },
+        Draw { frame: FrameContext },
+    }
+
+    //Records what the scene hands it, like a procedural drawable would receive it
+    struct RecordingDrawable {
+        calls: Rc<RefCell<Vec<Call>>>,
+    }
+
+    impl Drawable for RecordingDrawable {
+        fn update(&mut self, frame: &FrameContext, dt: f32) {
+            self.calls
+                .borrow_mut()
+                .push(Call::Update { frame: *frame, dt });
+        }
+        fn draw(&self, _command_buffer: &CommandBuffer, frame: &FrameContext) {
+            self.calls.borrow_mut().push(Call::Draw { frame: *frame });
+        }
+        fn bounds(&self) -> Sphere {
+            Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0)
+        }
+        fn set_transform(&mut self, _transform: Transform) {}
+    }
+
+    #[test]
+    fn custom_drawables_are_updated_then_drawn_each_frame() {
+        let calls = Rc::new(RefCell::new(vec![]));
+        let mut scene = Scene::new();
+        scene.add_object(SceneObject::new(Box::new(RecordingDrawable {
+            calls: calls.clone(),
+        })));
+        let command_buffer = CommandBuffer::detached();
+        let frames: Vec<_> = (0..2)
+            .map(|index| FrameContext {
+                frame_index: index,
+                image_index: index + 1,
+                extent: Extent2D {
+                    width: 800,
+                    height: 600,
+                },
+                shading: Shading::Lit,
+            })
+            .collect();
+
+        for (frame, dt) in frames.iter().zip([0.016, 0.033]) {
+            scene.update(frame, dt);
+            scene.render(&command_buffer, frame);
+        }
+
+        assert_eq!(
+            *calls.borrow(),
+            vec![
+                Call::Update {
+                    frame: frames[0],
+                    dt: 0.016
+                },
+                Call::Draw { frame: frames[0] },
+                Call::Update {
+                    frame: frames[1],
+                    dt: 0.033
+                },
+                Call::Draw { frame: frames[1] },
+            ]
+        );
+    }
+
+    //Uploads its model matrix on update the way Model does
+    struct UploadingDrawable {
+        transform: Transform,
+        uploads: Rc<RefCell<Vec<Mat4>>>,
+    }
+
+    impl Drawable for UploadingDrawable {
+        fn update(&mut self, _frame: &FrameContext, _dt: f32) {
+            self.uploads.borrow_mut().push(Mat4::from(&self.transform));
+        }
+        fn draw(&self, _command_buffer: &CommandBuffer, _frame: &FrameContext) {}
+        fn bounds(&self) -> Sphere {
+            Sphere::new(self.transform.position, self.transform.max_scale())
+        }
+        fn set_transform(&mut self, transform: Transform) {
+            self.transform = transform;
+        }
+    }
+
+    #[test]
+    fn moved_objects_upload_a_new_model_matrix_and_bounds() {
+        let uploads = Rc::new(RefCell::new(vec![]));
+        let mut scene = Scene::new();
+        scene.add_object(SceneObject::new(Box::new(UploadingDrawable {
+            transform: Transform::new(),
+            uploads: uploads.clone(),
+        })));
+        let frame = FrameContext {
+            frame_index: 0,
+            image_index: 0,
+            extent: Extent2D {
+                width: 800,
+                height: 600,
+            },
+            shading: Shading::Lit,
+        };
+        scene.update(&frame, 0.016);
+        scene.scene_objects[0]
+            .set_transform(Transform::new_from_position(Vec3::new(5.0, 0.0, 0.0)));
+        //Nothing is uploaded until the next update
+        assert_eq!(uploads.borrow().len(), 1);
+        scene.update(&frame, 0.016);
+
+        let uploads = uploads.borrow();
+        assert_eq!(uploads[0], Mat4::identity());
+        assert_ne!(uploads[1], uploads[0]);
+        assert_vec3_eq(
+            Vec3::new(uploads[1][3][0], uploads[1][3][1], uploads[1][3][2]),
+            Vec3::new(5.0, 0.0, 0.0),
+        );
+        assert_vec3_eq(scene.scene_objects[0].position, Vec3::new(5.0, 0.0, 0.0));
+        let bounds = scene.compute_bounds().unwrap();
+        assert_vec3_eq(bounds.min(), Vec3::new(4.0, -1.0, -1.0));
+        assert_vec3_eq(bounds.max(), Vec3::new(6.0, 1.0, 1.0));
     }
 }
@@ -1,14 +1,42 @@
 use crate::input::InputController;
 use crate::input::InputMapping;
-use katla_math::{Mat4, Vec3};
+use katla_math::{Mat4, Quat, Sphere, Vec3};
 use std::{cell::RefCell, rc::Rc};
 use winit::event::Event;
-use winit::event::{DeviceEvent, ElementState, MouseButton, WindowEvent};
+use winit::event::{DeviceEvent, ElementState, MouseButton, MouseScrollDelta, WindowEvent};
+
+pub const MIN_FOV: f32 = 10.0;
+pub const MAX_FOV: f32 = 120.0;
+//Degrees of FOV per scroll line
+const FOV_PER_LINE: f32 = 2.0;
+//Trackpads report pixels with high resolution, treat this many as one line
+const PIXELS_PER_LINE: f64 = 40.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScrollMode {
+    Zoom,
+    Dolly,
+}
+
+//Maps a scroll amount in lines to a new FOV, scrolling up zooms in
+pub fn scroll_to_fov(fov: f32, lines: f32) -> f32 {
+    (fov - lines * FOV_PER_LINE).clamp(MIN_FOV, MAX_FOV)
+}
+
+pub fn scroll_delta_lines(delta: &MouseScrollDelta) -> f32 {
+    match delta {
+        MouseScrollDelta::LineDelta(_, y) => *y,
+        MouseScrollDelta::PixelDelta(position) => (position.y / PIXELS_PER_LINE) as f32,
+    }
+}
 
 pub struct PerspectiveProjection {
     fov: f32,
+    aspect_ratio: f32,
     near_plane: f32,
     far_plane: f32,
+    //Only changes what the shaders write as depth, the matrix stays the same
+    logarithmic_depth: bool,
     matrix: Mat4,
 }
 
@@ -17,20 +45,44 @@ impl Default for PerspectiveProjection {
         let fov = 60.0;
         let near_plane = 0.001;
         let far_plane = 10000.0;
-        let matrix = Mat4::create_proj(fov, 1.0, near_plane, far_plane);
+        let aspect_ratio = 1.0;
+        let matrix = Mat4::create_proj(fov, aspect_ratio, near_plane, far_plane);
 
         Self {
             fov,
+            aspect_ratio,
             near_plane,
             far_plane,
+            logarithmic_depth: false,
             matrix,
         }
     }
 }
 
 impl PerspectiveProjection {
-    pub fn recreate_matrix(&mut self, aspect_ratio: f32) {
-        self.matrix = Mat4::create_proj(self.fov, aspect_ratio, self.near_plane, self.far_plane);
+    pub fn set_fov(&mut self, fov: f32) {
+        self.fov = fov.clamp(MIN_FOV, MAX_FOV);
+        self.recreate_matrix();
+    }
+
+    pub fn set_aspect_ratio(&mut self, aspect_ratio: f32) {
+        self.aspect_ratio = aspect_ratio;
+        self.recreate_matrix();
+    }
+
+    //Ignored unless 0 < near < far
+    pub fn set_clip_planes(&mut self, near_plane: f32, far_plane: f32) {
+        if near_plane <= 0.0 || far_plane <= near_plane {
+            return;
+        }
+        self.near_plane = near_plane;
+        self.far_plane = far_plane;
+        self.recreate_matrix();
+    }
+
+    pub fn recreate_matrix(&mut self) {
+        self.matrix =
+            Mat4::create_proj(self.fov, self.aspect_ratio, self.near_plane, self.far_plane);
     }
 }
 
@@ -41,9 +93,22 @@ pub struct Camera {
     velocity_dir: Vec3,
     input_dir: Vec3,
     speed: f32,
+    sprint_multiplier: f32,
     yaw: f64,
     pitch: f64,
     looking: bool,
+    scroll_mode: ScrollMode,
+    //Fractions of a line from trackpads, applied once they add up to a whole line
+    pending_scroll_lines: f32,
+}
+
+//Action inputs report their bound value on press and 0.0 on release
+fn sprint_multiplier(action_value: f32) -> f32 {
+    if action_value > 0.0 {
+        action_value
+    } else {
+        1.0
+    }
 }
 
 //This is not very fun... should find some better way for this in the future.
@@ -58,9 +123,18 @@ pub fn setup_camera_bindings(camera: Rc<RefCell<Camera>>, input_controller: &mut
         InputMapping::MoveHorizontal,
         Box::new(move |value| cam.borrow_mut().handle_steer_horiz(value)),
     );
+    let cam = camera.clone();
     input_controller.assign_axis_callback(
         InputMapping::MoveForward,
-        Box::new(move |value| camera.borrow_mut().handle_steer_fwd(value)),
+        Box::new(move |value| cam.borrow_mut().handle_steer_fwd(value)),
+    );
+    input_controller.bind_input_callback(
+        InputMapping::Sprint.into(),
+        Box::new(move |value| {
+            camera
+                .borrow_mut()
+                .set_sprint_multiplier(sprint_multiplier(value))
+        }),
     );
 }
 
@@ -75,6 +149,19 @@ impl Camera {
         self.input_dir[2] = value;
     }
 
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed;
+    }
+
+    pub fn set_sprint_multiplier(&mut self, multiplier: f32) {
+        self.sprint_multiplier = multiplier;
+    }
+
+    //The speed currently applied to movement, including any sprint multiplier
+    pub fn speed(&self) -> f32 {
+        self.speed * self.sprint_multiplier
+    }
+
     pub fn new() -> Self {
         let camera = Self {
             pos: Vec3::new(0.0, 50.0, -450.0),
@@ -82,9 +169,12 @@ impl Camera {
             velocity_dir: Vec3::new(0.0, 0.0, 0.0),
             input_dir: Vec3::new(0.0, 0.0, 0.0),
             speed: 100.0,
+            sprint_multiplier: 1.0,
             yaw: 0.0,
             pitch: 0.0,
             looking: false,
+            scroll_mode: ScrollMode::Zoom,
+            pending_scroll_lines: 0.0,
         };
 
         camera
@@ -104,7 +194,72 @@ impl Camera {
         }
     }
 
+    pub fn set_scroll_mode(&mut self, scroll_mode: ScrollMode) {
+        self.scroll_mode = scroll_mode;
+    }
+
+    pub fn set_fov(&mut self, fov: f32) {
+        self.projection.set_fov(fov);
+    }
+
+    pub fn fov(&self) -> f32 {
+        self.projection.fov
+    }
+
+    //A near plane far below the scene's scale wastes most of the depth precision
+    pub fn set_clip_planes(&mut self, near_plane: f32, far_plane: f32) {
+        self.projection.set_clip_planes(near_plane, far_plane);
+    }
+
+    pub fn clip_planes(&self) -> (f32, f32) {
+        (self.projection.near_plane, self.projection.far_plane)
+    }
+
+    pub fn set_logarithmic_depth(&mut self, enabled: bool) {
+        self.projection.logarithmic_depth = enabled;
+    }
+
+    pub fn logarithmic_depth(&self) -> bool {
+        self.projection.logarithmic_depth
+    }
+
+    //Value for the shaders' log_depth_coefficient uniform, 0.0 unless logarithmic depth is
+    //enabled. See katla_math::log_depth_coefficient
+    pub fn log_depth_coefficient(&self) -> f32 {
+        if self.projection.logarithmic_depth {
+            katla_math::log_depth_coefficient(self.projection.far_plane)
+        } else {
+            0.0
+        }
+    }
+
+    fn handle_scroll(&mut self, lines: f32) {
+        match self.scroll_mode {
+            ScrollMode::Zoom => {
+                let fov = scroll_to_fov(self.projection.fov, lines);
+                self.projection.set_fov(fov);
+            }
+            ScrollMode::Dolly => {
+                let fwd = self.get_view_rotation().rotate(Vec3::new(0.0, 0.0, 1.0));
+                //One line moves as far as a tenth of a second of flying
+                self.pos = self.pos + fwd.mul(lines * self.speed() * 0.1);
+            }
+        }
+    }
+
+    fn handle_scroll_delta(&mut self, delta: &MouseScrollDelta) {
+        self.pending_scroll_lines += scroll_delta_lines(delta);
+        let lines = self.pending_scroll_lines.trunc();
+        if lines != 0.0 {
+            self.pending_scroll_lines -= lines;
+            self.handle_scroll(lines);
+        }
+    }
+
     pub fn handle_window_event(&mut self, event: &WindowEvent) {
+        if let WindowEvent::MouseWheel { delta, .. } = event {
+            self.handle_scroll_delta(delta);
+        }
         if let WindowEvent::MouseInput {
             device_id: _,
             state,
@@ -119,30 +274,70 @@ impl Camera {
         }
     }
 
-    pub fn aspect_ratio_changed(&mut self, aspect_ratio: f32) {
-        self.projection.recreate_matrix(aspect_ratio);
+    //Returns false and keeps the previous projection for degenerate (e.g. minimized) viewports
+    pub fn set_viewport(&mut self, width: u32, height: u32) -> bool {
+        if width == 0 || height == 0 {
+            return false;
+        }
+        self.projection
+            .set_aspect_ratio(width as f32 / height as f32);
+        true
     }
 
-    // pub fn look_at_sphere(&mut self, sphere: &Sphere) {
-    //     self.pos = sphere.center - Vec3::new(0.0, 0.0, sphere.radius * 2.0);
-    //     self.yaw = 0.0;
-    //     self.pitch = 0.0;
-    // }
+    pub fn aspect_ratio(&self) -> f32 {
+        self.projection.aspect_ratio
+    }
+
+    pub fn position(&self) -> Vec3 {
+        self.pos
+    }
+
+    pub fn set_position(&mut self, position: Vec3) {
+        self.pos = position;
+    }
+
+    pub fn forward(&self) -> Vec3 {
+        self.get_view_rotation().rotate(Vec3::new(0.0, 0.0, 1.0))
+    }
+
+    //The view keeps -y as up, so only yaw and pitch are changed. Looking straight up or
+    //down is clamped just like mouse look
+    pub fn look_at(&mut self, target: Vec3) {
+        let dir = target - self.pos;
+        if dir.dot(dir) == 0.0 {
+            return;
+        }
+        let dir = dir.normalize();
+        self.yaw = f64::from(dir[0]).atan2(f64::from(dir[2]));
+        self.pitch = f64::from(dir[1].clamp(-1.0, 1.0)).asin().clamp(
+            -std::f64::consts::FRAC_PI_2 + 0.01,
+            std::f64::consts::FRAC_PI_2 - 0.01,
+        );
+    }
+
+    //Moves the camera back along its current forward until the sphere fits the narrowest FOV
+    pub fn frame_bounds(&mut self, sphere: &Sphere) {
+        let half_fov_vert = self.projection.fov.to_radians() * 0.5;
+        let half_fov_horiz = (half_fov_vert.tan() * self.projection.aspect_ratio).atan();
+        let half_fov = half_fov_vert.min(half_fov_horiz);
+        let distance = sphere.radius / half_fov.sin();
+        self.pos = sphere.center - self.forward().mul(distance);
+    }
 
     pub fn update(&mut self, dt: f32) {
-        let velocity_dir = katla_math::mat4_mul_vec3(&self.get_view_rotation(), &self.input_dir);
+        let velocity_dir = self.get_view_rotation().rotate(self.input_dir);
 
         self.velocity_dir = Vec3::lerp(self.velocity_dir, velocity_dir, 7.0 * dt);
 
-        self.pos = self.pos + self.velocity_dir.mul(self.speed * dt);
+        self.pos = self.pos + self.velocity_dir.mul(self.speed() * dt);
     }
 
     // Note to self:
     // This is valid since we are doing some assumptions w.r.t. rotation of the surfacetransformation
-    fn get_view_rotation(&self) -> Mat4 {
-        let yaw = Mat4::from_rotaxis(&(self.yaw as f32), [0.0, 1.0, 0.0]);
-        let pitch = Mat4::from_rotaxis(&(self.pitch as f32), [-1.0, 0.0, 0.0]);
-        yaw.mul(&pitch)
+    fn get_view_rotation(&self) -> Quat {
+        let yaw = Quat::new_from_axis_angle(Vec3::new(0.0, 1.0, 0.0), self.yaw as f32);
+        let pitch = Quat::new_from_axis_angle(Vec3::new(-1.0, 0.0, 0.0), self.pitch as f32);
+        yaw * pitch
     }
 
     // pub fn get_cam_pos(&self) -> Vec3 {
@@ -154,7 +349,7 @@ impl Camera {
 
     pub fn get_view_mat(&self) -> Mat4 {
         let fwd = Vec3::new(0.0, 0.0, 1.0);
-        let to = katla_math::mat4_mul_vec3(&self.get_view_rotation(), &fwd);
+        let to = self.get_view_rotation().rotate(fwd);
 
         Mat4::create_lookat(
             self.pos.clone(),
@@ -162,6 +357,12 @@ impl Camera {
             Vec3::new(0.0, -1.0, 0.0),
         )
     }
+
+    //The view matrix with the camera's position stripped, for things infinitely far away like
+    //the skybox
+    pub fn skybox_view_mat(&self) -> Mat4 {
+        self.get_view_mat().inverse().without_translation()
+    }
 }
 
 impl Default for Camera {
@@ -169,3 +370,167 @@ impl Default for Camera {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+    use katla_math::Vec4;
+
+    #[test]
+    fn set_viewport_updates_aspect_ratio() {
+        let mut camera = Camera::new();
+        assert!(camera.set_viewport(1920, 1080));
+        assert_abs_diff_eq!(camera.aspect_ratio(), 1920.0 / 1080.0);
+    }
+
+    #[test]
+    fn skybox_view_ignores_the_camera_position() {
+        let mut camera = Camera::new();
+        camera.set_position(Vec3::new(10.0, -4.0, 250.0));
+        camera.look_at(Vec3::new(0.0, 0.0, 0.0));
+        let view = camera.get_view_mat().inverse();
+        //The regular view does move things with the camera
+        let translation = view.column(3);
+        assert!(Vec3::new(translation[0], translation[1], translation[2]).distance() > 1.0);
+
+        let skybox_view = camera.skybox_view_mat();
+        assert_eq!(*skybox_view.column(3), Vec4([0.0, 0.0, 0.0, 1.0]));
+        for column in 0..3 {
+            assert_eq!(skybox_view.column(column), view.column(column));
+        }
+
+        //Moving without turning keeps the sky where it was
+        camera.set_position(Vec3::new(-3.0, 1.0, 0.5));
+        let moved = camera.skybox_view_mat();
+        for column in 0..4 {
+            for row in 0..4 {
+                assert_abs_diff_eq!(moved[column][row], skybox_view[column][row], epsilon = 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn set_viewport_rejects_zero_size() {
+        let mut camera = Camera::new();
+        camera.set_viewport(800, 600);
+        let proj = camera.get_proj_mat().clone();
+        assert!(!camera.set_viewport(0, 600));
+        assert!(!camera.set_viewport(800, 0));
+        assert!(!camera.set_viewport(0, 0));
+        assert_abs_diff_eq!(camera.aspect_ratio(), 800.0 / 600.0);
+        assert_eq!(camera.get_proj_mat(), &proj);
+    }
+
+    fn pixels(y: f64) -> MouseScrollDelta {
+        MouseScrollDelta::PixelDelta(winit::dpi::PhysicalPosition::new(0.0, y))
+    }
+
+    #[test]
+    fn scroll_to_fov_clamps() {
+        assert_abs_diff_eq!(scroll_to_fov(60.0, 1.0), 60.0 - FOV_PER_LINE);
+        assert_abs_diff_eq!(scroll_to_fov(60.0, 1000.0), MIN_FOV);
+        assert_abs_diff_eq!(scroll_to_fov(60.0, -1000.0), MAX_FOV);
+    }
+
+    #[test]
+    fn pixel_scrolling_matches_line_scrolling() {
+        let mut by_line = Camera::new();
+        by_line.handle_scroll_delta(&MouseScrollDelta::LineDelta(0.0, 1.0));
+
+        let mut by_pixel = Camera::new();
+        by_pixel.handle_scroll_delta(&pixels(PIXELS_PER_LINE));
+        assert_abs_diff_eq!(by_pixel.fov(), by_line.fov());
+    }
+
+    #[test]
+    fn sub_line_scrolling_accumulates() {
+        let mut camera = Camera::new();
+        let fov = camera.fov();
+        camera.handle_scroll_delta(&pixels(PIXELS_PER_LINE * 0.5));
+        assert_abs_diff_eq!(camera.fov(), fov);
+        camera.handle_scroll_delta(&pixels(PIXELS_PER_LINE * 0.5));
+        assert_abs_diff_eq!(camera.fov(), fov - FOV_PER_LINE);
+    }
+
+    #[test]
+    fn sprint_multiplies_the_speed_while_held() {
+        let mut camera = Camera::new();
+        camera.set_speed(10.0);
+        camera.set_sprint_multiplier(sprint_multiplier(4.0));
+        assert_abs_diff_eq!(camera.speed(), 40.0);
+        camera.set_sprint_multiplier(sprint_multiplier(0.0));
+        assert_abs_diff_eq!(camera.speed(), 10.0);
+    }
+
+    #[test]
+    fn sprint_covers_more_distance() {
+        let distance = |multiplier| {
+            let mut camera = Camera::new();
+            camera.set_sprint_multiplier(multiplier);
+            camera.handle_steer_fwd(1.0);
+            let start = camera.position();
+            for _ in 0..10 {
+                camera.update(0.1);
+            }
+            (camera.position() - start).distance()
+        };
+        assert_abs_diff_eq!(distance(2.0), distance(1.0) * 2.0, epsilon = 1e-2);
+    }
+
+    #[test]
+    fn log_depth_coefficient_is_zero_unless_enabled() {
+        let mut camera = Camera::new();
+        assert!(!camera.logarithmic_depth());
+        assert_eq!(camera.log_depth_coefficient(), 0.0);
+
+        camera.set_logarithmic_depth(true);
+        camera.set_clip_planes(0.1, 1000.0);
+        assert_abs_diff_eq!(
+            camera.log_depth_coefficient(),
+            katla_math::log_depth_coefficient(1000.0)
+        );
+        //The far plane maps to a depth of 1
+        assert_abs_diff_eq!(
+            (1.0f32 + 1000.0).log2() * camera.log_depth_coefficient(),
+            1.0,
+            epsilon = 1e-6
+        );
+    }
+
+    fn assert_vec3_eq(a: Vec3, b: Vec3) {
+        for i in 0..3 {
+            assert_abs_diff_eq!(a[i], b[i], epsilon = 1e-4);
+        }
+    }
+
+    #[test]
+    fn look_at_points_forward_at_the_target() {
+        let targets = [
+            Vec3::new(10.0, 0.0, 0.0),
+            Vec3::new(-3.0, 4.0, 5.0),
+            Vec3::new(1.0, -2.0, -8.0),
+        ];
+        for target in targets {
+            let mut camera = Camera::new();
+            camera.set_position(Vec3::new(1.0, 2.0, 3.0));
+            camera.look_at(target);
+            assert_vec3_eq(camera.forward(), (target - camera.position()).normalize());
+        }
+    }
+
+    #[test]
+    fn frame_bounds_backs_off_along_forward() {
+        let mut camera = Camera::new();
+        camera.set_viewport(800, 600);
+        camera.look_at(Vec3::new(3.0, 1.0, 2.0));
+        let sphere = Sphere::new(Vec3::new(5.0, -2.0, 7.0), 3.0);
+        camera.frame_bounds(&sphere);
+
+        let half_fov = (camera.fov().to_radians() * 0.5)
+            .min(((camera.fov().to_radians() * 0.5).tan() * camera.aspect_ratio()).atan());
+        let to_center = sphere.center - camera.position();
+        assert_abs_diff_eq!(to_center.distance(), 3.0 / half_fov.sin(), epsilon = 1e-3);
+        assert_vec3_eq(to_center.normalize(), camera.forward());
+    }
+}
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use winit::{
     event::{self, ElementState, WindowEvent},
     keyboard::{KeyCode, PhysicalKey},
@@ -6,16 +6,61 @@ use winit::{
 pub mod map;
 pub use map::*;
 
+//Bindings made before any context is pushed end up in this one, it can never be popped
+pub const DEFAULT_INPUT_CONTEXT: &str = "default";
+
 struct Modifier {
     code: KeyCode,
+    context: usize,
     state: ElementState,
     value: f32,
 }
 
+/// What a key drives within one input context, see InputController::bindings
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyBinding {
+    Action { input: u32, value: f32 },
+    Axis { input: u32, value: f32 },
+}
+
+struct ActiveContext {
+    id: usize,
+    fall_through: bool,
+}
+
+//Time constants in seconds for moving towards a larger (rise) or smaller (fall) magnitude
+#[derive(Debug, Clone, Copy)]
+pub struct AxisSmoothing {
+    pub rise: f32,
+    pub fall: f32,
+}
+
+impl AxisSmoothing {
+    //Exponential approach, so the result only depends on the total time stepped and not on dt
+    pub fn step(&self, current: f32, target: f32, dt: f32) -> f32 {
+        let time_constant = if target.abs() > current.abs() {
+            self.rise
+        } else {
+            self.fall
+        };
+        if time_constant <= 0.0 {
+            return target;
+        }
+        let value = current + (target - current) * (1.0 - (-dt / time_constant).exp());
+        if (target - value).abs() < 1e-4 {
+            target
+        } else {
+            value
+        }
+    }
+}
+
 #[derive(Default)]
 struct AxisHandler {
     axis: u32,
+    raw_value: f32,
     current_value: f32,
+    smoothing: Option<AxisSmoothing>,
     modifiers: Vec<Modifier>,
     callbacks: Vec<Box<dyn FnMut(f32)>>,
 }
@@ -25,34 +70,178 @@ impl AxisHandler {
         self.callbacks.push(callback);
     }
 
-    pub fn modifier_changed(&mut self, code: KeyCode, state: ElementState) {
-        let mut new_value = 0.0;
-        for modifier in &mut self.modifiers {
-            if modifier.code == code {
+    pub fn modifier_changed(&mut self, code: KeyCode, context: usize, state: ElementState) {
+        self.update_modifiers(|modifier| {
+            if modifier.code == code && modifier.context == context {
                 modifier.state = state;
             }
+        });
+    }
+
+    //The moved modifier starts out released, the held key it came from no longer drives it
+    fn rebind_modifier(&mut self, old_code: KeyCode, new_code: KeyCode, context: usize) {
+        self.update_modifiers(|modifier| {
+            if modifier.code == old_code && modifier.context == context {
+                modifier.code = new_code;
+                modifier.state = ElementState::Released;
+            }
+        });
+    }
+
+    fn remove_modifier(&mut self, code: KeyCode, context: usize) -> Option<f32> {
+        let index = self
+            .modifiers
+            .iter()
+            .position(|m| m.code == code && m.context == context)?;
+        let removed = self.modifiers.remove(index);
+        self.update_modifiers(|_| {});
+        Some(removed.value)
+    }
+
+    //Keys held in a context that stopped receiving input would otherwise never be released
+    fn release_contexts(&mut self, is_released: impl Fn(usize) -> bool) {
+        if self
+            .modifiers
+            .iter()
+            .any(|m| m.state == ElementState::Pressed && is_released(m.context))
+        {
+            self.update_modifiers(|modifier| {
+                if is_released(modifier.context) {
+                    modifier.state = ElementState::Released;
+                }
+            });
+        }
+    }
+
+    fn update_modifiers(&mut self, mut change: impl FnMut(&mut Modifier)) {
+        let mut new_value = 0.0;
+        for modifier in &mut self.modifiers {
+            change(modifier);
             if modifier.state == ElementState::Pressed {
                 new_value += modifier.value;
             }
         }
-        self.current_value = new_value;
+        self.raw_value = new_value;
+        if self.smoothing.is_none() {
+            self.set_value(new_value);
+        }
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        if let Some(smoothing) = self.smoothing {
+            if self.current_value != self.raw_value {
+                let value = smoothing.step(self.current_value, self.raw_value, dt);
+                self.set_value(value);
+            }
+        }
+    }
+
+    fn set_value(&mut self, value: f32) {
+        self.current_value = value;
         for callback in &mut self.callbacks {
-            callback(new_value);
+            callback(value);
         }
     }
 }
 
-#[derive(Default)]
+/// Maps keys to axis and action inputs.
+/// Axis and action bindings belong to the input context that was on top of the stack when
+/// they were assigned. Key events go to the topmost context binding that key; a context
+/// pushed with fall_through lets keys it doesn't bind reach the contexts below it.
+/// Raw keycode callbacks ignore contexts.
 pub struct InputController {
-    inputmap: HashMap<KeyCode, (u32, f32)>,
-    axis_key_map: HashMap<KeyCode, u32>,
+    inputmap: HashMap<(usize, KeyCode), (u32, f32)>,
+    axis_key_map: HashMap<(usize, KeyCode), u32>,
     axis_handlers: Vec<AxisHandler>,
     action_callbacks: HashMap<u32, Vec<Box<dyn FnMut(f32)>>>,
     keypressmap_callback: HashMap<KeyCode, Vec<Box<dyn FnMut(KeyCode, event::ElementState)>>>,
+    context_names: Vec<String>,
+    context_stack: Vec<ActiveContext>,
+    held_keys: HashSet<KeyCode>,
+}
+
+impl Default for InputController {
+    fn default() -> Self {
+        Self {
+            inputmap: HashMap::new(),
+            axis_key_map: HashMap::new(),
+            axis_handlers: vec![],
+            action_callbacks: HashMap::new(),
+            keypressmap_callback: HashMap::new(),
+            context_names: vec![DEFAULT_INPUT_CONTEXT.to_owned()],
+            context_stack: vec![ActiveContext {
+                id: 0,
+                fall_through: false,
+            }],
+            held_keys: HashSet::new(),
+        }
+    }
 }
 
 impl<'a> InputController {
+    fn context_id(&mut self, name: &str) -> usize {
+        match self.context_names.iter().position(|n| n == name) {
+            Some(id) => id,
+            None => {
+                self.context_names.push(name.to_owned());
+                self.context_names.len() - 1
+            }
+        }
+    }
+
+    fn active_context(&self) -> usize {
+        self.context_stack.last().map_or(0, |context| context.id)
+    }
+
+    pub fn active_context_name(&self) -> &str {
+        &self.context_names[self.active_context()]
+    }
+
+    //Bindings assigned after this belong to the new context until it is popped
+    pub fn push_context(&mut self, name: &str, fall_through: bool) {
+        let id = self.context_id(name);
+        self.context_stack.push(ActiveContext { id, fall_through });
+        self.release_unreachable_contexts();
+    }
+
+    //The default context stays at the bottom of the stack
+    pub fn pop_context(&mut self) -> Option<String> {
+        if self.context_stack.len() <= 1 {
+            return None;
+        }
+        let popped = self.context_stack.pop()?;
+        self.release_unreachable_contexts();
+        Some(self.context_names[popped.id].clone())
+    }
+
+    //Contexts that can receive key events, from the top of the stack down
+    fn reachable_contexts(&self) -> Vec<usize> {
+        let mut reachable = vec![];
+        for context in self.context_stack.iter().rev() {
+            reachable.push(context.id);
+            if !context.fall_through {
+                break;
+            }
+        }
+        reachable
+    }
+
+    fn release_unreachable_contexts(&mut self) {
+        let reachable = self.reachable_contexts();
+        for axis_handler in &mut self.axis_handlers {
+            axis_handler.release_contexts(|context| !reachable.contains(&context));
+        }
+    }
+
+    fn dispatch_context(&self, code: &KeyCode) -> Option<usize> {
+        self.reachable_contexts().into_iter().find(|&context| {
+            self.inputmap.contains_key(&(context, *code))
+                || self.axis_key_map.contains_key(&(context, *code))
+        })
+    }
+
     pub fn assign_axis_input(&mut self, key_event: KeyCode, input: u32, value: f32) {
+        let context = self.active_context();
         let axis_handler: &mut AxisHandler = {
             let mut axis_handler = None;
             for handler in &mut self.axis_handlers {
@@ -63,9 +252,7 @@ impl<'a> InputController {
             if axis_handler.is_none() {
                 self.axis_handlers.push(AxisHandler {
                     axis: input,
-                    current_value: 0.0,
-                    modifiers: vec![],
-                    callbacks: vec![],
+                    ..Default::default()
                 });
                 axis_handler = self.axis_handlers.last_mut();
             }
@@ -74,11 +261,12 @@ impl<'a> InputController {
 
         let modifier = Modifier {
             code: key_event,
+            context,
             state: ElementState::Released,
             value: value,
         };
         axis_handler.modifiers.push(modifier);
-        self.axis_key_map.insert(key_event, input);
+        self.axis_key_map.insert((context, key_event), input);
     }
 
     pub fn assign_axis_callback<T>(&mut self, input: T, callback: Box<dyn FnMut(f32)>)
@@ -100,12 +288,58 @@ impl<'a> InputController {
         }
     }
 
+    //Smooths the axis value over time instead of jumping straight to the pressed value,
+    //the smoothed value is what gets passed to the axis callbacks
+    pub fn assign_axis_smoothing<T>(&mut self, input: T, rise: f32, fall: f32)
+    where
+        T: Into<u32>,
+    {
+        let input = input.into();
+        if let Some(handler) = self.axis_handlers.iter_mut().find(|h| h.axis == input) {
+            handler.smoothing = Some(AxisSmoothing { rise, fall });
+        } else {
+            println!("Tried to setup axis smoothing for non-bound input!");
+        }
+    }
+
+    //Smoothed value of the axis
+    pub fn axis_value<T>(&self, input: T) -> f32
+    where
+        T: Into<u32>,
+    {
+        let input = input.into();
+        self.axis_handlers
+            .iter()
+            .find(|h| h.axis == input)
+            .map_or(0.0, |h| h.current_value)
+    }
+
+    //Value of the axis as given by the currently pressed keys
+    pub fn axis_raw_value<T>(&self, input: T) -> f32
+    where
+        T: Into<u32>,
+    {
+        let input = input.into();
+        self.axis_handlers
+            .iter()
+            .find(|h| h.axis == input)
+            .map_or(0.0, |h| h.raw_value)
+    }
+
+    //Steps smoothed axes, should be called once per frame
+    pub fn update(&mut self, dt: f32) {
+        for axis_handler in &mut self.axis_handlers {
+            axis_handler.update(dt);
+        }
+    }
+
     pub fn assign_action_input(&mut self, key: KeyCode, input: u32, value: f32) {
-        self.inputmap.insert(key, (input, value));
+        let context = self.active_context();
+        self.inputmap.insert((context, key), (input, value));
     }
 
-    fn handle_input(&mut self, code: &KeyCode, state: ElementState) {
-        if let Some((key, value)) = self.inputmap.get(code) {
+    fn handle_input(&mut self, code: &KeyCode, context: usize, state: ElementState) {
+        if let Some((key, value)) = self.inputmap.get(&(context, *code)) {
             if state == ElementState::Pressed {
                 if let Some(callbacks) = self.action_callbacks.get_mut(key) {
                     for callback in callbacks {
@@ -121,11 +355,11 @@ impl<'a> InputController {
             }
         }
     }
-    fn handle_axis(&mut self, code: &KeyCode, state: ElementState) {
-        if let Some(key) = self.axis_key_map.get(&code) {
+    fn handle_axis(&mut self, code: &KeyCode, context: usize, state: ElementState) {
+        if let Some(key) = self.axis_key_map.get(&(context, *code)) {
             for axis_handler in &mut self.axis_handlers {
                 if axis_handler.axis == *key {
-                    axis_handler.modifier_changed(*code, state);
+                    axis_handler.modifier_changed(*code, context, state);
                 }
             }
         }
@@ -139,19 +373,99 @@ impl<'a> InputController {
                 is_synthetic: _,
             } => {
                 if let PhysicalKey::Code(code) = event.physical_key {
-                    self.handle_input(&code, event.state);
-                    self.handle_axis(&code, event.state);
-                    if let Some(callbacks) = self.keypressmap_callback.get_mut(&code) {
-                        for callback in callbacks {
-                            callback(code, event.state);
-                        }
-                    }
+                    self.handle_key(code, event.state);
                 }
             }
             _ => {}
         }
     }
 
+    fn handle_key(&mut self, code: KeyCode, state: ElementState) {
+        match state {
+            ElementState::Pressed => self.held_keys.insert(code),
+            ElementState::Released => self.held_keys.remove(&code),
+        };
+        if let Some(context) = self.dispatch_context(&code) {
+            self.handle_input(&code, context, state);
+            self.handle_axis(&code, context, state);
+        }
+        if let Some(callbacks) = self.keypressmap_callback.get_mut(&code) {
+            for callback in callbacks {
+                callback(code, state);
+            }
+        }
+    }
+
+    //Bindings of the key in the active context, a key can drive both an action and an axis
+    pub fn bindings(&self, key: KeyCode) -> Vec<KeyBinding> {
+        let context = self.active_context();
+        let mut bindings = vec![];
+        if let Some(&(input, value)) = self.inputmap.get(&(context, key)) {
+            bindings.push(KeyBinding::Action { input, value });
+        }
+        if let Some(&input) = self.axis_key_map.get(&(context, key)) {
+            let value = self
+                .axis_handlers
+                .iter()
+                .find(|h| h.axis == input)
+                .and_then(|h| {
+                    h.modifiers
+                        .iter()
+                        .find(|m| m.code == key && m.context == context)
+                })
+                .map_or(0.0, |m| m.value);
+            bindings.push(KeyBinding::Axis { input, value });
+        }
+        bindings
+    }
+
+    //Moves everything bound to old_key in the active context over to new_key. Whatever
+    //new_key was bound to is unbound and returned, so a UI can ask where it should go.
+    //Actions and axes driven by a held key are released
+    pub fn rebind(&mut self, old_key: KeyCode, new_key: KeyCode) -> Vec<KeyBinding> {
+        if old_key == new_key {
+            return vec![];
+        }
+        let context = self.active_context();
+        if self.bindings(old_key).is_empty() {
+            println!("Tried to rebind a key without bindings: {:?}", old_key);
+            return vec![];
+        }
+
+        let displaced = self.bindings(new_key);
+        if let Some((input, _)) = self.inputmap.remove(&(context, new_key)) {
+            self.release_action(input, new_key);
+        }
+        if let Some(input) = self.axis_key_map.remove(&(context, new_key)) {
+            for axis_handler in self.axis_handlers.iter_mut().filter(|h| h.axis == input) {
+                axis_handler.remove_modifier(new_key, context);
+            }
+        }
+
+        if let Some(binding) = self.inputmap.remove(&(context, old_key)) {
+            self.release_action(binding.0, old_key);
+            self.inputmap.insert((context, new_key), binding);
+        }
+        if let Some(input) = self.axis_key_map.remove(&(context, old_key)) {
+            for axis_handler in self.axis_handlers.iter_mut().filter(|h| h.axis == input) {
+                axis_handler.rebind_modifier(old_key, new_key, context);
+            }
+            self.axis_key_map.insert((context, new_key), input);
+        }
+        displaced
+    }
+
+    fn release_action(&mut self, input: u32, key: KeyCode) {
+        if !self.held_keys.contains(&key) {
+            return;
+        }
+        if let Some(callbacks) = self.action_callbacks.get_mut(&input) {
+            for callback in callbacks {
+                callback(0.0)
+            }
+        }
+    }
+
     pub fn bind_input_callback(&mut self, input_key: u32, callback: Box<dyn FnMut(f32)>) {
         self.action_callbacks
             .entry(input_key)
@@ -170,3 +484,180 @@ impl<'a> InputController {
             .push(callback);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+    use std::{cell::RefCell, rc::Rc};
+
+    const JUMP: u32 = 10;
+    const MOVE: u32 = 11;
+
+    //Records every value passed to the action callbacks of input
+    fn record_action(controller: &mut InputController, input: u32) -> Rc<RefCell<Vec<f32>>> {
+        let values = Rc::new(RefCell::new(vec![]));
+        let recorded = values.clone();
+        controller.bind_input_callback(
+            input,
+            Box::new(move |value| recorded.borrow_mut().push(value)),
+        );
+        values
+    }
+
+    const SMOOTHING: AxisSmoothing = AxisSmoothing {
+        rise: 0.1,
+        fall: 0.05,
+    };
+
+    #[test]
+    fn smoothing_holds_still_without_time() {
+        assert_eq!(SMOOTHING.step(0.25, 1.0, 0.0), 0.25);
+    }
+
+    #[test]
+    fn smoothing_reaches_the_target_after_a_long_step() {
+        assert_eq!(SMOOTHING.step(0.0, 1.0, 1000.0), 1.0);
+        assert_eq!(SMOOTHING.step(1.0, 0.0, f32::MAX), 0.0);
+    }
+
+    #[test]
+    fn smoothing_does_not_depend_on_the_step_size() {
+        let once = SMOOTHING.step(0.0, 1.0, 0.1);
+        let mut split = 0.0;
+        for _ in 0..10 {
+            split = SMOOTHING.step(split, 1.0, 0.01);
+        }
+        assert_abs_diff_eq!(once, split, epsilon = 1e-5);
+        assert!(once > 0.0 && once < 1.0);
+    }
+
+    #[test]
+    fn smoothing_converges_without_overshooting() {
+        let mut value = 0.0;
+        for _ in 0..200 {
+            let next = SMOOTHING.step(value, -1.0, 1.0 / 60.0);
+            assert!(next <= value && next >= -1.0);
+            value = next;
+        }
+        assert_eq!(value, -1.0);
+    }
+
+    #[test]
+    fn zero_time_constant_snaps_to_the_target() {
+        let snap = AxisSmoothing {
+            rise: 0.0,
+            fall: 0.0,
+        };
+        assert_eq!(snap.step(0.0, 1.0, 0.0), 1.0);
+    }
+
+    #[test]
+    fn rebind_reports_the_displaced_bindings() {
+        let mut controller = InputController::default();
+        controller.assign_action_input(KeyCode::Space, JUMP, 1.0);
+        controller.assign_axis_input(KeyCode::KeyW, MOVE, 1.0);
+
+        let displaced = controller.rebind(KeyCode::Space, KeyCode::KeyW);
+        assert_eq!(
+            displaced,
+            vec![KeyBinding::Axis {
+                input: MOVE,
+                value: 1.0
+            }]
+        );
+        assert_eq!(
+            controller.bindings(KeyCode::KeyW),
+            vec![KeyBinding::Action {
+                input: JUMP,
+                value: 1.0
+            }]
+        );
+        assert!(controller.bindings(KeyCode::Space).is_empty());
+    }
+
+    #[test]
+    fn rebind_to_a_free_key_displaces_nothing() {
+        let mut controller = InputController::default();
+        controller.assign_axis_input(KeyCode::KeyW, MOVE, -1.0);
+        assert!(controller
+            .rebind(KeyCode::KeyW, KeyCode::ArrowUp)
+            .is_empty());
+        assert!(controller.rebind(KeyCode::KeyQ, KeyCode::KeyE).is_empty());
+        assert_eq!(
+            controller.bindings(KeyCode::ArrowUp),
+            vec![KeyBinding::Axis {
+                input: MOVE,
+                value: -1.0
+            }]
+        );
+    }
+
+    #[test]
+    fn rebind_releases_held_keys() {
+        let mut controller = InputController::default();
+        controller.assign_action_input(KeyCode::Space, JUMP, 2.0);
+        controller.assign_axis_input(KeyCode::KeyW, MOVE, 1.0);
+        let jumps = record_action(&mut controller, JUMP);
+
+        controller.handle_key(KeyCode::Space, ElementState::Pressed);
+        controller.handle_key(KeyCode::KeyW, ElementState::Pressed);
+        assert_eq!(controller.axis_raw_value(MOVE), 1.0);
+
+        controller.rebind(KeyCode::Space, KeyCode::Enter);
+        controller.rebind(KeyCode::KeyW, KeyCode::ArrowUp);
+        assert_eq!(*jumps.borrow(), vec![2.0, 0.0]);
+        assert_eq!(controller.axis_raw_value(MOVE), 0.0);
+    }
+
+    #[test]
+    fn the_default_context_is_never_popped() {
+        let mut controller = InputController::default();
+        assert_eq!(controller.pop_context(), None);
+        controller.push_context("menu", false);
+        assert_eq!(controller.active_context_name(), "menu");
+        assert_eq!(controller.pop_context(), Some("menu".to_owned()));
+        assert_eq!(controller.active_context_name(), DEFAULT_INPUT_CONTEXT);
+        assert_eq!(controller.pop_context(), None);
+    }
+
+    #[test]
+    fn the_top_context_shadows_the_keys_it_binds() {
+        let mut controller = InputController::default();
+        controller.assign_action_input(KeyCode::Space, JUMP, 1.0);
+        controller.push_context("menu", true);
+        controller.assign_action_input(KeyCode::Space, JUMP, 2.0);
+        let jumps = record_action(&mut controller, JUMP);
+
+        controller.handle_key(KeyCode::Space, ElementState::Pressed);
+        controller.pop_context();
+        controller.handle_key(KeyCode::Space, ElementState::Pressed);
+        assert_eq!(*jumps.borrow(), vec![2.0, 1.0]);
+    }
+
+    #[test]
+    fn fall_through_decides_if_lower_contexts_get_keys() {
+        let mut controller = InputController::default();
+        controller.assign_action_input(KeyCode::Space, JUMP, 1.0);
+        let jumps = record_action(&mut controller, JUMP);
+
+        controller.push_context("overlay", true);
+        controller.handle_key(KeyCode::Space, ElementState::Pressed);
+        controller.push_context("menu", false);
+        controller.handle_key(KeyCode::Space, ElementState::Pressed);
+        assert_eq!(*jumps.borrow(), vec![1.0]);
+    }
+
+    #[test]
+    fn pushing_a_blocking_context_releases_held_axes() {
+        let mut controller = InputController::default();
+        controller.assign_axis_input(KeyCode::KeyW, MOVE, 1.0);
+        controller.handle_key(KeyCode::KeyW, ElementState::Pressed);
+        assert_eq!(controller.axis_value(MOVE), 1.0);
+
+        controller.push_context("menu", false);
+        assert_eq!(controller.axis_value(MOVE), 0.0);
+        controller.pop_context();
+        assert_eq!(controller.axis_value(MOVE), 0.0);
+    }
+}
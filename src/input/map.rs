@@ -2,6 +2,7 @@ pub enum InputMapping {
     MoveForward = 0,
     MoveVertical,
     MoveHorizontal,
+    Sprint,
 }
 
 impl From<InputMapping> for u32 {
@@ -3,3 +3,5 @@ pub mod cameracontroller;
 pub mod input;
 mod rendering;
 mod util;
+
+pub use util::{TaskHandle, TaskPool, TaskScope};
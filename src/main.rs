@@ -6,7 +6,7 @@ use winit::keyboard::KeyCode;
 fn main() {
     let (mut application, event_loop) = ApplicationBuilder::new()
         .with_name("Katla")
-        .validation_layer(true)
+        .validation_layer_auto()
         //TODO: This seems to be typical to reside in configuration files:
         .with_axis_input(KeyCode::KeyA, InputMapping::MoveHorizontal, -1.0)
         .with_axis_input(KeyCode::KeyD, InputMapping::MoveHorizontal, 1.0)
@@ -14,9 +14,9 @@ fn main() {
         .with_axis_input(KeyCode::KeyW, InputMapping::MoveForward, 1.0)
         .with_axis_input(KeyCode::KeyQ, InputMapping::MoveVertical, -1.0)
         .with_axis_input(KeyCode::KeyE, InputMapping::MoveVertical, 1.0)
+        .with_action_input(KeyCode::ShiftLeft, InputMapping::Sprint, 4.0)
         .build();
 
-    //TODO: add some sort of system, so that we can run stuff in-loop?
     application.init();
     event_loop.run_app(&mut application).unwrap();
 }
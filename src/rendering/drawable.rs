@@ -1,7 +1,13 @@
-use katla_math::Mat4;
-use katla_vulkan::CommandBuffer;
+use katla_math::{Sphere, Transform};
+use katla_vulkan::{CommandBuffer, FrameContext};
 
+//Anything that can be rendered through the scene, meshes as well as procedural geometry
 pub trait Drawable {
-    fn update(&mut self, view: &Mat4, proj: &Mat4, dt: f32);
-    fn draw(&self, command_buffer: &CommandBuffer);
+    //Per-frame resources have to be picked with the frame-in-flight index, the same
+    //FrameContext is passed to draw. The camera is in the renderer's global uniform
+    fn update(&mut self, frame: &FrameContext, dt: f32);
+    fn draw(&self, command_buffer: &CommandBuffer, frame: &FrameContext);
+    fn bounds(&self) -> Sphere;
+    //Used for the model matrix from the next update on
+    fn set_transform(&mut self, transform: Transform);
 }
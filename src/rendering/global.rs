@@ -0,0 +1,51 @@
+use katla_math::Mat4;
+
+//Laid out like the Global uniform block of model_pbr.vert, see katla_vulkan::GLOBAL_UNIFORM_SIZE
+#[repr(C)]
+pub struct GlobalData {
+    pub view: Mat4,
+    pub proj: Mat4,
+    //0.0 keeps the regular depth
+    pub log_depth_coefficient: f32,
+    pub time: f32,
+    _padding: [f32; 2],
+}
+
+impl GlobalData {
+    pub fn new(view: Mat4, proj: Mat4, log_depth_coefficient: f32, time: f32) -> Self {
+        Self {
+            view,
+            proj,
+            log_depth_coefficient,
+            time,
+            _padding: [0.0; 2],
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe {
+            std::slice::from_raw_parts(
+                self as *const GlobalData as *const u8,
+                std::mem::size_of::<GlobalData>(),
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use katla_vulkan::GLOBAL_UNIFORM_SIZE;
+
+    #[test]
+    fn matches_the_shader_block_size() {
+        let data = GlobalData::new(Mat4::identity(), Mat4::identity(), 0.5, 2.0);
+        assert_eq!(data.as_bytes().len(), GLOBAL_UNIFORM_SIZE);
+        //log_depth_coefficient and time follow the two matrices
+        let floats: Vec<f32> = data.as_bytes()[128..136]
+            .chunks(4)
+            .map(|bytes| f32::from_ne_bytes(bytes.try_into().unwrap()))
+            .collect();
+        assert_eq!(floats, vec![0.5, 2.0]);
+    }
+}
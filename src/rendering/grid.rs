@@ -0,0 +1,109 @@
+use std::sync::Arc;
+
+use katla_math::{Mat4, Sphere, Transform, Vec3};
+use katla_vulkan::{AllocationError, CommandBuffer, FrameContext, RenderPass, VulkanContext};
+
+use crate::rendering::{Drawable, Material, Mesh, VertexPBR};
+
+const GRID_COLOR: [u8; 4] = [200, 200, 200, 255];
+
+//A flat grid on the y=0 plane, built from thin quads since the pipeline only draws triangles
+pub struct GridDrawable {
+    pub mesh: Mesh,
+    pub material: Material,
+    pub transform: Transform,
+    pub bounds: Sphere,
+    //Bounding radius at scale 1, set_transform scales it
+    radius: f32,
+}
+
+impl GridDrawable {
+    pub fn new(
+        context: Arc<VulkanContext>,
+        render_pass: &RenderPass,
+        frames_in_flight: usize,
+        half_extent: f32,
+        spacing: f32,
+    ) -> Result<Self, AllocationError> {
+        let (vertices, indices) = Self::create_geometry(half_extent, spacing);
+        let mesh = Mesh::new_from_data(context.clone(), vertices, indices)?;
+        let material =
+            Material::new_solid_color(context, render_pass, frames_in_flight, GRID_COLOR)?;
+        let bounds = Sphere::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(half_extent, 0.0, half_extent).distance(),
+        );
+
+        Ok(Self {
+            mesh,
+            material,
+            transform: Transform::new(),
+            radius: bounds.radius,
+            bounds,
+        })
+    }
+
+    pub fn create_geometry(half_extent: f32, spacing: f32) -> (Vec<VertexPBR>, Vec<u32>) {
+        let mut vertices = vec![];
+        let mut indices = vec![];
+        let half_width = spacing * 0.01;
+        let num_lines = (half_extent / spacing) as i32;
+
+        for line in -num_lines..=num_lines {
+            let offset = line as f32 * spacing;
+            //One line along x and one along z for every step
+            let quads = [
+                [
+                    [-half_extent, 0.0, offset - half_width],
+                    [half_extent, 0.0, offset - half_width],
+                    [half_extent, 0.0, offset + half_width],
+                    [-half_extent, 0.0, offset + half_width],
+                ],
+                [
+                    [offset - half_width, 0.0, -half_extent],
+                    [offset + half_width, 0.0, -half_extent],
+                    [offset + half_width, 0.0, half_extent],
+                    [offset - half_width, 0.0, half_extent],
+                ],
+            ];
+            for quad in quads {
+                let first = vertices.len() as u32;
+                for position in quad {
+                    vertices.push(VertexPBR {
+                        position,
+                        normal: [0.0, -1.0, 0.0],
+                        tangent: [0.0, 0.0, 0.0, 0.0],
+                        tex_coord0: [0.0, 0.0],
+                    });
+                }
+                //Both windings, so the grid is visible from above and below
+                indices.extend([first, first + 1, first + 2, first, first + 2, first + 3]);
+                indices.extend([first, first + 2, first + 1, first, first + 3, first + 2]);
+            }
+        }
+
+        (vertices, indices)
+    }
+}
+
+impl Drawable for GridDrawable {
+    fn update(&mut self, frame: &FrameContext, _dt: f32) {
+        let model = Mat4::from(&self.transform);
+        self.material.upload_pipeline_data(frame, model);
+    }
+
+    fn draw(&self, command_buffer: &CommandBuffer, frame: &FrameContext) {
+        self.material.bind(command_buffer, frame);
+        self.mesh.draw(command_buffer);
+    }
+
+    fn bounds(&self) -> Sphere {
+        self.bounds.clone()
+    }
+
+    fn set_transform(&mut self, transform: Transform) {
+        self.bounds.center = transform.position;
+        self.bounds.radius = self.radius * transform.max_scale();
+        self.transform = transform;
+    }
+}
@@ -0,0 +1,156 @@
+use katla_math::Vec3;
+use katla_vulkan::MAX_LIGHTS;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LightKind {
+    Point,
+    Directional,
+}
+
+/// A light shading every material. Point lights fall off with the squared distance from
+/// their position, for directional lights the position is the direction the light comes
+/// from and must not be zero.
+#[derive(Debug, Clone, Copy)]
+pub struct Light {
+    pub position: Vec3,
+    pub color: Vec3,
+    pub intensity: f32,
+    pub kind: LightKind,
+}
+
+impl Light {
+    pub fn point(position: Vec3, color: Vec3, intensity: f32) -> Self {
+        Self {
+            position,
+            color,
+            intensity,
+            kind: LightKind::Point,
+        }
+    }
+
+    pub fn directional(direction: Vec3, color: Vec3, intensity: f32) -> Self {
+        Self {
+            position: direction,
+            color,
+            intensity,
+            kind: LightKind::Directional,
+        }
+    }
+
+    //Directional lights reach everything, so they sort before every point light
+    fn distance_squared(&self, viewer: Vec3) -> f32 {
+        match self.kind {
+            LightKind::Point => (self.position - viewer).distance_squared(),
+            LightKind::Directional => 0.0,
+        }
+    }
+}
+
+//Laid out like the Light struct of model.frag
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct GpuLight {
+    //w is 0.0 for directional lights
+    position: [f32; 4],
+    //rgb color, intensity in a
+    color: [f32; 4],
+}
+
+//Laid out like the Lights uniform block of model.frag, see katla_vulkan::LIGHTS_UNIFORM_SIZE
+#[repr(C)]
+pub struct LightData {
+    count: u32,
+    _padding: [u32; 3],
+    lights: [GpuLight; MAX_LIGHTS],
+}
+
+impl LightData {
+    //Keeps at most MAX_LIGHTS lights: directional ones first, then the point lights nearest
+    //to the viewer. The rest is dropped for this frame
+    pub fn gather(lights: &[Light], viewer: Vec3) -> Self {
+        let mut nearest: Vec<&Light> = lights.iter().collect();
+        nearest.sort_by(|a, b| {
+            a.distance_squared(viewer)
+                .total_cmp(&b.distance_squared(viewer))
+        });
+        let mut data = Self {
+            count: 0,
+            _padding: [0; 3],
+            lights: [GpuLight::default(); MAX_LIGHTS],
+        };
+        for (slot, light) in data.lights.iter_mut().zip(nearest) {
+            let w = match light.kind {
+                LightKind::Point => 1.0,
+                LightKind::Directional => 0.0,
+            };
+            slot.position = [light.position[0], light.position[1], light.position[2], w];
+            slot.color = [
+                light.color[0],
+                light.color[1],
+                light.color[2],
+                light.intensity,
+            ];
+            data.count += 1;
+        }
+        data
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe {
+            std::slice::from_raw_parts(
+                self as *const LightData as *const u8,
+                std::mem::size_of::<LightData>(),
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use katla_vulkan::LIGHTS_UNIFORM_SIZE;
+
+    fn white_point(x: f32) -> Light {
+        Light::point(Vec3::new(x, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0), 1.0)
+    }
+
+    #[test]
+    fn matches_the_shader_block_size() {
+        let data = LightData::gather(&[], Vec3::new(0.0, 0.0, 0.0));
+        assert_eq!(data.as_bytes().len(), LIGHTS_UNIFORM_SIZE);
+        assert_eq!(data.count, 0);
+    }
+
+    #[test]
+    fn packs_every_light_below_the_cap() {
+        let lights = [
+            white_point(3.0),
+            Light::directional(Vec3::new(0.0, 1.0, 0.0), Vec3::new(1.0, 0.5, 0.0), 2.0),
+        ];
+        let data = LightData::gather(&lights, Vec3::new(0.0, 0.0, 0.0));
+        assert_eq!(data.count, 2);
+        assert_eq!(data.lights[0].position, [0.0, 1.0, 0.0, 0.0]);
+        assert_eq!(data.lights[0].color, [1.0, 0.5, 0.0, 2.0]);
+        assert_eq!(data.lights[1].position, [3.0, 0.0, 0.0, 1.0]);
+        assert_eq!(data.lights[2], GpuLight::default());
+    }
+
+    #[test]
+    fn keeps_the_nearest_lights_beyond_the_cap() {
+        //Farthest first, so the order of the input doesn't decide what is kept
+        let lights: Vec<_> = (0..MAX_LIGHTS + 3)
+            .rev()
+            .map(|distance| white_point(distance as f32 * 10.0))
+            .collect();
+        let viewer = Vec3::new(5.0, 0.0, 0.0);
+        let data = LightData::gather(&lights, viewer);
+
+        assert_eq!(data.count as usize, MAX_LIGHTS);
+        let mut kept: Vec<f32> = data.lights.iter().map(|light| light.position[0]).collect();
+        kept.sort_by(f32::total_cmp);
+        let expected: Vec<f32> = (0..MAX_LIGHTS)
+            .map(|distance| distance as f32 * 10.0)
+            .collect();
+        assert_eq!(kept, expected);
+    }
+}
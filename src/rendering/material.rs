@@ -3,14 +3,23 @@ use crate::{rendering::vertextypes::*, util::GLTFModel};
 use katla_math::Mat4;
 
 use katla_vulkan::{
-    context::VulkanContext, CommandBuffer, Format, ImageInfo, PipelineBindPoint, RenderPass,
-    RenderPipeline, Texture,
+    context::VulkanContext, AllocationError, CommandBuffer, Filter, Format, FrameContext,
+    ImageInfo, PipelineBindPoint, RenderPass, RenderPipeline, SamplerAddressMode, SamplerConfig,
+    SamplerMipmapMode, Shading, Texture, UnlitPipeline, MATERIAL_SET,
 };
 
 use std::{rc::Rc, sync::Arc};
 
+//Laid out like the Data uniform block of model_pbr.vert, the camera is in GlobalData
+#[repr(C)]
+struct PipelineData {
+    world: Mat4,
+}
+
 pub struct Material {
     pub renderpipeline: RenderPipeline,
+    //Bound instead of renderpipeline for Shading::Unlit frames
+    pub unlit_pipeline: UnlitPipeline,
     pub texture: Option<Texture>,
     context: Arc<VulkanContext>,
 }
@@ -20,18 +29,41 @@ impl Material {
         model: Rc<GLTFModel>,
         context: Arc<VulkanContext>,
         render_pass: &RenderPass,
-        num_images: usize,
-    ) -> Self {
-        let vertex_binding = VertexPBR::get_vertex_binding();
-        let mut renderpipeline = RenderPipeline::new(
+        frames_in_flight: usize,
+    ) -> Result<Self, AllocationError> {
+        //Must match the vertex layout Mesh::new_from_model uploads
+        let vertex_binding = if model.has_vertex_colors() {
+            VertexFull::get_vertex_binding()
+        } else {
+            VertexPBR::get_vertex_binding()
+        };
+        let renderpipeline = RenderPipeline::new(
+            context.clone(),
+            render_pass,
+            frames_in_flight,
+            vertex_binding.clone(),
+        )?;
+        let unlit_pipeline = UnlitPipeline::new(
             context.clone(),
-            render_pass.get_vk_renderpass(),
-            num_images,
+            render_pass,
+            &renderpipeline,
             vertex_binding,
         );
-        let mut texture = None;
+        //Dropping the material releases the pipelines if the texture upload fails
+        let mut material = Self {
+            renderpipeline,
+            unlit_pipeline,
+            context: context.clone(),
+            texture: None,
+        };
         if !model.images.is_empty() {
             let image = &model.images[0];
+            let sampler_config = model
+                .document
+                .textures()
+                .find(|texture| texture.source().index() == 0)
+                .map(|texture| sampler_config_from_gltf(&texture.sampler()))
+                .unwrap_or_default();
             //TODO: Support more image formats:
             match image.format {
                 gltf::image::Format::R8G8B8 => {
@@ -54,11 +86,13 @@ impl Material {
                         image.height,
                         Format::R8G8B8A8_SRGB,
                         new_pixels.as_slice(),
-                    );
-                    renderpipeline
+                        &sampler_config,
+                    )?;
+                    material
+                        .renderpipeline
                         .uniform
                         .add_image_info(ImageInfo::new(tex.image_view, tex.image_sampler));
-                    texture = Some(tex);
+                    material.texture = Some(tex);
                 }
                 gltf::image::Format::R8G8B8A8 => {
                     let pixels = &image.pixels;
@@ -69,44 +103,99 @@ impl Material {
                         image.height,
                         Format::R8G8B8A8_SRGB,
                         pixels.as_slice(),
-                    );
-                    renderpipeline
+                        &sampler_config,
+                    )?;
+                    material
+                        .renderpipeline
                         .uniform
                         .add_image_info(ImageInfo::new(tex.image_view, tex.image_sampler));
 
-                    texture = Some(tex);
+                    material.texture = Some(tex);
                 }
                 _ => {
                     println!("Unsupported texture format: {:?}", image.format);
                 }
             }
         }
-        Self {
+        Ok(material)
+    }
+
+    //A material sampling a single-pixel texture, for geometry without any image data
+    pub fn new_solid_color(
+        context: Arc<VulkanContext>,
+        render_pass: &RenderPass,
+        frames_in_flight: usize,
+        color: [u8; 4],
+    ) -> Result<Self, AllocationError> {
+        let vertex_binding = VertexPBR::get_vertex_binding();
+        let renderpipeline = RenderPipeline::new(
+            context.clone(),
+            render_pass,
+            frames_in_flight,
+            vertex_binding.clone(),
+        )?;
+        let unlit_pipeline = UnlitPipeline::new(
+            context.clone(),
+            render_pass,
+            &renderpipeline,
+            vertex_binding,
+        );
+        let mut material = Self {
             renderpipeline,
-            context,
-            texture,
-        }
+            unlit_pipeline,
+            context: context.clone(),
+            texture: None,
+        };
+        let tex = Texture::create_image(
+            &context,
+            1,
+            1,
+            Format::R8G8B8A8_SRGB,
+            &color,
+            &SamplerConfig::default(),
+        )?;
+        material
+            .renderpipeline
+            .uniform
+            .add_image_info(ImageInfo::new(tex.image_view, tex.image_sampler));
+        material.texture = Some(tex);
+
+        Ok(material)
     }
 
     //TODO: Can we in any way fix so that these bindings happen in a better way?
     //Maybe decouple the actual data of the uniform to the drawcall-creation and
-    //let the material stop caring about the image_index
-    pub fn bind(&self, command_buffer: &CommandBuffer) {
-        command_buffer.bind_pipeline(self.renderpipeline.pipeline, PipelineBindPoint::GRAPHICS);
+    //let the material stop caring about the frame_index
+    pub fn bind(&self, command_buffer: &CommandBuffer, frame: &FrameContext) {
+        //Both pipelines share the layout, so the descriptor sets are the same
+        let pipeline = match frame.shading {
+            Shading::Lit => self.renderpipeline.pipeline,
+            Shading::Unlit => self.unlit_pipeline.pipeline,
+        };
+        command_buffer.bind_pipeline(pipeline, PipelineBindPoint::GRAPHICS);
 
         command_buffer.bind_descriptor_sets(
             PipelineBindPoint::GRAPHICS,
             self.renderpipeline.pipeline_layout,
-            &[self.renderpipeline.uniform.next_descriptor().desc_set],
+            MATERIAL_SET,
+            &[self
+                .renderpipeline
+                .uniform
+                .descriptor(frame.frame_index)
+                .desc_set],
         );
     }
 
-    pub fn upload_pipeline_data(&mut self, view: Mat4, proj: Mat4, model: Mat4) {
-        let mat = [model, view, proj];
+    pub fn upload_pipeline_data(&mut self, frame: &FrameContext, model: Mat4) {
+        let data = PipelineData { world: model };
         let data_slice = unsafe {
-            std::slice::from_raw_parts(mat.as_ptr() as *const u8, std::mem::size_of_val(&mat))
+            std::slice::from_raw_parts(
+                &data as *const PipelineData as *const u8,
+                std::mem::size_of_val(&data),
+            )
         };
-        self.renderpipeline.update_buffer(data_slice);
+        self.renderpipeline
+            .update_buffer(frame.frame_index, data_slice);
     }
 }
 
@@ -116,5 +205,131 @@ impl Drop for Material {
             texture.destroy(&self.context);
         }
         self.renderpipeline.destroy();
+        self.unlit_pipeline.destroy();
+    }
+}
+
+//Maps the glTF sampler description onto the Vulkan sampler fields,
+//unspecified filters fall back to linear filtering
+pub fn sampler_config_from_gltf(sampler: &gltf::texture::Sampler) -> SamplerConfig {
+    use gltf::texture::{MagFilter, MinFilter, WrappingMode};
+
+    let mag_filter = match sampler.mag_filter() {
+        Some(MagFilter::Nearest) => Filter::NEAREST,
+        Some(MagFilter::Linear) | None => Filter::LINEAR,
+    };
+    let (min_filter, mipmap_mode) = match sampler.min_filter() {
+        Some(MinFilter::Nearest) | Some(MinFilter::NearestMipmapNearest) => {
+            (Filter::NEAREST, SamplerMipmapMode::NEAREST)
+        }
+        Some(MinFilter::NearestMipmapLinear) => (Filter::NEAREST, SamplerMipmapMode::LINEAR),
+        Some(MinFilter::LinearMipmapNearest) => (Filter::LINEAR, SamplerMipmapMode::NEAREST),
+        Some(MinFilter::Linear) | Some(MinFilter::LinearMipmapLinear) | None => {
+            (Filter::LINEAR, SamplerMipmapMode::LINEAR)
+        }
+    };
+    let address_mode = |mode: WrappingMode| match mode {
+        WrappingMode::ClampToEdge => SamplerAddressMode::CLAMP_TO_EDGE,
+        WrappingMode::MirroredRepeat => SamplerAddressMode::MIRRORED_REPEAT,
+        WrappingMode::Repeat => SamplerAddressMode::REPEAT,
+    };
+
+    SamplerConfig {
+        mag_filter,
+        min_filter,
+        mipmap_mode,
+        address_mode_u: address_mode(sampler.wrap_s()),
+        address_mode_v: address_mode(sampler.wrap_t()),
+        //Point sampled textures should stay crisp
+        anisotropy_enable: min_filter == Filter::LINEAR,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn configs_for(samplers: &str) -> Vec<SamplerConfig> {
+        let json = format!(
+            r#"{{"asset": {{"version": "2.0"}}, "samplers": [{}]}}"#,
+            samplers
+        );
+        let gltf = gltf::Gltf::from_slice(json.as_bytes()).unwrap();
+        gltf.samplers()
+            .map(|sampler| sampler_config_from_gltf(&sampler))
+            .collect()
+    }
+
+    #[test]
+    fn mag_filters_map_to_vulkan_filters() {
+        let table = [
+            (r#"{"magFilter": 9728}"#, Filter::NEAREST),
+            (r#"{"magFilter": 9729}"#, Filter::LINEAR),
+            ("{}", Filter::LINEAR),
+        ];
+        let samplers: Vec<_> = table.iter().map(|(sampler, _)| *sampler).collect();
+        let configs = configs_for(&samplers.join(","));
+        for ((sampler, expected), config) in table.iter().zip(configs) {
+            assert_eq!(config.mag_filter, *expected, "{}", sampler);
+        }
+    }
+
+    #[test]
+    fn min_filters_map_to_filter_and_mipmap_mode() {
+        let table = [
+            (9728, Filter::NEAREST, SamplerMipmapMode::NEAREST),
+            (9729, Filter::LINEAR, SamplerMipmapMode::LINEAR),
+            (9984, Filter::NEAREST, SamplerMipmapMode::NEAREST),
+            (9985, Filter::LINEAR, SamplerMipmapMode::NEAREST),
+            (9986, Filter::NEAREST, SamplerMipmapMode::LINEAR),
+            (9987, Filter::LINEAR, SamplerMipmapMode::LINEAR),
+        ];
+        let samplers: Vec<_> = table
+            .iter()
+            .map(|(min_filter, _, _)| format!(r#"{{"minFilter": {}}}"#, min_filter))
+            .collect();
+        let configs = configs_for(&samplers.join(","));
+        for ((min_filter, filter, mipmap_mode), config) in table.iter().zip(configs) {
+            assert_eq!(config.min_filter, *filter, "minFilter {}", min_filter);
+            assert_eq!(config.mipmap_mode, *mipmap_mode, "minFilter {}", min_filter);
+            assert_eq!(
+                config.anisotropy_enable,
+                *filter == Filter::LINEAR,
+                "minFilter {}",
+                min_filter
+            );
+        }
+    }
+
+    #[test]
+    fn wrap_modes_map_to_address_modes() {
+        let table = [
+            (33071, SamplerAddressMode::CLAMP_TO_EDGE),
+            (33648, SamplerAddressMode::MIRRORED_REPEAT),
+            (10497, SamplerAddressMode::REPEAT),
+        ];
+        //wrap_t is paired with the next entry so both fields see every mode
+        let samplers: Vec<_> = table
+            .iter()
+            .enumerate()
+            .map(|(i, (wrap_s, _))| {
+                let (wrap_t, _) = table[(i + 1) % table.len()];
+                format!(r#"{{"wrapS": {}, "wrapT": {}}}"#, wrap_s, wrap_t)
+            })
+            .collect();
+        let configs = configs_for(&samplers.join(","));
+        for (i, config) in configs.iter().enumerate() {
+            assert_eq!(config.address_mode_u, table[i].1, "wrapS {}", table[i].0);
+            let (wrap_t, expected) = table[(i + 1) % table.len()];
+            assert_eq!(config.address_mode_v, expected, "wrapT {}", wrap_t);
+        }
+    }
+
+    #[test]
+    fn unspecified_wrap_modes_repeat() {
+        let configs = configs_for("{}");
+        assert_eq!(configs[0].address_mode_u, SamplerAddressMode::REPEAT);
+        assert_eq!(configs[0].address_mode_v, SamplerAddressMode::REPEAT);
     }
 }
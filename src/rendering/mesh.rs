@@ -1,9 +1,17 @@
-use crate::util::GLTFModel;
+use crate::{
+    rendering::{cube_geometry, heightmap_geometry, plane_geometry, uv_sphere_geometry, VertexPBR},
+    util::{GLTFModel, ModelError},
+};
 
 use katla_vulkan::context::VulkanContext;
-use katla_vulkan::{self, IndexBuffer, IndexType, VertexBuffer};
+use katla_vulkan::{
+    self, AllocationError, IndexBuffer, IndexType, UploadManager, UploadStatus, VertexBuffer,
+};
 
-use std::{rc::Rc, sync::Arc};
+use std::{path::Path, rc::Rc, sync::Arc};
+
+//Heightmaps above this many vertices get a warning, they are uploaded as one mesh
+const HEIGHTMAP_VERTEX_WARNING: u64 = 1 << 20;
 
 //TODO:
 // Handle the GPU-side in katla_vulkan
@@ -14,86 +22,191 @@ pub struct Mesh {
     pub vertex_buffer: Option<VertexBuffer>,
     pub index_buffer: Option<IndexBuffer>,
     pub num_verts: u32,
+    uploads: Vec<UploadStatus>,
 }
 
 impl Mesh {
-    pub fn new_from_model(model: Rc<GLTFModel>, context: Arc<VulkanContext>) -> Self {
+    //The buffers are filled over the next frames by the UploadManager, the mesh is
+    //not drawn until that has finished
+    pub fn new_from_model(
+        model: Rc<GLTFModel>,
+        context: Arc<VulkanContext>,
+        uploads: &mut UploadManager,
+    ) -> Result<Self, AllocationError> {
         let index_type = match model.index_stride {
             1 => IndexType::UINT8_EXT,
             2 => IndexType::UINT16,
             4 => IndexType::UINT32,
             _ => IndexType::NONE_KHR,
         };
-        let index_buffer = Self::create_index_buffer(&context, model.index_data(), index_type);
-        let vertex_buffer = Self::create_vertex_buffer(&context, model.vertpbr());
+        let index_data = model.index_data();
+        let index_buffer = Self::create_index_buffer(&context, &index_data, index_type)?;
+        let (vertex_data, num_vertices) = if model.has_vertex_colors() {
+            let vertices = model.vertfull();
+            (as_bytes(&vertices).to_vec(), vertices.len())
+        } else {
+            let vertices = model.vertpbr();
+            (as_bytes(&vertices).to_vec(), vertices.len())
+        };
+        let vertex_buffer = Self::create_vertex_buffer(&context, &vertex_data, num_vertices)?;
+
+        let mut statuses = vec![];
+        if let Some(index_buffer) = &index_buffer {
+            statuses.push(index_buffer.queue_upload(uploads, as_bytes(&index_data).to_vec()));
+        }
+        if let Some(vertex_buffer) = &vertex_buffer {
+            statuses.push(vertex_buffer.queue_upload(uploads, vertex_data));
+        }
 
-        Self {
+        Ok(Self {
             vertex_buffer,
             index_buffer,
             num_verts: 0,
+            uploads: statuses,
+        })
+    }
+
+    pub fn new_from_data(
+        context: Arc<VulkanContext>,
+        vertices: Vec<VertexPBR>,
+        indices: Vec<u32>,
+    ) -> Result<Self, AllocationError> {
+        let mut index_buffer = Self::create_index_buffer(&context, &indices, IndexType::UINT32)?;
+        if let Some(index_buffer) = &mut index_buffer {
+            index_buffer.upload_data(as_bytes(&indices))?;
+        }
+        let mut vertex_buffer =
+            Self::create_vertex_buffer(&context, as_bytes(&vertices), vertices.len())?;
+        if let Some(vertex_buffer) = &mut vertex_buffer {
+            vertex_buffer.upload_data(as_bytes(&vertices))?;
+        }
+
+        Ok(Self {
+            vertex_buffer,
+            index_buffer,
+            num_verts: 0,
+            uploads: vec![],
+        })
+    }
+
+    pub fn cube(context: Arc<VulkanContext>, size: f32) -> Result<Self, AllocationError> {
+        let (vertices, indices) = cube_geometry(size);
+        Self::new_from_data(context, vertices, indices)
+    }
+
+    pub fn uv_sphere(
+        context: Arc<VulkanContext>,
+        radius: f32,
+        rings: u32,
+        sectors: u32,
+    ) -> Result<Self, AllocationError> {
+        let (vertices, indices) = uv_sphere_geometry(radius, rings, sectors);
+        Self::new_from_data(context, vertices, indices)
+    }
+
+    pub fn plane(
+        context: Arc<VulkanContext>,
+        size: f32,
+        subdivisions: u32,
+    ) -> Result<Self, AllocationError> {
+        let (vertices, indices) = plane_geometry(size, subdivisions);
+        Self::new_from_data(context, vertices, indices)
+    }
+
+    //Grayscale image where black is 0 and white is height_scale, every pixel becomes a vertex
+    pub fn from_heightmap<P>(
+        context: Arc<VulkanContext>,
+        image_path: P,
+        scale: f32,
+        height_scale: f32,
+    ) -> Result<Self, ModelError>
+    where
+        P: AsRef<Path>,
+    {
+        let image_path = image_path.as_ref();
+        let image = image::open(image_path)
+            .map_err(|err| match err {
+                image::ImageError::IoError(io_err)
+                    if io_err.kind() == std::io::ErrorKind::NotFound =>
+                {
+                    ModelError::NotFound(image_path.to_path_buf())
+                }
+                err => ModelError::Parse(err.to_string()),
+            })?
+            .to_luma8();
+        let (width, depth) = image.dimensions();
+        if (width as u64) * (depth as u64) > HEIGHTMAP_VERTEX_WARNING {
+            println!(
+                "Heightmap {} is {}x{}, the mesh will have {} vertices",
+                image_path.display(),
+                width,
+                depth,
+                width as u64 * depth as u64
+            );
         }
+        let heights = image
+            .pixels()
+            .map(|pixel| pixel[0] as f32 / u8::MAX as f32)
+            .collect::<Vec<f32>>();
+        let (vertices, indices) = heightmap_geometry(&heights, width, depth, scale, height_scale);
+        Ok(Self::new_from_data(context, vertices, indices)?)
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.uploads.iter().all(|status| status.is_ready())
     }
 
     fn create_index_buffer<DataType>(
         context: &Arc<VulkanContext>,
-        data: Vec<DataType>,
+        data: &[DataType],
         index_type: IndexType,
-    ) -> Option<IndexBuffer> {
+    ) -> Result<Option<IndexBuffer>, AllocationError> {
         if data.is_empty() {
-            None
+            Ok(None)
         } else {
-            let data_slice = unsafe {
-                std::slice::from_raw_parts(
-                    data.as_ptr() as *const u8,
-                    data.len() * std::mem::size_of::<DataType>(),
-                )
-            };
+            let data_slice = as_bytes(data);
             let count = match index_type {
                 IndexType::UINT8_EXT => data_slice.len() as u32,
                 IndexType::UINT16 => (data_slice.len() as u32) / 2,
                 IndexType::UINT32 => (data_slice.len() as u32) / 4,
                 _ => 0 as u32,
             };
-            let mut index_buffer =
-                IndexBuffer::new(context.clone(), data_slice.len() as u64, index_type, count);
-            index_buffer.upload_data(data_slice);
-            Some(index_buffer)
+            IndexBuffer::new(context.clone(), data_slice.len() as u64, index_type, count).map(Some)
         }
     }
 
-    fn create_vertex_buffer<DataType>(
+    fn create_vertex_buffer(
         context: &Arc<VulkanContext>,
-        data: Vec<DataType>,
-    ) -> Option<VertexBuffer> {
-        if data.is_empty() {
-            None
+        data_slice: &[u8],
+        count: usize,
+    ) -> Result<Option<VertexBuffer>, AllocationError> {
+        if data_slice.is_empty() {
+            Ok(None)
         } else {
-            let data_slice = unsafe {
-                std::slice::from_raw_parts(
-                    data.as_ptr() as *const u8,
-                    data.len() * std::mem::size_of::<DataType>(),
-                )
-            };
-            let mut vertex_buffer =
-                VertexBuffer::new(context.clone(), data_slice.len() as u64, data.len() as u32);
-            vertex_buffer.upload_data(data_slice);
-            Some(vertex_buffer)
+            VertexBuffer::new(context.clone(), data_slice.len() as u64, count as u32).map(Some)
         }
     }
 
     pub fn draw(&self, command_buffer: &katla_vulkan::CommandBuffer) {
+        if !self.is_ready() {
+            return;
+        }
         if let Some(index_buffer) = &self.index_buffer {
-            command_buffer.bind_index_buffer(index_buffer.object(), 0, index_buffer.index_type);
+            command_buffer.bind_index_buffer_object(index_buffer);
 
             if let Some(vertex_buffer) = &self.vertex_buffer {
-                command_buffer.bind_vertex_buffers(0, &[vertex_buffer.object()], &[0]);
+                command_buffer.bind_vertex_buffer_object(0, vertex_buffer);
                 command_buffer.draw_indexed(index_buffer.count(), 1, 0, 0, 0);
             }
         } else {
             if let Some(vertex_buffer) = &self.vertex_buffer {
-                command_buffer.bind_vertex_buffers(0, &[vertex_buffer.object()], &[0]);
+                command_buffer.bind_vertex_buffer_object(0, vertex_buffer);
                 command_buffer.draw_array(vertex_buffer.count(), 1, 0, 0);
             }
         }
     }
 }
+
+fn as_bytes<DataType>(data: &[DataType]) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(data.as_ptr() as *const u8, std::mem::size_of_val(data)) }
+}
@@ -1,9 +1,19 @@
 pub mod drawable;
+pub mod global;
+pub mod grid;
+pub mod light;
 pub mod material;
 pub mod mesh;
+pub mod primitives;
+pub mod skybox;
 pub mod vertextypes;
 
 pub use drawable::Drawable;
+pub use global::GlobalData;
+pub use grid::*;
+pub use light::*;
 pub use material::*;
 pub use mesh::*;
+pub use primitives::*;
+pub use skybox::*;
 pub use vertextypes::*;
@@ -0,0 +1,273 @@
+use std::f32::consts::PI;
+
+use katla_math::Vec3;
+
+use crate::{
+    rendering::VertexPBR,
+    util::{compute_normals, GLTFModel},
+};
+
+//CPU-side geometry for the built-in primitives, all of them wound counter-clockwise
+//around their outward normals just like glTF data. Note that -y is up in this engine.
+
+pub fn cube_geometry(size: f32) -> (Vec<VertexPBR>, Vec<u32>) {
+    let half = size * 0.5;
+    //(normal, u, v) where u x v == normal
+    let faces = [
+        ([1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]),
+        ([-1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]),
+        ([0.0, 1.0, 0.0], [0.0, 0.0, 1.0], [1.0, 0.0, 0.0]),
+        ([0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+        ([0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+        ([0.0, 0.0, -1.0], [0.0, 1.0, 0.0], [1.0, 0.0, 0.0]),
+    ];
+    let corners = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)];
+
+    let mut vertices = Vec::with_capacity(24);
+    let mut indices = Vec::with_capacity(36);
+    for (normal, u, v) in faces {
+        let first = vertices.len() as u32;
+        for (su, sv) in corners {
+            let position = [
+                (normal[0] + su * u[0] + sv * v[0]) * half,
+                (normal[1] + su * u[1] + sv * v[1]) * half,
+                (normal[2] + su * u[2] + sv * v[2]) * half,
+            ];
+            vertices.push(VertexPBR {
+                position,
+                normal,
+                tangent: [u[0], u[1], u[2], 1.0],
+                tex_coord0: [(su + 1.0) * 0.5, (sv + 1.0) * 0.5],
+            });
+        }
+        indices.extend([first, first + 1, first + 2, first, first + 2, first + 3]);
+    }
+    (vertices, indices)
+}
+
+//Rings go from pole to pole, sectors around the y axis. The seam and poles duplicate
+//vertices so that every vertex has a unique uv.
+pub fn uv_sphere_geometry(radius: f32, rings: u32, sectors: u32) -> (Vec<VertexPBR>, Vec<u32>) {
+    let rings = rings.max(2);
+    let sectors = sectors.max(3);
+    let mut vertices = Vec::with_capacity(((rings + 1) * (sectors + 1)) as usize);
+    let mut indices = Vec::with_capacity((rings * sectors * 6) as usize);
+
+    for ring in 0..=rings {
+        let phi = PI * ring as f32 / rings as f32;
+        for sector in 0..=sectors {
+            let theta = 2.0 * PI * sector as f32 / sectors as f32;
+            let normal = Vec3::new(phi.sin() * theta.cos(), phi.cos(), phi.sin() * theta.sin());
+            vertices.push(VertexPBR {
+                position: normal.mul(radius).0,
+                normal: normal.0,
+                tangent: [-theta.sin(), 0.0, theta.cos(), 1.0],
+                tex_coord0: [sector as f32 / sectors as f32, ring as f32 / rings as f32],
+            });
+        }
+    }
+
+    let stride = sectors + 1;
+    for ring in 0..rings {
+        for sector in 0..sectors {
+            let a = ring * stride + sector;
+            let b = a + stride;
+            let c = b + 1;
+            let d = a + 1;
+            indices.extend([a, d, c, a, c, b]);
+        }
+    }
+    (vertices, indices)
+}
+
+//A size x size plane on y=0 facing up (-y), split into subdivisions^2 quads
+pub fn plane_geometry(size: f32, subdivisions: u32) -> (Vec<VertexPBR>, Vec<u32>) {
+    let subdivisions = subdivisions.max(1);
+    let half = size * 0.5;
+    let mut vertices = Vec::with_capacity(((subdivisions + 1) * (subdivisions + 1)) as usize);
+    let mut indices = Vec::with_capacity((subdivisions * subdivisions * 6) as usize);
+
+    for j in 0..=subdivisions {
+        let v = j as f32 / subdivisions as f32;
+        for i in 0..=subdivisions {
+            let u = i as f32 / subdivisions as f32;
+            vertices.push(VertexPBR {
+                position: [u * size - half, 0.0, v * size - half],
+                normal: [0.0, -1.0, 0.0],
+                tangent: [1.0, 0.0, 0.0, 1.0],
+                tex_coord0: [u, v],
+            });
+        }
+    }
+
+    let stride = subdivisions + 1;
+    for j in 0..subdivisions {
+        for i in 0..subdivisions {
+            let a = j * stride + i;
+            let b = a + 1;
+            let c = b + stride;
+            let d = a + stride;
+            indices.extend([a, b, c, a, c, d]);
+        }
+    }
+    (vertices, indices)
+}
+
+//A grid with one vertex per height sample, heights are row-major width x depth in [0, 1].
+//Samples are spaced scale apart and centered around the origin, raised along -y
+pub fn heightmap_geometry(
+    heights: &[f32],
+    width: u32,
+    depth: u32,
+    scale: f32,
+    height_scale: f32,
+) -> (Vec<VertexPBR>, Vec<u32>) {
+    assert_eq!(heights.len(), (width * depth) as usize);
+    let half_width = (width.max(1) - 1) as f32 * scale * 0.5;
+    let half_depth = (depth.max(1) - 1) as f32 * scale * 0.5;
+    let mut vertices = Vec::with_capacity(heights.len());
+    let mut indices =
+        Vec::with_capacity((width.saturating_sub(1) * depth.saturating_sub(1) * 6) as usize);
+
+    for j in 0..depth {
+        for i in 0..width {
+            let height = heights[(j * width + i) as usize];
+            vertices.push(VertexPBR {
+                position: [
+                    i as f32 * scale - half_width,
+                    -height * height_scale,
+                    j as f32 * scale - half_depth,
+                ],
+                normal: [0.0, -1.0, 0.0],
+                tangent: [1.0, 0.0, 0.0, 1.0],
+                tex_coord0: [
+                    i as f32 / (width.max(2) - 1) as f32,
+                    j as f32 / (depth.max(2) - 1) as f32,
+                ],
+            });
+        }
+    }
+
+    for j in 0..depth.saturating_sub(1) {
+        for i in 0..width.saturating_sub(1) {
+            let a = j * width + i;
+            let b = a + 1;
+            let c = b + width;
+            let d = a + width;
+            indices.extend([a, b, c, a, c, d]);
+        }
+    }
+
+    let positions = vertices
+        .iter()
+        .map(|vertex| vertex.position)
+        .collect::<Vec<[f32; 3]>>();
+    for (vertex, normal) in vertices
+        .iter_mut()
+        .zip(compute_normals(&positions, &indices))
+    {
+        vertex.normal = normal;
+    }
+    GLTFModel::compute_tangents(&mut vertices, &indices);
+    (vertices, indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    //Indices in range, unit normals and every non-degenerate triangle wound
+    //counter-clockwise around the normals of its vertices
+    fn assert_well_formed(vertices: &[VertexPBR], indices: &[u32]) {
+        assert_eq!(indices.len() % 3, 0);
+        assert!(indices
+            .iter()
+            .all(|&index| (index as usize) < vertices.len()));
+        for vertex in vertices {
+            let normal = Vec3(vertex.normal);
+            assert_abs_diff_eq!(normal.dot(normal), 1.0, epsilon = 1e-4);
+        }
+        for triangle in indices.chunks_exact(3) {
+            let [p0, p1, p2] = [0, 1, 2].map(|i| Vec3(vertices[triangle[i] as usize].position));
+            let face_normal = (p1 - p0).cross(p2 - p0);
+            if face_normal.dot(face_normal) < 1e-10 {
+                continue;
+            }
+            for &index in triangle {
+                assert!(face_normal.dot(Vec3(vertices[index as usize].normal)) > 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn cube_is_well_formed() {
+        let (vertices, indices) = cube_geometry(2.0);
+        assert_eq!(vertices.len(), 24);
+        assert_eq!(indices.len(), 36);
+        assert_well_formed(&vertices, &indices);
+        for vertex in &vertices {
+            assert!(vertex.position.iter().all(|c| c.abs() == 1.0));
+        }
+    }
+
+    #[test]
+    fn uv_sphere_is_well_formed() {
+        let (vertices, indices) = uv_sphere_geometry(3.0, 8, 16);
+        assert_eq!(vertices.len(), 9 * 17);
+        assert_eq!(indices.len(), 8 * 16 * 6);
+        assert_well_formed(&vertices, &indices);
+        for vertex in &vertices {
+            assert_abs_diff_eq!(Vec3(vertex.position).distance(), 3.0, epsilon = 1e-4);
+        }
+    }
+
+    #[test]
+    fn uv_sphere_clamps_the_tessellation() {
+        let (vertices, indices) = uv_sphere_geometry(1.0, 0, 0);
+        assert_eq!(vertices.len(), 3 * 4);
+        assert_eq!(indices.len(), 2 * 3 * 6);
+        assert_well_formed(&vertices, &indices);
+    }
+
+    #[test]
+    fn plane_is_well_formed() {
+        let (vertices, indices) = plane_geometry(4.0, 3);
+        assert_eq!(vertices.len(), 16);
+        assert_eq!(indices.len(), 3 * 3 * 6);
+        assert_well_formed(&vertices, &indices);
+    }
+
+    #[test]
+    fn flat_heightmap_faces_up() {
+        let (vertices, indices) = heightmap_geometry(&[0.5; 12], 4, 3, 2.0, 10.0);
+        assert_eq!(vertices.len(), 12);
+        assert_eq!(indices.len(), 3 * 2 * 6);
+        assert_well_formed(&vertices, &indices);
+        for vertex in &vertices {
+            assert_abs_diff_eq!(vertex.position[1], -5.0);
+            assert_abs_diff_eq!(vertex.normal[1], -1.0, epsilon = 1e-5);
+        }
+        assert_eq!(vertices[0].position[0], -3.0);
+        assert_eq!(vertices[11].position[2], 2.0);
+    }
+
+    #[test]
+    fn sloped_heightmap_is_well_formed() {
+        let heights = [0.0, 0.25, 0.5, 0.0, 0.25, 0.5, 0.0, 0.25, 0.5];
+        let (vertices, indices) = heightmap_geometry(&heights, 3, 3, 1.0, 1.0);
+        assert_well_formed(&vertices, &indices);
+        //Rising along +x tilts the normals towards -x
+        assert!(vertices.iter().all(|vertex| vertex.normal[0] < 0.0));
+        for vertex in &vertices {
+            assert_eq!(vertex.tangent[3].abs(), 1.0);
+        }
+    }
+
+    #[test]
+    fn heightmap_rows_without_quads_have_no_indices() {
+        let (vertices, indices) = heightmap_geometry(&[0.0; 4], 4, 1, 1.0, 1.0);
+        assert_eq!(vertices.len(), 4);
+        assert!(indices.is_empty());
+    }
+}
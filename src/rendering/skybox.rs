@@ -0,0 +1,99 @@
+use std::path::Path;
+
+use katla_vulkan::{
+    context::VulkanContext, Filter, Format, SamplerAddressMode, SamplerConfig, SamplerMipmapMode,
+    Texture, CUBEMAP_FACES,
+};
+
+use crate::util::ModelError;
+
+//Faces in the order Texture::create_cubemap takes them: +X, -X, +Y, -Y, +Z, -Z. Every face
+//has to be a square image of the same size
+pub fn load_cubemap<P>(
+    context: &VulkanContext,
+    face_paths: &[P; CUBEMAP_FACES],
+) -> Result<Texture, ModelError>
+where
+    P: AsRef<Path>,
+{
+    let mut faces = Vec::with_capacity(CUBEMAP_FACES);
+    for path in face_paths {
+        let path = path.as_ref();
+        let image = image::open(path)
+            .map_err(|err| match err {
+                image::ImageError::IoError(io_err)
+                    if io_err.kind() == std::io::ErrorKind::NotFound =>
+                {
+                    ModelError::NotFound(path.to_path_buf())
+                }
+                err => ModelError::Parse(err.to_string()),
+            })?
+            .to_rgba8();
+        faces.push(image);
+    }
+    let dimensions: Vec<(u32, u32)> = faces.iter().map(|face| face.dimensions()).collect();
+    let size = cubemap_face_size(&dimensions)?;
+    let face_data = [
+        faces[0].as_raw().as_slice(),
+        faces[1].as_raw().as_slice(),
+        faces[2].as_raw().as_slice(),
+        faces[3].as_raw().as_slice(),
+        faces[4].as_raw().as_slice(),
+        faces[5].as_raw().as_slice(),
+    ];
+    //Clamped, so the edges of neighbouring faces don't wrap around
+    let sampler_config = SamplerConfig {
+        mag_filter: Filter::LINEAR,
+        min_filter: Filter::LINEAR,
+        mipmap_mode: SamplerMipmapMode::LINEAR,
+        address_mode_u: SamplerAddressMode::CLAMP_TO_EDGE,
+        address_mode_v: SamplerAddressMode::CLAMP_TO_EDGE,
+        anisotropy_enable: false,
+        ..Default::default()
+    };
+    Ok(Texture::create_cubemap(
+        context,
+        size,
+        Format::R8G8B8A8_SRGB,
+        face_data,
+        &sampler_config,
+    )?)
+}
+
+//The edge length shared by every face
+fn cubemap_face_size(dimensions: &[(u32, u32)]) -> Result<u32, ModelError> {
+    let (width, height) = dimensions[0];
+    if width != height {
+        return Err(ModelError::Unsupported(format!(
+            "cubemap faces have to be square, the first one is {}x{}",
+            width, height
+        )));
+    }
+    if let Some((width, height)) = dimensions.iter().find(|&&size| size != (width, height)) {
+        return Err(ModelError::Unsupported(format!(
+            "cubemap faces differ in size, {}x{} and {}x{}",
+            dimensions[0].0, dimensions[0].1, width, height
+        )));
+    }
+    Ok(width)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn faces_share_one_square_size() {
+        assert_eq!(cubemap_face_size(&[(64, 64); 6]).unwrap(), 64);
+        assert!(matches!(
+            cubemap_face_size(&[(64, 32); 6]),
+            Err(ModelError::Unsupported(_))
+        ));
+        let mut mixed = [(64, 64); 6];
+        mixed[4] = (32, 32);
+        assert!(matches!(
+            cubemap_face_size(&mixed),
+            Err(ModelError::Unsupported(_))
+        ));
+    }
+}
@@ -65,3 +65,27 @@ impl VertexPBR {
         }
     }
 }
+
+#[repr(C)]
+#[derive(Default, Debug, Clone)]
+pub struct VertexFull {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub tangent: [f32; 4],
+    pub tex_coord0: [f32; 2],
+    pub color: [f32; 4],
+}
+
+impl VertexFull {
+    pub fn get_vertex_binding() -> VertexBinding {
+        VertexBinding {
+            formats: vec![
+                VertexFormat::RGB32f,
+                VertexFormat::RGB32f,
+                VertexFormat::RGBA32f,
+                VertexFormat::RG32f,
+                VertexFormat::RGBA32f,
+            ],
+        }
+    }
+}
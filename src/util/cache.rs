@@ -1,4 +1,4 @@
-use std::{collections::HashMap, path::PathBuf, rc::Rc};
+use std::{collections::HashMap, convert::TryFrom, path::PathBuf, rc::Rc};
 
 pub struct FileCache<T> {
     objects: HashMap<PathBuf, Rc<T>>,
@@ -6,7 +6,7 @@ pub struct FileCache<T> {
 
 impl<T> FileCache<T>
 where
-    T: From<PathBuf>,
+    T: TryFrom<PathBuf>,
 {
     pub fn new() -> Self {
         Self {
@@ -14,13 +14,14 @@ where
         }
     }
 
-    pub fn read(&mut self, path: PathBuf) -> Rc<T> {
+    //Failed reads are not cached, so a later read of the same path tries again
+    pub fn read(&mut self, path: PathBuf) -> Result<Rc<T>, T::Error> {
         match self.objects.get(&path) {
-            Some(file) => file.clone(),
+            Some(file) => Ok(file.clone()),
             None => {
-                let cached_object = Rc::new(T::from(path.clone()));
+                let cached_object = Rc::new(T::try_from(path.clone())?);
                 self.objects.insert(path, cached_object.clone());
-                cached_object
+                Ok(cached_object)
             }
         }
     }
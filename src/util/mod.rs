@@ -1,7 +1,13 @@
 pub mod cache;
 pub mod modelcache;
+pub mod objmodel;
+pub mod taskpool;
+pub mod time;
 pub mod timer;
 
 pub use cache::*;
 pub use modelcache::*;
+pub use objmodel::*;
+pub use taskpool::*;
+pub use time::*;
 pub use timer::*;
@@ -1,4 +1,8 @@
-use std::path::{Path, PathBuf};
+use std::{
+    convert::TryFrom,
+    fmt,
+    path::{Path, PathBuf},
+};
 
 use byteorder::{ByteOrder, LittleEndian};
 
@@ -7,8 +11,38 @@ use gltf::image::Data as ImageData;
 use gltf::Document;
 use itertools::izip;
 use katla_math::{Sphere, Vec3};
+use katla_vulkan::AllocationError;
 
-use crate::rendering::{VertexNormal, VertexPBR, VertexPosition};
+use crate::rendering::{VertexFull, VertexNormal, VertexPBR, VertexPosition};
+
+const DEFAULT_VERTEX_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+
+#[derive(Debug)]
+pub enum ModelError {
+    NotFound(PathBuf),
+    Parse(String),
+    Unsupported(String),
+    Allocation(AllocationError),
+}
+
+impl fmt::Display for ModelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModelError::NotFound(path) => write!(f, "model file not found: {}", path.display()),
+            ModelError::Parse(reason) => write!(f, "failed to parse model: {}", reason),
+            ModelError::Unsupported(reason) => write!(f, "unsupported model: {}", reason),
+            ModelError::Allocation(err) => write!(f, "failed to upload model: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ModelError {}
+
+impl From<AllocationError> for ModelError {
+    fn from(err: AllocationError) -> Self {
+        ModelError::Allocation(err)
+    }
+}
 
 #[derive(Clone)]
 pub struct GLTFModel {
@@ -16,16 +50,103 @@ pub struct GLTFModel {
     pub buffers: Vec<BufferData>,
     pub images: Vec<ImageData>,
     pub vertex_data: Vec<VertexPBR>,
+    pub color_data: Vec<[f32; 4]>,
     pub index_data: Vec<u8>,
     pub index_stride: u8,
     pub bounds: Sphere,
 }
 
 impl GLTFModel {
-    fn parse_node(&self, node: &gltf::Node) -> (Vec<VertexPBR>, Vec<u8>, u8, Sphere) {
+    fn read_indices(index_data: &[u8], index_stride: u8) -> Vec<u32> {
+        match index_stride {
+            1 => index_data.iter().map(|index| *index as u32).collect(),
+            2 => index_data
+                .chunks(2)
+                .map(|bytes| LittleEndian::read_u16(bytes) as u32)
+                .collect(),
+            4 => index_data.chunks(4).map(LittleEndian::read_u32).collect(),
+            _ => vec![],
+        }
+    }
+
+    fn read_color(bytes: &[u8], accessor: &gltf::Accessor) -> [f32; 4] {
+        use gltf::accessor::{DataType, Dimensions};
+        let num_components = match accessor.dimensions() {
+            Dimensions::Vec3 => 3,
+            _ => 4,
+        };
+        let mut color = DEFAULT_VERTEX_COLOR;
+        for (component, value) in color.iter_mut().enumerate().take(num_components) {
+            *value = match accessor.data_type() {
+                DataType::U8 => bytes[component] as f32 / u8::MAX as f32,
+                DataType::U16 => {
+                    LittleEndian::read_u16(&bytes[component * 2..]) as f32 / u16::MAX as f32
+                }
+                _ => LittleEndian::read_f32(&bytes[component * 4..]),
+            };
+        }
+        color
+    }
+
+    //Accumulates per-triangle tangents onto the vertices, orthogonalized against the normals
+    pub(crate) fn compute_tangents(vertex_data: &mut [VertexPBR], indices: &[u32]) {
+        let mut tangents = vec![Vec3::new(0.0, 0.0, 0.0); vertex_data.len()];
+        let mut bitangents = vec![Vec3::new(0.0, 0.0, 0.0); vertex_data.len()];
+        let triangle_indices: Vec<u32> = if indices.is_empty() {
+            (0..vertex_data.len() as u32).collect()
+        } else {
+            indices.to_vec()
+        };
+        for triangle in triangle_indices.chunks_exact(3) {
+            let [i0, i1, i2] = [
+                triangle[0] as usize,
+                triangle[1] as usize,
+                triangle[2] as usize,
+            ];
+            if i0.max(i1).max(i2) >= vertex_data.len() {
+                continue;
+            }
+            let (tangent, bitangent) = katla_math::triangle_tangents(
+                [
+                    Vec3(vertex_data[i0].position),
+                    Vec3(vertex_data[i1].position),
+                    Vec3(vertex_data[i2].position),
+                ],
+                [
+                    vertex_data[i0].tex_coord0,
+                    vertex_data[i1].tex_coord0,
+                    vertex_data[i2].tex_coord0,
+                ],
+            );
+            for index in [i0, i1, i2] {
+                tangents[index] = tangents[index] + tangent;
+                bitangents[index] = bitangents[index] + bitangent;
+            }
+        }
+        for (vertex, (tangent, bitangent)) in vertex_data
+            .iter_mut()
+            .zip(tangents.into_iter().zip(bitangents))
+        {
+            let normal = Vec3(vertex.normal);
+            let tangent = (tangent - normal * normal.dot(tangent)).normalize();
+            let handedness = if normal.cross(tangent).dot(bitangent) < 0.0 {
+                -1.0
+            } else {
+                1.0
+            };
+            vertex.tangent = [tangent[0], tangent[1], tangent[2], handedness];
+        }
+    }
+
+    fn parse_node(
+        &self,
+        node: &gltf::Node,
+    ) -> (Vec<VertexPBR>, Vec<[f32; 4]>, Vec<u8>, u8, Sphere) {
         let mut positions: Vec<[f32; 3]> = vec![];
         let mut normals: Vec<[f32; 3]> = vec![];
         let mut tex_coords: Vec<[f32; 2]> = vec![];
+        let mut tangents: Vec<[f32; 4]> = vec![];
+        let mut colors: Vec<[f32; 4]> = vec![];
         let mut index_stride = 0u8;
         let mut index_data = vec![];
         let mut vertex_data = vec![];
@@ -79,6 +200,23 @@ impl GLTFModel {
                                 })
                                 .collect::<Vec<[f32; 3]>>();
                         }
+                        gltf::mesh::Semantic::Tangents => {
+                            tangents = iter
+                                .map(|bytes| {
+                                    [
+                                        LittleEndian::read_f32(&bytes[0..4]),
+                                        LittleEndian::read_f32(&bytes[4..8]),
+                                        LittleEndian::read_f32(&bytes[8..12]),
+                                        LittleEndian::read_f32(&bytes[12..16]),
+                                    ]
+                                })
+                                .collect::<Vec<[f32; 4]>>();
+                        }
+                        gltf::mesh::Semantic::Colors(0) => {
+                            colors = iter
+                                .map(|bytes| Self::read_color(bytes, &accessor))
+                                .collect::<Vec<[f32; 4]>>();
+                        }
                         gltf::mesh::Semantic::TexCoords(0) => {
                             tex_coords = iter
                                 .map(|bytes| {
@@ -161,8 +299,17 @@ impl GLTFModel {
                     })
                     .collect::<Vec<VertexPBR>>();
             }
+
+            if tangents.len() == vertex_data.len() {
+                for (vertex, tangent) in vertex_data.iter_mut().zip(tangents) {
+                    vertex.tangent = tangent;
+                }
+            } else if has_tex_coords && has_norm {
+                let indices = Self::read_indices(&index_data, index_stride);
+                Self::compute_tangents(&mut vertex_data, &indices);
+            }
         }
-        (vertex_data, index_data, index_stride, sphere)
+        (vertex_data, colors, index_data, index_stride, sphere)
     }
 
     fn parse_gltf(&mut self) {
@@ -179,7 +326,18 @@ impl GLTFModel {
 
         for node in self.document.nodes() {
             if used_nodes.contains(&node.index()) {
-                let (vertex_data, index_data, index_stride, sphere) = self.parse_node(&node);
+                let (vertex_data, colors, index_data, index_stride, sphere) =
+                    self.parse_node(&node);
+                //Vertices without a color attribute default to white
+                if !colors.is_empty() || !self.color_data.is_empty() {
+                    self.color_data
+                        .resize(self.vertex_data.len(), DEFAULT_VERTEX_COLOR);
+                    self.color_data.extend(colors);
+                    self.color_data.resize(
+                        self.vertex_data.len() + vertex_data.len(),
+                        DEFAULT_VERTEX_COLOR,
+                    );
+                }
                 self.vertex_data.extend(vertex_data);
                 self.index_data.extend(index_data);
                 self.index_stride = index_stride;
@@ -188,23 +346,30 @@ impl GLTFModel {
         }
     }
 
-    fn new<P>(path: P) -> Self
+    fn new<P>(path: P) -> Result<Self, ModelError>
     where
         P: AsRef<Path>,
     {
-        let (document, buffers, images) = gltf::import(path).unwrap();
+        let path = path.as_ref();
+        let (document, buffers, images) = gltf::import(path).map_err(|err| match err {
+            gltf::Error::Io(io_err) if io_err.kind() == std::io::ErrorKind::NotFound => {
+                ModelError::NotFound(path.to_path_buf())
+            }
+            err => ModelError::Parse(err.to_string()),
+        })?;
 
         let mut model = Self {
             document,
             buffers,
             images,
             vertex_data: vec![],
+            color_data: vec![],
             index_data: vec![],
             index_stride: 0,
             bounds: Sphere::new(Vec3::new(0.0, 0.0, 0.0), 0.0),
         };
         model.parse_gltf();
-        model
+        Ok(model)
     }
 
     //FIXME: This is only really valid for one node in the structure!
@@ -231,13 +396,145 @@ impl GLTFModel {
         self.vertex_data.clone()
     }
 
+    pub fn has_vertex_colors(&self) -> bool {
+        !self.color_data.is_empty()
+    }
+
+    pub fn vertfull(&self) -> Vec<VertexFull> {
+        self.vertex_data
+            .iter()
+            .enumerate()
+            .map(|(index, x)| VertexFull {
+                position: x.position,
+                normal: x.normal,
+                tangent: x.tangent,
+                tex_coord0: x.tex_coord0,
+                color: self
+                    .color_data
+                    .get(index)
+                    .cloned()
+                    .unwrap_or(DEFAULT_VERTEX_COLOR),
+            })
+            .collect::<Vec<VertexFull>>()
+    }
+
     pub fn index_data(&self) -> Vec<u8> {
         self.index_data.clone()
     }
 }
 
-impl From<PathBuf> for GLTFModel {
-    fn from(pathbuf: PathBuf) -> Self {
+impl TryFrom<PathBuf> for GLTFModel {
+    type Error = ModelError;
+
+    fn try_from(pathbuf: PathBuf) -> Result<Self, Self::Error> {
         GLTFModel::new(pathbuf.as_path())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    fn vertex(position: [f32; 3], tex_coord0: [f32; 2]) -> VertexPBR {
+        VertexPBR {
+            position,
+            normal: [0.0, 0.0, 1.0],
+            tangent: [0.0; 4],
+            tex_coord0,
+        }
+    }
+
+    fn triangle(tex_coords: [[f32; 2]; 3]) -> Vec<VertexPBR> {
+        vec![
+            vertex([0.0, 0.0, 0.0], tex_coords[0]),
+            vertex([1.0, 0.0, 0.0], tex_coords[1]),
+            vertex([0.0, 1.0, 0.0], tex_coords[2]),
+        ]
+    }
+
+    fn model(vertex_data: Vec<VertexPBR>, color_data: Vec<[f32; 4]>) -> GLTFModel {
+        let gltf = gltf::Gltf::from_slice(br#"{"asset":{"version":"2.0"}}"#).unwrap();
+        GLTFModel {
+            document: gltf.document,
+            buffers: vec![],
+            images: vec![],
+            vertex_data,
+            color_data,
+            index_data: vec![],
+            index_stride: 4,
+            bounds: Sphere::new(Vec3::new(0.0, 0.0, 0.0), 0.0),
+        }
+    }
+
+    #[test]
+    fn tangents_follow_the_uv_direction() {
+        let mut vertices = triangle([[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]]);
+        GLTFModel::compute_tangents(&mut vertices, &[0, 1, 2]);
+        for vertex in &vertices {
+            let tangent = Vec3::new(vertex.tangent[0], vertex.tangent[1], vertex.tangent[2]);
+            assert_relative_eq!(tangent.dot(tangent), 1.0, epsilon = 1e-5);
+            assert_relative_eq!(tangent.dot(Vec3(vertex.normal)), 0.0, epsilon = 1e-5);
+            assert_relative_eq!(tangent.0[0], 1.0, epsilon = 1e-5);
+            assert_eq!(vertex.tangent[3], 1.0);
+        }
+    }
+
+    #[test]
+    fn mirrored_uvs_flip_the_handedness() {
+        let mut vertices = triangle([[0.0, 0.0], [1.0, 0.0], [0.0, -1.0]]);
+        GLTFModel::compute_tangents(&mut vertices, &[]);
+        for vertex in &vertices {
+            assert_eq!(vertex.tangent[3], -1.0);
+        }
+    }
+
+    #[test]
+    fn indices_are_read_for_every_stride() {
+        assert_eq!(GLTFModel::read_indices(&[0, 1, 2], 1), vec![0, 1, 2]);
+        assert_eq!(GLTFModel::read_indices(&[1, 0, 0, 1], 2), vec![1, 256]);
+        assert_eq!(
+            GLTFModel::read_indices(&[1, 0, 0, 0, 0, 0, 1, 0], 4),
+            vec![1, 65536]
+        );
+        assert!(GLTFModel::read_indices(&[0, 1, 2], 3).is_empty());
+    }
+
+    #[test]
+    fn vertex_colors_select_the_full_vertex_layout() {
+        let vertices = triangle([[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]]);
+        assert!(!model(vertices.clone(), vec![]).has_vertex_colors());
+
+        let red = [1.0, 0.0, 0.0, 1.0];
+        let colored = model(vertices, vec![red]);
+        assert!(colored.has_vertex_colors());
+        let full = colored.vertfull();
+        assert_eq!(full.len(), 3);
+        assert_eq!(full[0].color, red);
+        assert_eq!(full[2].color, DEFAULT_VERTEX_COLOR);
+        assert_eq!(full[1].position, [1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn missing_files_are_not_found() {
+        let path = std::env::temp_dir().join("katla-missing-model.gltf");
+        match GLTFModel::new(&path) {
+            Err(ModelError::NotFound(missing)) => assert_eq!(missing, path),
+            Err(err) => panic!("expected NotFound, got {}", err),
+            Ok(_) => panic!("loaded a missing model"),
+        }
+        assert_eq!(
+            ModelError::NotFound(path.clone()).to_string(),
+            format!("model file not found: {}", path.display())
+        );
+    }
+
+    #[test]
+    fn invalid_files_fail_to_parse() {
+        let path = std::env::temp_dir().join(format!("katla-invalid-{}.gltf", std::process::id()));
+        std::fs::write(&path, b"not a gltf file").unwrap();
+        let result = GLTFModel::new(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(ModelError::Parse(_))));
+    }
+}
@@ -0,0 +1,201 @@
+use std::{
+    convert::TryFrom,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+};
+
+use katla_math::{Sphere, Vec3};
+
+use crate::{
+    rendering::VertexPBR,
+    util::{GLTFModel, ModelError},
+};
+
+/// Triangle data read from a Wavefront OBJ file, all objects in the file are merged.
+/// Materials are not read, OBJ models are drawn with a default material.
+#[derive(Clone)]
+pub struct ObjModel {
+    pub vertex_data: Vec<VertexPBR>,
+    pub index_data: Vec<u32>,
+    pub bounds: Sphere,
+}
+
+impl ObjModel {
+    fn new<P>(path: P) -> Result<Self, ModelError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|err| match err.kind() {
+            std::io::ErrorKind::NotFound => ModelError::NotFound(path.to_path_buf()),
+            _ => ModelError::Parse(err.to_string()),
+        })?;
+        Self::from_reader(&mut BufReader::new(file))
+    }
+
+    pub fn from_reader<B>(reader: &mut B) -> Result<Self, ModelError>
+    where
+        B: BufRead,
+    {
+        let (models, _) = tobj::load_obj_buf(reader, &tobj::GPU_LOAD_OPTIONS, |_| {
+            Err(tobj::LoadError::OpenFileFailed)
+        })
+        .map_err(|err| ModelError::Parse(err.to_string()))?;
+
+        let mut vertex_data = vec![];
+        let mut index_data = vec![];
+        for model in models {
+            let mesh = model.mesh;
+            let positions = mesh
+                .positions
+                .chunks_exact(3)
+                .map(|p| [p[0], p[1], p[2]])
+                .collect::<Vec<[f32; 3]>>();
+            let normals = if mesh.normals.len() == mesh.positions.len() {
+                mesh.normals
+                    .chunks_exact(3)
+                    .map(|n| [n[0], n[1], n[2]])
+                    .collect::<Vec<[f32; 3]>>()
+            } else {
+                compute_normals(&positions, &mesh.indices)
+            };
+            let has_tex_coords = mesh.texcoords.len() / 2 == positions.len();
+
+            let mut vertices = positions
+                .into_iter()
+                .zip(normals)
+                .enumerate()
+                .map(|(index, (position, normal))| VertexPBR {
+                    position,
+                    normal,
+                    tangent: [0.0, 0.0, 0.0, 0.0],
+                    tex_coord0: if has_tex_coords {
+                        //OBJ has v pointing up, Vulkan samples with v pointing down
+                        [
+                            mesh.texcoords[index * 2],
+                            1.0 - mesh.texcoords[index * 2 + 1],
+                        ]
+                    } else {
+                        [0.0, 0.0]
+                    },
+                })
+                .collect::<Vec<VertexPBR>>();
+            if has_tex_coords {
+                GLTFModel::compute_tangents(&mut vertices, &mesh.indices);
+            }
+
+            let first = vertex_data.len() as u32;
+            index_data.extend(mesh.indices.into_iter().map(|index| index + first));
+            vertex_data.extend(vertices);
+        }
+        if vertex_data.is_empty() {
+            return Err(ModelError::Unsupported(
+                "no triangle faces found".to_owned(),
+            ));
+        }
+
+        let positions = vertex_data
+            .iter()
+            .map(|vertex| vertex.position)
+            .collect::<Vec<[f32; 3]>>();
+        let bounds = Sphere::create_from_verts(&positions);
+        Ok(Self {
+            vertex_data,
+            index_data,
+            bounds,
+        })
+    }
+}
+
+impl TryFrom<PathBuf> for ObjModel {
+    type Error = ModelError;
+
+    fn try_from(path: PathBuf) -> Result<Self, Self::Error> {
+        Self::new(path)
+    }
+}
+
+//Area weighted face normals accumulated onto the vertices, faces are wound counter-clockwise
+pub fn compute_normals(positions: &[[f32; 3]], indices: &[u32]) -> Vec<[f32; 3]> {
+    let mut normals = vec![Vec3::new(0.0, 0.0, 0.0); positions.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [i0, i1, i2] = [
+            triangle[0] as usize,
+            triangle[1] as usize,
+            triangle[2] as usize,
+        ];
+        if i0.max(i1).max(i2) >= positions.len() {
+            continue;
+        }
+        let p0 = Vec3(positions[i0]);
+        let edge0 = Vec3(positions[i1]) - p0;
+        let edge1 = Vec3(positions[i2]) - p0;
+        let face_normal = edge0.cross(edge1);
+        for index in [i0, i1, i2] {
+            normals[index] = normals[index] + face_normal;
+        }
+    }
+    normals
+        .into_iter()
+        .map(|normal| {
+            if normal.dot(normal) > 0.0 {
+                normal.normalize().0
+            } else {
+                [0.0, 0.0, 0.0]
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    const TRIANGLE: &str = "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n";
+
+    #[test]
+    fn single_triangle_gets_its_face_normal() {
+        let model = ObjModel::from_reader(&mut TRIANGLE.as_bytes()).unwrap();
+        assert_eq!(model.vertex_data.len(), 3);
+        assert_eq!(model.index_data.len(), 3);
+        for vertex in &model.vertex_data {
+            assert_abs_diff_eq!(vertex.normal[0], 0.0);
+            assert_abs_diff_eq!(vertex.normal[1], 0.0);
+            assert_abs_diff_eq!(vertex.normal[2], 1.0);
+        }
+    }
+
+    #[test]
+    fn file_normals_are_kept() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 -1\nf 1//1 2//1 3//1\n";
+        let model = ObjModel::from_reader(&mut obj.as_bytes()).unwrap();
+        assert!(model
+            .vertex_data
+            .iter()
+            .all(|v| v.normal == [0.0, 0.0, -1.0]));
+    }
+
+    #[test]
+    fn files_without_faces_are_unsupported() {
+        let result = ObjModel::from_reader(&mut "v 0 0 0\n".as_bytes());
+        assert!(matches!(result, Err(ModelError::Unsupported(_))));
+    }
+
+    #[test]
+    fn shared_vertices_average_the_face_normals() {
+        let positions = [
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, 0.0, 1.0],
+        ];
+        let normals = compute_normals(&positions, &[0, 1, 2, 0, 3, 1]);
+        assert_abs_diff_eq!(normals[2][2], 1.0);
+        assert_abs_diff_eq!(normals[3][1], 1.0);
+        let shared = Vec3(normals[0]);
+        assert_abs_diff_eq!(shared.dot(shared), 1.0, epsilon = 1e-6);
+        assert_abs_diff_eq!(normals[0][1], normals[0][2]);
+    }
+}
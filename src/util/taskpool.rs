@@ -0,0 +1,263 @@
+use std::{
+    marker::PhantomData,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Condvar, Mutex,
+    },
+    thread,
+};
+
+type Task = Box<dyn FnOnce() + Send + 'static>;
+
+/// Fixed set of worker threads for background work, created once by the application and
+/// shared through an Arc so subsystems don't each start their own threads.
+/// A panicking task doesn't take its worker down, the panic is handed to whoever joins it.
+pub struct TaskPool {
+    sender: Option<mpsc::Sender<Task>>,
+    workers: Vec<thread::JoinHandle<()>>,
+}
+
+pub struct TaskHandle<T> {
+    receiver: mpsc::Receiver<thread::Result<T>>,
+}
+
+/// Spawns tasks that may borrow from the caller, see TaskPool::scope
+pub struct TaskScope<'pool, 'env> {
+    pool: &'pool TaskPool,
+    pending: Arc<(Mutex<usize>, Condvar)>,
+    panicked: Arc<AtomicBool>,
+    //Invariant over 'env, like std::thread::Scope
+    env: PhantomData<&'env mut &'env ()>,
+}
+
+impl TaskPool {
+    //At least one worker is always started
+    pub fn new(num_threads: usize) -> Self {
+        let (sender, receiver) = mpsc::channel::<Task>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let workers = (0..num_threads.max(1))
+            .map(|index| {
+                let receiver = receiver.clone();
+                thread::Builder::new()
+                    .name(format!("katla-worker-{}", index))
+                    .spawn(move || loop {
+                        //The lock is released before running the task
+                        let task = receiver.lock().unwrap().recv();
+                        match task {
+                            Ok(task) => task(),
+                            Err(_) => break,
+                        }
+                    })
+                    .unwrap()
+            })
+            .collect();
+        Self {
+            sender: Some(sender),
+            workers,
+        }
+    }
+
+    //Leaves one core for the main thread
+    pub fn default_num_threads() -> usize {
+        thread::available_parallelism()
+            .map(|cores| cores.get().saturating_sub(1))
+            .unwrap_or(1)
+            .max(1)
+    }
+
+    pub fn num_threads(&self) -> usize {
+        self.workers.len()
+    }
+
+    pub fn spawn<F, T>(&self, task: F) -> TaskHandle<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        self.send(Box::new(move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(task));
+            //The handle may have been dropped, nobody wants the result then
+            let _ = sender.send(result);
+        }));
+        TaskHandle { receiver }
+    }
+
+    //Runs f and waits for every task it spawned before returning, so the tasks can borrow
+    //anything that outlives the call. Must not be called from inside a pool task, the
+    //waiting worker could be the one its tasks are queued behind
+    pub fn scope<'env, F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&TaskScope<'_, 'env>) -> R,
+    {
+        let scope = TaskScope {
+            pool: self,
+            pending: Arc::new((Mutex::new(0), Condvar::new())),
+            panicked: Arc::new(AtomicBool::new(false)),
+            env: PhantomData,
+        };
+        let result = panic::catch_unwind(AssertUnwindSafe(|| f(&scope)));
+        //Also waits when f panicked, the tasks may still be using borrowed data
+        scope.wait();
+        match result {
+            Ok(result) => {
+                if scope.panicked.load(Ordering::Relaxed) {
+                    panic!("A scoped task panicked");
+                }
+                result
+            }
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    }
+
+    fn send(&self, task: Task) {
+        self.sender
+            .as_ref()
+            .unwrap()
+            .send(task)
+            .expect("TaskPool workers have stopped");
+    }
+}
+
+//Queued tasks still run, dropping waits for all of them
+impl Drop for TaskPool {
+    fn drop(&mut self) {
+        self.sender.take();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl<T> TaskHandle<T> {
+    //Blocks until the task is done, re-raises the task's panic on this thread
+    pub fn join(self) -> T {
+        match self.receiver.recv().expect("TaskPool dropped the task") {
+            Ok(result) => result,
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    }
+
+    //None while the task is still running
+    pub fn try_join(&self) -> Option<T> {
+        match self.receiver.try_recv() {
+            Ok(Ok(result)) => Some(result),
+            Ok(Err(payload)) => panic::resume_unwind(payload),
+            Err(_) => None,
+        }
+    }
+}
+
+impl<'pool, 'env> TaskScope<'pool, 'env> {
+    pub fn spawn<F>(&self, task: F)
+    where
+        F: FnOnce() + Send + 'env,
+    {
+        *self.pending.0.lock().unwrap() += 1;
+        let pending = self.pending.clone();
+        let panicked = self.panicked.clone();
+        let task: Box<dyn FnOnce() + Send + 'env> = Box::new(move || {
+            if panic::catch_unwind(AssertUnwindSafe(task)).is_err() {
+                panicked.store(true, Ordering::Relaxed);
+            }
+            let (count, finished) = &*pending;
+            *count.lock().unwrap() -= 1;
+            finished.notify_all();
+        });
+        //Safety: TaskPool::scope doesn't return before this task has run, so nothing it
+        //borrows for 'env is gone while it runs
+        let task: Task = unsafe { std::mem::transmute(task) };
+        self.pool.send(task);
+    }
+
+    fn wait(&self) {
+        let (count, finished) = &*self.pending;
+        let mut count = count.lock().unwrap();
+        while *count > 0 {
+            count = finished.wait(count).unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    #[test]
+    fn spawned_tasks_return_their_results() {
+        let pool = TaskPool::new(2);
+        let handles = (0..8)
+            .map(|i| pool.spawn(move || i * i))
+            .collect::<Vec<_>>();
+        let results = handles
+            .into_iter()
+            .map(TaskHandle::join)
+            .collect::<Vec<_>>();
+        assert_eq!(results, vec![0, 1, 4, 9, 16, 25, 36, 49]);
+    }
+
+    #[test]
+    fn at_least_one_worker_is_started() {
+        let pool = TaskPool::new(0);
+        assert_eq!(pool.num_threads(), 1);
+        assert_eq!(pool.spawn(|| 7).join(), 7);
+    }
+
+    #[test]
+    fn task_panics_reach_the_joiner_and_keep_the_worker() {
+        let pool = TaskPool::new(1);
+        let handle = pool.spawn(|| panic!("task failed"));
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| handle.join())).is_err());
+        assert_eq!(pool.spawn(|| 1).join(), 1);
+    }
+
+    #[test]
+    fn scoped_tasks_borrow_and_finish_before_returning() {
+        let pool = TaskPool::new(3);
+        let mut chunks = vec![vec![1, 2], vec![3, 4], vec![5, 6]];
+        let total = AtomicUsize::new(0);
+        pool.scope(|scope| {
+            for chunk in &mut chunks {
+                let total = &total;
+                scope.spawn(move || {
+                    chunk.iter_mut().for_each(|value| *value *= 10);
+                    total.fetch_add(chunk.iter().sum::<usize>(), Ordering::Relaxed);
+                });
+            }
+        });
+        assert_eq!(chunks, vec![vec![10, 20], vec![30, 40], vec![50, 60]]);
+        assert_eq!(total.load(Ordering::Relaxed), 210);
+    }
+
+    #[test]
+    fn scoped_task_panics_are_raised_after_waiting() {
+        let pool = TaskPool::new(2);
+        let finished = AtomicUsize::new(0);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            pool.scope(|scope| {
+                scope.spawn(|| panic!("scoped task failed"));
+                scope.spawn(|| {
+                    finished.fetch_add(1, Ordering::Relaxed);
+                });
+            })
+        }));
+        assert!(result.is_err());
+        assert_eq!(finished.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn dropping_the_pool_runs_queued_tasks() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let pool = TaskPool::new(1);
+        for _ in 0..16 {
+            let counter = counter.clone();
+            pool.spawn(move || {
+                counter.fetch_add(1, Ordering::Relaxed);
+            });
+        }
+        drop(pool);
+        assert_eq!(counter.load(Ordering::Relaxed), 16);
+    }
+}
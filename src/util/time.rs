@@ -0,0 +1,65 @@
+//Frame time bookkeeping, advanced once per frame by the application
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Time {
+    delta_seconds: f32,
+    elapsed_seconds: f64,
+    frame_count: u64,
+}
+
+impl Time {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn advance(&mut self, delta_seconds: f32) {
+        self.delta_seconds = delta_seconds;
+        self.elapsed_seconds += delta_seconds as f64;
+        self.frame_count += 1;
+    }
+
+    pub fn delta(&self) -> f32 {
+        self.delta_seconds
+    }
+
+    pub fn elapsed(&self) -> f64 {
+        self.elapsed_seconds
+    }
+
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn starts_at_zero() {
+        let time = Time::new();
+        assert_eq!(time.delta(), 0.0);
+        assert_eq!(time.elapsed(), 0.0);
+        assert_eq!(time.frame_count(), 0);
+    }
+
+    #[test]
+    fn advance_accumulates_frames() {
+        let mut time = Time::new();
+        time.advance(0.5);
+        time.advance(0.25);
+        assert_eq!(time.delta(), 0.25);
+        assert_abs_diff_eq!(time.elapsed(), 0.75);
+        assert_eq!(time.frame_count(), 2);
+    }
+
+    #[test]
+    fn elapsed_keeps_precision_over_many_frames() {
+        let mut time = Time::new();
+        for _ in 0..1_000_000 {
+            time.advance(0.001);
+        }
+        assert_abs_diff_eq!(time.elapsed(), 1000.0, epsilon = 1e-3);
+        assert_eq!(time.frame_count(), 1_000_000);
+    }
+}
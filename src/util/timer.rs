@@ -1,4 +1,26 @@
-use std::{collections::VecDeque, time::Instant};
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// Frame time statistics in milliseconds over the last frames of a Timer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameStats {
+    pub mean_ms: f64,
+    pub min_ms: f64,
+    pub max_ms: f64,
+}
+
+impl FrameStats {
+    //Frames per second at the mean frame time, zero before the first frame
+    pub fn fps(&self) -> f64 {
+        if self.mean_ms > 0.0 {
+            1000.0 / self.mean_ms
+        } else {
+            0.0
+        }
+    }
+}
 
 pub struct Timer {
     timestamps: VecDeque<f64>,
@@ -23,13 +45,50 @@ impl Timer {
         }
     }
 
+    //Seconds between the two latest add_timestamp calls
     pub fn get_delta(&self) -> f64 {
         self.delta
     }
 
+    //Frame time statistics in milliseconds over the last max_num_timestamps frames
+    pub fn get_mean_ms(&self) -> f64 {
+        self.current_mean
+    }
+
+    pub fn get_min_ms(&self) -> f64 {
+        self.current_min
+    }
+
+    pub fn get_max_ms(&self) -> f64 {
+        self.current_max
+    }
+
+    pub fn stats(&self) -> FrameStats {
+        FrameStats {
+            mean_ms: self.current_mean,
+            min_ms: self.current_min,
+            max_ms: self.current_max,
+        }
+    }
+
+    pub fn last_frame(&self) -> Instant {
+        self.last_frame
+    }
+
+    //When the next frame should start to hold target_interval, measured from the latest timestamp
+    pub fn next_frame_deadline(&self, target_interval: Duration) -> Instant {
+        Instant::now() + frame_sleep_duration(self.last_frame.elapsed(), target_interval)
+    }
+
+    //Measures the wall-clock time since the previous call, callers don't pass any time in
     pub fn add_timestamp(&mut self) {
-        let in_timestamp = self.last_frame.elapsed().as_micros() as f64 / 1000.0;
-        self.delta = self.last_frame.elapsed().as_micros() as f64 / 1_000_000.0;
+        self.add_timestamp_at(Instant::now());
+    }
+
+    fn add_timestamp_at(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.last_frame);
+        let in_timestamp = elapsed.as_secs_f64() * 1000.0;
+        self.delta = elapsed.as_secs_f64();
 
         self.timestamps.push_back(in_timestamp);
         let mut sum_timestamps = 0.0;
@@ -44,6 +103,83 @@ impl Timer {
             self.current_min = f64::min(self.current_min, *timestamp);
         }
         self.current_mean = sum_timestamps / self.timestamps.len() as f64;
-        self.last_frame = Instant::now();
+        self.last_frame = now;
+    }
+}
+
+//Time left to wait after a frame that took frame_time, zero if the frame ran over the interval
+pub fn frame_sleep_duration(frame_time: Duration, target_interval: Duration) -> Duration {
+    target_interval.saturating_sub(frame_time)
+}
+
+pub fn target_frame_interval(target_fps: u32) -> Duration {
+    Duration::from_secs_f64(1.0 / target_fps.max(1) as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    fn add_frames(timer: &mut Timer, frame_times_ms: &[u64]) {
+        for frame_time in frame_times_ms {
+            let now = timer.last_frame() + Duration::from_millis(*frame_time);
+            timer.add_timestamp_at(now);
+        }
+    }
+
+    #[test]
+    fn statistics_cover_the_added_frames() {
+        let mut timer = Timer::new(10);
+        add_frames(&mut timer, &[10, 20, 30]);
+        assert_relative_eq!(timer.get_min_ms(), 10.0, epsilon = 1e-9);
+        assert_relative_eq!(timer.get_mean_ms(), 20.0, epsilon = 1e-9);
+        assert_relative_eq!(timer.get_max_ms(), 30.0, epsilon = 1e-9);
+        assert_relative_eq!(timer.get_delta(), 0.03, epsilon = 1e-9);
+    }
+
+    #[test]
+    fn statistics_drop_the_oldest_frames() {
+        let mut timer = Timer::new(2);
+        add_frames(&mut timer, &[50, 10, 20]);
+        assert_relative_eq!(timer.get_min_ms(), 10.0, epsilon = 1e-9);
+        assert_relative_eq!(timer.get_mean_ms(), 15.0, epsilon = 1e-9);
+        assert_relative_eq!(timer.get_max_ms(), 20.0, epsilon = 1e-9);
+    }
+
+    #[test]
+    fn stats_report_the_mean_frame_rate() {
+        let mut timer = Timer::new(10);
+        assert_eq!(timer.stats().fps(), 0.0);
+        add_frames(&mut timer, &[10, 30]);
+        let stats = timer.stats();
+        assert_relative_eq!(stats.mean_ms, 20.0, epsilon = 1e-9);
+        assert_relative_eq!(stats.min_ms, 10.0, epsilon = 1e-9);
+        assert_relative_eq!(stats.max_ms, 30.0, epsilon = 1e-9);
+        assert_relative_eq!(stats.fps(), 50.0, epsilon = 1e-9);
+    }
+
+    #[test]
+    fn sleep_fills_the_rest_of_the_interval() {
+        assert_eq!(
+            frame_sleep_duration(Duration::from_millis(4), Duration::from_millis(16)),
+            Duration::from_millis(12)
+        );
+    }
+
+    #[test]
+    fn late_frames_do_not_sleep() {
+        let interval = Duration::from_millis(16);
+        assert_eq!(frame_sleep_duration(interval, interval), Duration::ZERO);
+        assert_eq!(
+            frame_sleep_duration(Duration::from_millis(40), interval),
+            Duration::ZERO
+        );
+    }
+
+    #[test]
+    fn target_interval_guards_against_zero_fps() {
+        assert_eq!(target_frame_interval(50), Duration::from_millis(20));
+        assert_eq!(target_frame_interval(0), Duration::from_secs(1));
     }
 }